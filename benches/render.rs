@@ -0,0 +1,38 @@
+//! Point render throughput against band height, from one band holding
+//! the whole image, drawn serially, down to many small ones
+//!
+//! Run with `cargo bench --bench render`.
+
+use std::time::Instant;
+
+use halide::emulsion::Emulsion;
+use halide::render::Look;
+
+/// renders timed per case, the fastest reported
+const RUNS: usize = 5;
+
+fn main() {
+    let look = Look::default();
+    for (width, height, grains) in [(1024, 1024, 4_000_000), (4096, 2048, 32_000_000)] {
+        let mut emulsion = Emulsion::create_random_emulsion(width, height, grains, Some(1));
+        for (i, grain) in emulsion.grains.iter_mut().enumerate() {
+            grain.developed_fraction = ((i % 97) as f32) / 96.0;
+        }
+        println!("{width}x{height}, {grains} grains");
+        let reference = emulsion.render_tiled(width, height, &look, height);
+        for tile_rows in [height, 256, 64, 16] {
+            let mut best = f64::MAX;
+            for _ in 0..RUNS {
+                let start = Instant::now();
+                let image = std::hint::black_box(emulsion.render_tiled(width, height, &look, tile_rows));
+                best = best.min(start.elapsed().as_secs_f64());
+                assert!(image == reference, "band height changed the render");
+            }
+            println!(
+                "  {tile_rows:>5} rows: {:>8.2} ms, {:>6.1} Mgrains/s",
+                best * 1e3,
+                (grains as f64) / best / 1e6
+            );
+        }
+    }
+}
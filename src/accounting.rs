@@ -0,0 +1,137 @@
+//! Photon accounting: where the light of a run goes, stage by stage, with
+//! a report at the end that shows any light the model creates or loses
+//! without a physical reason
+//!
+//! Every stage records the photons it received and passed on, and the
+//! flows in between it can explain: light reflected back by halation,
+//! light outside the simulated region, photons absorbed by the grains.
+//! What is left over is unaccounted for. Convolutions clamp at the edges
+//! of the frame and so return some of the light that would have spread
+//! past them, which shows up there; a kernel that does not sum to one, or
+//! resampling that does not keep the mean, shows up as the same residual
+//! in frames where nothing is near the edge.
+//!
+//! Photons are counted as the simulation sees them, exposure times the
+//! stock's sensitivity, the exposure time and the emulsion area, so the
+//! absorbed photons are the silver atoms formed.
+
+use std::sync::Mutex;
+
+use crate::field::Field;
+use crate::psf::Kernel;
+
+/// share of a stage's input it may gain or lose unaccounted before the
+/// report warns
+pub const TOLERANCE: f64 = 1e-3;
+
+/// Photons through one stage
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    pub stage: &'static str,
+    pub input: f64,
+    pub output: f64,
+    /// explained gains, or losses when negative
+    pub flows: Vec<(&'static str, f64)>,
+}
+
+impl Account {
+    /// Output the flows do not explain
+    pub fn unaccounted(&self) -> f64 {
+        self.output - self.input - self.flows.iter().map(|(_, photons)| photons).sum::<f64>()
+    }
+}
+
+/// Accounts of a run, filled in by stages that may run in parallel
+#[derive(Debug, Default)]
+pub struct Ledger {
+    accounts: Mutex<Vec<Account>>,
+    warnings: Mutex<Vec<String>>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add photons to a stage, summing with earlier records of the same
+    /// stage such as the bands of a banded run
+    pub fn record(&self, stage: &'static str, input: f64, output: f64, flows: &[(&'static str, f64)]) {
+        let mut accounts = self.accounts.lock().unwrap();
+        let Some(account) = accounts.iter_mut().find(|a| a.stage == stage) else {
+            accounts.push(Account { stage, input, output, flows: flows.to_vec() });
+            return;
+        };
+        account.input += input;
+        account.output += output;
+        for &(name, photons) in flows {
+            match account.flows.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, total)) => {
+                    *total += photons;
+                }
+                None => account.flows.push((name, photons)),
+            }
+        }
+    }
+
+    /// Record a problem the stage found itself
+    pub fn warn(&self, message: String) {
+        self.warnings.lock().unwrap().push(message);
+    }
+
+    /// Warn when `kernel` of a stage does not keep the light it spreads
+    pub fn check_kernel(&self, stage: &'static str, kernel: &Kernel) {
+        let sum = kernel.sum() as f64;
+        if (sum - 1.0).abs() > TOLERANCE {
+            self.warn(format!("{stage} kernel sums to {sum:.4}, not 1"));
+        }
+    }
+
+    pub fn accounts(&self) -> Vec<Account> {
+        self.accounts.lock().unwrap().clone()
+    }
+
+    /// Stage by stage table of the run
+    pub fn report(&self) -> String {
+        let mut lines = vec![format!("{:<26}{:>14}", "stage", "photons")];
+        for account in self.accounts() {
+            lines.push(format!("{:<26}{:>14.4e}", account.stage, account.input));
+            for (name, photons) in &account.flows {
+                lines.push(format!("  {name:<24}{photons:>+14.4e}"));
+            }
+            let unaccounted = account.unaccounted();
+            if unaccounted != 0.0 {
+                lines.push(format!("  {:<24}{:>+14.4e}", "unaccounted", unaccounted));
+            }
+            lines.push(format!("  {:<24}{:>14.4e}", "passed on", account.output));
+        }
+        lines.join("\n")
+    }
+
+    /// Problems the stages found, followed by every stage that gained or
+    /// lost more than [`TOLERANCE`] of its input unaccounted for
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = self.warnings.lock().unwrap().clone();
+        for account in self.accounts() {
+            let unaccounted = account.unaccounted();
+            if unaccounted.abs() > TOLERANCE * account.input.abs() {
+                warnings.push(
+                    format!(
+                        "{} changed the light by {:+.3}% of its input unaccounted for",
+                        account.stage,
+                        (100.0 * unaccounted) / account.input.abs().max(f64::EPSILON)
+                    )
+                );
+            }
+        }
+        warnings
+    }
+}
+
+/// Total of the three channels of an exposure
+pub fn total(exposure: &[Field; 3]) -> f64 {
+    exposure
+        .iter()
+        .flat_map(|channel| &channel.data)
+        .map(|&v| v as f64)
+        .sum()
+}
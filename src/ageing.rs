@@ -0,0 +1,193 @@
+//! Expired film: the slow changes of an emulsion kept past its date
+//!
+//! Over the years background radiation and heat build latent specks in
+//! grains that were never exposed, which develop as fog, while the
+//! sensitivity specks of the rest decay so they need more light. The decay
+//! is uneven from crystal to crystal, which lowers contrast and coarsens
+//! the grain, and the sensitizing dyes fade, the red ones fastest, so the
+//! layers lose speed unevenly. Everything scales with the storage: a year
+//! in a hot glovebox ages film as much as decades in a freezer.
+//!
+//! Some fog is not even over the frame. Airport X-ray scanners leave broad
+//! bands, heat reaching past the can fogs the edges of the strip most, and
+//! cosmic rays, which no freezer keeps out, leave specks that pile up on
+//! long-expired stock. These build latent silver directly, before the
+//! exposure, at the doses a [`FogPattern`] maps over the frame.
+
+use rand::Rng;
+
+use crate::error::{ Error, Result };
+use crate::field::Field;
+use crate::random::{ self, Noise };
+
+/// fraction of grains fogged per year at room temperature
+const FOG_PER_YEAR: f32 = 0.006;
+/// most of the grains that can end up fogged
+const MAX_FOG: f32 = 0.6;
+/// years at room temperature that double the light a grain needs, about
+/// a stop of speed lost per decade
+const DOUBLING_YEARS: f32 = 10.0;
+/// growth per year of the spread of log latent thresholds between grains
+const SPREAD_PER_YEAR: f32 = 0.03;
+/// fading per year of the red, green and blue response of the sensitizing
+/// dyes; blue is mostly the halide's own absorption and hardly fades
+const DYE_FADING: [f32; 3] = [0.04, 0.02, 0.005];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Storage {
+    Freezer,
+    Fridge,
+    /// room temperature, the conditions the rates are given for
+    #[default]
+    Room,
+    /// a hot car or attic
+    Hot,
+}
+
+impl Storage {
+    /// Parse `freezer`, `fridge`, `room` or `hot`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "freezer" | "frozen" => Ok(Storage::Freezer),
+            "fridge" | "refrigerated" => Ok(Storage::Fridge),
+            "room" => Ok(Storage::Room),
+            "hot" => Ok(Storage::Hot),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!("unknown storage '{text}', expected freezer, fridge, room or hot")
+                    )
+                ),
+        }
+    }
+
+    /// Rate of ageing against room temperature
+    pub fn severity(&self) -> f32 {
+        match self {
+            Storage::Freezer => 0.1,
+            Storage::Fridge => 0.3,
+            Storage::Room => 1.0,
+            Storage::Hot => 3.0,
+        }
+    }
+}
+
+/// What years of storage did to an emulsion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ageing {
+    /// share of the grains turned developable without exposure
+    pub fog_fraction: f32,
+    /// factor on every grain's latent threshold
+    pub threshold_scale: f32,
+    /// spread of the log latent threshold factor between grains
+    pub threshold_spread: f32,
+    /// remaining red, green and blue response
+    pub response: [f32; 3],
+}
+
+impl Ageing {
+    /// Effects of `years` in `storage`, `None` for fresh film
+    pub fn new(years: f32, storage: Storage) -> Option<Self> {
+        let years = years.max(0.0) * storage.severity();
+        (years > 0.0).then(|| Self {
+            fog_fraction: (FOG_PER_YEAR * years).min(MAX_FOG),
+            threshold_scale: (2.0f32).powf(years / DOUBLING_YEARS),
+            threshold_spread: SPREAD_PER_YEAR * years,
+            response: DYE_FADING.map(|rate| (-rate * years).exp()),
+        })
+    }
+}
+
+/// dose of an X-ray scan away from its band, against the band's middle
+const XRAY_FLOOR: f32 = 0.3;
+/// width of an X-ray band, in short sides of the frame
+const XRAY_BAND: f32 = 0.12;
+/// depth heat fog reaches in from the film edges, in short sides
+const HEAT_DEPTH: f32 = 0.08;
+/// dose inside a cosmic ray speck, fogging every grain it crosses
+const SPECK_DOSE: f32 = 4.0;
+/// diameters of cosmic ray specks in microns
+const SPECK_DIAMETER_UM: std::ops::Range<f32> = 8.0..40.0;
+
+/// Fog sources leaving a pattern over the frame. Doses are in units that
+/// bring an average grain to its latent threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FogPattern {
+    /// passes through airport X-ray scanners
+    pub xray_scans: u32,
+    /// dose of one scan at the middle of its band
+    pub xray_dose: f32,
+    /// dose at the edges of the strip from heat reaching past the can
+    pub heat_fog: f32,
+    /// cosmic ray specks per square millimetre
+    pub specks_per_mm2: f32,
+}
+
+impl FogPattern {
+    /// Whether any source leaves fog
+    pub fn is_empty(&self) -> bool {
+        (self.xray_scans == 0 || self.xray_dose <= 0.0) && self.heat_fog <= 0.0 && self.specks_per_mm2 <= 0.0
+    }
+
+    /// Dose over a `width`×`height` frame of `pixel_um` pixels
+    pub fn map(&self, width: u32, height: u32, pixel_um: f32, seed: Option<u64>) -> Field {
+        let mut rng = random::rng_for(seed, random::FOG_PATTERN_STREAM, 0);
+        // each scan a band along the frame, its middle wavering as the
+        // film lay in the bag
+        let bands: Vec<(f32, f32, f32, f32)> = (0..self.xray_scans)
+            .map(|_| {
+                (
+                    rng.random_range(0.1..0.9),
+                    rng.random_range(-0.2..0.2),
+                    rng.random_range(1.0..4.0),
+                    rng.random_range(0.0..std::f32::consts::TAU),
+                )
+            })
+            .collect();
+        let ragged = Noise::new(&mut rng, 12);
+        let short = width.min(height).max(1) as f32;
+        let mut field = Field::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let (u, v) = (((x as f32) + 0.5) / (width as f32), ((y as f32) + 0.5) / (height as f32));
+                let mut dose = 0.0;
+                for &(centre, tilt, frequency, phase) in &bands {
+                    let middle = centre + tilt * (u - 0.5) + 0.03 * (std::f32::consts::TAU * frequency * u + phase).sin();
+                    let across = ((v - middle) * (height as f32)) / (XRAY_BAND * short);
+                    dose += self.xray_dose * (XRAY_FLOOR + (1.0 - XRAY_FLOOR) * (-0.5 * across * across).exp());
+                }
+                if self.heat_fog > 0.0 {
+                    // the strip runs along the long side of the frame
+                    let edge = (if width >= height { y.min(height - 1 - y) } else { x.min(width - 1 - x) }) as f32;
+                    let depth = HEAT_DEPTH * short * (1.0 + 0.5 * ragged.at(u, v));
+                    dose += self.heat_fog * (-edge / depth.max(1.0)).exp();
+                }
+                field.set(x, y, dose);
+            }
+        }
+
+        let area_mm2 = ((width as f32) * pixel_um * (height as f32) * pixel_um) / 1.0e6;
+        for _ in 0..random::poisson(self.specks_per_mm2 * area_mm2, &mut rng) {
+            let (cx, cy) = (rng.random_range(0.0..width as f32), rng.random_range(0.0..height as f32));
+            let radius = rng.random_range(SPECK_DIAMETER_UM) / 2.0 / pixel_um;
+            // the pixels under the speck, or the one it falls in when it
+            // is smaller than a pixel, share its dose
+            let reach = radius.max(0.5);
+            let (x0, x1) = ((cx - reach).floor().max(0.0) as u32, ((cx + reach).ceil() as u32).min(width));
+            let (y0, y1) = ((cy - reach).floor().max(0.0) as u32, ((cy + reach).ceil() as u32).min(height));
+            let mut covered: Vec<(u32, u32)> = (y0..y1)
+                .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                .filter(|&(x, y)| ((x as f32) + 0.5 - cx).hypot((y as f32) + 0.5 - cy) <= reach)
+                .collect();
+            if covered.is_empty() {
+                covered.push(((cx as u32).min(width - 1), (cy as u32).min(height - 1)));
+            }
+            let share = (std::f32::consts::PI * radius * radius) / (covered.len() as f32);
+            let dose = SPECK_DOSE * share.min(1.0);
+            for (x, y) in covered {
+                field.set(x, y, field.get(x, y) + dose);
+            }
+        }
+        field
+    }
+}
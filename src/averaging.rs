@@ -0,0 +1,70 @@
+//! Multi-frame studies: the same exposure repeated on emulsions with
+//! different grain, and the average of the results
+
+use crate::error::{ Error, Result };
+use crate::params::Params;
+use crate::pipeline;
+
+/// Process `image` `count` times, developed one after another in the same
+/// bath. Frame `i` uses seed `params.seed + i` when a seed is set, so a
+/// study can be reproduced; otherwise every frame gets fresh grain.
+pub fn expose_frames(
+    image: &image::DynamicImage,
+    params: &Params,
+    count: usize
+) -> Result<Vec<image::RgbaImage>> {
+    (0..count)
+        .map(|i| {
+            tracing::info!("Exposing frame {} of {count}", i + 1);
+            let params = Params {
+                seed: params.seed.map(|seed| seed.wrapping_add(i as u64)),
+                ..params.batch_frame(i)
+            };
+            pipeline::process(image, &params)
+        })
+        .collect()
+}
+
+/// Per-pixel mean of equally sized frames
+pub fn mean(frames: &[image::RgbaImage]) -> Result<image::RgbaImage> {
+    let first = frames.first().ok_or_else(|| Error::Parse("no frames to average".into()))?;
+    let (width, height) = first.dimensions();
+    if frames.iter().any(|f| f.dimensions() != (width, height)) {
+        return Err(Error::Parse("frames differ in size".into()));
+    }
+    let mut sum = vec![0.0f32; first.as_raw().len()];
+    for frame in frames {
+        for (s, &v) in sum.iter_mut().zip(frame.as_raw()) {
+            *s += v as f32;
+        }
+    }
+    let n = frames.len() as f32;
+    let data = sum
+        .iter()
+        .map(|s| (s / n).round() as u8)
+        .collect();
+    Ok(image::RgbaImage::from_raw(width, height, data).expect("buffer matches dimensions"))
+}
+
+/// Frame to frame grain noise: the standard deviation of each pixel's
+/// luminance across frames, averaged over the image, in 0..1 units. The
+/// noise left in the mean of the frames is this over `sqrt(frames)`.
+pub fn frame_noise(frames: &[image::RgbaImage]) -> f32 {
+    if frames.len() < 2 {
+        return 0.0;
+    }
+    let lumas: Vec<image::ImageBuffer<image::Luma<f32>, Vec<f32>>> = frames
+        .iter()
+        .map(|f| image::DynamicImage::ImageRgba8(f.clone()).to_luma32f())
+        .collect();
+    let pixels = lumas[0].as_raw().len();
+    let n = lumas.len() as f32;
+    let total: f32 = (0..pixels)
+        .map(|i| {
+            let mean = lumas.iter().map(|l| l.as_raw()[i]).sum::<f32>() / n;
+            let variance = lumas.iter().map(|l| (l.as_raw()[i] - mean).powi(2)).sum::<f32>() / (n - 1.0);
+            variance.sqrt()
+        })
+        .sum();
+    total / (pixels.max(1) as f32)
+}
@@ -0,0 +1,93 @@
+//! Settings burned into the render as text, so sweeps and dailies stay
+//! identifiable when their file names are lost
+
+use crate::error::{ Error, Result };
+use crate::font;
+use crate::params::Params;
+
+/// space around the burned-in line in font pixels
+const PADDING: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BurnIn {
+    /// on a strip of bare paper added under the frame
+    Margin,
+    /// along the bottom edge of the frame itself, as edge printing in the
+    /// rebate, in whichever of black or white stands out against it
+    Rebate,
+}
+
+impl BurnIn {
+    /// Parse `margin` or `rebate`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "margin" => Ok(BurnIn::Margin),
+            "rebate" => Ok(BurnIn::Rebate),
+            _ => Err(Error::Parse(format!("invalid burn-in '{text}', expected margin or rebate"))),
+        }
+    }
+}
+
+/// The settings a render is identified by: stock, exposure, developer and
+/// time, and seed. The exposure is given as metered when rated at an ISO
+/// speed and in simulation units otherwise.
+pub fn caption(params: &Params) -> String {
+    let exposure = match params.iso {
+        Some(iso) if params.shutter_seconds < 1.0 =>
+            format!("ISO {iso} 1/{:.0}", 1.0 / params.shutter_seconds.max(f32::EPSILON)),
+        Some(iso) => format!("ISO {iso} {}S", params.shutter_seconds),
+        None => format!("EXP {}", params.exposure_time()),
+    };
+    let seed = params.seed.map_or_else(|| "RANDOM".to_string(), |seed| seed.to_string());
+    format!(
+        "{}  {exposure}  DEV {} FOR {}  SEED {seed}",
+        params.stock.name,
+        params.developer.strength,
+        params.development_time
+    )
+}
+
+/// `image` with `text` burned in as `placement` asks, scaled to the width
+/// of the image
+pub fn apply(image: &image::RgbaImage, text: &str, placement: BurnIn) -> image::RgbaImage {
+    // as large as fits across the frame, down to the font's own size
+    let scale = (image.width() / font::text_width(text, 1).max(1)).clamp(1, 4);
+    let padding = PADDING * scale;
+    let line_height = font::text_height(scale) + 2 * padding;
+    match placement {
+        BurnIn::Margin => {
+            let mut framed = image::RgbaImage::from_pixel(
+                image.width(),
+                image.height() + line_height,
+                image::Rgba([255; 4])
+            );
+            image::imageops::replace(&mut framed, image, 0, 0);
+            let y = image.height() + padding;
+            font::draw_text(&mut framed, padding as i64, y as i64, scale, text, image::Rgba([0, 0, 0, 255]));
+            framed
+        }
+        BurnIn::Rebate => {
+            let mut marked = image.clone();
+            let y = image.height().saturating_sub(line_height - padding);
+            let ink = contrasting(image, y);
+            font::draw_text(&mut marked, padding as i64, y as i64, scale, text, ink);
+            marked
+        }
+    }
+}
+
+/// Black on a light bottom edge from row `y` down, white on a dark one
+fn contrasting(image: &image::RgbaImage, y: u32) -> image::Rgba<u8> {
+    let (sum, count) = (y..image.height())
+        .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let [r, g, b, _] = image.get_pixel(x, y).0;
+            0.2126 * (r as f32) + 0.7152 * (g as f32) + 0.0722 * (b as f32)
+        })
+        .fold((0.0, 0u32), |(sum, count), luma| (sum + luma, count + 1));
+    if sum / (count.max(1) as f32) > 127.5 {
+        image::Rgba([0, 0, 0, 255])
+    } else {
+        image::Rgba([255, 255, 255, 255])
+    }
+}
@@ -0,0 +1,299 @@
+//! Intermediate results kept between runs on the same input, so changing
+//! only a late-stage parameter such as the development time re-runs just
+//! the stages it affects
+//!
+//! Each stage keeps the result of its most recent run together with a hash
+//! of the input image, of every parameter the stage depends on and of the
+//! size and modification time of the files it reads. A run whose hash
+//! matches takes a copy of the stored result instead of recomputing it.
+//! Without a seed a cache hit reuses the stored grain rather than drawing
+//! a fresh one, which is what look development wants.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{ Hash, Hasher };
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::emulsion::Emulsion;
+use crate::error::Result;
+use crate::field::Field;
+use crate::params::Params;
+
+/// Most recent result of one stage and the key it was computed for
+struct Slot<T> {
+    entry: Mutex<Option<(u64, T)>>,
+}
+
+impl<T: Clone> Slot<T> {
+    fn new() -> Self {
+        Self { entry: Mutex::new(None) }
+    }
+
+    /// The stored result when `key` matches, otherwise `compute`'s, which is
+    /// stored for the next run. The lock is not held while computing.
+    fn get_or_compute(&self, name: &str, key: u64, compute: impl FnOnce() -> Result<T>) -> Result<T> {
+        if let Some((stored, value)) = self.entry.lock().unwrap().as_ref() {
+            if *stored == key {
+                tracing::info!("Reusing cached {name}");
+                return Ok(value.clone());
+            }
+        }
+        let value = compute()?;
+        *self.entry.lock().unwrap() = Some((key, value.clone()));
+        Ok(value)
+    }
+}
+
+pub struct StageCache {
+    /// exposure of the region after the light profile and halation
+    exposure: Slot<[Field; 3]>,
+    /// emulsion holding the latent image, before development
+    latent: Slot<Emulsion>,
+    /// emulsion as coated, its grains placed and sensitized but unexposed
+    grain_field: Slot<Emulsion>,
+}
+
+impl Default for StageCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StageCache {
+    pub fn new() -> Self {
+        Self { exposure: Slot::new(), latent: Slot::new(), grain_field: Slot::new() }
+    }
+
+    pub fn exposure(&self, key: u64, compute: impl FnOnce() -> Result<[Field; 3]>) -> Result<[Field; 3]> {
+        self.exposure.get_or_compute("halated exposure", key, compute)
+    }
+
+    pub fn latent(&self, key: u64, compute: impl FnOnce() -> Result<Emulsion>) -> Result<Emulsion> {
+        self.latent.get_or_compute("latent image", key, compute)
+    }
+
+    pub fn grain_field(&self, key: u64, compute: impl FnOnce() -> Result<Emulsion>) -> Result<Emulsion> {
+        self.grain_field.get_or_compute("grain field", key, compute)
+    }
+}
+
+/// Hash of the decoded pixels of an input image
+pub fn input_key(image: &image::DynamicImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (image.width(), image.height(), image.color()).hash(&mut hasher);
+    image.as_bytes().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Key of the halated exposure: the parameters read before the emulsion
+pub fn exposure_key(input: u64, params: &Params) -> u64 {
+    let upstream = format!(
+        "{:?}",
+        (
+            (params.crop, params.frame, params.frame_fit),
+            (params.light_profile, params.shutter, params.shutter_seconds, params.light_phase),
+            (params.swing_angle, params.swing_banding),
+            (params.filter_gains(), params.polarizer, &params.polarizer_mask),
+            // the irradiation kernel, sized by the emulsion resolution
+            (params.irradiation_um, params.grain_pitch_um, params.emulsion_width, params.format_width_mm),
+            params.effective_halation_strength(),
+            params.halation_sigma,
+            params.halation_sigma_y,
+            params.halation_angle,
+            &params.halation_psf,
+            (&params.mask, params.mask_targets.halation),
+            params.convolution,
+        )
+    );
+    let files = file_stamps(&[&params.polarizer_mask, &params.halation_psf, &params.mask]);
+    combine(input, &format!("{upstream}{files:?}"))
+}
+
+/// Key of the unexposed grain field of a `width`×`height` exposure: the
+/// parameters that place grains and give them their make-up, but not the
+/// input, so frames of a sequence with the same grain seed share it
+pub fn grain_key(width: u32, height: u32, num_grains: usize, params: &Params) -> u64 {
+    let grains = format!(
+        "{:?}",
+        (
+            (width, height, num_grains, params.supersample, params.grains_per_pixel),
+            params.grain_field_seed(),
+            &params.stock,
+            params.dye_uptake_variation,
+            (params.expired_years, params.storage),
+            params.coating_thickness_um,
+        )
+    );
+    combine(0, &grains)
+}
+
+/// Key of the latent image: every parameter except those of development,
+/// rendering and output. Parameters added later count as upstream until
+/// they are listed here, so a new knob can never serve a stale latent.
+pub fn latent_key(input: u64, params: &Params) -> u64 {
+    let defaults = Params::default();
+    let upstream = Params {
+        developer: defaults.developer,
+        development_model: defaults.development_model,
+        development_time: defaults.development_time,
+        dt: defaults.dt,
+        water_bath_cycles: defaults.water_bath_cycles,
+        water_rest: defaults.water_rest,
+        developer_capacity: defaults.developer_capacity,
+        developer_diffusion: defaults.developer_diffusion,
+        agitation: defaults.agitation,
+        agitation_vigor: defaults.agitation_vigor,
+        development_mottle: defaults.development_mottle,
+        developer_celsius: defaults.developer_celsius,
+        ambient_celsius: defaults.ambient_celsius,
+        bath_time_constant: defaults.bath_time_constant,
+        batch_interval: defaults.batch_interval,
+        developer_penetration_um: defaults.developer_penetration_um,
+        grain_renderer: defaults.grain_renderer,
+        background_density: defaults.background_density,
+        background_tint: defaults.background_tint,
+        washing: defaults.washing,
+        polarity: defaults.polarity,
+        transfer: defaults.transfer,
+        downsample_filter: defaults.downsample_filter,
+        drying_marks: defaults.drying_marks,
+        fingerprints: defaults.fingerprints,
+        fingerprint_density: defaults.fingerprint_density,
+        silvering: defaults.silvering,
+        scan_dust: defaults.scan_dust,
+        infrared_cleaning: defaults.infrared_cleaning,
+        sharpen_amount: defaults.sharpen_amount,
+        sharpen_radius: defaults.sharpen_radius,
+        sharpen_protection: defaults.sharpen_protection,
+        ambrotype: defaults.ambrotype,
+        contact_print: defaults.contact_print,
+        projection: defaults.projection,
+        lamp_kelvin: defaults.lamp_kelvin,
+        lens_flare: defaults.lens_flare,
+        screen_gain: defaults.screen_gain,
+        ambient_flare: defaults.ambient_flare,
+        print_exposure_time: defaults.print_exposure_time,
+        print_age_years: defaults.print_age_years,
+        print_keeping: defaults.print_keeping,
+        burn_in: defaults.burn_in,
+        output_width: defaults.output_width,
+        densitometer: defaults.densitometer,
+        crop_paste: defaults.crop_paste,
+        mask_composite: defaults.mask_composite,
+        latent_export: defaults.latent_export,
+        development_history: defaults.development_history,
+        history_grains: defaults.history_grains,
+        history_every: defaults.history_every,
+        history_region: defaults.history_region,
+        photon_accounting: defaults.photon_accounting,
+        timelapse: defaults.timelapse,
+        timelapse_frames: defaults.timelapse_frames,
+        threads: defaults.threads,
+        stage_threads: defaults.stage_threads,
+        ..params.clone()
+    };
+    let files = file_stamps(
+        &[
+            &params.polarizer_mask,
+            &params.halation_psf,
+            &params.mask,
+            &params.clayden_pattern,
+            &params.latent_import,
+        ]
+    );
+    combine(input, &format!("{upstream:?}{files:?}"))
+}
+
+/// Length and modification time of each file a stage reads, as the keys
+/// otherwise only see their paths and a file edited in place would be
+/// served from before the edit
+fn file_stamps(paths: &[&Option<PathBuf>]) -> Vec<Option<(u64, Option<SystemTime>)>> {
+    paths
+        .iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(path.as_ref()?).ok()?;
+            Some((metadata.len(), metadata.modified().ok()))
+        })
+        .collect()
+}
+
+fn combine(input: u64, upstream: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    input.hash(&mut hasher);
+    upstream.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn keys(params: &Params) -> (u64, u64, u64) {
+        (exposure_key(7, params), latent_key(7, params), grain_key(64, 48, 1000, params))
+    }
+
+    #[test]
+    fn keys_follow_upstream_parameters_only() {
+        let base = Params { seed: Some(1), ..Params::default() };
+        let (exposure, latent, grain) = keys(&base);
+
+        let developed = Params { development_time: base.development_time * 2.0, ..base.clone() };
+        assert_eq!(keys(&developed), (exposure, latent, grain));
+
+        let halated = Params { halation_strength: base.halation_strength + 0.5, ..base.clone() };
+        let (halated_exposure, halated_latent, halated_grain) = keys(&halated);
+        assert_ne!(halated_exposure, exposure);
+        assert_ne!(halated_latent, latent);
+        // the grain field does not see the light
+        assert_eq!(halated_grain, grain);
+
+        let reseeded = Params { seed: Some(2), ..base.clone() };
+        let (reseeded_exposure, reseeded_latent, reseeded_grain) = keys(&reseeded);
+        assert_eq!(reseeded_exposure, exposure);
+        assert_ne!(reseeded_latent, latent);
+        assert_ne!(reseeded_grain, grain);
+
+        assert_ne!(exposure_key(8, &base), exposure);
+        assert_ne!(latent_key(8, &base), latent);
+    }
+
+    #[test]
+    fn touching_a_mask_changes_the_keys() {
+        let path = std::env::temp_dir().join(format!("halide-cache-mask-{}.pgm", std::process::id()));
+        std::fs::write(&path, b"P5 1 1 255\n\x80").unwrap();
+        let params = Params { mask: Some(path.clone()), ..Params::default() };
+        let before = (exposure_key(7, &params), latent_key(7, &params));
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        drop(file);
+        let after = (exposure_key(7, &params), latent_key(7, &params));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_ne!(before.0, after.0);
+        assert_ne!(before.1, after.1);
+    }
+
+    #[test]
+    fn stages_are_reused_only_for_their_key() {
+        let cache = StageCache::new();
+        let computed = std::cell::Cell::new(0);
+        let exposure = |key| {
+            cache.exposure(key, || {
+                computed.set(computed.get() + 1);
+                Ok([(); 3].map(|_| Field::new(2, 2)))
+            })
+        };
+        exposure(1).unwrap();
+        exposure(1).unwrap();
+        assert_eq!(computed.get(), 1);
+        exposure(2).unwrap();
+        assert_eq!(computed.get(), 2);
+        // only the most recent result is kept
+        exposure(1).unwrap();
+        assert_eq!(computed.get(), 3);
+    }
+}
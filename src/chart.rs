@@ -0,0 +1,305 @@
+//! Line charts of measured curves, written as SVG or drawn to PNG with
+//! the built-in font
+//!
+//! Both outputs share one layout: a plot area with gridlines at rounded
+//! tick values, axis labels, a title and a legend of the series.
+
+use std::path::Path;
+
+use crate::error::{ Error, Result };
+use crate::font;
+
+pub const BLACK: [u8; 3] = [0, 0, 0];
+pub const RED: [u8; 3] = [200, 30, 30];
+pub const GREEN: [u8; 3] = [30, 150, 50];
+pub const BLUE: [u8; 3] = [40, 70, 210];
+pub const GRAY: [u8; 3] = [130, 130, 130];
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 420;
+/// space around the plot area: left, right, top, bottom
+const MARGINS: [u32; 4] = [70, 20, 40, 50];
+const GRID: [u8; 3] = [225, 225, 225];
+/// ticks aimed for along an axis
+const TICKS: f32 = 6.0;
+
+/// One curve of a chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(f32, f32)>,
+    pub color: [u8; 3],
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Chart {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub series: Vec<Series>,
+}
+
+/// Value range of an axis and its ticks
+struct Axis {
+    min: f32,
+    max: f32,
+    ticks: Vec<f32>,
+}
+
+impl Axis {
+    /// Range covering `values`, widened to whole ticks of a 1, 2 or 5 step
+    fn of(values: impl Iterator<Item = f32>) -> Self {
+        let (low, high) = values
+            .filter(|v| v.is_finite())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let (low, high) = match (low.is_finite(), high > low) {
+            (false, _) => (0.0, 1.0),
+            (true, false) => (low - 0.5, high + 0.5),
+            (true, true) => (low, high),
+        };
+        let rough = (high - low) / TICKS;
+        let magnitude = (10.0f32).powf(rough.log10().floor());
+        let step = [1.0, 2.0, 5.0, 10.0]
+            .into_iter()
+            .map(|m| m * magnitude)
+            .find(|&step| step >= rough)
+            .unwrap_or(10.0 * magnitude);
+        let (first, last) = ((low / step).floor() as i32, (high / step).ceil() as i32);
+        Self {
+            min: (first as f32) * step,
+            max: (last as f32) * step,
+            ticks: (first..=last).map(|i| (i as f32) * step).collect(),
+        }
+    }
+
+    /// Share of the way along the axis of `value`
+    fn position(&self, value: f32) -> f32 {
+        (value - self.min) / (self.max - self.min).max(f32::EPSILON)
+    }
+
+    fn label(&self, value: f32) -> String {
+        let step = self.ticks.get(1).map_or(1.0, |second| second - self.ticks[0]);
+        let decimals = (-step.log10().floor()).max(0.0) as usize;
+        format!("{value:.decimals$}")
+    }
+}
+
+impl Chart {
+    pub fn new(title: &str, x_label: &str, y_label: &str) -> Self {
+        Self {
+            title: title.into(),
+            x_label: x_label.into(),
+            y_label: y_label.into(),
+            series: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: &str, points: Vec<(f32, f32)>, color: [u8; 3]) {
+        self.series.push(Series { name: name.into(), points, color });
+    }
+
+    /// Write the chart as SVG, or as PNG for a `.png` path
+    pub fn save(&self, path: &Path) -> Result<()> {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("svg") => Ok(std::fs::write(path, self.svg())?),
+            Some("png") => Ok(self.png().save(path)?),
+            _ => Err(Error::Parse(format!("chart {} must be .svg or .png", path.display()))),
+        }
+    }
+
+    fn axes(&self) -> (Axis, Axis) {
+        let points = || self.series.iter().flat_map(|s| s.points.iter());
+        (Axis::of(points().map(|p| p.0)), Axis::of(points().map(|p| p.1)))
+    }
+
+    /// Plot area: left, top, width, height
+    fn plot(&self) -> (f32, f32, f32, f32) {
+        let [left, right, top, bottom] = MARGINS.map(|m| m as f32);
+        (left, top, (WIDTH as f32) - left - right, (HEIGHT as f32) - top - bottom)
+    }
+
+    /// Position in the image of a point on the chart
+    fn to_image(&self, axes: &(Axis, Axis), (x, y): (f32, f32)) -> (f32, f32) {
+        let (left, top, width, height) = self.plot();
+        (left + axes.0.position(x) * width, top + (1.0 - axes.1.position(y)) * height)
+    }
+
+    pub fn svg(&self) -> String {
+        let axes = self.axes();
+        let (left, top, width, height) = self.plot();
+        let rgb = |c: [u8; 3]| format!("rgb({},{},{})", c[0], c[1], c[2]);
+        let mut svg = vec![
+            format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" font-family=\"sans-serif\" font-size=\"11\">"
+            ),
+            format!("<rect width=\"{WIDTH}\" height=\"{HEIGHT}\" fill=\"white\"/>")
+        ];
+        for &tick in &axes.0.ticks {
+            let (x, _) = self.to_image(&axes, (tick, axes.1.min));
+            svg.push(
+                format!(
+                    "<line x1=\"{x:.1}\" y1=\"{top:.1}\" x2=\"{x:.1}\" y2=\"{:.1}\" stroke=\"{}\"/>",
+                    top + height,
+                    rgb(GRID)
+                )
+            );
+            svg.push(
+                format!(
+                    "<text x=\"{x:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                    top + height + 15.0,
+                    axes.0.label(tick)
+                )
+            );
+        }
+        for &tick in &axes.1.ticks {
+            let (_, y) = self.to_image(&axes, (axes.0.min, tick));
+            svg.push(
+                format!(
+                    "<line x1=\"{left:.1}\" y1=\"{y:.1}\" x2=\"{:.1}\" y2=\"{y:.1}\" stroke=\"{}\"/>",
+                    left + width,
+                    rgb(GRID)
+                )
+            );
+            svg.push(
+                format!(
+                    "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+                    left - 6.0,
+                    y + 4.0,
+                    axes.1.label(tick)
+                )
+            );
+        }
+        svg.push(
+            format!(
+                "<rect x=\"{left:.1}\" y=\"{top:.1}\" width=\"{width:.1}\" height=\"{height:.1}\" fill=\"none\" stroke=\"black\"/>"
+            )
+        );
+        for series in &self.series {
+            let points: Vec<String> = series.points
+                .iter()
+                .filter(|p| p.0.is_finite() && p.1.is_finite())
+                .map(|&p| {
+                    let (x, y) = self.to_image(&axes, p);
+                    format!("{x:.1},{y:.1}")
+                })
+                .collect();
+            svg.push(
+                format!(
+                    "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>",
+                    points.join(" "),
+                    rgb(series.color)
+                )
+            );
+        }
+        for (i, series) in self.series.iter().enumerate() {
+            let y = top + 16.0 + (i as f32) * 14.0;
+            let x = left + width - 110.0;
+            svg.push(
+                format!(
+                    "<line x1=\"{x:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"2\"/>",
+                    y - 4.0,
+                    x + 16.0,
+                    y - 4.0,
+                    rgb(series.color)
+                )
+            );
+            svg.push(format!("<text x=\"{:.1}\" y=\"{y:.1}\">{}</text>", x + 22.0, escape(&series.name)));
+        }
+        svg.push(
+            format!(
+                "<text x=\"{:.1}\" y=\"22\" text-anchor=\"middle\" font-size=\"14\">{}</text>",
+                (WIDTH as f32) / 2.0,
+                escape(&self.title)
+            )
+        );
+        svg.push(
+            format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                left + width / 2.0,
+                (HEIGHT as f32) - 12.0,
+                escape(&self.x_label)
+            )
+        );
+        svg.push(
+            format!(
+                "<text x=\"16\" y=\"{:.1}\" text-anchor=\"middle\" transform=\"rotate(-90 16 {:.1})\">{}</text>",
+                top + height / 2.0,
+                top + height / 2.0,
+                escape(&self.y_label)
+            )
+        );
+        svg.push("</svg>\n".into());
+        svg.join("\n")
+    }
+
+    pub fn png(&self) -> image::RgbaImage {
+        let axes = self.axes();
+        let (left, top, width, height) = self.plot();
+        let mut image = image::RgbaImage::from_pixel(WIDTH, HEIGHT, image::Rgba([255; 4]));
+        let color = |c: [u8; 3]| image::Rgba([c[0], c[1], c[2], 255]);
+        let text = |image: &mut image::RgbaImage, x: f32, y: f32, scale: u32, label: &str| {
+            font::draw_text(image, x.round() as i64, y.round() as i64, scale, label, color(BLACK));
+        };
+
+        for &tick in &axes.0.ticks {
+            let (x, _) = self.to_image(&axes, (tick, axes.1.min));
+            line(&mut image, (x, top), (x, top + height), color(GRID), 1);
+            let label = axes.0.label(tick);
+            text(&mut image, x - (font::text_width(&label, 1) as f32) / 2.0, top + height + 8.0, 1, &label);
+        }
+        for &tick in &axes.1.ticks {
+            let (_, y) = self.to_image(&axes, (axes.0.min, tick));
+            line(&mut image, (left, y), (left + width, y), color(GRID), 1);
+            let label = axes.1.label(tick);
+            text(&mut image, left - 6.0 - (font::text_width(&label, 1) as f32), y - 3.0, 1, &label);
+        }
+        let corners = [(left, top), (left + width, top), (left + width, top + height), (left, top + height)];
+        for i in 0..4 {
+            line(&mut image, corners[i], corners[(i + 1) % 4], color(BLACK), 1);
+        }
+        for series in &self.series {
+            let points: Vec<(f32, f32)> = series.points
+                .iter()
+                .filter(|p| p.0.is_finite() && p.1.is_finite())
+                .map(|&p| self.to_image(&axes, p))
+                .collect();
+            for pair in points.windows(2) {
+                line(&mut image, pair[0], pair[1], color(series.color), 2);
+            }
+        }
+        for (i, series) in self.series.iter().enumerate() {
+            let y = top + 10.0 + (i as f32) * 14.0;
+            let x = left + width - 130.0;
+            line(&mut image, (x, y + 3.0), (x + 16.0, y + 3.0), color(series.color), 2);
+            text(&mut image, x + 22.0, y, 1, &series.name);
+        }
+        let title_x = ((WIDTH as f32) - (font::text_width(&self.title, 2) as f32)) / 2.0;
+        text(&mut image, title_x, 12.0, 2, &self.title);
+        let x_label_x = left + (width - (font::text_width(&self.x_label, 1) as f32)) / 2.0;
+        text(&mut image, x_label_x, (HEIGHT as f32) - 18.0, 1, &self.x_label);
+        // the bitmap font does not rotate, the y label sits above its axis
+        text(&mut image, 8.0, top - 14.0, 1, &self.y_label);
+        image
+    }
+}
+
+/// Draw a line `thickness` pixels wide from `a` to `b`
+fn line(image: &mut image::RgbaImage, a: (f32, f32), b: (f32, f32), color: image::Rgba<u8>, thickness: u32) {
+    let steps = (b.0 - a.0).abs().max((b.1 - a.1).abs()).ceil().max(1.0) as u32;
+    for i in 0..=steps {
+        let t = (i as f32) / (steps as f32);
+        let (x, y) = (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1));
+        for dy in 0..thickness {
+            for dx in 0..thickness {
+                let (px, py) = ((x.round() as i64) + (dx as i64), (y.round() as i64) + (dy as i64));
+                if px >= 0 && py >= 0 && px < (image.width() as i64) && py < (image.height() as i64) {
+                    image.put_pixel(px as u32, py as u32, color);
+                }
+            }
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
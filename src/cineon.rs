@@ -0,0 +1,113 @@
+//! Cineon log encoding: printing density stored as 10-bit code values, the
+//! convention of scanned motion-picture negatives
+//!
+//! Code value 95 is base plus fog and each code value is 0.002 density
+//! above it, so a normally exposed diffuse white lands near 685.
+
+use std::path::Path;
+
+use crate::error::{ Error, Result };
+use crate::field::Field;
+
+/// code value of base plus fog
+pub const BLACK: f32 = 95.0;
+/// nominal code value of diffuse white
+pub const WHITE: f32 = 685.0;
+/// density step of one code value
+pub const DENSITY_PER_CODE: f32 = 0.002;
+/// largest 10-bit code value
+pub const MAX_CODE: f32 = 1023.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How the developed negative is written out
+pub enum OutputEncoding {
+    /// drawn as an 8-bit image for viewing
+    Display,
+    /// printing density as Cineon code values, for film scan pipelines
+    Cineon,
+}
+
+impl OutputEncoding {
+    /// Parse `display` or `cineon`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "display" => Ok(OutputEncoding::Display),
+            "cineon" | "log" => Ok(OutputEncoding::Cineon),
+            _ =>
+                Err(
+                    Error::Parse(format!("invalid output encoding '{text}', expected display or cineon"))
+                ),
+        }
+    }
+}
+
+/// Code value of a density above base plus fog
+pub fn code_value(density: f32) -> f32 {
+    (BLACK + density / DENSITY_PER_CODE).clamp(0.0, MAX_CODE)
+}
+
+/// Encode a density field as 16-bit RGB with the 10-bit code values
+/// scaled to the full range
+pub fn encode(density: &Field) -> image::ImageBuffer<image::Rgb<u16>, Vec<u16>> {
+    let mut image = image::ImageBuffer::new(density.width, density.height);
+    for (pixel, &d) in image.pixels_mut().zip(&density.data) {
+        let value = ((code_value(d) / MAX_CODE) * (u16::MAX as f32)).round() as u16;
+        *pixel = image::Rgb([value; 3]);
+    }
+    image
+}
+
+/// Encode a density field as float RGB holding code values over 1023, the
+/// usual normalization of Cineon data in EXR
+pub fn encode_float(density: &Field) -> image::Rgb32FImage {
+    let mut image = image::Rgb32FImage::new(density.width, density.height);
+    for (pixel, &d) in image.pixels_mut().zip(&density.data) {
+        *pixel = image::Rgb([code_value(d) / MAX_CODE; 3]);
+    }
+    image
+}
+
+/// Save a density field Cineon encoded, as float EXR or 16-bit otherwise
+pub fn save(density: &Field, path: &Path) -> Result<()> {
+    let is_exr = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exr"));
+    if is_exr {
+        encode_float(density).save_with_format(path, image::ImageFormat::OpenExr)?;
+    } else {
+        encode(density).save(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_values_follow_density() {
+        assert_eq!(code_value(0.0), BLACK);
+        assert!((code_value((WHITE - BLACK) * DENSITY_PER_CODE) - WHITE).abs() < 1e-3);
+        assert!((code_value(0.2) - 195.0).abs() < 1e-3);
+        // out of range densities clamp to the 10-bit range
+        assert_eq!(code_value(-1.0), 0.0);
+        assert_eq!(code_value(10.0), MAX_CODE);
+    }
+
+    #[test]
+    fn encoding_scales_code_values_to_sixteen_bits() {
+        let mut density = Field::new(3, 1);
+        density.data = vec![0.0, 10.0, (WHITE - BLACK) * DENSITY_PER_CODE];
+        let image = encode(&density);
+        let expected = |code: f32| ((code / MAX_CODE) * (u16::MAX as f32)).round() as u16;
+        assert_eq!(image.get_pixel(0, 0).0, [expected(BLACK); 3]);
+        assert_eq!(image.get_pixel(1, 0).0, [u16::MAX; 3]);
+        assert_eq!(image.get_pixel(2, 0).0, [expected(WHITE); 3]);
+    }
+
+    #[test]
+    fn parses_encodings() {
+        assert_eq!(OutputEncoding::parse("cineon").unwrap(), OutputEncoding::Cineon);
+        assert_eq!(OutputEncoding::parse(" log ").unwrap(), OutputEncoding::Cineon);
+        assert_eq!(OutputEncoding::parse("display").unwrap(), OutputEncoding::Display);
+        assert!(OutputEncoding::parse("linear").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+//! Command line parsing shared by every subcommand
+
+use halide::{ Error, Params, Result };
+
+/// flags that never take a value
+const SWITCHES: &[&str] = &["crop-paste", "mask-composite", "expected-value", "single-thread", "negative-only", "negative", "skip-existing"];
+
+pub struct Args {
+    /// positional arguments in order
+    pub positional: Vec<String>,
+    /// `--name value` pairs in order, switches carry an empty value
+    pub flags: Vec<(String, String)>,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut positional = Vec::new();
+        let mut flags = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                positional.push(arg);
+                continue;
+            };
+            if let Some((name, value)) = name.split_once('=') {
+                flags.push((name.to_string(), value.to_string()));
+            } else if SWITCHES.contains(&name) {
+                flags.push((name.to_string(), String::new()));
+            } else {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Error::Parse(format!("missing value for --{name}")))?;
+                flags.push((name.to_string(), value));
+            }
+        }
+        Ok(Self { positional, flags })
+    }
+
+    /// Remove and return the last value given for `name`
+    pub fn take(&mut self, name: &str) -> Option<String> {
+        let mut found = None;
+        self.flags.retain(|(flag, value)| {
+            if flag == name {
+                found = Some(value.clone());
+                false
+            } else {
+                true
+            }
+        });
+        found
+    }
+
+    /// Remove and parse the last value given for `name`
+    pub fn take_parsed<T: std::str::FromStr>(&mut self, name: &str) -> Result<Option<T>> {
+        self.take(name)
+            .map(|value| {
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::Parse(format!("invalid value '{value}' for --{name}")))
+            })
+            .transpose()
+    }
+
+    /// Remove a switch, returning whether it was given
+    pub fn take_switch(&mut self, name: &str) -> bool {
+        self.take(name).is_some()
+    }
+
+    /// Apply all remaining flags as simulation parameters
+    pub fn params(&self) -> Result<Params> {
+        let mut params = Params::default();
+        for (name, value) in &self.flags {
+            params.set(name, value)?;
+        }
+        Ok(params)
+    }
+}
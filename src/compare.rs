@@ -0,0 +1,103 @@
+//! Side by side comparison of stocks: one scene rendered on each, their
+//! characteristic curves on one chart and the flat field readings of each
+//! at the metered exposure
+
+use crate::chart::{ self, Chart };
+use crate::error::Result;
+use crate::flatfield::{ self, Reading };
+use crate::font;
+use crate::params::Params;
+use crate::pipeline;
+use crate::sensitometry::{ self, Curve };
+use crate::stock::Stock;
+use crate::units::{ ExposureScale, Seconds };
+
+/// colours of the stocks' curves, in order
+const COLORS: [[u8; 3]; 5] = [chart::BLACK, chart::RED, chart::BLUE, chart::GREEN, chart::GRAY];
+/// space between and around the frames of the montage in pixels
+const GAP: u32 = 16;
+/// scale of the built-in font for the montage labels
+const LABEL_SCALE: u32 = 2;
+
+/// One stock run through the scene and the measurements
+pub struct Report {
+    pub stock: String,
+    pub render: image::RgbaImage,
+    /// characteristic curve, in log lux seconds when rated at an ISO speed
+    /// and simulation units otherwise
+    pub curve: Curve,
+    pub flat: Reading,
+}
+
+/// Render `scene` on the stock preset `stock` with the rest of `params`,
+/// and measure the stock
+pub fn report(params: &Params, stock: &str, scene: &image::DynamicImage) -> Result<Report> {
+    let params = Params { stock: Stock::preset(stock)?, ..params.clone() };
+    tracing::info!("Rendering the scene on {stock}");
+    let render = pipeline::process(scene, &params)?;
+
+    tracing::info!("Measuring {stock}");
+    let grains_per_pixel = params.grains_per_pixel.unwrap_or(sensitometry::DEFAULT_GRAINS_PER_PIXEL);
+    let (measured, scale) = match params.iso {
+        Some(iso) => {
+            let scale = sensitometry::calibrate(&params, iso, params.grains_per_pixel)?;
+            let exposure_time = scale.exposure_time(params.input_illuminance(), Seconds(params.shutter_seconds));
+            (Params { exposure_time: Some(exposure_time), iso: None, ..params.clone() }, scale)
+        }
+        None => (params.clone(), ExposureScale(1.0)),
+    };
+    let mut curve = sensitometry::measure(&measured, grains_per_pixel)?;
+    for log_exposure in &mut curve.log_exposure {
+        *log_exposure -= scale.log10();
+    }
+    let flat = flatfield::measure(&measured, flatfield::MIDDLE_GRAY)?;
+    Ok(Report { stock: stock.into(), render, curve, flat })
+}
+
+/// The renders in a row on white, each named underneath
+pub fn montage(reports: &[Report]) -> image::RgbaImage {
+    // wide enough for the frames and their names
+    let frame_width = reports
+        .iter()
+        .map(|r| r.render.width().max(font::text_width(&r.stock, LABEL_SCALE)))
+        .max()
+        .unwrap_or(1);
+    let frame_height = reports.iter().map(|r| r.render.height()).max().unwrap_or(1);
+    let label_height = font::text_height(LABEL_SCALE) + GAP;
+    let count = reports.len().max(1) as u32;
+    let mut montage = image::RgbaImage::from_pixel(
+        count * (frame_width + GAP) + GAP,
+        frame_height + label_height + 2 * GAP,
+        image::Rgba([255; 4])
+    );
+    for (i, report) in reports.iter().enumerate() {
+        let x = GAP + (i as u32) * (frame_width + GAP);
+        let frame_x = x + (frame_width - report.render.width()) / 2;
+        image::imageops::replace(&mut montage, &report.render, frame_x as i64, GAP as i64);
+        let label_x = x + frame_width.saturating_sub(font::text_width(&report.stock, LABEL_SCALE)) / 2;
+        font::draw_text(
+            &mut montage,
+            label_x as i64,
+            (GAP + frame_height + GAP) as i64,
+            LABEL_SCALE,
+            &report.stock,
+            image::Rgba([0, 0, 0, 255])
+        );
+    }
+    montage
+}
+
+/// The stocks' characteristic curves on one chart
+pub fn curves(reports: &[Report], rated: bool) -> Chart {
+    let x_label = if rated { "log lux seconds" } else { "log exposure" };
+    let mut chart = Chart::new("Characteristic curves", x_label, "density");
+    for (report, color) in reports.iter().zip(COLORS.iter().cycle()) {
+        let points = report.curve.log_exposure
+            .iter()
+            .copied()
+            .zip(report.curve.density.iter().copied())
+            .collect();
+        chart.add(&report.stock, points, *color);
+    }
+    chart
+}
@@ -0,0 +1,138 @@
+//! Contact sheets: processed negatives laid out in strips on a sheet and
+//! contact printed onto paper
+
+use crate::error::Result;
+use crate::font;
+use crate::params::Params;
+use crate::pipeline;
+
+/// transmission of the area between strips, where no film covers the paper
+const OPEN: u8 = 255;
+/// transmission of the unexposed film base in the rebate
+const BASE: u8 = 232;
+/// transmission of the edge markings exposed into the rebate
+const MARKING: u8 = 40;
+
+#[derive(Debug, Clone)]
+pub struct SheetLayout {
+    /// frames per row
+    pub columns: u32,
+    /// width of the film rebate around each frame in pixels
+    pub rebate: u32,
+    /// bare paper between strips and around the sheet in pixels
+    pub margin: u32,
+    /// scale of the built-in font used for frame labels
+    pub label_scale: u32,
+    /// motion-picture pulldown: perforations per frame, e.g. 4 for
+    /// standard 35mm and 3 for 3-perf, with the holes in register with the
+    /// frame lines; evenly spaced holes independent of the frames when unset
+    pub perforations: Option<u32>,
+}
+
+impl Default for SheetLayout {
+    fn default() -> Self {
+        Self { columns: 4, rebate: 24, margin: 16, label_scale: 2, perforations: None }
+    }
+}
+
+/// A processed negative and the marking printed next to it
+pub struct Frame {
+    pub label: String,
+    pub image: image::RgbaImage,
+}
+
+/// Lay the negatives out as the light pattern reaching the paper: open
+/// paper between strips, film base in the rebates with sprocket holes and
+/// frame markings, and the negatives themselves
+pub fn compose(frames: &[Frame], layout: &SheetLayout) -> image::RgbaImage {
+    let columns = layout.columns.max(1);
+    let rows = (frames.len() as u32).div_ceil(columns).max(1);
+    let frame_width = frames
+        .iter()
+        .map(|f| f.image.width())
+        .max()
+        .unwrap_or(1);
+    let frame_height = frames
+        .iter()
+        .map(|f| f.image.height())
+        .max()
+        .unwrap_or(1);
+    let cell_width = frame_width + layout.rebate;
+    let strip_height = frame_height + 2 * layout.rebate;
+    let width = 2 * layout.margin + columns * cell_width + layout.rebate;
+    let height = 2 * layout.margin + rows * strip_height + (rows - 1) * layout.margin;
+
+    let mut sheet = image::RgbaImage::from_pixel(width, height, gray(OPEN));
+    for row in 0..rows {
+        let strip_y = layout.margin + row * (strip_height + layout.margin);
+        let in_row = (frames.len() as u32).saturating_sub(row * columns).min(columns);
+        if in_row == 0 {
+            continue;
+        }
+        let strip_width = in_row * cell_width + layout.rebate;
+        fill(&mut sheet, layout.margin, strip_y, strip_width, strip_height, BASE);
+        // sprocket holes along the top edge, frame markings along the bottom
+        sprocket_holes(&mut sheet, layout, strip_y, strip_width, cell_width);
+
+        for column in 0..in_row {
+            let frame = &frames[(row * columns + column) as usize];
+            let cell_x = layout.margin + layout.rebate + column * cell_width;
+            let x = cell_x + (frame_width - frame.image.width()) / 2;
+            let y = strip_y + layout.rebate + (frame_height - frame.image.height()) / 2;
+            image::imageops::replace(&mut sheet, &frame.image, x as i64, y as i64);
+
+            let label_y = strip_y + strip_height - layout.rebate +
+                layout.rebate.saturating_sub(font::text_height(layout.label_scale)) / 2;
+            font::draw_text(
+                &mut sheet,
+                cell_x as i64,
+                label_y as i64,
+                layout.label_scale,
+                &frame.label,
+                gray(MARKING)
+            );
+        }
+    }
+    sheet
+}
+
+/// Contact print a composed sheet through the paper emulsion
+pub fn print(sheet: &image::RgbaImage, paper: &Params) -> Result<image::RgbaImage> {
+    pipeline::process(&image::DynamicImage::ImageRgba8(sheet.clone()), paper)
+}
+
+fn sprocket_holes(
+    sheet: &mut image::RgbaImage,
+    layout: &SheetLayout,
+    strip_y: u32,
+    strip_width: u32,
+    cell_width: u32
+) {
+    let hole = (layout.rebate / 3).max(1);
+    let (start, pitch) = match layout.perforations {
+        // every frame spans exactly `perforations` holes, starting at its
+        // frame line
+        Some(perforations) => {
+            let pitch = (cell_width / perforations.max(1)).max(hole + 1);
+            (layout.margin + layout.rebate + (pitch - hole) / 2, pitch)
+        }
+        None => (layout.margin + hole, hole * 3),
+    };
+    let mut x = start;
+    while x + hole < layout.margin + strip_width {
+        fill(sheet, x, strip_y + hole, hole, hole, OPEN);
+        x += pitch;
+    }
+}
+
+fn fill(sheet: &mut image::RgbaImage, x: u32, y: u32, width: u32, height: u32, value: u8) {
+    for py in y..(y + height).min(sheet.height()) {
+        for px in x..(x + width).min(sheet.width()) {
+            sheet.put_pixel(px, py, gray(value));
+        }
+    }
+}
+
+fn gray(value: u8) -> image::Rgba<u8> {
+    image::Rgba([value, value, value, 255])
+}
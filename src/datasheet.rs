@@ -0,0 +1,418 @@
+//! Stocks from published data: a datasheet description read as JSON and
+//! the simulation parameters fitted to it
+//!
+//! ```json
+//! {
+//!   "name": "example-400",
+//!   "stock": "iodobromide-film",
+//!   "iso": 400,
+//!   "characteristic_curve": [[-3.0, 0.25], [-2.0, 0.6], [-1.0, 1.4]],
+//!   "spectral_sensitivity": [[400, 2.0], [550, 1.8], [650, 1.6]],
+//!   "rms_granularity": 10,
+//!   "mtf": [[10, 1.0], [40, 0.7], [80, 0.4]]
+//! }
+//! ```
+//!
+//! The characteristic curve is density against log lux seconds, the
+//! spectral sensitivity log sensitivity against wavelength in nanometres,
+//! granularity the diffuse RMS granularity at a net density of 1.0 read
+//! through a 48 µm aperture, and the MTF the response against cycles per
+//! millimetre. Every entry but the name is optional.
+//!
+//! The stock's spectral bands and the emulsion's light spread are fitted
+//! directly. Contrast, maximum density and granularity come out of the
+//! whole simulation, so they are fitted by measuring the simulated stock
+//! and correcting the development and grain density a few times over.
+
+use std::path::Path;
+
+use crate::error::{ Error, Result };
+use crate::flatfield;
+use crate::json::{ self, Value };
+use crate::params::Params;
+use crate::sensitometry::{ self, Curve };
+use crate::spectral::{ Band, SpectralSensitivity };
+use crate::units::{ ExposureScale, LuxSeconds };
+
+/// rounds of measuring the simulated stock and correcting its grain
+/// density, taken as soon as its curve reaches the granularity density
+const GRAIN_ROUNDS: usize = 2;
+/// rounds of measuring the simulated stock and correcting its contrast
+const CONTRAST_ROUNDS: usize = 4;
+/// most sensitizing bands fitted to the spectral curve
+const MAX_BANDS: usize = 3;
+/// smallest residual sensitivity, relative to the native peak, still
+/// fitted with a band
+const MIN_BAND: f32 = 0.05;
+/// net density granularity is quoted at
+const GRANULARITY_DENSITY: f32 = 1.0;
+/// net density of the speed point
+const SPEED_DENSITY: f32 = 0.1;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Datasheet {
+    pub name: Option<String>,
+    /// built-in stock to start from, for the crystal and everything the
+    /// sheet does not describe
+    pub stock: Option<String>,
+    pub iso: Option<f32>,
+    /// log lux seconds and density
+    pub characteristic_curve: Vec<(f32, f32)>,
+    /// wavelength in nanometres and log sensitivity
+    pub spectral_sensitivity: Vec<(f32, f32)>,
+    pub rms_granularity: Option<f32>,
+    /// cycles per millimetre and response
+    pub mtf: Vec<(f32, f32)>,
+}
+
+impl Datasheet {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let value = json::parse(text)?;
+        let text = |key: &str| value.get(key).and_then(Value::as_param);
+        let number = |key: &str| {
+            value
+                .get(key)
+                .map(|v| match v.as_f64() {
+                    Some(n) => Ok(n as f32),
+                    None => Err(Error::Parse(format!("datasheet '{key}' must be a number"))),
+                })
+                .transpose()
+        };
+        let curve = |key: &str| points(value.get(key), key);
+        let mut sheet = Self {
+            name: text("name"),
+            stock: text("stock"),
+            iso: number("iso")?,
+            rms_granularity: number("rms_granularity")?,
+            characteristic_curve: curve("characteristic_curve")?,
+            spectral_sensitivity: curve("spectral_sensitivity")?,
+            mtf: curve("mtf")?,
+        };
+        for curve in [&mut sheet.characteristic_curve, &mut sheet.spectral_sensitivity, &mut sheet.mtf] {
+            curve.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+        Ok(sheet)
+    }
+
+    /// ISO speed given on the sheet, or read off its curve where it rises
+    /// 0.1 above base plus fog
+    pub fn speed(&self) -> Option<f32> {
+        self.iso.or_else(|| {
+            let fog = self.characteristic_curve.first()?.1;
+            let log_h = crossing(&self.characteristic_curve, fog + SPEED_DENSITY)?;
+            Some(LuxSeconds((10.0f32).powf(log_h)).speed())
+        })
+    }
+}
+
+/// Fit simulation parameters to `sheet`, starting from `base`. The result
+/// is a list of settings for [`Params::set`], in the order they apply.
+pub fn fit(sheet: &Datasheet, base: &Params) -> Result<Vec<(String, String)>> {
+    let mut fitted = Fitted { params: base.clone(), settings: Vec::new() };
+    if let Some(stock) = &sheet.stock {
+        fitted.set("stock", stock.clone())?;
+    }
+    if !sheet.spectral_sensitivity.is_empty() {
+        let native = fitted.params.stock.spectral_sensitivity();
+        let sensitivity = fit_sensitivity(&sheet.spectral_sensitivity, &native);
+        fitted.set("sensitization", sensitivity.describe())?;
+    }
+    if let Some(sigma_um) = fit_mtf(&sheet.mtf) {
+        fitted.set("irradiation_um", format!("{sigma_um}"))?;
+    }
+    let Some(iso) = sheet.speed() else {
+        return Ok(fitted.settings);
+    };
+    fitted.set("iso", format!("{iso}"))?;
+    fitted.params.iso = None;
+
+    let target = Shape::of(&sheet.characteristic_curve);
+    // the wedge tops out at the sheet's most exposed point, or three log
+    // units above the speed point
+    let top = sheet.characteristic_curve
+        .last()
+        .map_or(LuxSeconds::speed_point(iso).log10() + 3.0, |&(log_h, _)| log_h);
+    // grain density changes the contrast as much as the granularity, so
+    // it is settled first and the development fitted around it
+    let grain_rounds = if sheet.rms_granularity.is_some() { GRAIN_ROUNDS } else { 0 };
+    let mut grain_fits = 0;
+    for round in 0..grain_rounds + CONTRAST_ROUNDS {
+        let params = &fitted.params;
+        let grains_per_pixel = params.grains_per_pixel.unwrap_or(sensitometry::DEFAULT_GRAINS_PER_PIXEL);
+        let scale = sensitometry::calibrate(params, iso, Some(grains_per_pixel))?;
+        let measured = sensitometry::measure(
+            &Params { exposure_time: Some(scale.units(LuxSeconds((10.0f32).powf(top)))), ..params.clone() },
+            grains_per_pixel
+        )?;
+        let points = curve_points(&measured, scale);
+        let Some(shape) = Shape::of(&points) else {
+            break;
+        };
+        tracing::info!(
+            "Datasheet fit round {}: gamma {:.2}, net maximum density {:.2}",
+            round + 1,
+            shape.gamma,
+            shape.net_max
+        );
+
+        // granularity is read at a density the curve may not reach until
+        // the contrast has been raised
+        let granularity_time = crossing(&points, measured.fog() + GRANULARITY_DENSITY)
+            .map(|log_h| scale.units(LuxSeconds((10.0f32).powf(log_h))))
+            .filter(|_| grain_fits < grain_rounds);
+        if let (Some(target), Some(exposure_time)) = (sheet.rms_granularity, granularity_time) {
+            grain_fits += 1;
+            let measured = granularity(params, exposure_time, grains_per_pixel)?;
+            // granularity falls with the square root of the grain count
+            if measured > 0.0 {
+                let grains = grains_per_pixel * (measured / target).powi(2).clamp(0.25, 4.0);
+                fitted.set("grains_per_pixel", format!("{grains}"))?;
+            }
+        } else if let Some(target) = &target {
+            // gamma grows with development time, more slowly as the
+            // developable grains run out; correcting by the plain ratio
+            // approaches it without overshooting
+            let ratio = (target.gamma / shape.gamma).clamp(0.5, 2.0);
+            let time = fitted.params.development_time * ratio;
+            fitted.set("development_time", format!("{time}"))?;
+            if target.shouldered && shape.net_max > 0.0 {
+                let max = fitted.params.developer.max_development * target.net_max / shape.net_max;
+                fitted.set("max_development", format!("{}", max.clamp(0.05, 1.0)))?;
+            }
+        }
+    }
+    Ok(fitted.settings)
+}
+
+/// Parameters being fitted and the settings that produced them
+struct Fitted {
+    params: Params,
+    settings: Vec<(String, String)>,
+}
+
+impl Fitted {
+    /// Apply a setting, replacing any earlier value for the same key
+    fn set(&mut self, key: &str, value: String) -> Result<()> {
+        self.params.set(key, &value)?;
+        self.settings.retain(|(k, _)| k != key);
+        self.settings.push((key.to_string(), value));
+        Ok(())
+    }
+}
+
+/// Write fitted settings as a flat JSON object, readable back with
+/// `--params`
+pub fn to_json(settings: &[(String, String)]) -> String {
+    let members = settings
+        .iter()
+        .map(|(key, value)| {
+            let value = if json::is_number(value) {
+                Value::Number(value.clone())
+            } else {
+                Value::String(value.clone())
+            };
+            (key.clone(), value)
+        })
+        .collect();
+    format!("{}\n", Value::Object(members))
+}
+
+/// What the fit matches of a characteristic curve
+struct Shape {
+    /// slope of the straight line
+    gamma: f32,
+    /// density above base plus fog at the top of the curve
+    net_max: f32,
+    /// whether the curve levels off, so `net_max` is the real maximum
+    shouldered: bool,
+}
+
+impl Shape {
+    fn of(points: &[(f32, f32)]) -> Option<Self> {
+        let fog = points.first()?.1;
+        let net_max = points.iter().map(|&(_, d)| d - fog).fold(0.0, f32::max);
+        // least squares line through the middle of the density range
+        let middle: Vec<(f32, f32)> = points
+            .iter()
+            .copied()
+            .filter(|&(_, d)| d - fog >= 0.2 * net_max && d - fog <= 0.8 * net_max)
+            .collect();
+        let gamma = slope(&middle)?;
+        let last = &points[points.len().saturating_sub(2)..];
+        let shouldered = last.len() == 2 && slope(last).is_some_and(|s| s < 0.2 * gamma);
+        Some(Self { gamma, net_max, shouldered })
+    }
+}
+
+/// Fitted sensitivity: the native band of `base` plus Gaussian bands
+/// taken greedily from what the native band leaves unexplained
+fn fit_sensitivity(points: &[(f32, f32)], base: &SpectralSensitivity) -> SpectralSensitivity {
+    let peak = points.iter().map(|&(_, s)| s).fold(f32::MIN, f32::max);
+    // relative to the native band at its own peak, or to the curve's peak
+    // when the sheet does not reach down to it
+    let native_at_peak = points
+        .iter()
+        .min_by(|a, b| (a.0 - base.native.peak_nm).abs().total_cmp(&(b.0 - base.native.peak_nm).abs()))
+        .filter(|&&(nm, _)| (nm - base.native.peak_nm).abs() <= base.native.width_nm)
+        .map_or(peak, |&(_, s)| s);
+    let mut residual: Vec<(f32, f32)> = points
+        .iter()
+        .map(|&(nm, s)| (nm, (10.0f32).powf(s - native_at_peak) - base.native.response(nm)))
+        .collect();
+    let mut sensitivity = SpectralSensitivity { native: base.native, sensitizers: Vec::new() };
+    for _ in 0..MAX_BANDS {
+        let Some(&(peak_nm, height)) = residual.iter().max_by(|a, b| a.1.total_cmp(&b.1)) else {
+            break;
+        };
+        if height < MIN_BAND {
+            break;
+        }
+        // half width at half maximum, from the nearer side that falls
+        // below it
+        let below = half_width(residual.iter().rev().filter(|p| p.0 < peak_nm), peak_nm, height);
+        let above = half_width(residual.iter().filter(|p| p.0 > peak_nm), peak_nm, height);
+        let hwhm = match (below, above) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(w), None) | (None, Some(w)) => w,
+            (None, None) => 50.0,
+        };
+        let band = Band::new(peak_nm, (hwhm / (2.0 * (2.0f32).ln()).sqrt()).max(5.0), height);
+        for (nm, r) in residual.iter_mut() {
+            *r -= band.response(*nm);
+        }
+        sensitivity.sensitizers.push(band);
+    }
+    sensitivity
+}
+
+/// Distance from `peak_nm` to the first of `side` below half of `height`
+fn half_width<'a>(mut side: impl Iterator<Item = &'a (f32, f32)>, peak_nm: f32, height: f32) -> Option<f32> {
+    side.find(|&&(_, r)| r < 0.5 * height).map(|&(nm, _)| (nm - peak_nm).abs())
+}
+
+/// σ in microns of the Gaussian light spread giving the sheet's MTF,
+/// `exp(-2π²σ²f²)` fitted in least squares
+fn fit_mtf(points: &[(f32, f32)]) -> Option<f32> {
+    let (num, den) = points
+        .iter()
+        .filter(|&&(f, m)| f > 0.0 && m > 0.0 && m < 1.0)
+        .fold((0.0, 0.0), |(num, den), &(f, m)| (num - m.ln() * f * f, den + f.powi(4)));
+    (den > 0.0 && num > 0.0).then(|| {
+        let sigma_mm = (num / (2.0 * std::f32::consts::PI.powi(2) * den)).sqrt();
+        sigma_mm * 1000.0
+    })
+}
+
+/// RMS granularity of the simulated stock at the exposure
+/// `exposure_time`, read as a densitometer would through an aperture of
+/// [`flatfield::APERTURE_UM`]
+fn granularity(params: &Params, exposure_time: f32, grains_per_pixel: f32) -> Result<f32> {
+    let flat = Params {
+        exposure_time: Some(exposure_time),
+        grains_per_pixel: Some(grains_per_pixel),
+        iso: None,
+        ..params.clone()
+    };
+    Ok(flatfield::measure(&flat, 1.0)?.granularity)
+}
+
+/// Points of a measured curve in log lux seconds
+fn curve_points(curve: &Curve, scale: ExposureScale) -> Vec<(f32, f32)> {
+    curve.log_exposure
+        .iter()
+        .map(|log_e| log_e - scale.log10())
+        .zip(curve.density.iter().copied())
+        .collect()
+}
+
+/// Where a rising curve first reaches `density`, interpolated
+fn crossing(points: &[(f32, f32)], density: f32) -> Option<f32> {
+    points.windows(2).find_map(|pair| {
+        let [(x0, d0), (x1, d1)] = [pair[0], pair[1]];
+        (d0 < density && d1 >= density).then(|| x0 + ((density - d0) / (d1 - d0)) * (x1 - x0))
+    })
+}
+
+/// Least squares slope, `None` for fewer than two distinct points
+fn slope(points: &[(f32, f32)]) -> Option<f32> {
+    let n = points.len() as f32;
+    let (mx, my) = points.iter().fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+    let (sxy, sxx) = points
+        .iter()
+        .fold((0.0, 0.0), |(sxy, sxx), &(x, y)| (sxy + (x - mx) * (y - my), sxx + (x - mx).powi(2)));
+    (points.len() >= 2 && sxx > 0.0).then(|| sxy / sxx)
+}
+
+fn points(value: Option<&Value>, key: &str) -> Result<Vec<(f32, f32)>> {
+    let invalid = || Error::Parse(format!("datasheet '{key}' must be a list of [x, y] pairs"));
+    let Some(value) = value else {
+        return Ok(Vec::new());
+    };
+    let Value::Array(items) = value else {
+        return Err(invalid());
+    };
+    items
+        .iter()
+        .map(|item| match item {
+            Value::Array(pair) =>
+                match pair.as_slice() {
+                    [x, y] =>
+                        match (x.as_f64(), y.as_f64()) {
+                            (Some(x), Some(y)) => Ok((x as f32, y as f32)),
+                            _ => Err(invalid()),
+                        }
+                    _ => Err(invalid()),
+                }
+            _ => Err(invalid()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sheet's curve as the simulation draws it for `params` at `iso`,
+    /// up to two log units above the speed point, as the exposure of the
+    /// top steps is what the measurements spend their time on
+    fn simulated_curve(params: &Params, iso: f32) -> Vec<(f32, f32)> {
+        let grains_per_pixel = params.grains_per_pixel.unwrap();
+        let scale = sensitometry::calibrate(params, iso, Some(grains_per_pixel)).unwrap();
+        let top = LuxSeconds::speed_point(iso).log10() + 2.0;
+        let exposed = Params { exposure_time: Some(scale.units(LuxSeconds((10.0f32).powf(top)))), ..params.clone() };
+        curve_points(&sensitometry::measure(&exposed, grains_per_pixel).unwrap(), scale)
+    }
+
+    #[test]
+    fn settings_are_written_as_json() {
+        let settings = vec![
+            ("iso".to_string(), "400".to_string()),
+            ("sensitization".to_string(), "native, 550 \"green\"".to_string()),
+            ("development_time".to_string(), "inf".to_string()),
+        ];
+        let value = json::parse(&to_json(&settings)).unwrap();
+        assert_eq!(value.get("iso"), Some(&Value::Number("400".into())));
+        assert_eq!(value.get("sensitization").and_then(Value::as_param).as_deref(), Some("native, 550 \"green\""));
+        // not a JSON number, so kept as text
+        assert_eq!(value.get("development_time"), Some(&Value::String("inf".into())));
+    }
+
+    #[test]
+    fn fitted_development_reproduces_the_gamma() {
+        let known = Params { seed: Some(4), grains_per_pixel: Some(2.0), development_time: 2.0, ..Params::default() };
+        let sheet = Datasheet { iso: Some(200.0), characteristic_curve: simulated_curve(&known, 200.0), ..Datasheet::default() };
+        let target = Shape::of(&sheet.characteristic_curve).unwrap().gamma;
+
+        let mut fitted = Params { development_time: 1.4, ..known.clone() };
+        for (key, value) in fit(&sheet, &fitted).unwrap() {
+            fitted.set(&key, &value).unwrap();
+        }
+        fitted.iso = None;
+        let gamma = Shape::of(&simulated_curve(&fitted, 200.0)).unwrap().gamma;
+        assert!((gamma / target - 1.0).abs() < 0.15, "fitted gamma {gamma} against {target}");
+    }
+}
@@ -0,0 +1,220 @@
+//! Drying and archival defects of the finished negative: calcium streaks
+//! left by hard water, fingerprints on the emulsion and the silvering of
+//! dense areas as the film ages
+//!
+//! Each defect adds density. Patterns are laid out over the full frame in
+//! units of its short side, so a crop shows the same marks as the frame.
+//!
+//! - Film hangs to dry; drops running down it dry into streaks of mineral
+//!   deposit, heaviest along their rims and where the drop came to rest,
+//!   and stray drops dry into round spots with a tide mark.
+//! - Skin oil on the emulsion holds dust and tarnishes the silver below
+//!   it along the ridges of the print.
+//! - Silver migrating to the surface of the emulsion forms a mirror that
+//!   starts at the edges of the film, where air reaches it first, and
+//!   grows where there is most silver.
+
+use rand::Rng;
+
+use crate::field::{ Field, Rect };
+use crate::params::Params;
+use crate::random::{ self, smoothstep, Noise };
+use crate::render::Polarity;
+
+/// width of a drying streak, in short sides
+const STREAK_WIDTH: f32 = 0.012;
+/// radius of a drying spot, in short sides
+const SPOT_RADIUS: f32 = 0.02;
+/// spacing of the ridges of a fingerprint, in short sides
+const RIDGE_SPACING: f32 = 0.012;
+/// half axes of a fingerprint, in short sides
+const PRINT_AXES: (f32, f32) = (0.18, 0.24);
+/// depth of the silvered band along the edges, in short sides
+const SILVERING_DEPTH: f32 = 0.15;
+/// density at which silvering reaches its full strength
+const SILVERING_DENSITY: f32 = 2.0;
+/// lowest transmission read from an image, density 3
+const MIN_TRANSMISSION: f32 = 1e-3;
+
+/// Whether `params` asks for any defect
+pub fn enabled(params: &Params) -> bool {
+    params.drying_marks > 0.0 || (params.fingerprints > 0 && params.fingerprint_density > 0.0) ||
+        params.silvering > 0.0
+}
+
+/// Add the defects to a developed density field covering `region` of a
+/// `full` frame
+pub fn apply_density(density: &mut Field, params: &Params, full: Rect, region: Rect) {
+    let added = added_density(density, params, full, region);
+    for (d, a) in density.data.iter_mut().zip(&added.data) {
+        *d += a;
+    }
+}
+
+/// Add the defects to a rendered negative covering `region` of a `full`
+/// frame, darkening every channel by the added density, or lightening it
+/// where the render was inverted to a positive
+pub fn apply_image(image: &mut image::RgbaImage, params: &Params, full: Rect, region: Rect) {
+    let positive = params.polarity == Polarity::Positive;
+    let transmission = |value: u8| if positive { 255 - value } else { value };
+    let base = Field {
+        width: image.width(),
+        height: image.height(),
+        data: image
+            .pixels()
+            .map(|p| {
+                let [r, g, b] = [0, 1, 2].map(|c| transmission(p.0[c]) as f32);
+                let luma = (0.2126 * r + 0.7152 * g + 0.0722 * b) / 255.0;
+                -luma.max(MIN_TRANSMISSION).log10()
+            })
+            .collect(),
+    };
+    let added = added_density(&base, params, full, region);
+    for (pixel, &a) in image.pixels_mut().zip(&added.data) {
+        let through = (10.0f32).powf(-a);
+        for value in &mut pixel.0[..3] {
+            *value = transmission(((transmission(*value) as f32) * through).round() as u8);
+        }
+    }
+}
+
+/// Density the defects add over a field sampling `region`, read against
+/// the `base` density of the negative
+fn added_density(base: &Field, params: &Params, full: Rect, region: Rect) -> Field {
+    let (width, height) = (base.width, base.height);
+    let short = full.width.min(full.height).max(1) as f32;
+    let mut rng = random::rng_for(params.seed, random::DEFECT_STREAM, 0);
+    let streaks: Vec<Streak> = (0..rng.random_range(3..8)).map(|_| Streak::new(&mut rng, full, short)).collect();
+    let spots: Vec<(f32, f32, f32)> = (0..rng.random_range(4..12))
+        .map(|_| {
+            let (x, y) = random_point(&mut rng, full, short);
+            (x, y, SPOT_RADIUS * rng.random_range(0.5..1.5))
+        })
+        .collect();
+    let prints: Vec<Print> = (0..params.fingerprints).map(|_| Print::new(&mut rng, full, short)).collect();
+    let patches = Noise::new(&mut rng, 8);
+
+    let mut added = Field::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            // position on the full frame, in short sides from its corner
+            let fx = (region.x as f32) + (((x as f32) + 0.5) * (region.width as f32)) / (width as f32);
+            let fy = (region.y as f32) + (((y as f32) + 0.5) * (region.height as f32)) / (height as f32);
+            let (px, py) = (fx / short, fy / short);
+            let (u, v) = (fx / (full.width.max(1) as f32), fy / (full.height.max(1) as f32));
+            let mut d = 0.0;
+            if params.drying_marks > 0.0 {
+                let streak = streaks.iter().map(|s| s.at(px, py)).fold(0.0, f32::max);
+                let spot = spots
+                    .iter()
+                    .map(|&(sx, sy, r)| tide_mark((px - sx).hypot(py - sy) / r))
+                    .fold(0.0, f32::max);
+                d += params.drying_marks * streak.max(spot);
+            }
+            if params.fingerprint_density > 0.0 {
+                let print = prints.iter().map(|p| p.at(px, py)).fold(0.0, f32::max);
+                d += params.fingerprint_density * print;
+            }
+            if params.silvering > 0.0 {
+                let edge = (px.min(py).min(full.width as f32 / short - px).min(full.height as f32 / short - py)) /
+                    SILVERING_DEPTH;
+                let patchy = 0.5 + 0.5 * patches.at(u, v);
+                let silver = (base.get(x, y) / SILVERING_DENSITY).clamp(0.0, 1.0);
+                d += params.silvering * (1.0 - smoothstep(edge * (0.6 + 0.8 * patchy))) * silver;
+            }
+            added.set(x, y, d);
+        }
+    }
+    added
+}
+
+/// A drop that ran down the hanging film and dried where it stopped
+struct Streak {
+    x: f32,
+    top: f32,
+    bottom: f32,
+    width: f32,
+    /// sideways drift of the run per short side travelled
+    drift: f32,
+}
+
+impl Streak {
+    fn new(rng: &mut impl Rng, full: Rect, short: f32) -> Self {
+        let (x, top) = random_point(rng, full, short);
+        let length = rng.random_range(0.1..0.6);
+        Self {
+            x,
+            top,
+            bottom: top + length,
+            width: STREAK_WIDTH * rng.random_range(0.6..1.4),
+            drift: rng.random_range(-0.05..0.05),
+        }
+    }
+
+    /// Deposit at `(x, y)`, 1 at the heaviest
+    fn at(&self, x: f32, y: f32) -> f32 {
+        if y < self.top - self.width || y > self.bottom + 2.0 * self.width {
+            return 0.0;
+        }
+        let centre = self.x + self.drift * (y - self.top);
+        let across = ((x - centre) / self.width).abs();
+        // the rims dry first and hold most of the minerals
+        let rim = (-((across - 0.8) / 0.25).powi(2)).exp() + 0.3 * (1.0 - smoothstep(across));
+        // the run thickens downwards and ends in the drop that dried last
+        let along = ((y - self.top) / (self.bottom - self.top)).clamp(0.0, 1.0);
+        let end = tide_mark((x - centre).hypot(y - self.bottom) / (1.6 * self.width));
+        (rim * (0.3 + 0.7 * along)).max(end)
+    }
+}
+
+/// A fingerprint pressed into the emulsion
+struct Print {
+    x: f32,
+    y: f32,
+    angle: f32,
+    /// ridge pattern wobble
+    warp: Noise,
+}
+
+impl Print {
+    fn new(rng: &mut impl Rng, full: Rect, short: f32) -> Self {
+        let (x, y) = random_point(rng, full, short);
+        Self { x, y, angle: rng.random_range(0.0..std::f32::consts::PI), warp: Noise::new(rng, 6) }
+    }
+
+    /// Deposit at `(x, y)`, 1 on a ridge in the middle of the print
+    fn at(&self, x: f32, y: f32) -> f32 {
+        let (dx, dy) = (x - self.x, y - self.y);
+        let (sin, cos) = self.angle.sin_cos();
+        let (a, b) = (dx * cos + dy * sin, -dx * sin + dy * cos);
+        let radius = (a / PRINT_AXES.0).hypot(b / PRINT_AXES.1);
+        if radius >= 1.0 {
+            return 0.0;
+        }
+        let (u, v) = (0.5 + 0.5 * a / PRINT_AXES.0, 0.5 + 0.5 * b / PRINT_AXES.1);
+        // ridges run roughly as concentric loops around the core
+        let phase = (radius * PRINT_AXES.0) / RIDGE_SPACING + 1.5 * self.warp.at(u, v);
+        let ridge = 0.5 + 0.5 * (std::f32::consts::TAU * phase).cos();
+        // pressed hardest in the middle, fading out toward the rim
+        ridge * (1.0 - smoothstep(radius))
+    }
+}
+
+/// Ring of deposit left by a drying drop, at `r` drop radii from its
+/// centre, with a lighter film inside
+fn tide_mark(r: f32) -> f32 {
+    if r > 1.3 {
+        return 0.0;
+    }
+    let ring = (-((r - 1.0) / 0.12).powi(2)).exp();
+    let inside = if r < 1.0 { 0.25 } else { 0.0 };
+    ring.max(inside)
+}
+
+/// Uniform point on the `full` frame, in short sides
+fn random_point(rng: &mut impl Rng, full: Rect, short: f32) -> (f32, f32) {
+    (
+        rng.random_range(0.0..1.0) * (full.width as f32) / short,
+        rng.random_range(0.0..1.0) * (full.height as f32) / short,
+    )
+}
@@ -0,0 +1,78 @@
+//! Densitometer responses: how a density reading weighs the light through
+//! the film, so measured curves line up with the ones on datasheets
+//!
+//! Black and white datasheets plot visual diffuse density. Colour
+//! materials are measured through three narrow filters instead, Status A
+//! for reversal and print films that are looked at, Status M for
+//! negatives that are printed, which sits its red filter further out to
+//! match the sensitivity of print stock.
+
+use crate::error::{ Error, Result };
+use crate::spectral::{ self, Band, RGB_PRIMARIES };
+
+/// Rec. 709 weights of a visual reading
+const VISUAL: [f32; 3] = [0.2126, 0.7152, 0.0722];
+/// lowest transmission read, density 4
+const MIN_TRANSMISSION: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Densitometer {
+    /// one reading weighted by the eye's luminance response
+    #[default]
+    Visual,
+    /// red, green and blue readings through Status A filters
+    StatusA,
+    /// red, green and blue readings through Status M filters
+    StatusM,
+}
+
+impl Densitometer {
+    /// Parse `visual`, `status-a` or `status-m`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "visual" => Ok(Densitometer::Visual),
+            "status-a" | "status_a" | "a" => Ok(Densitometer::StatusA),
+            "status-m" | "status_m" | "m" => Ok(Densitometer::StatusM),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!("unknown densitometer '{text}', expected visual, status-a or status-m")
+                    )
+                ),
+        }
+    }
+
+    /// Red, green and blue filter bands, none for a visual reading
+    pub fn bands(&self) -> Option<[Band; 3]> {
+        match self {
+            Densitometer::Visual => None,
+            Densitometer::StatusA =>
+                Some([Band::new(610.0, 10.0, 1.0), Band::new(540.0, 12.0, 1.0), Band::new(440.0, 12.0, 1.0)]),
+            Densitometer::StatusM =>
+                Some([Band::new(685.0, 15.0, 1.0), Band::new(535.0, 15.0, 1.0), Band::new(445.0, 15.0, 1.0)]),
+        }
+    }
+
+    /// Share of each image channel in each reading, rows summing to 1
+    pub fn weights(&self) -> [[f32; 3]; 3] {
+        let Some(bands) = self.bands() else {
+            return [VISUAL; 3];
+        };
+        bands.map(|band| {
+            let row = RGB_PRIMARIES.map(|primary| {
+                spectral::integrate(|nm| band.response(nm) * primary.response(nm))
+            });
+            let total = row.iter().sum::<f32>().max(f32::EPSILON);
+            row.map(|w| w / total)
+        })
+    }
+
+    /// Red, green and blue densities of a patch with channel
+    /// transmissions `rgb`; a visual reading repeats its one density
+    pub fn read(&self, rgb: [f32; 3]) -> [f32; 3] {
+        self.weights().map(|row| {
+            let transmission: f32 = row.iter().zip(rgb).map(|(w, t)| w * t).sum();
+            -transmission.max(MIN_TRANSMISSION).log10()
+        })
+    }
+}
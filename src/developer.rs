@@ -1,3 +1,4 @@
+#[derive(Debug, Clone)]
 pub struct Developer {
     /// strength of the developer
     pub strength: f32,
@@ -0,0 +1,312 @@
+//! Developer diffusion: the developer soaked into the emulsion held as a
+//! grid of local strength, used up where development is heavy, evening
+//! out sideways and exchanged with the liquid the film sits in
+//!
+//! Under constant agitation the exchange is fast enough that every grain
+//! sees fresh developer and the grid is not needed. Compensating
+//! techniques rely on the opposite: in a water bath the film alternates
+//! between the developer and still water, and during each rest the
+//! developer carried over works on until it is spent. It is spent first
+//! in the highlights, where there is most to develop, while the shadows
+//! keep developing, so the highlights are held back and the shadows
+//! filled in.
+//!
+//! Sheet film agitated by hand in a tray or a tank is in between. Where
+//! the developer surges past the edges of the sheet, the holes of the
+//! hanger and the code notches it is replaced fastest and those parts
+//! develop further. Elsewhere the spent developer, heavy with bromide,
+//! sinks down the sheet and holds back development in streaks below
+//! dense areas. The gentler the agitation, the stronger both are.
+//! However the film is agitated, the flow is never quite even over the
+//! frame, which leaves a faint mottle in the development.
+
+use crate::error::{ Error, Result };
+use crate::field::Field;
+use crate::random::{ self, Noise };
+
+/// exchange rate with the bath per unit of development time; the layer
+/// takes on the bath's strength within a fraction of a time unit
+const BATH_EXCHANGE: f32 = 8.0;
+/// exchange rate with still water, developer slowly leaching out
+const WATER_EXCHANGE: f32 = 0.3;
+/// how far the surge reaches in from the edges, in short sides
+const SURGE_DEPTH: f32 = 0.05;
+/// extra exchange where the surge is strongest, against the middle of
+/// the sheet
+const SURGE_GAIN: f32 = 3.0;
+/// code notches cut into the edge of sheet film near a corner
+const NOTCHES: usize = 3;
+/// reach of the surge through a notch, in short sides
+const NOTCH_RADIUS: f32 = 0.03;
+/// speed at which spent developer sinks down a hanging sheet, in microns
+/// per unit of development time
+const DRAG_UM: f32 = 200.0;
+/// lattice cells of the development mottle across the frame
+const MOTTLE_CELLS: usize = 6;
+/// largest upwind advection step in pixels that stays stable
+const STABLE_DRAG: f32 = 0.5;
+/// largest explicit diffusion step, `diffusion * dt` in pixels squared,
+/// that stays stable
+const STABLE_STEP: f32 = 0.2;
+
+/// Alternating developer and water, the development split evenly over
+/// `cycles` immersions each followed by a rest of `rest_time`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaterBath {
+    pub cycles: usize,
+    /// time in the developer per immersion
+    pub developer_time: f32,
+    /// time in still water per rest
+    pub rest_time: f32,
+}
+
+impl WaterBath {
+    /// Total time from the first immersion to the end of the last rest
+    pub fn total_time(&self) -> f32 {
+        (self.cycles as f32) * (self.developer_time + self.rest_time)
+    }
+
+    /// Whether the film is in the developer at time `t`
+    pub fn in_developer(&self, t: f32) -> bool {
+        let cycle = self.developer_time + self.rest_time;
+        cycle <= 0.0 || t.rem_euclid(cycle) < self.developer_time
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How the film is agitated in the developer
+pub enum Agitation {
+    /// constant agitation keeping the developer fresh everywhere
+    #[default]
+    Continuous,
+    /// a sheet in a rocked tray, the wave washing over its edges and
+    /// draining toward the tipped end
+    Tray,
+    /// a sheet in a hanger in a tank, lifted and drained at intervals
+    Tank,
+}
+
+impl Agitation {
+    /// Parse `continuous`, `tray` or `tank`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "continuous" | "constant" => Ok(Agitation::Continuous),
+            "tray" => Ok(Agitation::Tray),
+            "tank" | "hanger" => Ok(Agitation::Tank),
+            _ =>
+                Err(
+                    Error::Parse(format!("unknown agitation '{text}', expected continuous, tray or tank"))
+                ),
+        }
+    }
+
+    /// Surge over a `width`×`height` sheet, 1 where the developer is
+    /// replaced fastest, `None` under continuous agitation
+    pub fn surge(&self, width: u32, height: u32) -> Option<Field> {
+        if *self == Agitation::Continuous {
+            return None;
+        }
+        let short = width.min(height).max(1) as f32;
+        // the notches sit on the right edge near the top
+        let notches: Vec<f32> = (0..NOTCHES).map(|i| 0.05 + 0.04 * (i as f32)).collect();
+        let mut field = Field::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let (px, py) = (((x as f32) + 0.5) / short, ((y as f32) + 0.5) / short);
+                let right = (width as f32) / short - px;
+                let bottom = (height as f32) / short - py;
+                let edge = |d: f32| (-d / SURGE_DEPTH).exp();
+                // a hanger holds the sheet in channels along its sides and
+                // bottom; the top bar leaves the top edge more sheltered
+                let top = if *self == Agitation::Tank { 0.5 * edge(py) } else { edge(py) };
+                let mut surge = edge(px).max(edge(right)).max(edge(bottom)).max(top);
+                for &notch in &notches {
+                    let d = right.hypot(py - notch) / NOTCH_RADIUS;
+                    surge = surge.max((-d * d).exp());
+                }
+                field.set(x, y, surge);
+            }
+        }
+        Some(field)
+    }
+
+    /// Speed at which spent developer drifts down the sheet, in microns per
+    /// unit of development time
+    pub fn drag_um(&self) -> f32 {
+        match self {
+            Agitation::Continuous => 0.0,
+            Agitation::Tray => 0.5 * DRAG_UM,
+            Agitation::Tank => DRAG_UM,
+        }
+    }
+}
+
+/// Developer activity over a `width`×`height` frame, varying by
+/// `strength` about 1 as the flow does
+pub fn mottle(width: u32, height: u32, strength: f32, seed: Option<u64>) -> Field {
+    let mut rng = random::rng_for(seed, random::MOTTLE_STREAM, 0);
+    let noise = Noise::new(&mut rng, MOTTLE_CELLS);
+    let mut field = Field::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let (u, v) = (((x as f32) + 0.5) / (width as f32), ((y as f32) + 0.5) / (height as f32));
+            field.set(x, y, (1.0 + strength * noise.at(u, v)).max(0.0));
+        }
+    }
+    field
+}
+
+pub struct DeveloperGrid {
+    /// local developer strength per pixel, 1 for fresh developer
+    pub strength: Field,
+    /// bromide released by development per pixel under hand agitation,
+    /// halving the developer's activity at 1
+    bromide: Option<Field>,
+    /// lateral spread in pixels squared per unit of development time
+    diffusion: f32,
+    /// developed grain area per pixel area that exhausts fresh developer
+    capacity: f32,
+    /// exchange rate with the developer per pixel under hand agitation,
+    /// uniform and fast when unset
+    flow: Option<Field>,
+    /// downward drift of the spent developer in pixels per unit of
+    /// development time
+    drag: f32,
+}
+
+impl DeveloperGrid {
+    /// Grid of fresh developer over `width`×`height` pixels
+    pub fn new(width: u32, height: u32, diffusion: f32, capacity: f32) -> Self {
+        let mut strength = Field::new(width, height);
+        strength.data.fill(1.0);
+        Self {
+            strength,
+            bromide: None,
+            diffusion: diffusion.max(0.0),
+            capacity: capacity.max(f32::EPSILON),
+            flow: None,
+            drag: 0.0,
+        }
+    }
+
+    /// Agitate by hand with `vigor`, 1 matching the exchange of continuous
+    /// agitation away from the `surge`, while the spent developer drifts
+    /// down by `drag` pixels per unit of development time
+    pub fn with_agitation(mut self, surge: Option<&Field>, vigor: f32, drag: f32) -> Self {
+        let (width, height) = (self.strength.width, self.strength.height);
+        let rate = BATH_EXCHANGE * vigor.max(0.0);
+        let mut flow = Field::new(width, height);
+        for (i, f) in flow.data.iter_mut().enumerate() {
+            *f = rate * (1.0 + SURGE_GAIN * surge.map_or(0.0, |surge| surge.data[i]));
+        }
+        self.flow = Some(flow);
+        self.bromide = Some(Field::new(width, height));
+        self.drag = drag.max(0.0);
+        self
+    }
+
+    /// Activity of the developer at a pixel, against fresh developer
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        let restraint = self.bromide.as_ref().map_or(1.0, |bromide| 1.0 + bromide.get(x, y));
+        self.strength.get(x, y) / restraint
+    }
+
+    /// Use up developer for `developed`, the grain area developed in each
+    /// pixel as a share of the pixel's area, releasing bromide
+    pub fn consume(&mut self, developed: &Field) {
+        for (strength, &used) in self.strength.data.iter_mut().zip(&developed.data) {
+            *strength = (*strength - used / self.capacity).max(0.0);
+        }
+        if let Some(bromide) = &mut self.bromide {
+            for (bromide, &used) in bromide.data.iter_mut().zip(&developed.data) {
+                *bromide += used / self.capacity;
+            }
+        }
+    }
+
+    /// Let the developer spread between neighbouring pixels for `dt`
+    pub fn diffuse(&mut self, dt: f32) {
+        let spread = self.diffusion * dt;
+        diffuse(&mut self.strength, spread);
+        if let Some(bromide) = &mut self.bromide {
+            diffuse(bromide, spread);
+        }
+    }
+
+    /// Exchange with the liquid around the film for `dt`: fresh developer
+    /// when `in_developer`, plain water otherwise. Spent developer drifts
+    /// down either way.
+    pub fn exchange(&mut self, in_developer: bool, dt: f32) {
+        let shift = self.drag * dt;
+        sink(&mut self.strength, shift, 1.0);
+        if let Some(bromide) = &mut self.bromide {
+            sink(bromide, shift, 0.0);
+        }
+        match (&self.flow, in_developer) {
+            (Some(flow), true) => {
+                for (i, &rate) in flow.data.iter().enumerate() {
+                    let keep = (-rate * dt).exp();
+                    self.strength.data[i] = 1.0 + (self.strength.data[i] - 1.0) * keep;
+                    if let Some(bromide) = &mut self.bromide {
+                        bromide.data[i] *= keep;
+                    }
+                }
+            }
+            _ => {
+                let (bath, rate) = if in_developer { (1.0, BATH_EXCHANGE) } else { (0.0, WATER_EXCHANGE) };
+                let keep = (-rate * dt).exp();
+                for strength in &mut self.strength.data {
+                    *strength = bath + (*strength - bath) * keep;
+                }
+                if let Some(bromide) = &mut self.bromide {
+                    bromide.data.iter_mut().for_each(|b| *b *= keep);
+                }
+            }
+        }
+    }
+}
+
+/// Spread `field` between neighbouring pixels by `spread` pixels squared
+fn diffuse(field: &mut Field, spread: f32) {
+    if spread <= 0.0 {
+        return;
+    }
+    let substeps = (spread / STABLE_STEP).ceil().max(1.0);
+    let step = spread / substeps;
+    let (width, height) = (field.width as i64, field.height as i64);
+    for _ in 0..substeps as usize {
+        let previous = field.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let centre = previous.get_clamped(x, y);
+                let laplacian =
+                    previous.get_clamped(x - 1, y) +
+                    previous.get_clamped(x + 1, y) +
+                    previous.get_clamped(x, y - 1) +
+                    previous.get_clamped(x, y + 1) -
+                    4.0 * centre;
+                field.set(x as u32, y as u32, centre + step * laplacian);
+            }
+        }
+    }
+}
+
+/// Carry `field` down by `shift` pixels, `inflow` entering over the top
+/// edge
+fn sink(field: &mut Field, shift: f32, inflow: f32) {
+    if shift <= 0.0 {
+        return;
+    }
+    let substeps = (shift / STABLE_DRAG).ceil().max(1.0);
+    let step = shift / substeps;
+    for _ in 0..substeps as usize {
+        // bottom up, so each row takes the one above before it moves
+        for y in (0..field.height).rev() {
+            for x in 0..field.width {
+                let above = if y == 0 { inflow } else { field.get(x, y - 1) };
+                let here = field.get(x, y);
+                field.set(x, y, here + step * (above - here));
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Static marks: discharges of static electricity built up by winding film
+//! fast in dry air, which expose the emulsion to their own blue light
+//!
+//! A discharge starts where the film touched a roller or the cassette lip,
+//! usually along the edges of the strip, and forks as it runs into the
+//! frame like lightning, each fork fainter and finer than the one it left.
+//! Marks are laid out over the full frame in units of its short side, so a
+//! crop shows the same marks as the frame, and give a dose like the fog of
+//! [`crate::ageing::FogPattern`].
+
+use std::f32::consts::{ FRAC_PI_2, PI, TAU };
+
+use rand::Rng;
+
+use crate::field::Field;
+use crate::random;
+
+/// length of a straight piece of a discharge, in short sides
+const STEP: f32 = 0.008;
+/// wander of the direction from one step to the next in radians
+const WANDER: f32 = 0.35;
+/// chance per step that a discharge forks
+const FORK_CHANCE: f32 = 0.07;
+/// forks of forks followed at most
+const MAX_DEPTH: u32 = 4;
+/// half width of the trunk of a discharge, in short sides
+const TRUNK_WIDTH: f32 = 0.003;
+/// share of the discharges starting at the edges of the strip
+const EDGE_START: f32 = 0.7;
+
+/// A straight piece of a discharge, in short sides
+struct Stroke {
+    from: (f32, f32),
+    to: (f32, f32),
+    /// dose at the middle, against the trunk's
+    strength: f32,
+    width: f32,
+}
+
+/// Dose of `count` discharges over a `width`×`height` frame, `dose` along
+/// their trunks
+pub fn marks(width: u32, height: u32, count: u32, dose: f32, seed: Option<u64>) -> Field {
+    let mut rng = random::rng_for(seed, random::STATIC_STREAM, 0);
+    let short = width.min(height).max(1) as f32;
+    let (long_u, long_v) = ((width as f32) / short, (height as f32) / short);
+    let mut strokes = Vec::new();
+    for _ in 0..count {
+        // the edges of the strip are the long sides of the frame
+        let (start, heading) = if rng.random::<f32>() < EDGE_START {
+            let along = rng.random::<f32>();
+            let near = rng.random::<bool>();
+            let edge = if near { 0.0 } else { 1.0 };
+            if width >= height {
+                let heading = if near { FRAC_PI_2 } else { -FRAC_PI_2 };
+                ((along * long_u, edge * long_v), heading)
+            } else {
+                ((edge * long_u, along * long_v), if near { 0.0 } else { PI })
+            }
+        } else {
+            ((rng.random::<f32>() * long_u, rng.random::<f32>() * long_v), rng.random_range(0.0..TAU))
+        };
+        let angle = heading + rng.random_range(-0.6..0.6);
+        let length = rng.random_range(0.2..0.6);
+        grow(&mut rng, start, angle, length, 1.0, 0, &mut strokes);
+    }
+
+    let mut field = Field::new(width, height);
+    for stroke in &strokes {
+        draw(&mut field, stroke, short, dose);
+    }
+    field
+}
+
+/// Follow one discharge from `start`, forking on the way
+fn grow(
+    rng: &mut impl Rng,
+    start: (f32, f32),
+    mut angle: f32,
+    length: f32,
+    strength: f32,
+    depth: u32,
+    strokes: &mut Vec<Stroke>
+) {
+    let steps = (length / STEP).ceil().max(1.0) as u32;
+    let mut at = start;
+    for step in 0..steps {
+        angle += rng.random_range(-WANDER..WANDER);
+        let to = (at.0 + STEP * angle.cos(), at.1 + STEP * angle.sin());
+        // fading toward the tip
+        let fade = 1.0 - (0.6 * (step as f32)) / (steps as f32);
+        strokes.push(Stroke { from: at, to, strength: strength * fade, width: TRUNK_WIDTH * strength.sqrt() });
+        if depth < MAX_DEPTH && rng.random::<f32>() < FORK_CHANCE {
+            let side = if rng.random::<bool>() { 1.0 } else { -1.0 };
+            let fork = angle + side * rng.random_range(0.4..1.0);
+            let remaining = length * (1.0 - (step as f32) / (steps as f32));
+            let length = remaining * rng.random_range(0.3..0.7);
+            grow(rng, to, fork, length, strength * fade * 0.6, depth + 1, strokes);
+        }
+        at = to;
+    }
+}
+
+/// Add the dose of a stroke, keeping the strongest where strokes cross
+fn draw(field: &mut Field, stroke: &Stroke, short: f32, dose: f32) {
+    let sigma = (stroke.width * short).max(0.5);
+    let reach = 3.0 * sigma;
+    let (x0, y0) = (stroke.from.0 * short, stroke.from.1 * short);
+    let (x1, y1) = (stroke.to.0 * short, stroke.to.1 * short);
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let length2 = (dx * dx + dy * dy).max(f32::EPSILON);
+    let clamp = |v: f32, size: u32| v.clamp(0.0, size as f32) as u32;
+    let (left, right) = (clamp(x0.min(x1) - reach, field.width), clamp(x0.max(x1) + reach + 1.0, field.width));
+    let (top, bottom) = (clamp(y0.min(y1) - reach, field.height), clamp(y0.max(y1) + reach + 1.0, field.height));
+    for y in top..bottom {
+        for x in left..right {
+            let (px, py) = ((x as f32) + 0.5, (y as f32) + 0.5);
+            // distance to the nearest point of the stroke
+            let t = (((px - x0) * dx + (py - y0) * dy) / length2).clamp(0.0, 1.0);
+            let distance = (px - (x0 + t * dx)).hypot(py - (y0 + t * dy));
+            let value = dose * stroke.strength * (-0.5 * (distance / sigma).powi(2)).exp();
+            if value > field.get(x, y) {
+                field.set(x, y, value);
+            }
+        }
+    }
+}
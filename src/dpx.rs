@@ -0,0 +1,206 @@
+//! DPX (SMPTE 268M) output of Cineon encoded negatives, the interchange
+//! format of film scanners, labs and restoration tools
+//!
+//! Files are written big-endian as a single 10-bit RGB element packed three
+//! samples to a 32-bit word (method A), with the printing density reference
+//! points and the film header filled in from [`FilmInfo`].
+
+use std::io::{ BufWriter, Write };
+use std::path::Path;
+
+use crate::cineon;
+use crate::error::Result;
+use crate::field::Field;
+
+/// generic plus industry headers; image data starts right after them
+const HEADER_SIZE: usize = 2048;
+const GENERIC_HEADER_SIZE: u32 = 768 + 640 + 256;
+const INDUSTRY_HEADER_SIZE: u32 = 256 + 128;
+/// image element descriptor for RGB
+const DESCRIPTOR_RGB: u8 = 50;
+/// transfer characteristic and colorimetric specification code for
+/// printing density
+const PRINTING_DENSITY: u8 = 1;
+
+/// Film header fields describing where a frame came from
+#[derive(Debug, Clone, Default)]
+pub struct FilmInfo {
+    /// film gauge and pulldown, e.g. `35mm 4-perf`
+    pub format: String,
+    /// frame number within the sequence
+    pub frame_position: Option<u32>,
+    /// frames in the sequence
+    pub sequence_length: Option<u32>,
+    /// frames per second the sequence was shot at
+    pub frame_rate: Option<f32>,
+    /// what produced the file, written to the creator field
+    pub creator: String,
+}
+
+/// Write a density field as a Cineon encoded DPX file
+pub fn write(path: &Path, density: &Field, film: &FilmInfo) -> Result<()> {
+    let (width, height) = (density.width, density.height);
+    let image_size = (width as usize) * (height as usize) * 4;
+    let mut header = Header::new();
+
+    // file information
+    header.bytes(0, b"SDPX");
+    header.u32(4, HEADER_SIZE as u32);
+    header.bytes(8, b"V2.0");
+    header.u32(16, (HEADER_SIZE + image_size) as u32);
+    header.u32(20, 1);
+    header.u32(24, GENERIC_HEADER_SIZE);
+    header.u32(28, INDUSTRY_HEADER_SIZE);
+    header.u32(32, 0);
+    if let Some(name) = path.file_name() {
+        header.text(36, 100, &name.to_string_lossy());
+    }
+    header.text(160, 100, &film.creator);
+    header.u32(660, u32::MAX);
+
+    // image information, one element
+    header.u16(768, 0);
+    header.u16(770, 1);
+    header.u32(772, width);
+    header.u32(776, height);
+    let element = 780;
+    header.u32(element, 0);
+    header.u32(element + 4, cineon::BLACK as u32);
+    header.f32(element + 8, 0.0);
+    header.u32(element + 12, cineon::WHITE as u32);
+    header.f32(element + 16, (cineon::WHITE - cineon::BLACK) * cineon::DENSITY_PER_CODE);
+    header.data[element + 20] = DESCRIPTOR_RGB;
+    header.data[element + 21] = PRINTING_DENSITY;
+    header.data[element + 22] = PRINTING_DENSITY;
+    header.data[element + 23] = 10;
+    header.u16(element + 24, 1);
+    header.u16(element + 26, 0);
+    header.u32(element + 28, HEADER_SIZE as u32);
+    header.u32(element + 32, 0);
+    header.u32(element + 36, 0);
+    header.text(element + 40, 32, "printing density");
+
+    // orientation
+    header.u32(1408, 0);
+    header.u32(1412, 0);
+    header.u32(1424, width);
+    header.u32(1428, height);
+
+    // film industry header
+    header.text(1680, 32, &film.format);
+    header.optional_u32(1712, film.frame_position);
+    header.optional_u32(1716, film.sequence_length);
+    header.optional_u32(1720, None);
+    header.optional_f32(1724, film.frame_rate);
+    header.optional_f32(1728, None);
+
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    out.write_all(&header.data)?;
+    for &d in &density.data {
+        // method A: the three 10-bit samples fill the top 30 bits of a word
+        let code = cineon::code_value(d).round() as u32;
+        out.write_all(&((code << 22) | (code << 12) | (code << 2)).to_be_bytes())?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Header bytes with every numeric field undefined until written, as the
+/// format asks for fields a writer does not fill
+struct Header {
+    data: Vec<u8>,
+}
+
+impl Header {
+    fn new() -> Self {
+        let mut data = vec![0xff; HEADER_SIZE];
+        // text fields are undefined as NUL instead, cleared where written;
+        // the generic text fields are cleared up front
+        for range in [36..260, 260..660, 1432..1620, 1664..1712, 1732..1864] {
+            data[range].fill(0);
+        }
+        Self { data }
+    }
+
+    fn bytes(&mut self, offset: usize, bytes: &[u8]) {
+        self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// NUL padded text, cut to leave room for the terminator
+    fn text(&mut self, offset: usize, len: usize, text: &str) {
+        let field = &mut self.data[offset..offset + len];
+        field.fill(0);
+        let bytes = text.as_bytes();
+        let n = bytes.len().min(len - 1);
+        field[..n].copy_from_slice(&bytes[..n]);
+    }
+
+    fn u16(&mut self, offset: usize, value: u16) {
+        self.bytes(offset, &value.to_be_bytes());
+    }
+
+    fn u32(&mut self, offset: usize, value: u32) {
+        self.bytes(offset, &value.to_be_bytes());
+    }
+
+    fn f32(&mut self, offset: usize, value: f32) {
+        self.bytes(offset, &value.to_be_bytes());
+    }
+
+    fn optional_u32(&mut self, offset: usize, value: Option<u32>) {
+        self.u32(offset, value.unwrap_or(u32::MAX));
+    }
+
+    fn optional_f32(&mut self, offset: usize, value: Option<f32>) {
+        self.bytes(offset, &value.map_or([0xff; 4], f32::to_be_bytes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn be_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn header_offsets_and_sizes() {
+        let mut density = Field::new(3, 2);
+        density.data[4] = 0.2;
+        let film = FilmInfo { format: "35mm 4-perf".into(), frame_position: Some(7), ..FilmInfo::default() };
+        let path = std::env::temp_dir().join(format!("halide-dpx-test-{}.dpx", std::process::id()));
+        write(&path, &density, &film).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&data[0..4], b"SDPX");
+        assert_eq!(be_u32(&data, 4), HEADER_SIZE as u32);
+        assert_eq!(be_u32(&data, 16) as usize, data.len());
+        assert_eq!(data.len(), HEADER_SIZE + 3 * 2 * 4);
+        assert_eq!(be_u32(&data, 24), GENERIC_HEADER_SIZE);
+        assert_eq!(be_u32(&data, 28), INDUSTRY_HEADER_SIZE);
+        assert_eq!((be_u32(&data, 772), be_u32(&data, 776)), (3, 2));
+        // the image element starts right after the headers
+        assert_eq!(be_u32(&data, 780 + 28), HEADER_SIZE as u32);
+        assert_eq!(data[780 + 23], 10);
+        assert_eq!(&data[1680..1691], b"35mm 4-perf");
+        assert_eq!(be_u32(&data, 1712), 7);
+        assert_eq!(be_u32(&data, 1716), u32::MAX);
+
+        // method A packing: three equal samples above two padding bits
+        let word = be_u32(&data, HEADER_SIZE + 4 * 4);
+        assert_eq!(word & 0b11, 0);
+        let code = (word >> 2) & 0x3ff;
+        assert_eq!(code, 195);
+        assert_eq!([(word >> 22) & 0x3ff, (word >> 12) & 0x3ff], [code, code]);
+        assert_eq!((be_u32(&data, HEADER_SIZE) >> 2) & 0x3ff, cineon::BLACK as u32);
+    }
+
+    #[test]
+    fn text_fields_leave_room_for_the_terminator() {
+        let mut header = Header::new();
+        header.text(160, 4, "halide");
+        assert_eq!(&header.data[160..164], b"hal\0");
+    }
+}
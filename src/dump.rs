@@ -0,0 +1,41 @@
+//! Intermediate stage output for debugging a look
+
+use std::path::{ Path, PathBuf };
+
+use crate::error::Result;
+use crate::field::Field;
+
+/// Writes intermediate fields of a run as 32-bit float EXRs into a directory
+pub struct StageDump {
+    dir: PathBuf,
+}
+
+impl StageDump {
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self { dir: dir.as_ref().to_path_buf() })
+    }
+
+    /// Write a single channel field as a gray EXR named `name.exr`
+    pub fn field(&self, name: &str, field: &Field) -> Result<()> {
+        self.rgb(name, &[field.clone(), field.clone(), field.clone()])
+    }
+
+    /// Write three fields as the channels of an RGB EXR named `name.exr`
+    pub fn rgb(&self, name: &str, channels: &[Field; 3]) -> Result<()> {
+        let path = self.dir.join(format!("{name}.exr"));
+        tracing::info!("Dumping {}", path.display());
+        write_rgb(&path, channels)
+    }
+}
+
+/// Write three fields as the channels of a 32-bit float RGB EXR
+pub fn write_rgb(path: &Path, channels: &[Field; 3]) -> Result<()> {
+    let (width, height) = (channels[0].width, channels[0].height);
+    let mut image = image::Rgb32FImage::new(width, height);
+    for (i, pixel) in image.pixels_mut().enumerate() {
+        pixel.0 = [channels[0].data[i], channels[1].data[i], channels[2].data[i]];
+    }
+    image.save_with_format(path, image::ImageFormat::OpenExr)?;
+    Ok(())
+}
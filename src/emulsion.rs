@@ -1,74 +1,554 @@
 use rayon::prelude::*;
-use std::{ collections::HashSet, sync::{ Arc, Mutex } };
 use rand::Rng;
+use rand::rngs::StdRng;
+use crate::ageing::Ageing;
+use crate::error::{ Error, Result };
+use crate::field::Field;
 use crate::halide::Halide;
+use crate::random;
+use crate::render::{ Look, Transfer };
+use crate::spatial::GrainIndex;
+use crate::spectral::SpectralSensitivity;
 
+/// ranges the properties of a new grain are drawn from uniformly
+pub const RADIUS_RANGE: std::ops::Range<f32> = 0.1..0.5;
+pub const LATENT_THRESHOLD_RANGE: std::ops::Range<usize> = 5..20;
+pub const ABSORPTION_RANGE: std::ops::Range<f32> = 0.3..0.6;
+
+/// grains handled by one generator when work is split across threads
+const CHUNK: usize = 4096;
+/// rows of the point render drawn together
+const TILE_ROWS: u32 = 64;
+/// latent silver atoms per square micron of grain that a fog dose of 1
+/// leaves, about the threshold of an average grain
+const FOG_DOSE_ATOMS: f32 = 40.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How the exposure reaches the grains
+pub enum ExposureSampling {
+    /// photons land anywhere in a pixel and are caught by whichever grain
+    /// covers the spot, so grain count, size and exposure interact
+    Splat,
+    /// every grain receives the photons falling on its own area, as if it
+    /// were alone in the pixel
+    PerGrain,
+}
+
+impl ExposureSampling {
+    /// Parse `splat` or `per-grain`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "splat" => Ok(ExposureSampling::Splat),
+            "per-grain" | "per_grain" => Ok(ExposureSampling::PerGrain),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!("invalid exposure sampling '{text}', expected splat or per-grain")
+                    )
+                ),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Emulsion {
     pub grains: Vec<Halide>,
 }
 
+/// One coating of a multi-layer emulsion
+#[derive(Clone)]
+pub struct Layer {
+    pub emulsion: Emulsion,
+    /// thickness of the coating in microns, including any interlayer
+    /// below it
+    pub thickness_um: f32,
+    /// share of the light passing through the coating to the layers
+    /// below, lowered by its filter dyes and the grains themselves
+    pub transmission: f32,
+}
+
 impl Emulsion {
-    pub fn create_random_emulsion(width: u32, height: u32, num_grains: usize) -> Self {
-        let emulsion = Arc::new(Mutex::new(Vec::with_capacity(num_grains)));
-        // let grain_positions = Arc::new(Mutex::new(HashSet::new()));
-
-        (0..num_grains).into_par_iter().for_each(|_| {
-            let mut rng = rand::rng();
-            let x = rng.random_range(0..width as usize);
-            let y = rng.random_range(0..height as usize);
-
-            let radius = rng.random_range(0.1..0.5);
-            let latent_threshold = rng.random_range(5..20);
-            let absorption_probability = rng.random_range(0.3..0.6);
-
-            let halide = Halide {
-                x,
-                y,
-                radius,
-                silver_count: 0,
-                latent_threshold,
-                activated: false,
-                spectral_sensitivity: 0.0,
-                absorption_probability,
-                developed_fraction: 0.0,
-            };
-
-            emulsion.lock().unwrap().push(halide);
-        });
-        let lock = emulsion.lock().unwrap();
-        let grains = lock.clone();
+    /// Scatter `num_grains` grains uniformly over the emulsion. The same
+    /// `seed` gives the same grains; without one every call differs.
+    pub fn create_random_emulsion(
+        width: u32,
+        height: u32,
+        num_grains: usize,
+        seed: Option<u64>
+    ) -> Self {
+        let chunks = num_grains.div_ceil(CHUNK);
+        let grains = (0..chunks)
+            .into_par_iter()
+            .flat_map_iter(|chunk| {
+                let mut rng = random::rng_for(seed, random::PLACEMENT_STREAM, chunk as u64);
+                let count = CHUNK.min(num_grains - chunk * CHUNK);
+                (0..count)
+                    .map(|_| {
+                        let x = rng.random_range(0..width as usize);
+                        let y = rng.random_range(0..height as usize);
+                        random_grain(x, y, &mut rng)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        Self { grains }
+    }
+
+    /// Place grains pixel by pixel, the number under each pixel drawn from
+    /// a Poisson distribution with mean `grains_per_pixel`. Unlike a fixed
+    /// count per pixel this reproduces the grain-count noise that makes
+    /// sparsely populated shadows look relatively noisier.
+    pub fn create_poisson_emulsion(
+        width: u32,
+        height: u32,
+        grains_per_pixel: f32,
+        seed: Option<u64>
+    ) -> Self {
+        Self::create_budgeted_emulsion(width, height, grains_per_pixel, |_, _| 1.0, seed)
+    }
+
+    /// Poisson placement with the density under each pixel scaled by
+    /// `budget(x, y)`. Every grain is weighted by the inverse of its
+    /// pixel's budget so that thinned areas keep their total density.
+    pub fn create_budgeted_emulsion<B>(
+        width: u32,
+        height: u32,
+        grains_per_pixel: f32,
+        budget: B,
+        seed: Option<u64>
+    ) -> Self
+        where B: Fn(usize, usize) -> f32 + Sync
+    {
+        let grains = (0..height as usize)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                let mut rng = random::rng_for(seed, random::PLACEMENT_STREAM, y as u64);
+                let mut row = Vec::new();
+                for x in 0..width as usize {
+                    let share = budget(x, y).clamp(f32::EPSILON, 1.0);
+                    for _ in 0..grains_in_pixel(grains_per_pixel * share, &mut rng) {
+                        row.push(Halide { weight: 1.0 / share, ..random_grain(x, y, &mut rng) });
+                    }
+                }
+                row
+            })
+            .collect();
         Self { grains }
     }
 
-    pub fn render_emulsion(&self, width: u32, height: u32) -> image::RgbaImage {
-        let mut output = image::RgbaImage::new(width, height);
-        for pixel in output.pixels_mut() {
-            *pixel = image::Rgba([255, 255, 255, 255]);
+    /// Add the grains of `other`, sharing this emulsion's coating
+    pub fn merge(&mut self, other: Emulsion) {
+        self.grains.extend(other.grains);
+    }
+
+    /// Stack independently created emulsions into one, top layer first.
+    /// Each layer's grains are moved below the layers above it, so light
+    /// and developer reach them through those layers, and receive only
+    /// the light the layers above let through.
+    pub fn layered(layers: impl IntoIterator<Item = Layer>) -> Self {
+        let mut stacked = Self { grains: Vec::new() };
+        let (mut depth, mut transmission) = (0.0, 1.0);
+        for mut layer in layers {
+            layer.emulsion.grains.par_iter_mut().for_each(|grain| {
+                grain.depth += depth;
+                grain.overlying_transmission *= transmission;
+            });
+            stacked.merge(layer.emulsion);
+            depth += layer.thickness_um.max(0.0);
+            transmission *= layer.transmission.clamp(0.0, 1.0);
         }
+        stacked
+    }
 
-        for grain in self.grains.iter() {
-            let gx = grain.x as i32;
-            let gy = grain.y as i32;
-            if gx < 0 || gy < 0 || gx >= (width as i32) || gy >= (height as i32) {
-                continue;
+    /// Visit every grain in parallel with a random generator. For a given
+    /// `seed` and `stream` each grain sees the same random sequence no
+    /// matter how the work is scheduled.
+    pub fn for_each_grain<F>(&mut self, seed: Option<u64>, stream: u64, f: F)
+        where F: Fn(&mut Halide, &mut StdRng) + Sync
+    {
+        self.grains
+            .par_chunks_mut(CHUNK)
+            .enumerate()
+            .for_each(|(chunk, grains)| {
+                let mut rng = random::rng_for(seed, stream, chunk as u64);
+                for grain in grains {
+                    f(grain, &mut rng);
+                }
+            });
+    }
+
+    /// Expose every grain for `exposure_time` to the intensity
+    /// `intensity(grain)` gives it, adding to any earlier exposure. Passes
+    /// that should not share their randomness need different `seed`s.
+    pub fn expose<I>(&mut self, intensity: I, exposure_time: f32, seed: Option<u64>)
+        where I: Fn(&Halide) -> f32 + Sync
+    {
+        self.for_each_grain(seed, random::EXPOSURE_STREAM, |grain, rng| {
+            let intensity = intensity(grain);
+            grain.expose(intensity, exposure_time, rng);
+        });
+    }
+
+    /// Undo the development of every grain, keeping the latent image
+    pub fn reset_development(&mut self) {
+        self.grains.par_iter_mut().for_each(Halide::reset_development);
+    }
+
+    /// Expose by splatting photons. Each pixel of the `width`×`height` grid
+    /// receives a Poisson number of photons per channel with mean
+    /// `photons(x, y)`, landing at random within its `pixel_area` square
+    /// microns. A photon is caught by a grain with the share of the pixel
+    /// its projected area covers: sparse pixels lose light between grains
+    /// and crowded ones saturate as grains shade each other. A caught photon
+    /// of channel `c` is absorbed with probability `absorption(grain, c)`.
+    pub fn splat_photons<P, A>(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixel_area: f32,
+        seed: Option<u64>,
+        photons: P,
+        absorption: A
+    )
+        where P: Fn(u32, u32) -> [f32; 3] + Sync, A: Fn(&Halide, usize) -> f32 + Sync
+    {
+        // grains sorted into rows so that each row can be splatted on its own
+        self.grains.par_sort_by_key(|g| (g.y, g.x));
+        let mut rows = Vec::with_capacity(height as usize);
+        let mut rest = &mut self.grains[..];
+        for y in 0..height as usize {
+            let split = rest.partition_point(|g| g.y <= y);
+            let (row, tail) = rest.split_at_mut(split);
+            rows.push(row);
+            rest = tail;
+        }
+        rows.into_par_iter()
+            .enumerate()
+            .for_each(|(y, row)| {
+                let mut rng = random::rng_for(seed, random::EXPOSURE_STREAM, y as u64);
+                let mut cumulative = Vec::new();
+                let mut start = 0;
+                while start < row.len() {
+                    let x = row[start].x;
+                    let end = start + row[start..].partition_point(|g| g.x == x);
+                    if x < (width as usize) {
+                        splat_pixel(
+                            &mut row[start..end],
+                            photons(x as u32, y as u32),
+                            pixel_area,
+                            &absorption,
+                            &mut cumulative,
+                            &mut rng
+                        );
+                    }
+                    start = end;
+                }
+            });
+    }
+
+    /// Index the grains on a `width`×`height` grid for region queries, in
+    /// cells of `cell` pixels
+    pub fn index(&self, width: u32, height: u32, cell: u32) -> GrainIndex {
+        GrainIndex::new(&self.grains, width, height, cell)
+    }
+
+    /// Number of grains under each pixel, in row-major order
+    pub fn grain_counts(&self, width: u32, height: u32) -> Vec<u32> {
+        let mut counts = vec![0; (width as usize) * (height as usize)];
+        for grain in &self.grains {
+            if grain.x < (width as usize) && grain.y < (height as usize) {
+                counts[grain.y * (width as usize) + grain.x] += 1;
             }
+        }
+        counts
+    }
 
-            // For a log-like final density (inverted):
-            // D = A * log(1 + B * developed_fraction)
-            let A = 0.5;
-            let B = 10.0;
-            let log_density = A * (1.0 + B * grain.developed_fraction).ln();
-
-            // Convert to grayscale
-            // If log_density ~0 => bright, if log_density is large => dark
-            let intensity = (255.0 * (1.0 - log_density)).clamp(0.0, 255.0) as u8;
-
-            output.put_pixel(
-                gx as u32,
-                gy as u32,
-                image::Rgba([intensity, intensity, intensity, 255])
-            );
+    /// Average a per-grain quantity over the grains under each pixel;
+    /// pixels without grains are zero
+    pub fn rasterize(&self, width: u32, height: u32, value: impl Fn(&Halide) -> f32) -> Field {
+        let mut field = Field::new(width, height);
+        let mut counts = vec![0u32; field.data.len()];
+        for grain in &self.grains {
+            if grain.x < (width as usize) && grain.y < (height as usize) {
+                let i = grain.y * (width as usize) + grain.x;
+                field.data[i] += value(grain);
+                counts[i] += 1;
+            }
+        }
+        for (v, &n) in field.data.iter_mut().zip(&counts) {
+            if n > 0 {
+                *v /= n as f32;
+            }
         }
+        field
+    }
+
+    /// Give every grain its own spectral response from the emulsion's
+    /// sensitivity, with dye uptake varying from grain to grain
+    pub fn sensitize(
+        &mut self,
+        sensitivity: &SpectralSensitivity,
+        uptake_variation: f32,
+        seed: Option<u64>
+    ) {
+        let response = sensitivity.rgb_response();
+        self.for_each_grain(seed, random::SENSITIZATION_STREAM, |grain, rng| {
+            grain.spectral_response = response.sample(uptake_variation, rng);
+        });
+    }
+
+    /// Spread the grains evenly through a coating `thickness` microns deep
+    pub fn coat(&mut self, thickness: f32, seed: Option<u64>) {
+        let thickness = thickness.max(0.0);
+        self.for_each_grain(seed, random::COATING_STREAM, |grain, rng| {
+            grain.depth = rng.random::<f32>() * thickness;
+        });
+    }
+
+    /// Apply chemical sensitization: scale every grain's latent threshold
+    /// and turn a random `fog_fraction` of grains developable as fog centres
+    pub fn apply_chemical_sensitization(
+        &mut self,
+        threshold_scale: f32,
+        fog_fraction: f32,
+        seed: Option<u64>
+    ) {
+        self.for_each_grain(seed, random::FOG_STREAM, |grain, rng| {
+            let threshold = ((grain.latent_threshold as f32) * threshold_scale).round();
+            grain.latent_threshold = (threshold as usize).max(1);
+            if fog_fraction > 0.0 && rng.random::<f32>() < fog_fraction {
+                grain.silver_count = grain.silver_count.max(grain.latent_threshold);
+                grain.activated = true;
+            }
+        });
+    }
+
+    /// Age the grains as `ageing` describes: raise and spread their latent
+    /// thresholds, fade their dye response and fog a share of them
+    pub fn age(&mut self, ageing: &Ageing, seed: Option<u64>) {
+        self.for_each_grain(seed, random::AGEING_STREAM, |grain, rng| {
+            let spread = ageing.threshold_spread * (2.0 * rng.random::<f32>() - 1.0);
+            let threshold = ((grain.latent_threshold as f32) * ageing.threshold_scale * spread.exp()).round();
+            grain.latent_threshold = (threshold as usize).max(1);
+            for (response, remaining) in grain.spectral_response.iter_mut().zip(ageing.response) {
+                *response *= remaining;
+            }
+            // fog centres stay developable whatever their threshold now is
+            if rng.random::<f32>() < ageing.fog_fraction || grain.activated {
+                grain.silver_count = grain.silver_count.max(grain.latent_threshold);
+                grain.activated = true;
+            }
+        });
+    }
+
+    /// Build latent silver in every grain from fog that is not light, at
+    /// the dose `dose(grain)` gives its position
+    pub fn irradiate<D>(&mut self, dose: D, seed: Option<u64>) where D: Fn(&Halide) -> f32 + Sync {
+        self.for_each_grain(seed, random::RADIATION_STREAM, |grain, rng| {
+            let mean = dose(grain) * FOG_DOSE_ATOMS * grain.area();
+            for _ in 0..random::poisson(mean, rng) {
+                grain.absorb_photon();
+            }
+        });
+    }
+
+    /// Draw one pixel per grain over the clear base, each grain's density
+    /// falling linearly to black over one unit unless `look` chooses
+    /// another transfer. The last grain on a pixel covers the others.
+    pub fn render_emulsion(&self, width: u32, height: u32, look: &Look) -> image::RgbaImage {
+        self.render_tiled(width, height, look, TILE_ROWS)
+    }
+
+    /// [`Emulsion::render_emulsion`] in bands of `tile_rows` rows drawn in
+    /// parallel. Grains are binned by band in their own order, so the
+    /// image is the same whatever the band height.
+    pub fn render_tiled(&self, width: u32, height: u32, look: &Look, tile_rows: u32) -> image::RgbaImage {
+        let native = Transfer::Log { range: 1.0 };
+        let shade = |d: f32| {
+            let value = |c| (255.0 * look.value(d, c, native)).clamp(0.0, 255.0) as u8;
+            [value(0), value(1), value(2), 255]
+        };
+        let mut output = image::RgbaImage::from_pixel(width, height, image::Rgba(shade(0.0)));
+        if width == 0 || height == 0 {
+            return output;
+        }
+        let tile_rows = tile_rows.clamp(1, height);
+        let tiles = height.div_ceil(tile_rows) as usize;
+
+        // each chunk of grains shaded and sorted into bands as offsets into
+        // the band; a band visits the chunks in order, keeping the grains
+        // in order
+        let stride = (width as usize) * 4;
+        let band_rows = tile_rows as usize;
+        let bins: Vec<Vec<Vec<Shaded>>> = self.grains
+            .par_chunks(CHUNK)
+            .map(|grains| {
+                let mut bins = vec![Vec::new(); tiles];
+                for grain in grains {
+                    if grain.x < (width as usize) && grain.y < (height as usize) {
+                        let offset = (grain.y % band_rows) * stride + grain.x * 4;
+                        bins[grain.y / band_rows].push((offset, shade(grain.density())));
+                    }
+                }
+                bins
+            })
+            .collect();
+
         output
+            .par_chunks_mut(stride * band_rows)
+            .enumerate()
+            .for_each(|(tile, rows)| {
+                for &(offset, value) in bins.iter().flat_map(|bins| &bins[tile]) {
+                    rows[offset..offset + 4].copy_from_slice(&value);
+                }
+            });
+        output
+    }
+}
+
+/// Byte offset of a grain's pixel within its band of the point render and
+/// the colour drawn there
+type Shaded = (usize, [u8; 4]);
+
+/// Splat the photons of one pixel onto the grains under it
+fn splat_pixel(
+    grains: &mut [Halide],
+    photons: [f32; 3],
+    pixel_area: f32,
+    absorption: &impl Fn(&Halide, usize) -> f32,
+    cumulative: &mut Vec<f32>,
+    rng: &mut impl Rng
+) {
+    // a weighted grain stands for `weight` real grains, all of which shade
+    // the pixel but only one of which is simulated
+    cumulative.clear();
+    let mut covered = 0.0;
+    for grain in grains.iter() {
+        covered += grain.weight * grain.area();
+        cumulative.push(covered);
+    }
+    if covered <= 0.0 {
+        return;
+    }
+    let target = covered.max(pixel_area);
+    let caught = covered / target;
+    for (channel, &mean) in photons.iter().enumerate() {
+        for _ in 0..random::poisson(mean * caught, rng) {
+            let spot = rng.random::<f32>() * covered;
+            let index = cumulative.partition_point(|&c| c <= spot).min(grains.len() - 1);
+            let grain = &mut grains[index];
+            if grain.weight > 1.0 && rng.random::<f32>() * grain.weight >= 1.0 {
+                continue;
+            }
+            if rng.random::<f32>() < absorption(grain, channel) {
+                grain.absorb_photon();
+            }
+        }
+    }
+}
+
+/// Sample how many grains fall under one pixel of an emulsion with the
+/// given mean density
+pub fn grains_in_pixel(grains_per_pixel: f32, rng: &mut impl Rng) -> usize {
+    random::poisson(grains_per_pixel, rng)
+}
+
+/// Grain with randomly drawn size, sensitivity and absorption
+fn random_grain(x: usize, y: usize, rng: &mut impl Rng) -> Halide {
+    let radius = rng.random_range(RADIUS_RANGE);
+    let latent_threshold = rng.random_range(LATENT_THRESHOLD_RANGE);
+    let absorption_probability = rng.random_range(ABSORPTION_RANGE);
+
+    Halide::new_with_params(x, y, radius, latent_threshold, absorption_probability)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mean silver per grain after exposing a `grains_per_pixel` emulsion
+    /// to a flat field as `sampling` does, with thresholds out of reach so
+    /// that no grain stops counting
+    fn mean_silver(sampling: ExposureSampling, grains_per_pixel: f32) -> f32 {
+        const SIZE: u32 = 16;
+        const PITCH_UM: f32 = 2.0;
+        const INTENSITY: f32 = 1000.0;
+        let mut emulsion = Emulsion::create_poisson_emulsion(SIZE, SIZE, grains_per_pixel, Some(3));
+        for grain in &mut emulsion.grains {
+            grain.latent_threshold = usize::MAX;
+        }
+        match sampling {
+            ExposureSampling::Splat => {
+                let area = PITCH_UM * PITCH_UM;
+                emulsion.splat_photons(
+                    SIZE,
+                    SIZE,
+                    area,
+                    Some(5),
+                    |_, _| [INTENSITY * area; 3],
+                    |grain, channel| grain.effective_absorption() * grain.spectral_response[channel]
+                );
+            }
+            ExposureSampling::PerGrain => {
+                emulsion.expose(|grain| grain.spectral_intensity([INTENSITY; 3]), 1.0, Some(5));
+            }
+        }
+        let silver: usize = emulsion.grains
+            .iter()
+            .map(|g| g.silver_count)
+            .sum();
+        (silver as f32) / (emulsion.grains.len() as f32)
+    }
+
+    #[test]
+    fn splatting_matches_per_grain_exposure_until_grains_crowd() {
+        // sparse grains cover a fraction of each pixel and catch what
+        // falls on them, as they would alone
+        let splat = mean_silver(ExposureSampling::Splat, 2.0);
+        let per_grain = mean_silver(ExposureSampling::PerGrain, 2.0);
+        assert!((splat / per_grain - 1.0).abs() < 0.03, "splat {splat} against per-grain {per_grain}");
+        // crowded grains shade each other, which only splatting sees
+        let splat = mean_silver(ExposureSampling::Splat, 40.0);
+        let per_grain = mean_silver(ExposureSampling::PerGrain, 40.0);
+        assert!(splat < 0.8 * per_grain, "splat {splat} against per-grain {per_grain}");
+    }
+
+    fn coating(depth: f32, grains: usize) -> Emulsion {
+        let grain = Halide { depth, ..Halide::new_with_params(1, 1, 0.3, 5, 0.5) };
+        Emulsion { grains: vec![grain; grains] }
+    }
+
+    #[test]
+    fn merged_grains_keep_their_coating() {
+        let mut emulsion = coating(0.5, 2);
+        emulsion.merge(coating(1.5, 3));
+        let depths: Vec<f32> = emulsion.grains
+            .iter()
+            .map(|g| g.depth)
+            .collect();
+        assert_eq!(depths, [0.5, 0.5, 1.5, 1.5, 1.5]);
+        assert!(emulsion.grains.iter().all(|g| g.overlying_transmission == 1.0));
+    }
+
+    #[test]
+    fn layers_stack_top_first() {
+        let stacked = Emulsion::layered([
+            Layer { emulsion: coating(0.5, 1), thickness_um: 4.0, transmission: 0.5 },
+            Layer { emulsion: coating(0.5, 1), thickness_um: 3.0, transmission: 0.8 },
+            // a thickness or transmission out of range is taken at its limit
+            Layer { emulsion: coating(0.5, 1), thickness_um: -1.0, transmission: 1.5 },
+            Layer { emulsion: coating(0.5, 1), thickness_um: 2.0, transmission: 0.5 },
+        ]);
+        let layers: Vec<(f32, f32)> = stacked.grains
+            .iter()
+            .map(|g| (g.depth, g.overlying_transmission))
+            .collect();
+        assert_eq!(layers, [(0.5, 1.0), (4.5, 0.5), (7.5, 0.4), (7.5, 0.4)]);
+        // deeper grains see less of the light however it is attenuated
+        let light: Vec<f32> = stacked.grains
+            .iter()
+            .map(|g| g.light_transmission(0.1))
+            .collect();
+        assert!(light.windows(2).all(|pair| pair[1] <= pair[0]));
     }
 }
@@ -0,0 +1,46 @@
+use std::fmt;
+
+/// Errors surfaced by the simulator
+#[derive(Debug)]
+pub enum Error {
+    /// reading or writing a file or socket failed
+    Io(std::io::Error),
+    /// decoding or encoding an image failed
+    Image(image::ImageError),
+    /// a parameter, option or request could not be understood
+    Parse(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "io error: {err}"),
+            Error::Image(err) => write!(f, "image error: {err}"),
+            Error::Parse(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Image(err) => Some(err),
+            Error::Parse(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Self {
+        Error::Image(err)
+    }
+}
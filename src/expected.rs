@@ -0,0 +1,242 @@
+//! Expected-value rendering: the mean of the stochastic pipeline computed
+//! directly, without placing or exposing individual grains
+//!
+//! Every emulsion pixel's expected point render is a function of the
+//! exposure reaching it alone, so it is tabulated once over exposure by
+//! integrating over the grain size, absorption, threshold and depth
+//! distributions, with the absorbed photon count binomially distributed as
+//! in [`Halide::expose`]. The result is noise free, quick enough for
+//! previews, and what averaging many seeds of the stochastic pipeline
+//! should converge to.
+//!
+//! Only the point renderer is modelled. The Clayden and Herschel stages,
+//! latent import and export and stage dumps need real grains and are
+//! ignored here.
+
+use rayon::prelude::*;
+
+use crate::developer::Developer;
+use crate::emulsion::{ ABSORPTION_RANGE, LATENT_THRESHOLD_RANGE, RADIUS_RANGE };
+use crate::field::Field;
+use crate::halide::Halide;
+use crate::params::Params;
+use crate::temperature;
+
+/// exposure entries of a response table
+const TABLE_SIZE: usize = 512;
+/// lowest tabulated exposure relative to the highest
+const TABLE_RANGE: f32 = 1e-6;
+/// quadrature nodes over the grain property distributions
+const RADIUS_NODES: usize = 16;
+const ABSORPTION_NODES: usize = 8;
+const DEPTH_NODES: usize = 8;
+/// latent ratios at which development is precomputed
+const RATIO_NODES: usize = 65;
+/// levels a development mask is quantized to
+const CONCENTRATION_LEVELS: usize = 16;
+
+/// Expected render of an emulsion exposed to `exposure`, at the exposure
+/// field's resolution
+pub fn render(
+    exposure: &[Field; 3],
+    mask: Option<&Field>,
+    num_grains: usize,
+    params: &Params
+) -> image::RgbaImage {
+    let (width, height) = (exposure[0].width, exposure[0].height);
+    let weights = params.stock.spectral_sensitivity().rgb_response().nominal();
+    let scale = params.effective_sensitivity() * params.exposure_time();
+    let exposure_mask = mask.filter(|_| params.mask_targets.exposure);
+    let photons: Vec<f32> = (0..(width as usize) * (height as usize))
+        .map(|i| {
+            let intensity: f32 = (0..3).map(|c| weights[c] * exposure[c].data[i]).sum();
+            intensity * scale * exposure_mask.map_or(1.0, |m| m.data[i])
+        })
+        .collect();
+    let max_photons = photons.iter().copied().fold(0.0, f32::max).max(f32::EPSILON);
+
+    let mut developer = params.developer.clone();
+    developer.strength *= params.stock.crystal.development_rate() * temperature::activity(params.developer_celsius);
+    let development_mask = mask.filter(|_| params.mask_targets.development);
+    let level = |i: usize| {
+        development_mask.map_or(CONCENTRATION_LEVELS - 1, |m| {
+            (m.data[i].clamp(0.0, 1.0) * ((CONCENTRATION_LEVELS - 1) as f32)).round() as usize
+        })
+    };
+    let mut tables: Vec<Option<Response>> = (0..CONCENTRATION_LEVELS).map(|_| None).collect();
+    for i in 0..photons.len() {
+        let level = level(i);
+        if tables[level].is_none() {
+            let concentration = (level as f32) / ((CONCENTRATION_LEVELS - 1) as f32);
+            tables[level] = Some(Response::build(params, &developer, concentration, max_photons));
+        }
+    }
+
+    // chance that no grain lands on a pixel, which then stays clear
+    let grains_per_pixel = params.grains_per_pixel.unwrap_or_else(|| {
+        (num_grains as f32) / (((width as usize) * (height as usize)).max(1) as f32)
+    });
+    let factor = params.supersample.max(1) as f32;
+    let empty = (-grains_per_pixel / (factor * factor)).exp();
+
+    let mut output = image::RgbaImage::new(width, height);
+    for (i, pixel) in output.pixels_mut().enumerate() {
+        let table = tables[level(i)].as_ref().expect("table built above");
+        let value = empty * 255.0 + (1.0 - empty) * table.eval(photons[i]);
+        let value = value.round().clamp(0.0, 255.0) as u8;
+        *pixel = image::Rgba([value, value, value, 255]);
+    }
+    output
+}
+
+/// Expected point render value of an occupied pixel against the photons
+/// per square micron reaching the emulsion surface, log spaced
+struct Response {
+    log_min: f32,
+    log_step: f32,
+    values: Vec<f32>,
+}
+
+impl Response {
+    fn build(params: &Params, developer: &Developer, concentration: f32, max_photons: f32) -> Self {
+        let log_min = (max_photons * TABLE_RANGE).ln();
+        let log_step = (max_photons.ln() - log_min) / ((TABLE_SIZE - 1) as f32);
+
+        // depth only matters in a thick coating
+        let depths: Vec<f32> = if params.coating_thickness_um > 0.0 {
+            nodes(0.0..params.coating_thickness_um, DEPTH_NODES)
+        } else {
+            vec![0.0]
+        };
+        let layers: Vec<(f32, Vec<f32>)> = depths
+            .iter()
+            .map(|&depth| {
+                let grain = Halide { depth, ..synthetic_grain(0, 1) };
+                let access = grain.developer_access(params.developer_penetration_um);
+                let values = (0..RATIO_NODES)
+                    .map(|k| developed_value(params, developer, concentration * access, k))
+                    .collect();
+                (grain.light_transmission(params.light_attenuation), values)
+            })
+            .collect();
+
+        let scale = params.stock.latent_threshold_scale();
+        let thresholds: Vec<usize> = LATENT_THRESHOLD_RANGE.map(|t| {
+            (((t as f32) * scale).round() as usize).max(1)
+        }).collect();
+        let radii = nodes(RADIUS_RANGE, RADIUS_NODES);
+        let absorptions = nodes(ABSORPTION_RANGE, ABSORPTION_NODES);
+        let fog = params.stock.fog_fraction();
+        // fog centres develop like fully exposed grains
+        let fogged =
+            layers
+                .iter()
+                .map(|(_, developed)| developed[RATIO_NODES - 1])
+                .sum::<f32>() / (layers.len() as f32);
+
+        let values = (0..TABLE_SIZE)
+            .into_par_iter()
+            .map(|entry| {
+                let photons = (log_min + (entry as f32) * log_step).exp();
+                let mut sum = 0.0;
+                for (transmission, developed) in &layers {
+                    let value_at = |ratio: f32| {
+                        let x = ratio.min(1.0) * ((RATIO_NODES - 1) as f32);
+                        let k = (x.floor() as usize).min(RATIO_NODES - 2);
+                        developed[k] + (x - (k as f32)) * (developed[k + 1] - developed[k])
+                    };
+                    for &radius in &radii {
+                        let n = (photons * transmission * std::f32::consts::PI * radius * radius) as usize;
+                        for &p in &absorptions {
+                            for &threshold in &thresholds {
+                                sum += expected_over_silver(n, p, threshold, value_at);
+                            }
+                        }
+                    }
+                }
+                let count = layers.len() * radii.len() * absorptions.len() * thresholds.len();
+                (1.0 - fog) * (sum / (count as f32)) + fog * fogged
+            })
+            .collect();
+        Self { log_min, log_step, values }
+    }
+
+    fn eval(&self, photons: f32) -> f32 {
+        if photons <= 0.0 {
+            return self.values[0];
+        }
+        let x = ((photons.ln() - self.log_min) / self.log_step).clamp(0.0, (TABLE_SIZE - 1) as f32);
+        let i = (x.floor() as usize).min(TABLE_SIZE - 2);
+        self.values[i] + (x - (i as f32)) * (self.values[i + 1] - self.values[i])
+    }
+}
+
+/// Expected value over the binomial number of silver atoms formed when
+/// `n` photons each get absorbed with probability `p`. Counts at or past
+/// the threshold all develop fully, so only those below it are summed.
+fn expected_over_silver(n: usize, p: f32, threshold: usize, value_at: impl Fn(f32) -> f32) -> f32 {
+    let q = 1.0 - p;
+    let mut probability = q.powi(n as i32);
+    let mut below = 0.0;
+    let mut sum = 0.0;
+    for s in 0..threshold.min(n + 1) {
+        sum += probability * value_at((s as f32) / (threshold as f32));
+        below += probability;
+        probability *= (((n - s) as f32) / ((s + 1) as f32)) * (p / q);
+    }
+    sum + (1.0 - below).max(0.0) * value_at(1.0)
+}
+
+/// Point render value of a grain with a latent ratio of
+/// `k / (RATIO_NODES - 1)` after the full development
+fn developed_value(params: &Params, developer: &Developer, concentration: f32, k: usize) -> f32 {
+    let model = params.development_model.as_ref();
+    let mut grain = synthetic_grain(k, RATIO_NODES - 1);
+    for step in 0..params.development_steps() {
+        let t = (step as f32) * params.dt;
+        grain.developed_fraction = model.advance(&grain, developer, concentration, t, params.dt);
+    }
+    (255.0 * (1.0 - grain.density())).clamp(0.0, 255.0)
+}
+
+fn synthetic_grain(silver_count: usize, latent_threshold: usize) -> Halide {
+    Halide {
+        silver_count,
+        activated: silver_count >= latent_threshold,
+        ..Halide::new_with_params(0, 0, 0.0, latent_threshold, 0.0)
+    }
+}
+
+/// Midpoints of `count` equal divisions of `range`
+fn nodes(range: std::ops::Range<f32>, count: usize) -> Vec<f32> {
+    let step = (range.end - range.start) / (count as f32);
+    (0..count).map(|i| range.start + ((i as f32) + 0.5) * step).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pipeline;
+
+    fn mean_green(image: &image::RgbaImage) -> f32 {
+        image.pixels().map(|p| p.0[1] as f32).sum::<f32>() / ((image.width() * image.height()) as f32)
+    }
+
+    #[test]
+    fn expected_render_is_the_mean_of_seeded_runs() {
+        for level in [0.05, 0.2, 0.6] {
+            let flat = image::DynamicImage::ImageRgb32F(image::Rgb32FImage::from_pixel(24, 24, image::Rgb([level; 3])));
+            let params = Params { grains_per_pixel: Some(8.0), development_time: 1.0, ..Params::default() };
+            let expected = pipeline::process(&flat, &Params { expected_value: true, ..params.clone() }).unwrap();
+            let runs: Vec<f32> = (0..6)
+                .map(|seed| mean_green(&pipeline::process(&flat, &Params { seed: Some(seed), ..params.clone() }).unwrap()))
+                .collect();
+            let sampled = runs.iter().sum::<f32>() / (runs.len() as f32);
+            let expected = mean_green(&expected);
+            // the runs average display values rather than densities, which
+            // the expected render is the mean of, so allow for a little bias
+            assert!((expected - sampled).abs() < 2.5, "expected {expected} against {sampled} from {runs:?} at {level}");
+        }
+    }
+}
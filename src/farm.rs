@@ -0,0 +1,358 @@
+//! Render farm: the frames of a sequence shared out among workers, in this
+//! process and on other machines running `halide serve`
+//!
+//! Every worker takes the next frame from one queue, so fast machines end
+//! up rendering more of the sequence than slow ones. A remote worker is
+//! sent the encoded input as the body of `POST /process` and the run's
+//! parameters as the `X-Halide-Params` header, the protocol of
+//! [`crate::serve`], which refuses parameters naming files, so a run with
+//! any of those can only use local workers. Each frame is seeded from the run's seed
+//! and its frame number alone, so it comes out the same whichever worker
+//! renders it and however the sequence is split, and part of a sequence
+//! can be rendered again to match the rest. Blocks of `grain_reuse_frames`
+//! frames share one grain field, which local workers and servers keep in
+//! their stage cache rather than generating it for every frame. A frame
+//! that fails goes back on the queue for any worker; a worker that fails
+//! several frames in a row is retired.
+
+use std::collections::VecDeque;
+use std::io::{ Read, Write };
+use std::net::{ TcpStream, ToSocketAddrs };
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::cache::StageCache;
+use crate::error::{ Error, Result };
+use crate::json;
+use crate::params::Params;
+use crate::serve;
+
+/// failures in a row after which a worker is retired
+const MAX_FAILURES: u32 = 3;
+/// how long an idle worker waits before looking at the queue again while
+/// frames are still out with others, which may come back
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+/// how long a server may take to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// how long a server may go silent while a frame is sent or rendered,
+/// which takes a while for large frames
+const IO_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Worker {
+    /// this process, using every thread it is given
+    Local,
+    /// a `halide serve` instance at `HOST:PORT`
+    Remote(String),
+}
+
+impl Worker {
+    /// Parse `local` or `HOST:PORT`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "local" => Ok(Worker::Local),
+            addr if addr.contains(':') => Ok(Worker::Remote(addr.to_string())),
+            _ => Err(Error::Parse(format!("invalid worker '{text}', expected local or HOST:PORT"))),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Worker::Local => "local",
+            Worker::Remote(addr) => addr,
+        }
+    }
+}
+
+/// Parse a comma separated list of workers. A worker listed twice takes
+/// two frames at a time.
+pub fn parse_workers(text: &str) -> Result<Vec<Worker>> {
+    text.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(Worker::parse)
+        .collect()
+}
+
+/// One frame of the sequence
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// frame number, which the frame's seed is derived from
+    pub number: u64,
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// Output path of frame `number`, the last run of `#` in `pattern`
+/// replaced by the number padded to its length
+pub fn output_path(pattern: &str, number: u64) -> Result<PathBuf> {
+    let end = pattern
+        .rfind('#')
+        .ok_or_else(|| Error::Parse(format!("output pattern '{pattern}' has no # for the frame number")))?;
+    let start = pattern[..end].trim_end_matches('#').len();
+    let width = end + 1 - start;
+    Ok(PathBuf::from(format!("{}{number:0width$}{}", &pattern[..start], &pattern[end + 1..])))
+}
+
+pub struct Farm {
+    pub workers: Vec<Worker>,
+    /// command line parameters of the run, forwarded to remote workers
+    pub flags: Vec<(String, String)>,
+}
+
+/// Frames waiting and frames out with a worker
+struct Queue {
+    waiting: VecDeque<Job>,
+    out: usize,
+}
+
+impl Farm {
+    /// Render every job with `params`, each seeded from `params.seed` and
+    /// its frame number. Fails if frames are left when every worker has
+    /// been retired.
+    pub fn render(&self, jobs: Vec<Job>, params: &Params) -> Result<()> {
+        if self.workers.is_empty() {
+            return Err(Error::Parse("no workers to render on".into()));
+        }
+        let remote = self.workers.iter().any(|worker| matches!(worker, Worker::Remote(_)));
+        if let Some((key, _)) = self.flags.iter().find(|(key, _)| remote && serve::is_local_only(key)) {
+            return Err(Error::Parse(format!("'{key}' names a file, which remote workers refuse")));
+        }
+        // without a seed one is drawn for the whole run, so frames still
+        // agree wherever they are rendered
+        let seed = params.seed.unwrap_or_else(rand::random);
+        tracing::info!("Rendering {} frames on {} workers with seed {seed}", jobs.len(), self.workers.len());
+        let queue = Mutex::new(Queue { waiting: jobs.into(), out: 0 });
+
+        std::thread::scope(|scope| {
+            for worker in &self.workers {
+                let queue = &queue;
+                scope.spawn(move || {
+                    let cache = StageCache::new();
+                    let mut failures = 0;
+                    while let Some(job) = next(queue) {
+                        let frame = params.sequence_frame(seed, job.number);
+                        let result = self.render_frame(worker, &job, &frame, &cache);
+                        let number = job.number;
+                        queue.lock().unwrap().give_back(job, result.is_err());
+                        match result {
+                            Ok(()) => {
+                                failures = 0;
+                                tracing::info!("Frame {number} rendered on {}", worker.name());
+                            }
+                            Err(err) => {
+                                failures += 1;
+                                tracing::warn!("Frame {number} failed on {}: {err}", worker.name());
+                                if failures >= MAX_FAILURES {
+                                    tracing::error!("Retiring {} after {failures} failures in a row", worker.name());
+                                    return;
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let left = queue.into_inner().unwrap().waiting;
+        if !left.is_empty() {
+            let numbers: Vec<String> = left.iter().map(|job| job.number.to_string()).collect();
+            return Err(Error::Parse(format!("every worker failed, frames {} not rendered", numbers.join(", "))));
+        }
+        Ok(())
+    }
+
+    /// Render `job` on `worker` with the parameters of its frame
+    fn render_frame(&self, worker: &Worker, job: &Job, frame: &Params, cache: &StageCache) -> Result<()> {
+        let rendered = match worker {
+            Worker::Local => serve::render(&image::open(&job.input)?, frame, Some(cache))?,
+            Worker::Remote(addr) => {
+                let body = std::fs::read(&job.input)?;
+                image::load_from_memory(&self.post(addr, &body, frame)?)?
+            }
+        };
+        rendered.save(&job.output)?;
+        Ok(())
+    }
+
+    /// Send a frame to `addr`, with the run's parameters and the frame's
+    /// seeds, and return the body of its answer
+    fn post(&self, addr: &str, body: &[u8], frame: &Params) -> Result<Vec<u8>> {
+        let seeds = [("seed", frame.seed), ("grain_seed", frame.grain_seed)]
+            .into_iter()
+            .filter_map(|(key, seed)| Some((key.to_string(), seed?.to_string())));
+        let members = self.flags
+            .iter()
+            .cloned()
+            .chain(seeds)
+            .map(|(key, value)| (key, json::Value::String(value)))
+            .collect();
+        let mut stream = connect(addr)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        write!(
+            stream,
+            "POST /process HTTP/1.1\r\nHost: {addr}\r\nContent-Length: {}\r\nX-Halide-Params: {}\r\nConnection: close\r\n\r\n",
+            body.len(),
+            json::Value::Object(members)
+        )?;
+        stream.write_all(body)?;
+        stream.flush()?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+
+        let split = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| Error::Parse(format!("malformed response from {addr}")))?;
+        let head = String::from_utf8_lossy(&response[..split]).to_string();
+        let body = &response[split + 4..];
+        let status = head.lines().next().unwrap_or_default();
+        let body = if head.to_ascii_lowercase().contains("transfer-encoding: chunked") {
+            dechunk(body).ok_or_else(|| Error::Parse(format!("malformed chunked body from {addr}")))?
+        } else {
+            body.to_vec()
+        };
+        if status.split_whitespace().nth(1) != Some("200") {
+            return Err(Error::Parse(format!("{addr} answered {status}: {}", String::from_utf8_lossy(&body).trim())));
+        }
+        Ok(body)
+    }
+}
+
+impl Queue {
+    /// Take back a frame that was out with a worker, to be rendered again
+    /// by any worker if it failed
+    fn give_back(&mut self, job: Job, failed: bool) {
+        self.out -= 1;
+        if failed {
+            self.waiting.push_back(job);
+        }
+    }
+}
+
+/// Connect to the first address of `addr` that answers in time
+fn connect(addr: &str) -> Result<TcpStream> {
+    let mut last = None;
+    for socket in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                return Ok(stream);
+            }
+            Err(err) => {
+                last = Some(err);
+            }
+        }
+    }
+    Err(last.map_or_else(|| Error::Parse(format!("{addr} resolves to no address")), Error::from))
+}
+
+/// The next frame to render, waiting while the queue is empty but frames
+/// are still out and may fail back onto it; `None` once all are done
+fn next(queue: &Mutex<Queue>) -> Option<Job> {
+    loop {
+        {
+            let mut queue = queue.lock().unwrap();
+            if let Some(job) = queue.waiting.pop_front() {
+                queue.out += 1;
+                return Some(job);
+            }
+            if queue.out == 0 {
+                return None;
+            }
+        }
+        std::thread::sleep(IDLE_WAIT);
+    }
+}
+
+/// Body of a `Transfer-Encoding: chunked` response
+fn dechunk(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&data[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend_from_slice(data.get(..size)?);
+        data = data.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(number: u64) -> Job {
+        Job { number, input: format!("missing-{number}.png").into(), output: format!("out-{number}.png").into() }
+    }
+
+    fn numbers(queue: &Queue) -> Vec<u64> {
+        queue.waiting.iter().map(|job| job.number).collect()
+    }
+
+    #[test]
+    fn parses_worker_lists() {
+        assert_eq!(parse_workers("local, render2:7878,,local").unwrap(), [
+            Worker::Local,
+            Worker::Remote("render2:7878".into()),
+            Worker::Local,
+        ]);
+        assert!(parse_workers("").unwrap().is_empty());
+        assert!(parse_workers("local,render2").is_err());
+    }
+
+    #[test]
+    fn output_paths_pad_the_last_run_of_hashes() {
+        let path = |pattern, number| output_path(pattern, number).unwrap();
+        assert_eq!(path("out/frame_####.png", 7), PathBuf::from("out/frame_0007.png"));
+        assert_eq!(path("take#2/f_##.tif", 3), PathBuf::from("take#2/f_03.tif"));
+        assert_eq!(path("f#.exr", 123), PathBuf::from("f123.exr"));
+        assert!(output_path("frame.png", 1).is_err());
+    }
+
+    #[test]
+    fn dechunks_bodies() {
+        let body = b"5\r\nhello\r\n6;name=value\r\n world\r\n0\r\n\r\n";
+        assert_eq!(dechunk(body).unwrap(), b"hello world");
+        assert_eq!(dechunk(b"0\r\n\r\n").unwrap(), b"");
+        assert!(dechunk(b"5\r\nhel").is_none());
+        assert!(dechunk(b"zz\r\nhello\r\n0\r\n\r\n").is_none());
+        assert!(dechunk(b"5\r\nhello\r\n").is_none());
+    }
+
+    #[test]
+    fn failed_frames_go_back_on_the_queue() {
+        let queue = Mutex::new(Queue { waiting: vec![job(1), job(2)].into(), out: 0 });
+        let first = next(&queue).unwrap();
+        assert_eq!(first.number, 1);
+        queue.lock().unwrap().give_back(first, true);
+        assert_eq!(numbers(&queue.lock().unwrap()), [2, 1]);
+
+        let (second, third) = (next(&queue).unwrap(), next(&queue).unwrap());
+        assert_eq!((second.number, third.number, queue.lock().unwrap().out), (2, 1, 2));
+        queue.lock().unwrap().give_back(second, false);
+        queue.lock().unwrap().give_back(third, false);
+        assert!(next(&queue).is_none());
+    }
+
+    #[test]
+    fn workers_failing_in_a_row_are_retired() {
+        // nothing listens on port 1, and the inputs do not exist either
+        let farm = Farm { workers: vec![Worker::Remote("127.0.0.1:1".into())], flags: Vec::new() };
+        let err = farm.render(vec![job(1), job(2)], &Params::default()).unwrap_err().to_string();
+        assert!(err.contains("frames 2, 1 not rendered"), "{err}");
+    }
+
+    #[test]
+    fn remote_workers_refuse_file_parameters() {
+        let farm = Farm {
+            workers: vec![Worker::Local, Worker::Remote("render2:7878".into())],
+            flags: vec![("mask".into(), "sky.png".into())],
+        };
+        let err = farm.render(vec![job(1)], &Params::default()).unwrap_err().to_string();
+        assert!(err.contains("'mask'"), "{err}");
+    }
+}
@@ -0,0 +1,189 @@
+use crate::error::{ Error, Result };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Axis aligned pixel rectangle
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Parse `x,y,w,h`
+    pub fn parse(text: &str) -> Result<Self> {
+        let parts: Vec<u32> = text
+            .split(',')
+            .map(|p| p.trim().parse::<u32>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| Error::Parse(format!("invalid rectangle '{text}', expected x,y,w,h")))?;
+        match parts[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Self::new(x, y, width, height)),
+            _ => Err(Error::Parse(format!("invalid rectangle '{text}', expected x,y,w,h"))),
+        }
+    }
+
+    /// Intersection with a `width`×`height` frame
+    pub fn clamp_to(self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        let x1 = self.x.saturating_add(self.width).min(width);
+        let y1 = self.y.saturating_add(self.height).min(height);
+        Self::new(x, y, x1 - x, y1 - y)
+    }
+
+    /// Grow by `pad` on every side without leaving a `width`×`height` frame
+    pub fn expand(self, pad: u32, width: u32, height: u32) -> Self {
+        let x = self.x.saturating_sub(pad);
+        let y = self.y.saturating_sub(pad);
+        let x1 = self.x.saturating_add(self.width).saturating_add(pad).min(width);
+        let y1 = self.y.saturating_add(self.height).saturating_add(pad).min(height);
+        Self::new(x, y, x1 - x, y1 - y)
+    }
+
+    pub fn area(&self) -> u64 {
+        (self.width as u64) * (self.height as u64)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Single channel floating point image, e.g. an exposure or density map
+pub struct Field {
+    pub width: u32,
+    pub height: u32,
+    /// row-major samples
+    pub data: Vec<f32>,
+}
+
+impl Field {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, data: vec![0.0; (width as usize) * (height as usize)] }
+    }
+
+    /// Normalized 0..1 field from a 16-bit grayscale image
+    pub fn from_luma16(image: &image::ImageBuffer<image::Luma<u16>, Vec<u16>>) -> Self {
+        let (width, height) = image.dimensions();
+        let data = image
+            .as_raw()
+            .iter()
+            .map(|&v| (v as f32) / (u16::MAX as f32))
+            .collect();
+        Self { width, height, data }
+    }
+
+    /// Normalized 0..1 red, green and blue fields of an image
+    pub fn from_rgb(image: &image::DynamicImage) -> [Self; 3] {
+        let rgb = image.to_rgb32f();
+        let (width, height) = rgb.dimensions();
+        let mut channels = [(); 3].map(|_| Self::new(width, height));
+        for (i, pixel) in rgb.pixels().enumerate() {
+            for (channel, &value) in channels.iter_mut().zip(&pixel.0) {
+                channel.data[i] = value;
+            }
+        }
+        channels
+    }
+
+    #[inline]
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.data[(y as usize) * (self.width as usize) + (x as usize)]
+    }
+
+    #[inline]
+    pub fn set(&mut self, x: u32, y: u32, value: f32) {
+        let width = self.width as usize;
+        self.data[(y as usize) * width + (x as usize)] = value;
+    }
+
+    /// Sample with coordinates clamped to the edge
+    #[inline]
+    pub fn get_clamped(&self, x: i64, y: i64) -> f32 {
+        let x = x.clamp(0, (self.width as i64) - 1) as u32;
+        let y = y.clamp(0, (self.height as i64) - 1) as u32;
+        self.get(x, y)
+    }
+
+    /// Smallest rectangle holding every sample above zero, `None` when no
+    /// sample is
+    pub fn support(&self) -> Option<Rect> {
+        let width = self.width.max(1) as usize;
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for (i, _) in self.data.iter().enumerate().filter(|(_, &v)| v > 0.0) {
+            let (x, y) = ((i % width) as u32, (i / width) as u32);
+            let (x0, y0, x1, y1) = bounds.unwrap_or((x, y, x, y));
+            bounds = Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y)));
+        }
+        bounds.map(|(x0, y0, x1, y1)| Rect::new(x0, y0, x1 + 1 - x0, y1 + 1 - y0))
+    }
+
+    /// Copy out a sub-rectangle, which must lie inside the field
+    pub fn crop(&self, rect: Rect) -> Self {
+        let mut out = Self::new(rect.width, rect.height);
+        for y in 0..rect.height {
+            let start = ((rect.y + y) as usize) * (self.width as usize) + (rect.x as usize);
+            let row = (y as usize) * (rect.width as usize);
+            out.data[row..row + (rect.width as usize)].copy_from_slice(
+                &self.data[start..start + (rect.width as usize)]
+            );
+        }
+        out
+    }
+
+    /// Bilinear resample to a new size, sampling at pixel centres
+    pub fn resize(&self, width: u32, height: u32) -> Self {
+        if (width, height) == (self.width, self.height) {
+            return self.clone();
+        }
+        self.resize_rows(width, height, 0..height)
+    }
+
+    /// Average every `factor`×`factor` block into one sample, dropping
+    /// partial blocks at the right and bottom edges
+    pub fn downsample(&self, factor: u32) -> Self {
+        if factor <= 1 {
+            return self.clone();
+        }
+        let mut out = Self::new(self.width / factor, self.height / factor);
+        let count = (factor * factor) as f32;
+        for y in 0..out.height {
+            for x in 0..out.width {
+                let mut sum = 0.0;
+                for sy in 0..factor {
+                    for sx in 0..factor {
+                        sum += self.get(x * factor + sx, y * factor + sy);
+                    }
+                }
+                out.set(x, y, sum / count);
+            }
+        }
+        out
+    }
+
+    /// Rows `rows` of the field resampled to `width`×`height`, without
+    /// producing the rest of it
+    pub fn resize_rows(&self, width: u32, height: u32, rows: std::ops::Range<u32>) -> Self {
+        let sx = (self.width as f32) / (width as f32);
+        let sy = (self.height as f32) / (height as f32);
+        let mut out = Self::new(width, rows.len() as u32);
+        for (row, y) in rows.enumerate() {
+            let fy = ((y as f32) + 0.5) * sy - 0.5;
+            let y0 = fy.floor();
+            let ty = fy - y0;
+            for x in 0..width {
+                let fx = ((x as f32) + 0.5) * sx - 0.5;
+                let x0 = fx.floor();
+                let tx = fx - x0;
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.get_clamped(x0, y0) * (1.0 - tx) + self.get_clamped(x0 + 1, y0) * tx;
+                let bottom =
+                    self.get_clamped(x0, y0 + 1) * (1.0 - tx) + self.get_clamped(x0 + 1, y0 + 1) * tx;
+                out.set(x, row as u32, top * (1.0 - ty) + bottom * ty);
+            }
+        }
+        out
+    }
+}
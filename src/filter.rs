@@ -0,0 +1,115 @@
+//! Filters on the lens: contrast filters for black and white film,
+//! neutral density and a polarizer
+//!
+//! A coloured filter passes the part of the spectrum of its own colour and
+//! holds back the rest, so subjects of that colour print lighter and those
+//! of the complementary colour darker: a yellow, orange or red filter
+//! darkens a blue sky, a green one lightens foliage. Each input channel is
+//! weighted by how much of its proxy spectrum the filter passes, as seen
+//! through the stock's spectral sensitivity. The exposure is then raised
+//! by the filter factor, which gives a neutral subject the exposure it has
+//! without the filter.
+//!
+//! A neutral density filter cuts all light alike, apart from a slight
+//! colour cast. It is usually compensated by a longer exposure, which
+//! leaves the exposure unchanged but moves it to where reciprocity fails.
+//! A polarizer darkens the clear sky away from the sun; it is taken as
+//! compensated elsewhere, so only the sky changes.
+
+use crate::error::{ Error, Result };
+use crate::field::Field;
+use crate::spectral::{ self, SpectralSensitivity, RGB_PRIMARIES };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LensFilter {
+    /// Wratten 8 style yellow, holding back blue
+    Yellow,
+    /// Wratten 21 style orange, holding back blue and some green
+    Orange,
+    /// Wratten 25 style red, passing red only
+    Red,
+    /// Wratten 11 style yellow-green, holding back blue and some red
+    Green,
+}
+
+impl LensFilter {
+    /// Parse `yellow`, `orange`, `red` or `green`, or a Wratten number
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "yellow" | "8" | "k2" => Ok(LensFilter::Yellow),
+            "orange" | "21" => Ok(LensFilter::Orange),
+            "red" | "25" => Ok(LensFilter::Red),
+            "green" | "11" | "x1" => Ok(LensFilter::Green),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!("unknown lens filter '{text}', expected yellow, orange, red or green")
+                    )
+                ),
+        }
+    }
+
+    /// Share of the light passed at a wavelength
+    pub fn transmission(&self, wavelength_nm: f32) -> f32 {
+        // long pass filters rise from a small leak to full transmission
+        // around their cut-on wavelength
+        let long_pass = |cut_on: f32, slope: f32| {
+            0.01 + 0.99 / (1.0 + (-(wavelength_nm - cut_on) / slope).exp())
+        };
+        match self {
+            LensFilter::Yellow => long_pass(495.0, 12.0),
+            LensFilter::Orange => long_pass(550.0, 10.0),
+            LensFilter::Red => long_pass(595.0, 8.0),
+            LensFilter::Green => {
+                let d = (wavelength_nm - 540.0) / 45.0;
+                0.05 + 0.8 * (-0.5 * d * d).exp()
+            }
+        }
+    }
+
+    /// Share of the red, green and blue channels passed, as `sensitivity`
+    /// sees them
+    pub fn channel_transmission(&self, sensitivity: &SpectralSensitivity) -> [f32; 3] {
+        RGB_PRIMARIES.map(|primary| {
+            let open = sensitivity.integrate(|nm| primary.response(nm));
+            let filtered = sensitivity.integrate(|nm| primary.response(nm) * self.transmission(nm));
+            filtered / open.max(f32::EPSILON)
+        })
+    }
+
+    /// Exposure increase giving a neutral subject on `sensitivity` the
+    /// exposure it has without the filter
+    pub fn factor(&self, sensitivity: &SpectralSensitivity) -> f32 {
+        let neutral = |nm: f32| RGB_PRIMARIES.iter().map(|primary| primary.response(nm)).sum::<f32>();
+        let open = sensitivity.integrate(neutral);
+        let filtered = spectral::integrate(|nm| sensitivity.response(nm) * neutral(nm) * self.transmission(nm));
+        open / filtered.max(f32::EPSILON)
+    }
+}
+
+/// most a polarizer at full strength darkens the sky, about two stops
+const POLARIZER_DEPTH: f32 = 0.75;
+
+/// Share of a pixel taken to be clear sky, from how bright and how much
+/// bluer than its other channels it is
+pub fn sky_weight(rgb: [f32; 3]) -> f32 {
+    let [r, g, b] = rgb;
+    let blueness = ((b - r.max(g)) / b.max(f32::EPSILON)) * 2.0;
+    let brightness = (b / 0.5).min(1.0);
+    blueness.clamp(0.0, 1.0) * brightness
+}
+
+/// Darken the sky of an exposure through a polarizer of `strength`
+/// between 0 and 1, where `sky` marks it or, without one, where
+/// [`sky_weight`] finds it
+pub fn polarize(exposure: &mut [Field; 3], strength: f32, sky: Option<&Field>) {
+    let strength = strength.clamp(0.0, 1.0) * POLARIZER_DEPTH;
+    let [red, green, blue] = exposure;
+    for (i, ((r, g), b)) in red.data.iter_mut().zip(&mut green.data).zip(&mut blue.data).enumerate() {
+        let weight = sky.map_or_else(|| sky_weight([*r, *g, *b]), |sky| sky.data[i].clamp(0.0, 1.0));
+        let gain = 1.0 - strength * weight;
+        *r *= gain;
+        *g *= gain;
+        *b *= gain;
+    }
+}
@@ -0,0 +1,105 @@
+//! Flat field test: a uniform patch exposed, developed and read back, the
+//! quickest check of where a stock and developer put a given exposure
+//!
+//! Exposure is given in stops from the metered exposure, which an input of
+//! middle gray receives (see [`crate::sensitometry`]). The patch is read
+//! for its density with the densitometer of the run, for the share of
+//! grains that formed a developable latent image, for its RMS granularity
+//! through a round aperture of [`APERTURE_UM`], and for the Wiener
+//! spectrum of its grain noise.
+
+use crate::error::Result;
+use crate::halide::GrainCluster;
+use crate::params::Params;
+use crate::pipeline;
+use crate::sensitometry;
+
+/// input value of middle gray, exposed at the metered exposure
+pub use crate::units::MIDDLE_GRAY;
+/// diameter of the granularity aperture in microns
+pub const APERTURE_UM: f32 = 48.0;
+/// apertures across the patch
+pub const APERTURES: u32 = 8;
+
+/// Readings of one developed patch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    /// red, green and blue density; a visual reading repeats its one
+    /// density
+    pub density: [f32; 3],
+    /// statistics of the developed grains
+    pub grains: GrainCluster,
+    /// RMS granularity of the visual density, times 1000
+    pub granularity: f32,
+    /// noise power of the visual density along the rows, in density²·µm,
+    /// against spatial frequency in cycles per millimetre
+    pub spectrum: Vec<(f32, f32)>,
+}
+
+/// Input value exposing `ev` stops over the metered exposure
+pub fn input_value(ev: f32) -> f32 {
+    MIDDLE_GRAY * (2.0f32).powf(ev)
+}
+
+/// Expose a patch of input `value`, develop and read it
+pub fn measure(params: &Params, value: f32) -> Result<Reading> {
+    // a square aperture of the round one's area, in input pixels of one
+    // grain pitch
+    let side = ((APERTURE_UM * std::f32::consts::PI.sqrt()) / 2.0 / params.grain_pitch_um).round().max(1.0) as u32;
+    let size = side * APERTURES;
+    let patch = image::DynamicImage::ImageRgb32F(
+        image::Rgb32FImage::from_pixel(size, size, image::Rgb([value; 3]))
+    );
+    let flat = Params {
+        emulsion_width: None,
+        frame: None,
+        format_width_mm: None,
+        output_width: None,
+        crop: None,
+        ..params.clone()
+    };
+    let (render, grains) = pipeline::process_with_grains(&patch, &flat)?;
+    let render = image::DynamicImage::ImageRgba8(render);
+
+    let rgb = render.to_rgb32f();
+    let mut transmission = [0.0f32; 3];
+    for pixel in rgb.pixels() {
+        for (t, v) in transmission.iter_mut().zip(pixel.0) {
+            *t += v;
+        }
+    }
+    let density = params.densitometer.read(transmission.map(|t| t / ((size * size) as f32)));
+
+    let luma = render.to_luma32f();
+    let pixel_density = |x: u32, y: u32| -luma.get_pixel(x, y).0[0].max(1e-4).log10();
+    let mut power = vec![0.0; (size / 2 + 1) as usize];
+    for y in 0..size {
+        let row: Vec<f32> = (0..size).map(|x| pixel_density(x, y)).collect();
+        let mean = row.iter().sum::<f32>() / (size as f32);
+        let deviations: Vec<f32> = row.iter().map(|d| d - mean).collect();
+        for (p, a) in power.iter_mut().zip(sensitometry::amplitude_spectrum(&deviations)) {
+            *p += (a * a * params.grain_pitch_um) / ((size * size) as f32);
+        }
+    }
+    let spectrum = power
+        .iter()
+        .enumerate()
+        .map(|(k, p)| ((1000.0 * (k as f32)) / ((size as f32) * params.grain_pitch_um), *p))
+        .collect();
+
+    let readings: Vec<f32> = (0..APERTURES * APERTURES)
+        .map(|i| {
+            let (x0, y0) = ((i % APERTURES) * side, (i / APERTURES) * side);
+            let mut sum = 0.0;
+            for y in y0..y0 + side {
+                for x in x0..x0 + side {
+                    sum += luma.get_pixel(x, y).0[0];
+                }
+            }
+            -(sum / ((side * side) as f32)).max(1e-4).log10()
+        })
+        .collect();
+    let mean = readings.iter().sum::<f32>() / (readings.len() as f32);
+    let variance = readings.iter().map(|d| (d - mean).powi(2)).sum::<f32>() / (readings.len() as f32);
+    Ok(Reading { density, grains, granularity: 1000.0 * variance.sqrt(), spectrum })
+}
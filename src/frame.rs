@@ -0,0 +1,154 @@
+//! Film formats: the size of the image area on the film, which sets the
+//! aspect the input is fitted to and the physical width of the emulsion,
+//! so how many microns each input pixel covers
+//!
+//! A frame is turned to the orientation of the input, its long side along
+//! the input's long side.
+
+use crate::error::{ Error, Result };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// 35 mm film, 36×24 mm
+    FullFrame,
+    /// 35 mm film in a half-frame camera, 24×18 mm
+    HalfFrame,
+    /// 120 film, 56×41.5 mm
+    SixByFourHalf,
+    /// 120 film, 56×56 mm
+    SixBySix,
+    /// 120 film, 69.5×56 mm
+    SixBySeven,
+    /// 120 film, 84×56 mm
+    SixByNine,
+    /// 4×5 inch sheet film
+    FourByFive,
+    /// 8×10 inch sheet film
+    EightByTen,
+}
+
+impl FrameFormat {
+    /// Parse `135` (or `full-frame`), `half-frame`, `6x4.5`, `6x6`, `6x7`,
+    /// `6x9`, `4x5` or `8x10`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "135" | "35mm" | "full-frame" => Ok(FrameFormat::FullFrame),
+            "half-frame" => Ok(FrameFormat::HalfFrame),
+            "6x4.5" | "645" => Ok(FrameFormat::SixByFourHalf),
+            "6x6" => Ok(FrameFormat::SixBySix),
+            "6x7" => Ok(FrameFormat::SixBySeven),
+            "6x9" => Ok(FrameFormat::SixByNine),
+            "4x5" => Ok(FrameFormat::FourByFive),
+            "8x10" => Ok(FrameFormat::EightByTen),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!(
+                            "unknown frame '{text}', expected 135, half-frame, 6x4.5, 6x6, 6x7, 6x9, 4x5 or 8x10"
+                        )
+                    )
+                ),
+        }
+    }
+
+    /// Long and short side of the image area in millimetres
+    pub fn size_mm(&self) -> (f32, f32) {
+        match self {
+            FrameFormat::FullFrame => (36.0, 24.0),
+            FrameFormat::HalfFrame => (24.0, 18.0),
+            FrameFormat::SixByFourHalf => (56.0, 41.5),
+            FrameFormat::SixBySix => (56.0, 56.0),
+            FrameFormat::SixBySeven => (69.5, 56.0),
+            FrameFormat::SixByNine => (84.0, 56.0),
+            FrameFormat::FourByFive => (120.0, 96.0),
+            FrameFormat::EightByTen => (245.0, 194.0),
+        }
+    }
+
+    /// Size in millimetres of the frame along the film and across it, as
+    /// the camera lays successive frames side by side; a half-frame camera
+    /// turns its frames upright on the 35 mm strip
+    pub fn along_film_mm(&self) -> (f32, f32) {
+        match self {
+            FrameFormat::HalfFrame => (18.0, 24.0),
+            FrameFormat::SixByFourHalf => (41.5, 56.0),
+            _ => self.size_mm(),
+        }
+    }
+
+    /// Unexposed film between neighbouring frames in millimetres
+    pub fn frame_gap_mm(&self) -> f32 {
+        match self {
+            FrameFormat::FullFrame | FrameFormat::HalfFrame => 2.0,
+            FrameFormat::SixByFourHalf |
+            FrameFormat::SixBySix |
+            FrameFormat::SixBySeven |
+            FrameFormat::SixByNine => 4.0,
+            // the line a split dark slide leaves across the sheet
+            FrameFormat::FourByFive | FrameFormat::EightByTen => 3.0,
+        }
+    }
+
+    /// Width and height in millimetres of the frame turned to the
+    /// orientation of a `width`×`height` input
+    pub fn dimensions_mm(&self, width: u32, height: u32) -> (f32, f32) {
+        let (long, short) = self.size_mm();
+        if width >= height { (long, short) } else { (short, long) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How an input of another aspect is fitted to the frame
+pub enum FrameFit {
+    /// cut to the frame's aspect about the middle
+    #[default]
+    Crop,
+    /// kept whole, the rest of the frame left unexposed
+    Letterbox,
+}
+
+impl FrameFit {
+    /// Parse `crop` or `letterbox`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "crop" => Ok(FrameFit::Crop),
+            "letterbox" => Ok(FrameFit::Letterbox),
+            _ => Err(Error::Parse(format!("invalid frame fit '{text}', expected crop or letterbox"))),
+        }
+    }
+}
+
+/// `image` fitted to the aspect of `frame`, centred
+pub fn fit(image: &image::DynamicImage, frame: FrameFormat, fit: FrameFit) -> image::DynamicImage {
+    let (frame_width, frame_height) = frame.dimensions_mm(image.width(), image.height());
+    fit_aspect(image, frame_width / frame_height, fit)
+}
+
+/// `image` fitted to a width to height `aspect`, centred
+pub fn fit_aspect(image: &image::DynamicImage, aspect: f32, fit: FrameFit) -> image::DynamicImage {
+    let (width, height) = (image.width(), image.height());
+    // the frame's aspect at the input's width or height, whichever keeps
+    // the whole frame inside the input, or the whole input inside it
+    let wide = (width as f32) / (height.max(1) as f32) > aspect;
+    let (target_width, target_height) = match (fit, wide) {
+        (FrameFit::Crop, true) | (FrameFit::Letterbox, false) =>
+            (((height as f32) * aspect).round() as u32, height),
+        (FrameFit::Crop, false) | (FrameFit::Letterbox, true) =>
+            (width, ((width as f32) / aspect).round() as u32),
+    };
+    let (target_width, target_height) = (target_width.max(1), target_height.max(1));
+    match fit {
+        FrameFit::Crop => {
+            let x = width.saturating_sub(target_width) / 2;
+            let y = height.saturating_sub(target_height) / 2;
+            image.crop_imm(x, y, target_width.min(width), target_height.min(height))
+        }
+        FrameFit::Letterbox => {
+            let mut framed = image::Rgb32FImage::new(target_width, target_height);
+            let x = target_width.saturating_sub(width) / 2;
+            let y = target_height.saturating_sub(height) / 2;
+            image::imageops::replace(&mut framed, &image.to_rgb32f(), x as i64, y as i64);
+            image::DynamicImage::ImageRgb32F(framed)
+        }
+    }
+}
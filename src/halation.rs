@@ -0,0 +1,123 @@
+//! Halation: light that passes the emulsion, reflects off the film base and
+//! re-exposes the emulsion as a wide glow around highlights
+
+use rayon::prelude::*;
+
+use crate::field::Field;
+use crate::psf::{ Convolution, Kernel };
+
+/// Add the light scattered back from the base: `E + strength * (K * E)`
+pub fn simulate_halation_2d(exposure: &Field, kernel: &Kernel, strength: f32) -> Field {
+    let mut halated = kernel.convolve(exposure);
+    halated.data
+        .par_iter_mut()
+        .zip(exposure.data.par_iter())
+        .for_each(|(h, &e)| {
+            *h = e + strength * *h;
+        });
+    halated
+}
+
+/// [`simulate_halation_2d`] in place, using `scratch` for the scattered
+/// light so repeated calls need no allocation once it has grown to size.
+/// Direct convolution needs no row pass, so none is kept.
+pub fn simulate_halation_2d_in_place(
+    exposure: &mut Field,
+    kernel: &Kernel,
+    strength: f32,
+    scratch: &mut Field
+) {
+    let mut rows = Field::new(0, 0);
+    add_halation_in_place(exposure, kernel, Convolution::Direct, strength, None, scratch, &mut rows);
+}
+
+/// [`simulate_halation_2d`] that also returns the glow on its own, the
+/// light scattered back from the base that was added to the exposure
+pub fn simulate_halation_2d_split(exposure: &Field, kernel: &Kernel, strength: f32) -> (Field, Field) {
+    let mut halated = exposure.clone();
+    let mut glow = Field::new(exposure.width, exposure.height);
+    add_halation_in_place(
+        &mut halated,
+        kernel,
+        Convolution::Direct,
+        strength,
+        None,
+        &mut glow,
+        &mut Field::new(0, 0)
+    );
+    (halated, glow)
+}
+
+/// Add halation in place, spread as `convolution` asks, with the glow
+/// received by each pixel scaled by `mask`, or everywhere when there is
+/// none. `scratch` is left holding the glow that was added, and `rows`
+/// holds the row pass of separable convolution.
+pub fn add_halation_in_place(
+    exposure: &mut Field,
+    kernel: &Kernel,
+    convolution: Convolution,
+    strength: f32,
+    mask: Option<&Field>,
+    scratch: &mut Field,
+    rows: &mut Field
+) {
+    kernel.convolve_with_into(exposure, convolution, scratch, rows);
+    match mask {
+        Some(mask) => {
+            exposure.data
+                .par_iter_mut()
+                .zip(scratch.data.par_iter_mut())
+                .zip(mask.data.par_iter())
+                .for_each(|((e, h), &m)| {
+                    *h *= m * strength;
+                    *e += *h;
+                });
+        }
+        None => {
+            exposure.data
+                .par_iter_mut()
+                .zip(scratch.data.par_iter_mut())
+                .for_each(|(e, h)| {
+                    *h *= strength;
+                    *e += *h;
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exposure(width: u32, height: u32) -> Field {
+        let mut field = Field::new(width, height);
+        for (i, value) in field.data.iter_mut().enumerate() {
+            *value = if i % 7 == 0 { 1.0 } else { 0.1 * ((i % 5) as f32) };
+        }
+        field
+    }
+
+    #[test]
+    fn in_place_halation_matches_the_allocating_one() {
+        let kernel = Kernel::gaussian(2.5);
+        let (mut scratch, mut rows) = (Field::new(0, 0), Field::new(0, 0));
+        // buffers carried over from a larger frame must not leak into a smaller one
+        for (width, height) in [(40, 30), (23, 17)] {
+            let field = exposure(width, height);
+            let expected = simulate_halation_2d(&field, &kernel, 0.3);
+
+            let mut halated = field.clone();
+            simulate_halation_2d_in_place(&mut halated, &kernel, 0.3, &mut scratch);
+            assert_eq!(halated.data, expected.data);
+
+            for (convolution, tolerance) in [(Convolution::Direct, 0.0), (Convolution::Separable, 1e-4)] {
+                let mut halated = field.clone();
+                add_halation_in_place(&mut halated, &kernel, convolution, 0.3, None, &mut scratch, &mut rows);
+                assert_eq!((halated.width, halated.height), (width, height));
+                for (a, b) in halated.data.iter().zip(&expected.data) {
+                    assert!((a - b).abs() <= tolerance, "{convolution:?}: {a} against {b}");
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
+use rand::Rng;
+
 use crate::developer::Developer;
+use crate::development::{ DevelopmentModel, FirstOrder };
 
 #[derive(Debug, Clone)]
 /// Individual silver halide grain in a photographic emulsion
@@ -10,16 +13,28 @@ pub struct Halide {
 
     /// radius of grain in microns
     pub radius: f32,
+    /// depth of the grain below the emulsion surface in microns
+    pub depth: f32,
+    /// number of real grains this simulated grain stands for, above one
+    /// where the grain budget was thinned
+    pub weight: f32,
+    /// share of the light entering the emulsion that gets through the
+    /// layers coated above this grain's own, 1 in a single coating
+    pub overlying_transmission: f32,
 
     /// number of metalic silver atoms in each grain
     pub silver_count: usize,
     /// number of silver atoms needed to activate grain
     pub latent_threshold: usize,
+    /// silver atoms of an internal latent image, left by intense brief
+    /// exposures; not developable by surface developers
+    pub internal_latent: usize,
 
     /// whether the grain has been activated
     pub activated: bool,
-    /// sensitivity of the grain certain wavelengths of light
-    pub spectral_sensitivity: f32,
+    /// response of the grain to red, green and blue light, from its
+    /// native absorption plus adsorbed sensitizing dyes
+    pub spectral_response: [f32; 3],
     /// probability of a photon being absorbed by the grain
     pub absorption_probability: f32,
 
@@ -27,35 +42,435 @@ pub struct Halide {
     pub developed_fraction: f32,
 }
 
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Combined statistics of a group of grains, e.g. all grains under a pixel.
+/// Each simulated grain counts for the real grains its weight stands for.
+pub struct GrainCluster {
+    /// number of grains in the cluster
+    pub count: f64,
+    /// number of grains that reached their latent threshold
+    pub activated: f64,
+    /// total metallic silver atoms across the cluster
+    pub silver_count: f64,
+    /// mean grain radius in microns
+    pub mean_radius: f32,
+    /// standard deviation of grain radius in microns
+    pub radius_std: f32,
+    /// total projected grain area in square microns
+    pub total_area: f32,
+    /// projected-area weighted mean developed fraction
+    pub developed_fraction: f32,
+}
+
+impl GrainCluster {
+    /// Fraction of grains carrying a developable latent image
+    pub fn activation_fraction(&self) -> f32 {
+        if self.count <= 0.0 {
+            0.0
+        } else {
+            (self.activated / self.count) as f32
+        }
+    }
+
+    /// Developed silver area, the quantity optical density depends on
+    pub fn developed_area(&self) -> f32 {
+        self.total_area * self.developed_fraction
+    }
+
+    /// Add the grains of `other`, as if both had been aggregated together
+    pub fn merge(&mut self, other: &GrainCluster) {
+        let count = self.count + other.count;
+        if count <= 0.0 {
+            return;
+        }
+        let (a, b) = ((self.count / count) as f32, (other.count / count) as f32);
+        let mean_radius = a * self.mean_radius + b * other.mean_radius;
+        // pooled variance: each side's spread plus its mean's distance
+        // from the combined mean
+        let spread = |cluster: &GrainCluster| {
+            cluster.radius_std.powi(2) + (cluster.mean_radius - mean_radius).powi(2)
+        };
+        let variance = a * spread(self) + b * spread(other);
+        let total_area = self.total_area + other.total_area;
+        let developed_area = self.developed_area() + other.developed_area();
+        *self = GrainCluster {
+            count,
+            activated: self.activated + other.activated,
+            silver_count: self.silver_count + other.silver_count,
+            mean_radius,
+            radius_std: variance.sqrt(),
+            total_area,
+            developed_fraction: if total_area > 0.0 { developed_area / total_area } else { 0.0 },
+        };
+    }
+}
+
+/// reduction in surface absorption per internal latent silver atom
+const CLAYDEN_COMPETITION: f32 = 0.05;
+
 impl Halide {
-    pub fn expose(&mut self, intensity: f32, exposure_time: f32) {
+    /// Unexposed, undeveloped grain at the surface of a single coating,
+    /// responding equally to red, green and blue
+    pub fn new_with_params(
+        x: usize,
+        y: usize,
+        radius: f32,
+        latent_threshold: usize,
+        absorption_probability: f32
+    ) -> Self {
+        Self {
+            x,
+            y,
+            radius,
+            depth: 0.0,
+            weight: 1.0,
+            overlying_transmission: 1.0,
+            silver_count: 0,
+            latent_threshold,
+            internal_latent: 0,
+            activated: false,
+            spectral_response: [1.0 / 3.0; 3],
+            absorption_probability,
+            developed_fraction: 0.0,
+        }
+    }
+
+    /// Projected area of the grain in square microns
+    pub fn area(&self) -> f32 {
+        std::f32::consts::PI * self.radius.powi(2)
+    }
+
+    /// Combine a group of grains, each standing for `weight` real grains.
+    /// Counts add, size statistics are computed over the whole group and
+    /// development is weighted by projected area, so the result does not
+    /// depend on the order of the grains.
+    pub fn aggregate(grains: &[Halide]) -> GrainCluster {
+        let count: f64 = grains.iter().map(|g| g.weight as f64).sum();
+        if count <= 0.0 {
+            return GrainCluster::default();
+        }
+        let mean_radius = (grains
+            .iter()
+            .map(|g| (g.radius as f64) * (g.weight as f64))
+            .sum::<f64>() / count) as f32;
+        let variance = grains
+            .iter()
+            .map(|g| ((g.radius - mean_radius).powi(2) as f64) * (g.weight as f64))
+            .sum::<f64>() / count;
+        let total_area: f32 = grains.iter().map(|g| g.area() * g.weight).sum();
+        let developed_area: f32 = grains
+            .iter()
+            .map(|g| g.area() * g.weight * g.developed_fraction)
+            .sum();
+        GrainCluster {
+            count,
+            activated: grains
+                .iter()
+                .filter(|g| g.activated)
+                .map(|g| g.weight as f64)
+                .sum(),
+            silver_count: grains
+                .iter()
+                .map(|g| (g.silver_count as f64) * (g.weight as f64))
+                .sum(),
+            mean_radius,
+            radius_std: (variance as f32).sqrt(),
+            total_area,
+            developed_fraction: if total_area > 0.0 { developed_area / total_area } else { 0.0 },
+        }
+    }
+
+    /// Share of the light entering the emulsion surface that reaches this
+    /// grain, attenuated by `attenuation` per micron of emulsion above it
+    /// and by whatever the layers above absorb
+    pub fn light_transmission(&self, attenuation: f32) -> f32 {
+        self.overlying_transmission * (-attenuation.max(0.0) * self.depth).exp()
+    }
+
+    /// Developer activity reaching this grain relative to the surface, for
+    /// developer that is used up over `penetration` microns of emulsion
+    pub fn developer_access(&self, penetration: f32) -> f32 {
+        if penetration <= 0.0 {
+            return 1.0;
+        }
+        (-self.depth / penetration).exp()
+    }
+
+    /// Effective intensity of an RGB exposure as seen by this grain
+    pub fn spectral_intensity(&self, rgb: [f32; 3]) -> f32 {
+        self.spectral_response
+            .iter()
+            .zip(rgb)
+            .map(|(w, c)| w * c)
+            .sum()
+    }
+
+    /// Expose the grain to `intensity` for `exposure_time`. Exposures add
+    /// up until the grain reaches its latent threshold; once activated it
+    /// takes no more light, as its latent image is already developable.
+    pub fn expose(&mut self, intensity: f32, exposure_time: f32, rng: &mut impl Rng) {
         if self.activated {
             return;
         }
 
-        let area = std::f32::consts::PI * (self.radius as f32).powi(2);
+        let area = self.area();
         let photon_count = (intensity * area * exposure_time) as usize;
+        let absorption_probability = self.effective_absorption();
         for _ in 0..photon_count {
-            if rand::random::<f32>() < self.absorption_probability {
-                self.silver_count += 1; // each photon that’s absorbed can form 1 Ag atom
-                if self.silver_count >= self.latent_threshold {
-                    self.activated = true;
-                }
+            if rng.random::<f32>() < absorption_probability {
+                self.absorb_photon();
             }
         }
     }
 
-    pub fn develop_grain(grain: &mut Halide, dev: &Developer, dt: f32) {
-        // 'development_factor' goes from 0..1, representing how far the grain is to full silver
-        let latent_ratio = (grain.silver_count as f32) / (grain.latent_threshold as f32);
-        if latent_ratio > 1e-6 {
-            // simulate some fraction of completion based on developer strength, latent ratio, and dt
-            let rate = dev.strength * latent_ratio;
-            // accumulate development in e.g. 'grain.developed_fraction' (0..1)
-            grain.developed_fraction += rate * dt;
-            if grain.developed_fraction > dev.max_development {
-                grain.developed_fraction = dev.max_development;
+    /// Record one absorbed photon; each can form one silver atom
+    pub fn absorb_photon(&mut self) {
+        self.silver_count += 1;
+        if self.silver_count >= self.latent_threshold {
+            self.activated = true;
+        }
+    }
+
+    /// Optical density of the developed grain, log-like in the developed
+    /// fraction: `D = A * ln(1 + B * developed_fraction)`
+    pub fn density(&self) -> f32 {
+        let a = 0.5;
+        let b = 10.0;
+        a * (1.0 + b * self.developed_fraction).ln()
+    }
+
+    /// Absorption probability after internal latent sites compete for
+    /// photoelectrons with the surface sites (Clayden desensitization)
+    pub fn effective_absorption(&self) -> f32 {
+        self.absorption_probability / (1.0 + CLAYDEN_COMPETITION * (self.internal_latent as f32))
+    }
+
+    /// Brief, very intense exposure. At such irradiance photoelectrons are
+    /// trapped inside the crystal faster than they can migrate, building an
+    /// internal latent image instead of a developable surface one.
+    pub fn pre_expose_internal(&mut self, intensity: f32, exposure_time: f32, rng: &mut impl Rng) {
+        let photon_count = (intensity * self.area() * exposure_time) as usize;
+        for _ in 0..photon_count {
+            if rng.random::<f32>() < self.absorption_probability {
+                self.internal_latent += 1;
             }
         }
     }
+
+    /// Red or infrared exposure after the main exposure. Latent silver
+    /// absorbs the long wavelengths and gives up atoms with probability
+    /// `efficiency` per absorbed photon, partially erasing the latent image
+    /// (Herschel effect).
+    pub fn herschel_bleach(
+        &mut self,
+        intensity: f32,
+        exposure_time: f32,
+        efficiency: f32,
+        rng: &mut impl Rng
+    ) {
+        if self.silver_count == 0 {
+            return;
+        }
+        let photon_count = (intensity * self.area() * exposure_time) as usize;
+        for _ in 0..photon_count {
+            if self.silver_count == 0 {
+                break;
+            }
+            if rng.random::<f32>() < efficiency {
+                self.silver_count -= 1;
+            }
+        }
+        self.activated = self.silver_count >= self.latent_threshold;
+    }
+
+    /// Undo any development, keeping the latent image, so the exposed
+    /// grain can be developed again
+    pub fn reset_development(&mut self) {
+        self.developed_fraction = 0.0;
+    }
+
+    /// Advance development by `dt`. The developed fraction grows toward
+    /// `dev.max_development` as `df/dt = k * (max - f)`, with the rate `k`
+    /// set by developer strength and how complete the latent image is.
+    /// The step is integrated exactly, so it never overshoots the maximum
+    /// however large `dt` or the number of steps.
+    pub fn develop_grain(grain: &mut Halide, dev: &Developer, dt: f32) {
+        grain.developed_fraction = FirstOrder.advance(grain, dev, 1.0, 0.0, dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn grain(silver_count: usize, latent_threshold: usize) -> Halide {
+        Halide {
+            silver_count,
+            activated: silver_count >= latent_threshold,
+            ..Halide::new_with_params(0, 0, 0.3, latent_threshold, 0.5)
+        }
+    }
+
+    fn developer() -> Developer {
+        Developer { strength: 2.0, max_development: 0.8 }
+    }
+
+    #[test]
+    fn development_saturates_at_max() {
+        let dev = developer();
+        let mut g = grain(40, 10);
+        let mut previous = 0.0;
+        for _ in 0..10_000 {
+            Halide::develop_grain(&mut g, &dev, 0.1);
+            assert!(g.developed_fraction >= previous);
+            assert!(g.developed_fraction <= dev.max_development);
+            previous = g.developed_fraction;
+        }
+        assert!((g.developed_fraction - dev.max_development).abs() < 1e-4);
+    }
+
+    #[test]
+    fn large_steps_do_not_overshoot() {
+        let dev = developer();
+        let mut g = grain(1_000, 5);
+        Halide::develop_grain(&mut g, &dev, 1e6);
+        assert!(g.developed_fraction <= dev.max_development);
+    }
+
+    #[test]
+    fn density_increments_shrink() {
+        let dev = developer();
+        let mut g = grain(10, 10);
+        let mut increments = Vec::new();
+        for _ in 0..20 {
+            let before = g.developed_fraction;
+            Halide::develop_grain(&mut g, &dev, 0.1);
+            increments.push(g.developed_fraction - before);
+        }
+        assert!(increments.windows(2).all(|w| w[1] < w[0]));
+    }
+
+    #[test]
+    fn partial_latent_image_develops_slower() {
+        let dev = developer();
+        let mut full = grain(10, 10);
+        let mut partial = grain(3, 10);
+        Halide::develop_grain(&mut full, &dev, 0.1);
+        Halide::develop_grain(&mut partial, &dev, 0.1);
+        assert!(partial.developed_fraction < full.developed_fraction);
+    }
+
+    #[test]
+    fn aggregate_is_order_independent() {
+        let mut small = grain(4, 10);
+        small.radius = 0.1;
+        small.developed_fraction = 0.2;
+        let mut large = grain(20, 10);
+        large.radius = 0.4;
+        large.developed_fraction = 0.9;
+
+        let a = Halide::aggregate(&[small.clone(), large.clone()]);
+        let b = Halide::aggregate(&[large, small]);
+        assert_eq!(a, b);
+        assert_eq!(a.silver_count, 24.0);
+        assert_eq!(a.activated, 1.0);
+        // the larger grain dominates the area weighted development
+        assert!(a.developed_fraction > 0.8);
+    }
+
+    #[test]
+    fn merged_clusters_match_aggregate() {
+        let grains: Vec<Halide> = (0..5)
+            .map(|i| {
+                let mut g = grain(i * 3, 6);
+                g.radius = 0.1 + 0.05 * (i as f32);
+                g.developed_fraction = 0.2 * (i as f32);
+                g
+            })
+            .collect();
+        let mut merged = Halide::aggregate(&grains[..2]);
+        merged.merge(&Halide::aggregate(&grains[2..]));
+        let whole = Halide::aggregate(&grains);
+        assert_eq!(merged.count, whole.count);
+        assert_eq!(merged.activated, whole.activated);
+        assert_eq!(merged.silver_count, whole.silver_count);
+        assert!((merged.mean_radius - whole.mean_radius).abs() < 1e-6);
+        assert!((merged.radius_std - whole.radius_std).abs() < 1e-5);
+        assert!((merged.developed_fraction - whole.developed_fraction).abs() < 1e-5);
+    }
+
+    #[test]
+    fn aggregate_counts_grain_weight() {
+        let mut thinned = grain(20, 10);
+        thinned.radius = 0.4;
+        thinned.weight = 3.0;
+        let mut small = grain(4, 10);
+        small.radius = 0.1;
+        let cluster = Halide::aggregate(&[thinned.clone(), small.clone()]);
+        // the thinned grain counts as three, as if listed three times
+        let single = Halide { weight: 1.0, ..thinned };
+        let expanded = Halide::aggregate(&[single.clone(), single.clone(), single, small]);
+        assert_eq!(cluster.count, 4.0);
+        assert_eq!(cluster.activated, 3.0);
+        assert_eq!(cluster.silver_count, 64.0);
+        assert!((cluster.mean_radius - expanded.mean_radius).abs() < 1e-6);
+        assert!((cluster.radius_std - expanded.radius_std).abs() < 1e-5);
+        assert!((cluster.total_area - expanded.total_area).abs() < 1e-5);
+    }
+
+    #[test]
+    fn new_grain_is_unexposed_and_neutral() {
+        let g = Halide::new_with_params(3, 4, 0.5, 6, 0.25);
+        assert_eq!((g.x, g.y, g.radius, g.latent_threshold), (3, 4, 0.5, 6));
+        assert_eq!((g.silver_count, g.internal_latent, g.activated), (0, 0, false));
+        assert_eq!((g.depth, g.weight, g.overlying_transmission), (0.0, 1.0, 1.0));
+        assert_eq!(g.developed_fraction, 0.0);
+        assert_eq!(g.effective_absorption(), 0.25);
+        assert!((g.spectral_intensity([0.3, 0.6, 0.9]) - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn exposures_add_up_until_activated() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(1);
+        // every photon is absorbed: 9 on a grain of area pi over 3 units
+        let mut dim = Halide::new_with_params(0, 0, 1.0, 100, 1.0);
+        dim.expose(1.0, 3.0, &mut rng);
+        dim.expose(1.0, 3.0, &mut rng);
+        assert_eq!(dim.silver_count, 18);
+        assert!(!dim.activated);
+
+        let mut bright = Halide::new_with_params(0, 0, 1.0, 10, 1.0);
+        bright.expose(1.0, 3.0, &mut rng);
+        assert!(!bright.activated);
+        bright.expose(1.0, 3.0, &mut rng);
+        assert!(bright.activated);
+        assert_eq!(bright.silver_count, 18);
+        bright.expose(1.0, 3.0, &mut rng);
+        assert_eq!(bright.silver_count, 18);
+    }
+
+    #[test]
+    fn reset_development_keeps_the_latent_image() {
+        let mut g = grain(40, 10);
+        Halide::develop_grain(&mut g, &developer(), 1.0);
+        assert!(g.developed_fraction > 0.0);
+        g.reset_development();
+        assert_eq!(g.developed_fraction, 0.0);
+        assert_eq!(g.silver_count, 40);
+        assert!(g.activated);
+        // and develops the same way again
+        let mut fresh = grain(40, 10);
+        Halide::develop_grain(&mut g, &developer(), 1.0);
+        Halide::develop_grain(&mut fresh, &developer(), 1.0);
+        assert_eq!(g.developed_fraction, fresh.developed_fraction);
+    }
+
+    #[test]
+    fn unexposed_grain_stays_clear() {
+        let mut g = grain(0, 10);
+        Halide::develop_grain(&mut g, &developer(), 10.0);
+        assert_eq!(g.developed_fraction, 0.0);
+    }
 }
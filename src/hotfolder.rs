@@ -0,0 +1,205 @@
+//! Hot folder: images dropped into one directory are processed into
+//! another as they arrive, for tethered shooting or a renderer's output
+//!
+//! The input directory is polled. A file is only taken once its size and
+//! modification time have held still for the settle time, so one still
+//! being copied in is not read half written. One that fails is tried again
+//! after another settle time, up to a number of retries, and then left
+//! alone until it changes. Inputs whose output is already newer are
+//! skipped, so a restarted daemon picks up where it stopped. Parameters can
+//! come from a watched file, reloaded for later frames whenever it is
+//! saved.
+
+use std::collections::HashMap;
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant, SystemTime };
+
+use crate::error::{ Error, Result };
+use crate::params::Params;
+use crate::pipeline;
+use crate::reload::{ Watched, POLL_INTERVAL };
+
+pub struct HotFolder {
+    /// directory watched for new images
+    pub input: PathBuf,
+    /// directory the processed images are written to, under the input's
+    /// name
+    pub output: PathBuf,
+    /// extension, and so format, of the processed images
+    pub extension: String,
+    /// time a file must go unchanged before it is read
+    pub settle: Duration,
+    /// further attempts at a file that failed to process
+    pub retries: u32,
+}
+
+/// What is known of one input file
+struct Entry {
+    size: u64,
+    modified: Option<SystemTime>,
+    /// when the file was last seen to change, or last failed
+    since: Instant,
+    failures: u32,
+    /// processed, or given up on, until it changes
+    done: bool,
+}
+
+impl HotFolder {
+    /// Process files as they appear, with `params` and `config` applied
+    /// over them when given. Runs until an error reading the directories.
+    pub fn run(&self, params: Params, config: Option<&Path>) -> Result<()> {
+        std::fs::create_dir_all(&self.output)?;
+        if self.input.canonicalize()? == self.output.canonicalize()? {
+            return Err(Error::Parse("the output directory must differ from the input".into()));
+        }
+        let mut watched = config.map(|path| Watched::new(path, params.clone()));
+        let mut params = match &mut watched {
+            Some(watched) => watched.load()?,
+            None => params,
+        };
+        let mut entries: HashMap<PathBuf, Entry> = HashMap::new();
+        tracing::info!("Watching {} for images", self.input.display());
+        loop {
+            if let Some(reloaded) = watched.as_mut().and_then(Watched::poll) {
+                params = reloaded;
+            }
+            self.scan(&mut entries)?;
+            let mut ready: Vec<PathBuf> = entries
+                .iter()
+                .filter(|(_, entry)| !entry.done && entry.since.elapsed() >= self.settle)
+                .map(|(path, _)| path.clone())
+                .collect();
+            ready.sort();
+            for path in ready {
+                let entry = entries.get_mut(&path).expect("ready files are entries");
+                match self.process(&path, &params) {
+                    Ok(output) => {
+                        tracing::info!("Processed {} to {}", path.display(), output.display());
+                        entry.done = true;
+                    }
+                    Err(err) if entry.failures < self.retries => {
+                        entry.failures += 1;
+                        entry.since = Instant::now();
+                        tracing::warn!(
+                            "{} failed, retry {} of {}: {err}",
+                            path.display(),
+                            entry.failures,
+                            self.retries
+                        );
+                    }
+                    Err(err) => {
+                        entry.done = true;
+                        tracing::error!("Giving up on {} until it changes: {err}", path.display());
+                    }
+                }
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Bring the entries up to date with the input directory
+    fn scan(&self, entries: &mut HashMap<PathBuf, Entry>) -> Result<()> {
+        let mut present = Vec::new();
+        for item in std::fs::read_dir(&self.input)? {
+            let path = item?.path();
+            let hidden = path
+                .file_name()
+                .is_none_or(|name| name.to_string_lossy().starts_with('.'));
+            if hidden || image::ImageFormat::from_path(&path).is_err() {
+                continue;
+            }
+            // a file that vanished since it was listed is left for the next scan
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let (size, modified) = (metadata.len(), metadata.modified().ok());
+            match entries.get_mut(&path) {
+                Some(entry) if entry.size == size && entry.modified == modified => {}
+                Some(entry) => {
+                    *entry = Entry { size, modified, since: Instant::now(), failures: 0, done: false };
+                }
+                None => {
+                    let done = self.up_to_date(&path, modified);
+                    entries.insert(path.clone(), Entry {
+                        size,
+                        modified,
+                        since: Instant::now(),
+                        failures: 0,
+                        done,
+                    });
+                }
+            }
+            present.push(path);
+        }
+        entries.retain(|path, _| present.contains(path));
+        Ok(())
+    }
+
+    /// Where `input` is written to
+    fn output_path(&self, input: &Path) -> PathBuf {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        self.output.join(format!("{stem}.{}", self.extension))
+    }
+
+    /// Whether the output of `input` was written after its last change
+    fn up_to_date(&self, input: &Path, modified: Option<SystemTime>) -> bool {
+        let output = std::fs::metadata(self.output_path(input)).and_then(|m| m.modified()).ok();
+        matches!((output, modified), (Some(output), Some(input)) if output >= input)
+    }
+
+    /// Process one file, writing it under a hidden name first so nothing
+    /// watching the output sees it half written
+    fn process(&self, input: &Path, params: &Params) -> Result<PathBuf> {
+        let image = image::open(input)?;
+        let processed = pipeline::process(&image, params)?;
+        let output = self.output_path(input);
+        let name = output.file_name().unwrap_or_default().to_string_lossy();
+        let partial = self.output.join(format!(".{name}"));
+        processed.save(&partial)?;
+        std::fs::rename(&partial, &output)?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(input: PathBuf, output: PathBuf) -> HotFolder {
+        HotFolder { input, output, extension: "png".into(), settle: Duration::ZERO, retries: 0 }
+    }
+
+    #[test]
+    fn output_keeps_dotted_stems() {
+        let hot = folder("in".into(), "out".into());
+        assert_eq!(hot.output_path(Path::new("in/shot.v2.tif")), Path::new("out/shot.v2.png"));
+    }
+
+    #[test]
+    fn scan_takes_visible_images_only() {
+        let root = std::env::temp_dir().join(format!("halide-hotfolder-test-{}", std::process::id()));
+        let (input, output) = (root.join("in"), root.join("out"));
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::create_dir_all(&output).unwrap();
+        for name in ["frame.png", ".partial.png", "notes.txt"] {
+            std::fs::write(input.join(name), b"x").unwrap();
+        }
+        std::fs::create_dir_all(input.join("nested.png")).unwrap();
+
+        let hot = folder(input.clone(), output);
+        let mut entries = HashMap::new();
+        hot.scan(&mut entries).unwrap();
+        let found: Vec<&PathBuf> = entries.keys().collect();
+        assert_eq!(found, [&input.join("frame.png")]);
+        assert!(!entries[&input.join("frame.png")].done);
+
+        // a vanished file is forgotten
+        std::fs::remove_file(input.join("frame.png")).unwrap();
+        hot.scan(&mut entries).unwrap();
+        assert!(entries.is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+//! Infrared dust removal, as film scanners do it: a fourth exposure in
+//! infrared, which dust and scratches block but the dyes of colour film
+//! pass, marks the defects, and they are painted over from their
+//! surroundings
+//!
+//! The repair is not clean. The scanner grows every defect a little
+//! before filling it and softens the image around it to hide the seam, so
+//! repaired spots leave soft patches behind. Silver blocks infrared as
+//! much as visible light, so on a black and white negative every dense
+//! area looks like dust and is painted over, smearing the image.
+//!
+//! Dust is laid out over the full frame in units of its short side, so a
+//! crop shows the same specks as the frame.
+
+use rand::Rng;
+
+use crate::field::{ Field, Rect };
+use crate::params::Params;
+use crate::random;
+use crate::render::Polarity;
+
+/// radii of dust specks, in short sides
+const SPECK_RADIUS: std::ops::Range<f32> = 0.002..0.01;
+/// share of the dust that is fibres rather than specks
+const FIBRE_SHARE: f32 = 0.2;
+/// length of a fibre, in short sides
+const FIBRE_LENGTH: std::ops::Range<f32> = 0.05..0.2;
+/// half width of a fibre, in short sides
+const FIBRE_WIDTH: f32 = 0.0015;
+/// infrared transmission below which a pixel is taken for a defect
+const DEFECT_THRESHOLD: f32 = 0.7;
+/// pixels a defect is grown by before it is filled
+const DEFECT_GROWTH: u32 = 1;
+/// pixels around a repair that are softened
+const SOFTEN_RADIUS: u32 = 3;
+
+/// Dust on the negative in the scanner
+enum Dust {
+    Speck { x: f32, y: f32, radius: f32 },
+    /// a curved fibre, as a quadratic curve through three points
+    Fibre { points: [(f32, f32); 3] },
+}
+
+impl Dust {
+    fn new(rng: &mut impl Rng, full: Rect, short: f32) -> Self {
+        let (x, y) = (
+            (rng.random::<f32>() * (full.width as f32)) / short,
+            (rng.random::<f32>() * (full.height as f32)) / short,
+        );
+        if rng.random::<f32>() >= FIBRE_SHARE {
+            return Dust::Speck { x, y, radius: rng.random_range(SPECK_RADIUS) };
+        }
+        let length = rng.random_range(FIBRE_LENGTH);
+        let angle = rng.random_range(0.0..std::f32::consts::TAU);
+        let bend = rng.random_range(-0.3..0.3) * length;
+        let (dx, dy) = (0.5 * length * angle.cos(), 0.5 * length * angle.sin());
+        let middle = (x - (bend * dy) / length, y + (bend * dx) / length);
+        Dust::Fibre { points: [(x - dx, y - dy), middle, (x + dx, y + dy)] }
+    }
+
+    /// Share of the light blocked at `(x, y)`, in short sides
+    fn coverage(&self, x: f32, y: f32, pixel: f32) -> f32 {
+        // soft edges a pixel wide
+        let edge = |distance: f32, radius: f32| (0.5 - (distance - radius) / pixel).clamp(0.0, 1.0);
+        match self {
+            Dust::Speck { x: sx, y: sy, radius } => edge((x - sx).hypot(y - sy), *radius),
+            Dust::Fibre { points: [a, b, c] } => {
+                let distance = (0..=16)
+                    .map(|i| {
+                        let t = (i as f32) / 16.0;
+                        let (u, v) = (1.0 - t, t);
+                        let px = u * u * a.0 + 2.0 * u * v * b.0 + v * v * c.0;
+                        let py = u * u * a.1 + 2.0 * u * v * b.1 + v * v * c.1;
+                        (x - px).hypot(y - py)
+                    })
+                    .fold(f32::MAX, f32::min);
+                edge(distance, FIBRE_WIDTH.max(0.5 * pixel))
+            }
+        }
+    }
+}
+
+/// Whether `params` asks for dust or its removal
+pub fn enabled(params: &Params) -> bool {
+    params.scan_dust > 0 || params.infrared_cleaning
+}
+
+/// Scan a rendered negative covering `region` of a `full` frame with the
+/// dust of `params` on it, and clean it with infrared if asked to
+pub fn scan(image: &mut image::RgbaImage, params: &Params, full: Rect, region: Rect) {
+    let (width, height) = image.dimensions();
+    let positive = params.polarity == Polarity::Positive;
+    let transmission = |value: u8| (if positive { 255 - value } else { value }) as f32 / 255.0;
+    let channels: [Field; 3] = [0, 1, 2].map(|c| Field {
+        width,
+        height,
+        data: image.pixels().map(|p| transmission(p.0[c])).collect(),
+    });
+
+    let clear = dust_clearance(width, height, params, full, region);
+    let mut scanned = channels.clone();
+    for channel in &mut scanned {
+        channel.data.iter_mut().zip(&clear.data).for_each(|(t, c)| *t *= c);
+    }
+
+    if params.infrared_cleaning {
+        let transparent = params.grain_renderer.infrared_transparent();
+        if !transparent {
+            tracing::warn!("Infrared cleaning takes the silver image for dust");
+        }
+        // what the infrared exposure sees: the dust, and silver as dense
+        // as it looks
+        let infrared: Vec<f32> = (0..scanned[0].data.len())
+            .map(|i| {
+                let image = if transparent {
+                    1.0
+                } else {
+                    channels.iter().map(|c| c.data[i]).sum::<f32>() / 3.0
+                };
+                clear.data[i] * image
+            })
+            .collect();
+        let flagged: Vec<bool> = infrared.iter().map(|&t| t < DEFECT_THRESHOLD).collect();
+        let defects = grow(&flagged, width, height, DEFECT_GROWTH);
+        let repaired = defects.iter().filter(|&&d| d).count();
+        tracing::info!(
+            "Infrared cleaning repaired {:.1}% of the frame",
+            (100.0 * (repaired as f32)) / (defects.len().max(1) as f32)
+        );
+        for channel in &mut scanned {
+            inpaint(channel, &defects);
+        }
+        soften(&mut scanned, &defects);
+    }
+
+    let value = |t: f32| {
+        let v = (t.clamp(0.0, 1.0) * 255.0).round() as u8;
+        if positive { 255 - v } else { v }
+    };
+    for (i, pixel) in image.pixels_mut().enumerate() {
+        for (c, channel) in scanned.iter().enumerate() {
+            pixel.0[c] = value(channel.data[i]);
+        }
+    }
+}
+
+/// Share of the light the dust lets through over a `width`×`height` image
+/// of `region`
+fn dust_clearance(width: u32, height: u32, params: &Params, full: Rect, region: Rect) -> Field {
+    let short = full.width.min(full.height).max(1) as f32;
+    let mut rng = random::rng_for(params.seed, random::DUST_STREAM, 0);
+    let dust: Vec<Dust> = (0..params.scan_dust).map(|_| Dust::new(&mut rng, full, short)).collect();
+    // one image pixel, in short sides
+    let pixel = (region.width as f32) / (width.max(1) as f32) / short;
+    let mut clear = Field::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let fx = (region.x as f32) + (((x as f32) + 0.5) * (region.width as f32)) / (width as f32);
+            let fy = (region.y as f32) + (((y as f32) + 0.5) * (region.height as f32)) / (height as f32);
+            let blocked = dust.iter().map(|d| d.coverage(fx / short, fy / short, pixel)).fold(0.0, f32::max);
+            clear.set(x, y, 1.0 - blocked);
+        }
+    }
+    clear
+}
+
+/// `mask` grown by `steps` pixels
+fn grow(mask: &[bool], width: u32, height: u32, steps: u32) -> Vec<bool> {
+    let (w, h) = (width as usize, height as usize);
+    let mut grown = mask.to_vec();
+    for _ in 0..steps {
+        let previous = grown.clone();
+        for y in 0..h {
+            for x in 0..w {
+                grown[y * w + x] = previous[y * w + x] ||
+                    (x > 0 && previous[y * w + x - 1]) ||
+                    (x + 1 < w && previous[y * w + x + 1]) ||
+                    (y > 0 && previous[(y - 1) * w + x]) ||
+                    (y + 1 < h && previous[(y + 1) * w + x]);
+            }
+        }
+    }
+    grown
+}
+
+/// Fill the pixels of `field` under `mask` from the outside in, each from
+/// the mean of its neighbours already known
+fn inpaint(field: &mut Field, mask: &[bool]) {
+    let (w, h) = (field.width as i64, field.height as i64);
+    let mut known: Vec<bool> = mask.iter().map(|&m| !m).collect();
+    if !known.contains(&true) {
+        return;
+    }
+    loop {
+        let mut filled = Vec::new();
+        for y in 0..h {
+            for x in 0..w {
+                let i = (y * w + x) as usize;
+                if known[i] {
+                    continue;
+                }
+                let (mut sum, mut count) = (0.0, 0);
+                for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx >= 0 && ny >= 0 && nx < w && ny < h && known[(ny * w + nx) as usize] {
+                        sum += field.data[(ny * w + nx) as usize];
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    filled.push((i, sum / (count as f32)));
+                }
+            }
+        }
+        if filled.is_empty() {
+            return;
+        }
+        for (i, value) in filled {
+            field.data[i] = value;
+            known[i] = true;
+        }
+    }
+}
+
+/// Blur the image around the repaired pixels of `mask`, fully at the
+/// repair and fading out over [`SOFTEN_RADIUS`]
+fn soften(channels: &mut [Field; 3], mask: &[bool]) {
+    let (width, height) = (channels[0].width, channels[0].height);
+    // how many pixels out from a repair each pixel is, up to the radius
+    let mut distance: Vec<u32> = mask.iter().map(|&m| if m { 0 } else { u32::MAX }).collect();
+    let mut reached = mask.to_vec();
+    for step in 1..=SOFTEN_RADIUS {
+        reached = grow(&reached, width, height, 1);
+        for (d, &r) in distance.iter_mut().zip(&reached) {
+            if r && *d == u32::MAX {
+                *d = step;
+            }
+        }
+    }
+    for channel in channels.iter_mut() {
+        let blurred = box_blur(channel);
+        for (i, &d) in distance.iter().enumerate() {
+            if d == u32::MAX {
+                continue;
+            }
+            let weight = 1.0 - (d as f32) / ((SOFTEN_RADIUS + 1) as f32);
+            channel.data[i] += weight * (blurred.data[i] - channel.data[i]);
+        }
+    }
+}
+
+/// Mean over each pixel's 3×3 neighbourhood
+fn box_blur(field: &Field) -> Field {
+    let mut blurred = Field::new(field.width, field.height);
+    for y in 0..field.height as i64 {
+        for x in 0..field.width as i64 {
+            let mut sum = 0.0;
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    sum += field.get_clamped(x + dx, y + dy);
+                }
+            }
+            blurred.set(x as u32, y as u32, sum / 9.0);
+        }
+    }
+    blurred
+}
@@ -0,0 +1,389 @@
+//! Minimal JSON reader for parameter blobs
+
+use crate::error::{ Error, Result };
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// a number as written, so integers past the precision of `f64`, such
+    /// as seeds, reach the parameters exactly
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    /// object members in document order
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) =>
+                members
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Numeric value of a number
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Scalar rendered the way it would be typed on the command line
+    pub fn as_param(&self) -> Option<String> {
+        match self {
+            Value::Bool(b) => Some(b.to_string()),
+            // integers as written, anything else as its value, so `2e6`
+            // still counts grains
+            Value::Number(n) if n.bytes().all(|b| b == b'-' || b.is_ascii_digit()) => Some(n.clone()),
+            Value::Number(_) => self.as_f64().map(|n| n.to_string()),
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Compact JSON, with everything outside printable ASCII escaped so it
+/// fits in an HTTP header
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) if is_number(n) => write!(f, "{n}"),
+            Value::Number(_) => write!(f, "null"),
+            Value::String(s) => write_string(f, s),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "{}{item}", if i > 0 { "," } else { "" })?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    write!(f, "{}", if i > 0 { "," } else { "" })?;
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut std::fmt::Formatter<'_>, text: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            ' '..='~' => write!(f, "{c}")?,
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    write!(f, "\\u{unit:04x}")?;
+                }
+            }
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Whether `text` is a number in JSON syntax
+pub(crate) fn is_number(text: &str) -> bool {
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let text = text.strip_prefix('-').unwrap_or(text);
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))),
+        None => (text, None),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (mantissa, None),
+    };
+    digits(whole) &&
+        (whole == "0" || !whole.starts_with('0')) &&
+        fraction.is_none_or(digits) &&
+        exponent.is_none_or(digits)
+}
+
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser { bytes: text.as_bytes(), pos: 0, depth: 0 };
+    let value = parser.value()?;
+    parser.skip_ws();
+    if parser.pos != parser.bytes.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+/// deepest nesting of arrays and objects read, so a hostile document
+/// cannot exhaust the stack
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// arrays and objects open around the current position
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> Error {
+        Error::Parse(format!("json: {msg} at byte {}", self.pos))
+    }
+
+    fn skip_ws(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        self.skip_ws();
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value> {
+        if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("invalid literal"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_ws();
+        match self.peek() {
+            Some(b'{' | b'[') => {
+                if self.depth >= MAX_DEPTH {
+                    return Err(self.error("nested too deeply"));
+                }
+                self.depth += 1;
+                let value = if self.peek() == Some(b'{') { self.object() } else { self.array() };
+                self.depth -= 1;
+                value
+            }
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        self.skip_ws();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_ws();
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.push((key, self.value()?));
+            self.skip_ws();
+            match self.peek() {
+                Some(b',') => {
+                    self.pos += 1;
+                }
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => {
+                    return Err(self.error("expected ',' or '}'"));
+                }
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_ws();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_ws();
+            match self.peek() {
+                Some(b',') => {
+                    self.pos += 1;
+                }
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => {
+                    return Err(self.error("expected ',' or ']'"));
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("expected string"));
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(b) = self.peek() {
+                if b == b'"' || b == b'\\' {
+                    break;
+                }
+                self.pos += 1;
+            }
+            out.push_str(
+                std::str::from_utf8(&self.bytes[start..self.pos]).map_err(|_| self.error("invalid utf-8"))?
+            );
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = self.peek().ok_or_else(|| self.error("unterminated escape"))?;
+                    self.pos += 1;
+                    match escaped {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => {
+                            let unit = self.unicode_escape()?;
+                            // a character outside the basic plane comes as a
+                            // surrogate pair of escapes
+                            let paired = (0xd800..0xdc00).contains(&unit) &&
+                                self.bytes[self.pos..].starts_with(b"\\u");
+                            let c = if paired {
+                                let before = self.pos;
+                                self.pos += 2;
+                                let second = self.unicode_escape()?;
+                                if (0xdc00..0xe000).contains(&second) {
+                                    char::from_u32(0x10000 + ((unit - 0xd800) << 10) + (second - 0xdc00))
+                                } else {
+                                    self.pos = before;
+                                    None
+                                }
+                            } else {
+                                char::from_u32(unit)
+                            };
+                            out.push(c.unwrap_or('\u{fffd}'));
+                        }
+                        _ => {
+                            return Err(self.error("invalid escape"));
+                        }
+                    }
+                }
+                _ => {
+                    return Err(self.error("unterminated string"));
+                }
+            }
+        }
+    }
+
+    /// The four hex digits after `\u`
+    fn unicode_escape(&mut self) -> Result<u32> {
+        let unit = self.bytes
+            .get(self.pos..self.pos + 4)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u32::from_str_radix(h, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(unit)
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        std::str
+            ::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .filter(|s| is_number(s))
+            .map(|s| Value::Number(s.to_string()))
+            .ok_or_else(|| self.error("invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_ascii_round_trips_through_escapes() {
+        let text = "grain 🎞 at 18 °C";
+        let written = Value::String(text.into()).to_string();
+        assert!(written.is_ascii());
+        assert!(written.contains("\\ud83c\\udf9e"));
+        assert_eq!(parse(&written).unwrap(), Value::String(text.into()));
+    }
+
+    #[test]
+    fn unpaired_surrogates_become_replacement_characters() {
+        let parsed = |text| parse(text).unwrap().as_param().unwrap();
+        assert_eq!(parsed(r#""\ud83c""#), "\u{fffd}");
+        assert_eq!(parsed(r#""\ud83cA""#), "\u{fffd}A");
+        assert_eq!(parsed(r#""\udf9e\ud83c""#), "\u{fffd}\u{fffd}");
+    }
+
+    #[test]
+    fn integers_stay_exact() {
+        let seed = u64::MAX - 2;
+        let value = parse(&format!("{{\"seed\":{seed},\"dt\":-0.5e-2}}")).unwrap();
+        assert_eq!(value.get("seed").unwrap().as_param().unwrap(), seed.to_string());
+        assert_eq!(value.get("dt").unwrap().as_f64(), Some(-0.005));
+        assert_eq!(parse("2e6").unwrap().as_param().unwrap(), "2000000");
+        assert_eq!(parse(&value.to_string()).unwrap(), value);
+    }
+
+    #[test]
+    fn refuses_deep_nesting() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(parse(&"[".repeat(60_000)).is_err());
+        assert!(parse(&"{\"a\":".repeat(60_000)).is_err());
+    }
+
+    #[test]
+    fn refuses_malformed_numbers() {
+        for text in ["01", "1.", ".5", "-", "1e", "+1", "1-2", "1.2.3"] {
+            assert!(parse(text).is_err(), "{text:?} should be refused");
+        }
+        assert_eq!(Value::Number("1-2".into()).to_string(), "null");
+    }
+}
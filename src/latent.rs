@@ -0,0 +1,199 @@
+//! Latent image import and export, so external tools can inspect or
+//! author the state development starts from
+//!
+//! Two representations are supported, picked by file extension:
+//! - `.csv`: one row per grain with its position, size and latent state
+//! - `.exr`: the mean silver count per pixel; other image formats are read
+//!   as a 0..1 fraction of each grain's latent threshold, which is the
+//!   convenient form for painted or generated test patterns
+
+use std::io::{ BufRead, BufReader, BufWriter, Write };
+use std::path::Path;
+
+use crate::emulsion::Emulsion;
+use crate::error::{ Error, Result };
+use crate::field::Field;
+use crate::halide::Halide;
+
+const CSV_HEADER: &str =
+    "x,y,radius,silver_count,latent_threshold,internal_latent,absorption_probability,response_r,response_g,response_b,depth,weight,overlying_transmission";
+
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+fn is_exr(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exr"))
+}
+
+/// Write the latent image of an emulsion on a `width`×`height` grid
+pub fn export(emulsion: &Emulsion, width: u32, height: u32, path: &Path) -> Result<()> {
+    tracing::info!("Exporting latent image to {}", path.display());
+    if is_csv(path) {
+        write_grains(emulsion, path)
+    } else {
+        let map = emulsion.rasterize(width, height, |g| g.silver_count as f32);
+        let mut image = image::Rgb32FImage::new(width, height);
+        for (pixel, &v) in image.pixels_mut().zip(&map.data) {
+            pixel.0 = [v; 3];
+        }
+        image.save_with_format(path, image::ImageFormat::OpenExr)?;
+        Ok(())
+    }
+}
+
+/// Load a latent image into an emulsion on a `width`×`height` grid. A grain
+/// list replaces the emulsion's grains; a map sets the silver count of the
+/// existing grains from the pixel each one lies on. Grains listed off the
+/// grid are refused.
+pub fn import(emulsion: &mut Emulsion, width: u32, height: u32, path: &Path) -> Result<()> {
+    tracing::info!("Importing latent image from {}", path.display());
+    if is_csv(path) {
+        *emulsion = read_grains(path, width, height)?;
+        return Ok(());
+    }
+    let raw_counts = is_exr(path);
+    let mut map = image::open(path)?.to_luma32f();
+    if map.dimensions() != (width, height) {
+        map = image::imageops::resize(&map, width, height, image::imageops::FilterType::Triangle);
+    }
+    let map = Field { width, height, data: map.into_raw() };
+    for grain in emulsion.grains.iter_mut() {
+        let value = map.get_clamped(grain.x as i64, grain.y as i64).max(0.0);
+        let silver = if raw_counts { value } else { value * (grain.latent_threshold as f32) };
+        grain.silver_count = silver.round() as usize;
+        grain.activated = grain.silver_count >= grain.latent_threshold;
+    }
+    Ok(())
+}
+
+/// Write every grain as a CSV row
+pub fn write_grains(emulsion: &Emulsion, path: &Path) -> Result<()> {
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    writeln!(out, "{CSV_HEADER}")?;
+    for g in &emulsion.grains {
+        let [r, gr, b] = g.spectral_response;
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            g.x,
+            g.y,
+            g.radius,
+            g.silver_count,
+            g.latent_threshold,
+            g.internal_latent,
+            g.absorption_probability,
+            r,
+            gr,
+            b,
+            g.depth,
+            g.weight,
+            g.overlying_transmission
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Read grains written by [`write_grains`] for a `width`×`height` grid
+pub fn read_grains(path: &Path, width: u32, height: u32) -> Result<Emulsion> {
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    let mut grains = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if number == 0 || line.trim().is_empty() {
+            continue;
+        }
+        let invalid = || Error::Parse(format!("{}:{}: invalid grain row", path.display(), number + 1));
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        // files written by earlier versions lack the depth, weight and
+        // overlying transmission columns
+        if !(10..=13).contains(&fields.len()) {
+            return Err(invalid());
+        }
+        let float = |i: usize| fields[i].parse::<f32>().map_err(|_| invalid());
+        let int = |i: usize| fields[i].parse::<usize>().map_err(|_| invalid());
+        let (x, y) = (int(0)?, int(1)?);
+        if x >= (width as usize) || y >= (height as usize) {
+            return Err(
+                Error::Parse(
+                    format!("{}:{}: grain at {x},{y} lies off the {width}x{height} grid", path.display(), number + 1)
+                )
+            );
+        }
+        let silver_count = int(3)?;
+        let latent_threshold = int(4)?;
+        grains.push(Halide {
+            x,
+            y,
+            radius: float(2)?,
+            depth: if fields.len() > 10 { float(10)? } else { 0.0 },
+            weight: if fields.len() > 11 { float(11)? } else { 1.0 },
+            overlying_transmission: if fields.len() > 12 { float(12)? } else { 1.0 },
+            silver_count,
+            latent_threshold,
+            internal_latent: int(5)?,
+            activated: silver_count >= latent_threshold,
+            spectral_response: [float(7)?, float(8)?, float(9)?],
+            absorption_probability: float(6)?,
+            developed_fraction: 0.0,
+        });
+    }
+    Ok(Emulsion { grains })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("halide-latent-test-{}-{name}", std::process::id()))
+    }
+
+    fn emulsion(positions: &[(usize, usize)]) -> Emulsion {
+        Emulsion {
+            grains: positions
+                .iter()
+                .map(|&(x, y)| Halide::new_with_params(x, y, 0.3, 10, 0.5))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn grain_lists_round_trip() {
+        let path = temp("round.csv");
+        let mut written = emulsion(&[(1, 2), (15, 15)]);
+        written.grains[0].silver_count = 12;
+        write_grains(&written, &path).unwrap();
+        let mut read = emulsion(&[]);
+        import(&mut read, 16, 16, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.grains.len(), 2);
+        assert_eq!((read.grains[0].x, read.grains[0].y, read.grains[0].silver_count), (1, 2, 12));
+        assert!(read.grains[0].activated);
+    }
+
+    #[test]
+    fn grains_off_the_grid_are_refused() {
+        let path = temp("off.csv");
+        write_grains(&emulsion(&[(3, 3), (5000, 5000)]), &path).unwrap();
+        let err = import(&mut emulsion(&[]), 16, 16, &path).unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.contains("off the 16x16 grid"), "{err}");
+    }
+
+    #[test]
+    fn maps_set_silver_from_the_pixel_under_each_grain() {
+        let path = temp("map.png");
+        let mut map = image::GrayImage::new(4, 4);
+        map.put_pixel(1, 1, image::Luma([255]));
+        map.save(&path).unwrap();
+        // a grain off the map takes the nearest edge pixel instead of panicking
+        let mut grains = emulsion(&[(1, 1), (0, 0), (40, 40)]);
+        import(&mut grains, 4, 4, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let silver: Vec<usize> = grains.grains.iter().map(|g| g.silver_count).collect();
+        assert_eq!(silver, [10, 0, 0]);
+        assert!(grains.grains[0].activated);
+    }
+}
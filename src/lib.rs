@@ -0,0 +1,68 @@
+pub mod accounting;
+pub mod ageing;
+pub mod archival;
+pub mod averaging;
+pub mod burnin;
+pub mod cache;
+pub mod chart;
+pub mod cineon;
+pub mod compare;
+pub mod contactsheet;
+pub mod datasheet;
+pub mod defects;
+pub mod densitometer;
+pub mod developer;
+pub mod development;
+pub mod diffusion;
+pub mod discharge;
+pub mod dpx;
+pub mod dump;
+pub mod emulsion;
+pub mod error;
+pub mod expected;
+pub mod farm;
+pub mod field;
+pub mod filter;
+pub mod flatfield;
+pub mod font;
+pub mod frame;
+pub mod grainfield;
+pub mod halation;
+pub mod halide;
+pub mod hotfolder;
+pub mod history;
+pub mod infrared;
+pub mod json;
+pub mod latent;
+pub mod multispectral;
+pub mod panoramic;
+pub mod parallel;
+pub mod params;
+pub mod pinhole;
+pub mod pipeline;
+pub mod plate;
+pub mod projection;
+pub mod psf;
+pub mod random;
+pub mod reload;
+pub mod render;
+pub mod resample;
+pub mod safelight;
+pub mod scene;
+pub mod sensitometry;
+pub mod separation;
+pub mod serve;
+pub mod sharpen;
+pub mod spatial;
+pub mod spectral;
+pub mod stock;
+pub mod strip;
+pub mod temperature;
+pub mod teststrip;
+pub mod temporal;
+pub mod tonecurve;
+pub mod toml;
+pub mod units;
+
+pub use error::{ Error, Result };
+pub use params::Params;
@@ -1,47 +1,742 @@
-mod halide;
-mod developer;
-mod emulsion;
+mod cli;
 
-use developer::Developer;
-use emulsion::Emulsion;
-use halide::Halide;
+use cli::Args;
+use halide::archival::DyeFade;
+use halide::averaging;
+use halide::cache::StageCache;
+use halide::chart::{ self, Chart };
+use halide::cineon::{ self, OutputEncoding };
+use halide::compare;
+use halide::contactsheet::{ self, Frame, SheetLayout };
+use halide::datasheet::{ self, Datasheet };
+use halide::densitometer::Densitometer;
+use halide::dpx::{ self, FilmInfo };
+use halide::farm::{ self, Farm, Job };
+use halide::flatfield;
+use halide::frame::FrameFormat;
+use halide::grainfield::{ Distribution, GrainField };
+use halide::hotfolder::HotFolder;
+use halide::multispectral;
+use halide::pinhole::{ self, Pinhole };
+use halide::reload::Watched;
+use halide::scene::Scene;
+use halide::sensitometry;
+use halide::separation;
+use halide::stock::Stock;
+use halide::strip;
+use halide::teststrip::{ self, TestStrip };
+use halide::tonecurve::ToneCurve;
+use halide::units::Seconds;
+use halide::{ pipeline, serve, Error, Params, Result };
 
-use rayon::prelude::*;
+const USAGE: &str = "usage:
+  halide [INPUT [OUTPUT]] [--PARAM VALUE ...] [--watch PARAMS.json]
+      [--film-gauge TEXT] [--frame-position N] [--sequence-length N] [--frame-rate FPS]
+  halide serve [--addr HOST:PORT] [--watch PARAMS.json] [--PARAM VALUE ...]
+  halide watch IN_DIR OUT_DIR [--config LOOK.{toml,json}] [--settle SECONDS] [--retries N]
+      [--extension png|tif|...] [--PARAM VALUE ...]
+  halide farm OUTPUT_PATTERN INPUT... [--workers local,HOST:PORT,...] [--first-frame N]
+      [--skip-existing] [--PARAM VALUE ...]
+  halide contactsheet OUTPUT INPUT... [--columns N] [--perforations N] [--sweep KEY=V1,V2,...]
+      [--paper-stock NAME] [--paper-exposure-time T] [--paper-grains-per-pixel N]
+      [--negative-only] [--PARAM VALUE ...]
+  halide strip OUTPUT INPUT... [--gap-mm MM] [--frames OUTPUT_STEM] [--PARAM VALUE ...]
+  halide teststrip OUTPUT INPUT [--bands N] [--first T] [--increment T] [--PARAM VALUE ...]
+  halide multispectral OUTPUT NM=IMAGE... [--PARAM VALUE ...]
+  halide separate OUTPUT_STEM INPUT [--filter-factors R,G,B] [--recombine OUTPUT]
+      [--PARAM VALUE ...]
+  halide recombine OUTPUT RED GREEN BLUE
+  halide calibrate --iso SPEED [--chart OUTPUT.{svg,png}] [--PARAM VALUE ...]
+  halide datasheet SHEET.json [OUTPUT.json] [--PARAM VALUE ...]
+  halide flatfield [--ev STOPS] [--chart OUTPUT.{svg,png}] [--PARAM VALUE ...]
+  halide mtf [--chart OUTPUT.{svg,png}] [--PARAM VALUE ...]
+  halide compare STOCK STOCK... --scene INPUT [--output STEM] [--PARAM VALUE ...]
+  halide average OUTPUT_STEM INPUT [--frames N] [--PARAM VALUE ...]
+  halide fade OUTPUT_STEM PRINT [--years Y1,Y2,...] [--print-keeping dark|display]
+  halide tonecurve OUTPUT.{csv,cube,xmp} [--samples N] [--negative] [--name NAME]
+      [--PARAM VALUE ...]
+  halide scene NAME OUTPUT [--width W] [--height H] [--peak P]
+  halide pinhole --focal-length MM --ev EV --iso SPEED [--diameter MM] [--PARAM VALUE ...]
+  halide grainfield OUTPUT.csv --width-mm W --height-mm H --density GRAINS_PER_MM2
+      [--distribution uniform|poisson|jittered] [--seed N]";
 
 fn main() {
     tracing_subscriber::fmt::init();
 
-    tracing::info!("Creating emulsion");
+    if let Err(err) = run() {
+        tracing::error!("{err}");
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let mut args = Args::parse(std::env::args().skip(1))?;
+    match args.positional.first().map(String::as_str) {
+        Some("serve") => {
+            args.positional.remove(0);
+            let addr = args.take("addr").unwrap_or_else(|| "127.0.0.1:8080".to_string());
+            let watch = args.take("watch").map(std::path::PathBuf::from);
+            serve::serve(&addr, args.params()?, watch.as_deref())
+        }
+        Some("watch") => {
+            args.positional.remove(0);
+            watch_folder(args)
+        }
+        Some("farm") => {
+            args.positional.remove(0);
+            farm(args)
+        }
+        Some("contactsheet") => {
+            args.positional.remove(0);
+            contact_sheet(args)
+        }
+        Some("strip") => {
+            args.positional.remove(0);
+            film_strip(args)
+        }
+        Some("teststrip") => {
+            args.positional.remove(0);
+            test_strip(args)
+        }
+        Some("multispectral") => {
+            args.positional.remove(0);
+            multispectral(args)
+        }
+        Some("separate") => {
+            args.positional.remove(0);
+            separate(args)
+        }
+        Some("recombine") => {
+            args.positional.remove(0);
+            recombine(args)
+        }
+        Some("calibrate") => {
+            args.positional.remove(0);
+            calibrate(args)
+        }
+        Some("datasheet") => {
+            args.positional.remove(0);
+            fit_datasheet(args)
+        }
+        Some("flatfield") => {
+            args.positional.remove(0);
+            flat_field(args)
+        }
+        Some("mtf") => {
+            args.positional.remove(0);
+            mtf(args)
+        }
+        Some("compare") => {
+            args.positional.remove(0);
+            compare(args)
+        }
+        Some("tonecurve") => {
+            args.positional.remove(0);
+            tone_curve(args)
+        }
+        Some("average") => {
+            args.positional.remove(0);
+            average(args)
+        }
+        Some("fade") => {
+            args.positional.remove(0);
+            fade(args)
+        }
+        Some("scene") => {
+            args.positional.remove(0);
+            scene(args)
+        }
+        Some("pinhole") => {
+            args.positional.remove(0);
+            pinhole(args)
+        }
+        Some("grainfield") => {
+            args.positional.remove(0);
+            grain_field(args)
+        }
+        Some("help") => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => process(args),
+    }
+}
+
+fn process(mut args: Args) -> Result<()> {
+    // film header of DPX output
+    let film = FilmInfo {
+        format: args.take("film-gauge").unwrap_or_default(),
+        frame_position: args.take_parsed("frame-position")?,
+        sequence_length: args.take_parsed("sequence-length")?,
+        frame_rate: args.take_parsed("frame-rate")?,
+        creator: format!("halide {}", env!("CARGO_PKG_VERSION")),
+    };
+    let watch = args.take("watch").map(std::path::PathBuf::from);
+    let params = args.params()?;
+    let mut positional = args.positional.into_iter();
+    let input = positional.next().unwrap_or_else(|| "test_images/inputs/input.png".to_string());
+    let output = positional.next().unwrap_or_else(|| "test_images/negative.png".to_string());
+    if let Some(extra) = positional.next() {
+        return Err(Error::Parse(format!("unexpected argument '{extra}'")));
+    }
 
     // open input image
-    let image = image::open("test_images/inputs/input.png").unwrap();
-    let image = image.to_luma16();
-    let (width, height) = image.dimensions();
-
-    let num_grains = 10_000_000;
-    let mut emulsion = Emulsion::create_random_emulsion(width, height, num_grains);
-
-    // expose emulsion to image
-    tracing::info!("Exposing emulsion to image");
-    emulsion.grains.par_iter_mut().for_each(|grain| {
-        let pixel_val = image.get_pixel(grain.x as u32, grain.y as u32).0[0];
-        let intensity = (pixel_val as f32) / (u16::MAX as f32);
-        grain.expose(intensity, 700.0);
-    });
-
-    // develop emulsion
-    tracing::info!("Developing emulsion");
-    let dev = Developer {
-        strength: 0.1,
-        max_development: 1.0,
-    };
-    let dt = 0.1;
-    emulsion.grains.par_iter_mut().for_each(|grain| {
-        Halide::develop_grain(grain, &dev, dt);
-    });
-
-    // save activated grains to output image
-    tracing::info!("Saving activated grains to negative image");
-    let output = emulsion.render_emulsion(width, height);
-    output.save("test_images/negative.png").unwrap();
+    let image = image::open(&input)?;
+    let output_path = std::path::Path::new(&output);
+    let develop = |params: &Params, cache: Option<&StageCache>| -> Result<()> {
+        if output_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("dpx")) {
+            if params.output_encoding == Some(OutputEncoding::Display) {
+                return Err(
+                    Error::Parse("DPX output holds Cineon log density, not display encoding".to_string())
+                );
+            }
+            let density = pipeline::process_density(&image, params, cache)?;
+            tracing::info!("Saving negative as DPX printing density");
+            return dpx::write(output_path, &density, &film);
+        }
+        if params.encoding() == OutputEncoding::Cineon {
+            let density = pipeline::process_density(&image, params, cache)?;
+            tracing::info!("Saving negative as Cineon log density");
+            return cineon::save(&density, output_path);
+        }
+        let output_image = pipeline::process_cached(&image, params, cache)?;
+
+        tracing::info!("Saving activated grains to negative image");
+        output_image.save(output_path)?;
+        Ok(())
+    };
+    let Some(watch) = watch else {
+        return develop(&params, None);
+    };
+
+    // develop again on every save of the watched file, repeating only the
+    // stages its changes reach
+    let cache = StageCache::new();
+    let mut watched = Watched::new(&watch, params);
+    develop(&watched.load()?, Some(&cache))?;
+    tracing::info!("Watching {} for changes", watch.display());
+    loop {
+        let params = watched.wait();
+        if let Err(err) = develop(&params, Some(&cache)) {
+            tracing::warn!("{err}");
+        }
+    }
+}
+
+fn watch_folder(mut args: Args) -> Result<()> {
+    let config = args.take("config").map(std::path::PathBuf::from);
+    let settle: f32 = args.take_parsed("settle")?.unwrap_or(1.0);
+    let retries = args.take_parsed("retries")?.unwrap_or(3);
+    let extension = args.take("extension").unwrap_or_else(|| "png".to_string());
+    let params = args.params()?;
+    let [input, output] = &args.positional[..] else {
+        return Err(Error::Parse("watch needs an input and an output directory".into()));
+    };
+    if image::ImageFormat::from_extension(&extension).is_none_or(|format| !format.can_write()) {
+        return Err(Error::Parse(format!("cannot write images with extension '{extension}'")));
+    }
+
+    let folder = HotFolder {
+        input: input.into(),
+        output: output.into(),
+        extension,
+        settle: std::time::Duration::from_secs_f32(settle.max(0.0)),
+        retries,
+    };
+    folder.run(params, config.as_deref())
+}
+
+fn farm(mut args: Args) -> Result<()> {
+    let workers = farm::parse_workers(&args.take("workers").unwrap_or_else(|| "local".to_string()))?;
+    let first_frame: u64 = args.take_parsed("first-frame")?.unwrap_or(1);
+    let skip_existing = args.take_switch("skip-existing");
+    let params = args.params()?;
+    let mut positional = args.positional.into_iter();
+    let pattern = positional
+        .next()
+        .ok_or_else(|| Error::Parse("farm needs an output pattern".into()))?;
+    let inputs: Vec<String> = positional.collect();
+    if inputs.is_empty() {
+        return Err(Error::Parse("farm needs at least one input".into()));
+    }
+
+    let mut jobs = Vec::new();
+    for (i, input) in inputs.into_iter().enumerate() {
+        let number = first_frame + (i as u64);
+        let output = farm::output_path(&pattern, number)?;
+        if skip_existing && output.exists() {
+            continue;
+        }
+        jobs.push(Job { number, input: input.into(), output });
+    }
+    let farm = Farm { workers, flags: args.flags };
+    farm.render(jobs, &params)
+}
+
+fn contact_sheet(mut args: Args) -> Result<()> {
+    let mut layout = SheetLayout::default();
+    if let Some(columns) = args.take_parsed("columns")? {
+        layout.columns = columns;
+    }
+    layout.perforations = args.take_parsed("perforations")?;
+    let sweep = args.take("sweep");
+    let paper_stock = Stock::preset(
+        &args.take("paper-stock").unwrap_or_else(|| "chloride-paper".to_string())
+    )?;
+    let paper_exposure_time = args.take_parsed("paper-exposure-time")?.unwrap_or(5000.0);
+    let paper_grains_per_pixel = args.take_parsed("paper-grains-per-pixel")?.unwrap_or(4.0);
+    let negative_only = args.take_switch("negative-only");
+    let params = args.params()?;
+
+    let mut positional = args.positional.into_iter();
+    let output = positional
+        .next()
+        .ok_or_else(|| Error::Parse("contactsheet needs an output path".into()))?;
+    let inputs: Vec<String> = positional.collect();
+    if inputs.is_empty() {
+        return Err(Error::Parse("contactsheet needs at least one input".into()));
+    }
+
+    let mut frames = Vec::new();
+    match sweep {
+        // one input processed once per swept value
+        Some(sweep) => {
+            let (key, values) = sweep
+                .split_once('=')
+                .ok_or_else(|| Error::Parse(format!("invalid sweep '{sweep}', expected KEY=V1,V2")))?;
+            let image = image::open(&inputs[0])?;
+            for (i, value) in values.split(',').enumerate() {
+                tracing::info!("Processing frame {key}={value}");
+                let mut frame_params = params.batch_frame(i);
+                frame_params.set(key, value)?;
+                frames.push(Frame {
+                    label: format!("{key}={value}"),
+                    image: pipeline::process(&image, &frame_params)?,
+                });
+            }
+        }
+        None => {
+            for (i, input) in inputs.iter().enumerate() {
+                tracing::info!("Processing frame {input}");
+                let name = std::path::Path
+                    ::new(input)
+                    .file_stem()
+                    .map_or_else(|| input.clone(), |s| s.to_string_lossy().into_owned());
+                frames.push(Frame {
+                    label: format!("{} {name}", i + 1),
+                    image: pipeline::process(&image::open(input)?, &params.batch_frame(i))?,
+                });
+            }
+        }
+    }
+
+    let sheet = contactsheet::compose(&frames, &layout);
+    let sheet = if negative_only {
+        sheet
+    } else {
+        tracing::info!("Contact printing sheet");
+        let paper = Params {
+            stock: paper_stock,
+            exposure_time: Some(paper_exposure_time),
+            grains_per_pixel: Some(paper_grains_per_pixel),
+            ..Params::default()
+        };
+        contactsheet::print(&sheet, &paper)?
+    };
+    sheet.save(&output)?;
+    Ok(())
+}
+
+fn film_strip(mut args: Args) -> Result<()> {
+    let gap_mm = args.take_parsed("gap-mm")?;
+    let stem = args.take("frames");
+    let params = args.params()?;
+    let mut positional = args.positional.into_iter();
+    let output = positional
+        .next()
+        .ok_or_else(|| Error::Parse("strip needs an output path".into()))?;
+    let inputs = positional.map(image::open).collect::<std::result::Result<Vec<_>, _>>()?;
+    if inputs.is_empty() {
+        return Err(Error::Parse("strip needs at least one input".into()));
+    }
+
+    // a half-frame diptych unless another format is asked for
+    let format = params.frame.unwrap_or(FrameFormat::HalfFrame);
+    let strip = strip::layout(&inputs, format, params.frame_fit, gap_mm.unwrap_or(format.frame_gap_mm()))?;
+    tracing::info!("Exposing {} frames on one {:.1} mm strip", strip.frames.len(), strip.width_mm);
+    let developed = strip::process(&strip, &params)?;
+    developed.save(&output)?;
+    if let Some(stem) = stem {
+        for (i, frame) in strip::cut(&strip, &developed).iter().enumerate() {
+            frame.save(format!("{stem}-{:03}.png", i + 1))?;
+        }
+    }
+    Ok(())
+}
+
+fn test_strip(mut args: Args) -> Result<()> {
+    let bands = args.take_parsed("bands")?;
+    let first = args.take_parsed("first")?;
+    let increment = args.take_parsed("increment")?;
+    let params = args.params()?;
+    let (output, input) = match &args.positional[..] {
+        [output, input] => (output, input),
+        _ => {
+            return Err(Error::Parse("teststrip needs an output path and one input".into()));
+        }
+    };
+
+    let mut strip = TestStrip::around(params.print_exposure_time, bands.unwrap_or(6));
+    if let Some(increment) = increment {
+        strip = TestStrip { first: increment, increment, ..strip };
+    }
+    if let Some(first) = first {
+        strip.first = first;
+    }
+    teststrip::print(&image::open(input)?, &params, &strip)?.save(output)?;
+    Ok(())
+}
+
+fn multispectral(args: Args) -> Result<()> {
+    let params = args.params()?;
+    let mut positional = args.positional.into_iter();
+    let output = positional
+        .next()
+        .ok_or_else(|| Error::Parse("multispectral needs an output path".into()))?;
+    let channels = positional
+        .map(|text| multispectral::open_channel(&text))
+        .collect::<Result<Vec<_>>>()?;
+
+    tracing::info!("Integrating {} spectral channels against {}", channels.len(), params.stock.name);
+    let image = multispectral::to_rgb(&channels, &params.stock.spectral_sensitivity())?;
+    pipeline::process(&image, &params)?.save(&output)?;
+    Ok(())
+}
+
+fn separate(mut args: Args) -> Result<()> {
+    let factors = match args.take("filter-factors") {
+        Some(text) => separation::parse_factors(&text)?,
+        None => separation::TRICOLOR.map(|filter| filter.factor),
+    };
+    let positive = args.take("recombine");
+    let params = args.params()?;
+    let [stem, input] = &args.positional[..] else {
+        return Err(Error::Parse("separate needs an output stem and an input".into()));
+    };
+
+    let image = image::open(input)?;
+    let negatives = separation::expose_all(&image, &params, factors)?;
+    for (filter, negative) in separation::TRICOLOR.iter().zip(&negatives) {
+        negative.save(format!("{stem}-{}.png", filter.name))?;
+    }
+    if let Some(path) = positive {
+        tracing::info!("Recombining separations");
+        separation::recombine(&negatives)?.save(&path)?;
+    }
+    Ok(())
+}
+
+fn recombine(args: Args) -> Result<()> {
+    let [output, red, green, blue] = &args.positional[..] else {
+        return Err(Error::Parse("recombine needs an output and three separations".into()));
+    };
+    let negatives = [
+        image::open(red)?.to_rgba8(),
+        image::open(green)?.to_rgba8(),
+        image::open(blue)?.to_rgba8(),
+    ];
+    separation::recombine(&negatives)?.save(output)?;
+    Ok(())
+}
+
+fn calibrate(mut args: Args) -> Result<()> {
+    let chart_path = args.take("chart");
+    let params = args.params()?;
+    let iso = params.iso.ok_or_else(|| Error::Parse("calibrate needs --iso".into()))?;
+    let scale = sensitometry::calibrate(&params, iso, params.grains_per_pixel)?;
+    let exposure_time = scale.exposure_time(params.input_illuminance(), Seconds(params.shutter_seconds));
+
+    // characteristic curve at the calibrated exposure, in lux seconds
+    let calibrated = halide::Params { exposure_time: Some(exposure_time), iso: None, ..params.clone() };
+    let grains_per_pixel = params.grains_per_pixel.unwrap_or(sensitometry::DEFAULT_GRAINS_PER_PIXEL);
+    let mut chart = Chart::new(
+        &format!("{} at ISO {iso}", params.stock.name),
+        "log lux seconds",
+        "density"
+    );
+    let lux_seconds = |curve: &sensitometry::Curve| -> Vec<(f32, f32)> {
+        curve.log_exposure
+            .iter()
+            .map(|log_exposure| log_exposure - scale.log10())
+            .zip(curve.density.iter().copied())
+            .collect()
+    };
+    if params.densitometer == Densitometer::Visual {
+        let curve = sensitometry::measure(&calibrated, grains_per_pixel)?;
+        chart.add("visual", lux_seconds(&curve), chart::BLACK);
+        println!("log_lux_seconds,density");
+        for (log_exposure, density) in curve.log_exposure.iter().zip(&curve.density) {
+            println!("{:.3},{:.3}", log_exposure - scale.log10(), density);
+        }
+        println!("fog: {:.3}", curve.fog());
+    } else {
+        let [red, green, blue] = sensitometry::measure_channels(&calibrated, grains_per_pixel)?;
+        chart.add("red", lux_seconds(&red), chart::RED);
+        chart.add("green", lux_seconds(&green), chart::GREEN);
+        chart.add("blue", lux_seconds(&blue), chart::BLUE);
+        println!("log_lux_seconds,red,green,blue");
+        for (i, log_exposure) in red.log_exposure.iter().enumerate() {
+            println!(
+                "{:.3},{:.3},{:.3},{:.3}",
+                log_exposure - scale.log10(),
+                red.density[i],
+                green.density[i],
+                blue.density[i]
+            );
+        }
+        println!("fog: {:.3},{:.3},{:.3}", red.fog(), green.fog(), blue.fog());
+    }
+    println!("units per lux second: {}", scale.0);
+    println!("exposure time at {} s: {exposure_time}", params.shutter_seconds);
+    if let Some(path) = chart_path {
+        chart.save(std::path::Path::new(&path))?;
+    }
+    Ok(())
+}
+
+fn fit_datasheet(args: Args) -> Result<()> {
+    let params = args.params()?;
+    let (sheet, output) = match &args.positional[..] {
+        [sheet] => (sheet, None),
+        [sheet, output] => (sheet, Some(output)),
+        _ => {
+            return Err(Error::Parse("datasheet needs a sheet and an optional output".into()));
+        }
+    };
+
+    let sheet = Datasheet::load(std::path::Path::new(sheet))?;
+    let settings = datasheet::fit(&sheet, &params)?;
+    let json = datasheet::to_json(&settings);
+    match output {
+        Some(output) => std::fs::write(output, json)?,
+        None => print!("{json}"),
+    }
+    Ok(())
+}
+
+fn flat_field(mut args: Args) -> Result<()> {
+    let ev = args.take_parsed("ev")?.unwrap_or(0.0);
+    let chart_path = args.take("chart");
+    let params = args.params()?;
+    let reading = flatfield::measure(&params, flatfield::input_value(ev))?;
+
+    println!("exposure: {ev:+.1} EV, input {:.4}", flatfield::input_value(ev));
+    match reading.density {
+        [red, green, blue] if params.densitometer != Densitometer::Visual => {
+            println!("density: {red:.3},{green:.3},{blue:.3}");
+        }
+        [density, ..] => println!("density: {density:.3}"),
+    }
+    println!(
+        "activated: {:.1}% of {:.0} grains",
+        100.0 * reading.grains.activation_fraction(),
+        reading.grains.count
+    );
+    println!("granularity: {:.1} (RMS x1000, {} um aperture)", reading.granularity, flatfield::APERTURE_UM);
+    if let Some(path) = chart_path {
+        let mut chart = Chart::new(&format!("Noise spectrum at {ev:+.1} EV"), "cycles/mm", "density^2 um");
+        // zero frequency carries no noise once the mean is removed
+        chart.add(&params.stock.name, reading.spectrum[1..].to_vec(), chart::BLACK);
+        chart.save(std::path::Path::new(&path))?;
+    }
+    Ok(())
+}
+
+fn mtf(mut args: Args) -> Result<()> {
+    let chart_path = args.take("chart");
+    let params = args.params()?;
+    let grains_per_pixel = params.grains_per_pixel.unwrap_or(sensitometry::DEFAULT_GRAINS_PER_PIXEL);
+    let mtf = sensitometry::mtf(&params, grains_per_pixel)?;
+    println!("cycles_per_mm,modulation");
+    for (frequency, modulation) in &mtf {
+        println!("{frequency:.2},{modulation:.4}");
+    }
+    if let Some(path) = chart_path {
+        let mut chart = Chart::new("Modulation transfer", "cycles/mm", "modulation");
+        chart.add(&params.stock.name, mtf, chart::BLACK);
+        chart.save(std::path::Path::new(&path))?;
+    }
+    Ok(())
+}
+
+fn compare(mut args: Args) -> Result<()> {
+    let scene = args.take("scene").ok_or_else(|| Error::Parse("compare needs --scene".into()))?;
+    let stem = args.take("output").unwrap_or_else(|| "compare".to_string());
+    let params = args.params()?;
+    if args.positional.len() < 2 {
+        return Err(Error::Parse("compare needs at least two stocks".into()));
+    }
+
+    let scene = image::open(&scene)?;
+    let reports = args.positional
+        .iter()
+        .map(|stock| compare::report(&params, stock, &scene))
+        .collect::<Result<Vec<_>>>()?;
+    compare::montage(&reports).save(format!("{stem}-montage.png"))?;
+    let curves = std::path::PathBuf::from(format!("{stem}-curves.svg"));
+    compare::curves(&reports, params.iso.is_some()).save(&curves)?;
+
+    println!("stock,fog,max_density,density_at_metered,activated,granularity");
+    for report in &reports {
+        println!(
+            "{},{:.3},{:.3},{:.3},{:.3},{:.1}",
+            report.stock,
+            report.curve.fog(),
+            report.curve.density.iter().copied().fold(0.0, f32::max),
+            report.flat.density[0],
+            report.flat.grains.activation_fraction(),
+            report.flat.granularity
+        );
+    }
+    Ok(())
+}
+
+fn tone_curve(mut args: Args) -> Result<()> {
+    let samples = args.take_parsed("samples")?.unwrap_or(17);
+    let negative = args.take_switch("negative");
+    let name = args.take("name");
+    let params = args.params()?;
+    let [output] = &args.positional[..] else {
+        return Err(Error::Parse("tonecurve needs an output path".into()));
+    };
+
+    let curve = ToneCurve::measure(
+        &params,
+        samples,
+        params.grains_per_pixel.unwrap_or(sensitometry::DEFAULT_GRAINS_PER_PIXEL),
+        negative
+    )?;
+    let name = name.unwrap_or_else(|| format!("halide {}", params.stock.name));
+    curve.export(std::path::Path::new(output), &name)
+}
+
+fn average(mut args: Args) -> Result<()> {
+    let count = args.take_parsed("frames")?.unwrap_or(8);
+    let params = args.params()?;
+    let [stem, input] = &args.positional[..] else {
+        return Err(Error::Parse("average needs an output stem and an input".into()));
+    };
+
+    let image = image::open(input)?;
+    let frames = averaging::expose_frames(&image, &params, count)?;
+    for (i, frame) in frames.iter().enumerate() {
+        frame.save(format!("{stem}-{:03}.png", i + 1))?;
+    }
+    averaging::mean(&frames)?.save(format!("{stem}-mean.png"))?;
+
+    let noise = averaging::frame_noise(&frames);
+    println!("frames: {count}");
+    println!("single frame noise: {noise:.5}");
+    println!("mean noise: {:.5}", noise / (count.max(1) as f32).sqrt());
+    Ok(())
+}
+
+fn fade(mut args: Args) -> Result<()> {
+    let years = args.take("years").unwrap_or_else(|| "0,10,25,50,100".to_string());
+    let params = args.params()?;
+    let [stem, input] = &args.positional[..] else {
+        return Err(Error::Parse("fade needs an output stem and a print".into()));
+    };
+
+    let print = image::open(input)?.to_rgba8();
+    for text in years.split(',') {
+        let years: f32 = text
+            .trim()
+            .parse()
+            .map_err(|_| Error::Parse(format!("invalid years '{text}'")))?;
+        let fade = DyeFade::after(years, params.print_keeping);
+        tracing::info!(
+            "After {years} years {:.0}% of the cyan, {:.0}% of the magenta and {:.0}% of the yellow dye are left",
+            100.0 * fade.remaining[0],
+            100.0 * fade.remaining[1],
+            100.0 * fade.remaining[2]
+        );
+        fade.apply(&print).save(format!("{stem}-{years:03}y.png"))?;
+    }
+    Ok(())
+}
+
+fn scene(mut args: Args) -> Result<()> {
+    let width = args.take_parsed("width")?.unwrap_or(1024);
+    let height = args.take_parsed("height")?.unwrap_or(683);
+    let peak = args.take_parsed("peak")?.unwrap_or(64.0);
+    let [name, output] = &args.positional[..] else {
+        return Err(Error::Parse("scene needs a scene name and an output path".into()));
+    };
+
+    let image = Scene::parse(name)?.render(width, height, peak);
+    if output.to_ascii_lowercase().ends_with(".exr") {
+        image.save(output)?;
+    } else {
+        tracing::warn!("{output} cannot hold values above 1, highlights will clip");
+        image.to_rgb16().save(output)?;
+    }
+    Ok(())
+}
+
+fn pinhole(mut args: Args) -> Result<()> {
+    let focal_length = args
+        .take_parsed("focal-length")?
+        .ok_or_else(|| Error::Parse("pinhole needs --focal-length".into()))?;
+    let ev = args.take_parsed("ev")?.ok_or_else(|| Error::Parse("pinhole needs --ev".into()))?;
+    let diameter = args.take_parsed("diameter")?;
+    let params = args.params()?;
+    let iso = params.iso.ok_or_else(|| Error::Parse("pinhole needs --iso".into()))?;
+
+    let wavelength = params.stock.spectral_sensitivity().mean_wavelength();
+    let optimal = Pinhole::optimal(focal_length, wavelength);
+    let pinhole = match diameter {
+        Some(diameter_mm) => Pinhole { focal_length_mm: focal_length, diameter_mm },
+        None => optimal,
+    };
+    let plan = pinhole::plan(&pinhole, ev, iso, &params.stock);
+    println!(
+        "pinhole: {:.3} mm (optimal {:.3} mm at {wavelength:.0} nm)",
+        pinhole.diameter_mm,
+        optimal.diameter_mm
+    );
+    println!("aperture: f/{:.0}", plan.f_number);
+    println!("metered: {:.1} s", plan.metered_seconds);
+    println!("corrected for reciprocity: {:.1} s", plan.corrected_seconds);
+    println!(
+        "simulate with: --iso {iso} --shutter-seconds {:.4} --exposure-seconds {:.1}",
+        plan.shutter_seconds,
+        plan.corrected_seconds
+    );
+    Ok(())
+}
+
+fn grain_field(mut args: Args) -> Result<()> {
+    let required = |value: Option<f32>, name: &str| {
+        value.ok_or_else(|| Error::Parse(format!("grainfield needs --{name}")))
+    };
+    let width = required(args.take_parsed("width-mm")?, "width-mm")?;
+    let height = required(args.take_parsed("height-mm")?, "height-mm")?;
+    let density = required(args.take_parsed("density")?, "density")?;
+    let distribution = match args.take("distribution") {
+        Some(text) => Distribution::parse(&text)?,
+        None => Distribution::Poisson,
+    };
+    let seed = args.take_parsed("seed")?;
+    let [output] = &args.positional[..] else {
+        return Err(Error::Parse("grainfield needs an output path".into()));
+    };
+    if let Some((flag, _)) = args.flags.first() {
+        return Err(Error::Parse(format!("unknown flag --{flag} for grainfield")));
+    }
+
+    let field = GrainField::generate(width, height, density, distribution, seed);
+    tracing::info!("Placed {} grains, {:.1} per square millimetre", field.grains.len(), field.density());
+    field.write_csv(std::path::Path::new(output))
 }
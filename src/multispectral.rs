@@ -0,0 +1,160 @@
+//! Multispectral input: a stack of single-channel images, each the scene's
+//! spectral radiance at one wavelength, integrated against the emulsion's
+//! own sensitivity instead of the RGB proxy spectra
+//!
+//! The rest of the pipeline carries light as red, green and blue exposure,
+//! each grain responding to a weighted sum of the three. Each pixel's
+//! spectrum is turned into the three values that give every absorption
+//! band of the stock, native and sensitizing dyes alike, the response the
+//! spectrum itself gives it. With three bands or fewer that is exact, so
+//! the grain to grain dye uptake variation still acts on the true
+//! spectrum; what the bands leave free is taken from the spectrum's
+//! projection onto the RGB proxies, so later stages working in RGB, such
+//! as the polarizer's sky detection, see plausible colours.
+
+use crate::error::{ Error, Result };
+use crate::spectral::{ self, Band, SpectralSensitivity, RGB_PRIMARIES };
+
+/// pull of the RGB projection against matching the bands, relative to
+/// the bands' own weight
+const REGULARIZATION: f32 = 1e-3;
+
+/// One channel of the stack
+pub struct Channel {
+    /// wavelength the channel samples in nanometres
+    pub wavelength_nm: f32,
+    /// spectral radiance at that wavelength, linear
+    pub image: image::ImageBuffer<image::Luma<f32>, Vec<f32>>,
+}
+
+/// Parse `NM=PATH` and open the image as one channel
+pub fn open_channel(text: &str) -> Result<Channel> {
+    let (nm, path) = text
+        .split_once('=')
+        .ok_or_else(|| Error::Parse(format!("invalid channel '{text}', expected NM=PATH")))?;
+    let wavelength_nm = nm
+        .trim()
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid wavelength '{nm}' in channel '{text}'")))?;
+    Ok(Channel { wavelength_nm, image: image::open(path)?.to_luma32f() })
+}
+
+/// The stack as the red, green and blue exposure that `sensitivity` sees
+/// the same as the spectra. A flat spectrum of 1 exposes like an RGB white
+/// of 1 on an emulsion equally sensitive at every wavelength.
+pub fn to_rgb(channels: &[Channel], sensitivity: &SpectralSensitivity) -> Result<image::DynamicImage> {
+    let mut channels: Vec<&Channel> = channels.iter().collect();
+    channels.sort_by(|a, b| a.wavelength_nm.total_cmp(&b.wavelength_nm));
+    let first = channels.first().ok_or_else(|| Error::Parse("no spectral channels".into()))?;
+    let (width, height) = first.image.dimensions();
+    if channels.iter().any(|c| c.image.dimensions() != (width, height)) {
+        return Err(Error::Parse("spectral channels differ in size".into()));
+    }
+    if channels.windows(2).any(|pair| pair[0].wavelength_nm == pair[1].wavelength_nm) {
+        return Err(Error::Parse("two spectral channels share a wavelength".into()));
+    }
+
+    let wavelengths: Vec<f32> = channels.iter().map(|c| c.wavelength_nm).collect();
+    let mix = mixing(&wavelengths, sensitivity);
+    let mut rgb = image::Rgb32FImage::new(width, height);
+    for (i, pixel) in rgb.pixels_mut().enumerate() {
+        pixel.0 = mix.each_ref().map(|row| {
+            row.iter()
+                .zip(&channels)
+                .map(|(weight, channel)| weight * channel.image.as_raw()[i])
+                .sum::<f32>()
+                .max(0.0)
+        });
+    }
+    Ok(image::DynamicImage::ImageRgb32F(rgb))
+}
+
+/// Weights taking the channels at `wavelengths` to red, green and blue.
+/// Everything is linear in the spectrum, so the fit of the bands is worked
+/// out once for the stack rather than once per pixel.
+fn mixing(wavelengths: &[f32], sensitivity: &SpectralSensitivity) -> [Vec<f32>; 3] {
+    // each channel stands for the spectrum halfway to its neighbours
+    let widths: Vec<f32> = (0..wavelengths.len())
+        .map(|i| {
+            let below = if i > 0 { wavelengths[i - 1] } else { wavelengths[i] };
+            let above = wavelengths.get(i + 1).copied().unwrap_or(wavelengths[i]);
+            let width = 0.5 * (above - below);
+            // a single channel stands for the whole spectrum
+            if width > 0.0 { width } else { spectral::integrate(|_| 1.0) }
+        })
+        .collect();
+    // the proxies' summed response over a flat spectrum, per nanometre
+    let flat = spectral::integrate(|nm| RGB_PRIMARIES.iter().map(|p| p.response(nm)).sum()) /
+        spectral::integrate(|_| 1.0);
+
+    let bands: Vec<Band> = std::iter::once(sensitivity.native).chain(sensitivity.sensitizers.iter().copied()).collect();
+    // response of each band to each proxy, and to each channel
+    let proxy: Vec<[f32; 3]> = bands
+        .iter()
+        .map(|band| RGB_PRIMARIES.map(|primary| spectral::integrate(|nm| band.response(nm) * primary.response(nm))))
+        .collect();
+    let direct: Vec<Vec<f32>> = bands
+        .iter()
+        .map(|band| {
+            wavelengths
+                .iter()
+                .zip(&widths)
+                .map(|(&nm, &width)| flat * band.response(nm) * width)
+                .collect()
+        })
+        .collect();
+    // the channels projected onto the proxies, each normalized so a flat
+    // spectrum of 1 gives 1 in every channel
+    let projection: [Vec<f32>; 3] = RGB_PRIMARIES.map(|primary| {
+        let area = spectral::integrate(|nm| primary.response(nm)).max(f32::EPSILON);
+        wavelengths
+            .iter()
+            .zip(&widths)
+            .map(|(&nm, &width)| (primary.response(nm) * width) / area)
+            .collect()
+    });
+
+    // minimize |proxy x - direct s|^2 + epsilon |x - projection s|^2
+    let mut normal = [[0.0f32; 3]; 3];
+    for row in &proxy {
+        for a in 0..3 {
+            for b in 0..3 {
+                normal[a][b] += row[a] * row[b];
+            }
+        }
+    }
+    let epsilon = (REGULARIZATION * (normal[0][0] + normal[1][1] + normal[2][2])) / 3.0;
+    for (a, row) in normal.iter_mut().enumerate() {
+        row[a] += epsilon.max(f32::EPSILON);
+    }
+    let inverse = invert(normal);
+    let rhs: [Vec<f32>; 3] = [0, 1, 2].map(|a| {
+        (0..wavelengths.len())
+            .map(|i| {
+                let bands: f32 = proxy
+                    .iter()
+                    .zip(&direct)
+                    .map(|(row, direct)| row[a] * direct[i])
+                    .sum();
+                bands + epsilon * projection[a][i]
+            })
+            .collect()
+    });
+    [0, 1, 2].map(|a| {
+        (0..wavelengths.len())
+            .map(|i| (0..3).map(|b| inverse[a][b] * rhs[b][i]).sum())
+            .collect()
+    })
+}
+
+/// Inverse of a symmetric positive definite 3×3 matrix
+fn invert(m: [[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let adjugate = [
+        [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+        [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+        [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+    ];
+    let determinant = m[0][0] * adjugate[0][0] + m[0][1] * adjugate[1][0] + m[0][2] * adjugate[2][0];
+    adjugate.map(|row| row.map(|v| v / determinant))
+}
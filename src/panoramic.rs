@@ -0,0 +1,74 @@
+//! Swing-lens panoramic cameras in the manner of the Widelux and Horizon
+//!
+//! The lens turns about its rear node behind a vertical slit while the
+//! film lies curved along the arc it sweeps, so the frame is exposed
+//! column by column over the sweep, each column seeing the scene straight
+//! ahead of the lens at that moment. The frame is a cylindrical projection
+//! of the scene: straight lines off the horizon bow toward the middle of
+//! the frame and the edges are not stretched as in a wide rectilinear lens.
+//! The drive does not turn quite evenly, so the exposure bands faintly
+//! across the frame where the lens sped up or dragged.
+//!
+//! The input is taken as a rectilinear view covering the angle of the
+//! sweep. The column timing itself is [`crate::temporal::Shutter`]'s.
+
+/// ripples of the drive over one sweep, as cycles and share of the depth;
+/// a slow governor wobble and the faster mesh of the gear train
+const DRIVE_RIPPLES: [(f32, f32, f32); 3] = [(2.3, 0.5, 0.4), (7.0, 0.3, 1.9), (23.0, 0.2, 4.1)];
+/// share of the sweep over which the lens comes up to speed, exposing the
+/// first columns more
+const SPIN_UP: f32 = 0.04;
+
+/// `image` as a swing lens covering `angle` degrees across it records it,
+/// the same size
+pub fn cylindrical(image: &image::DynamicImage, angle: f32) -> image::DynamicImage {
+    let source = image.to_rgb32f();
+    let (width, height) = source.dimensions();
+    let half = (0.5 * angle.clamp(1.0, 179.0)).to_radians();
+    let (cx, cy) = (0.5 * (width as f32), 0.5 * (height as f32));
+    // focal lengths in pixels of the input's rectilinear view and of the
+    // cylinder the film lies on, both spanning the frame's width
+    let rectilinear = cx / half.tan();
+    let cylinder = cx / half;
+    let mut projected = image::Rgb32FImage::new(width, height);
+    for (x, y, pixel) in projected.enumerate_pixels_mut() {
+        let theta = ((x as f32) + 0.5 - cx) / cylinder;
+        let elevation = ((y as f32) + 0.5 - cy) / cylinder;
+        let sx = cx + rectilinear * theta.tan();
+        let sy = cy + (rectilinear * elevation) / theta.cos();
+        pixel.0 = bilinear(&source, sx - 0.5, sy - 0.5);
+    }
+    image::DynamicImage::ImageRgb32F(projected)
+}
+
+/// Exposure of every column of a `width` wide frame against an evenly
+/// turning lens, banding by `depth`
+pub fn banding(width: u32, depth: f32) -> Vec<f32> {
+    (0..width)
+        .map(|x| {
+            let u = ((x as f32) + 0.5) / (width.max(1) as f32);
+            let ripple: f32 = DRIVE_RIPPLES.iter()
+                .map(|&(cycles, share, phase)| share * (std::f32::consts::TAU * cycles * u + phase).sin())
+                .sum();
+            // slower turning leaves the slit over a column longer
+            let speed = (1.0 + depth * ripple) * (1.0 - depth * (-u / SPIN_UP).exp());
+            1.0 / speed.max(0.1)
+        })
+        .collect()
+}
+
+/// Value of `image` at `(x, y)` in pixel centres, clamped to its edges
+fn bilinear(image: &image::Rgb32FImage, x: f32, y: f32) -> [f32; 3] {
+    let (width, height) = image.dimensions();
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - (x0 as f32), y - (y0 as f32));
+    let [a, b, c, d] = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)].map(|(x, y)| image.get_pixel(x, y).0);
+    [0, 1, 2].map(|i| {
+        let top = a[i] + fx * (b[i] - a[i]);
+        let bottom = c[i] + fx * (d[i] - c[i]);
+        top + fy * (bottom - top)
+    })
+}
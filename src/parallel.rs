@@ -0,0 +1,99 @@
+//! Thread pool configuration: how many threads the simulation may use, as
+//! a whole and per stage, so a run can share a machine with other jobs
+
+use crate::error::{ Error, Result };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Stages that can be given their own thread count
+pub enum Stage {
+    Halation,
+    Emulsion,
+    Exposure,
+    Development,
+    Render,
+}
+
+impl Stage {
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "halation" => Ok(Stage::Halation),
+            "emulsion" => Ok(Stage::Emulsion),
+            "exposure" => Ok(Stage::Exposure),
+            "development" => Ok(Stage::Development),
+            "render" => Ok(Stage::Render),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!(
+                            "unknown stage '{text}', expected halation, emulsion, exposure, development or render"
+                        )
+                    )
+                ),
+        }
+    }
+}
+
+/// Parse per-stage thread counts such as `exposure=4,render=1`
+pub fn parse_stage_threads(text: &str) -> Result<Vec<(Stage, usize)>> {
+    text.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (stage, threads) = entry
+                .split_once('=')
+                .ok_or_else(|| Error::Parse(format!("invalid stage threads '{entry}', expected STAGE=N")))?;
+            let threads = threads
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| Error::Parse(format!("invalid thread count in '{entry}'")))?;
+            Ok((Stage::parse(stage)?, threads))
+        })
+        .collect()
+}
+
+/// Thread pools for one run. Stages without a pool of their own run on the
+/// run's pool, or on rayon's global pool when no limit was set.
+pub struct Pools {
+    default: Option<rayon::ThreadPool>,
+    stages: Vec<(Stage, rayon::ThreadPool)>,
+}
+
+impl Pools {
+    /// Pools for `threads` overall (0 or unset for every core) and the
+    /// given per-stage counts
+    pub fn new(threads: Option<usize>, stage_threads: &[(Stage, usize)]) -> Result<Self> {
+        let default = threads
+            .filter(|&n| n > 0)
+            .map(build)
+            .transpose()?;
+        let stages = stage_threads
+            .iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|&(stage, n)| Ok((stage, build(n)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self { default, stages })
+    }
+
+    /// Run a whole job on the run's pool
+    pub fn install<R: Send>(&self, job: impl FnOnce() -> R + Send) -> R {
+        match &self.default {
+            Some(pool) => pool.install(job),
+            None => job(),
+        }
+    }
+
+    /// Run one stage on its own pool, if it has one
+    pub fn run<R: Send>(&self, stage: Stage, job: impl FnOnce() -> R + Send) -> R {
+        match self.stages.iter().find(|(s, _)| *s == stage) {
+            Some((_, pool)) => pool.install(job),
+            None => job(),
+        }
+    }
+}
+
+fn build(threads: usize) -> Result<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder
+        ::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|err| Error::Io(std::io::Error::other(err)))
+}
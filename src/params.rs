@@ -0,0 +1,1447 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::ageing::{ FogPattern, Storage };
+use crate::archival::Keeping;
+use crate::burnin::BurnIn;
+use crate::cineon::OutputEncoding;
+use crate::densitometer::Densitometer;
+use crate::developer::Developer;
+use crate::diffusion::{ Agitation, WaterBath };
+use crate::development::{ self, DevelopmentModel, FirstOrder };
+use crate::error::{ Error, Result };
+use crate::emulsion::ExposureSampling;
+use crate::field::Rect;
+use crate::filter::LensFilter;
+use crate::frame::{ FrameFit, FrameFormat };
+use crate::projection::Projector;
+use crate::psf::{ Convolution, Kernel };
+use crate::random;
+use crate::render::{ self, GrainRenderer, Look, Point, Polarity, Transfer };
+use crate::resample::Filter;
+use crate::safelight::Safelight;
+use crate::sensitometry;
+use crate::spectral::SpectralSensitivity;
+use crate::stock::{ CrystalComposition, Stock };
+use crate::temperature::Bath;
+use crate::temporal::{ LightProfile, Shutter };
+use crate::units::{ ExposureScale, Lux, Seconds, MIDDLE_GRAY };
+use crate::json;
+use crate::toml;
+use crate::parallel::{ self, Stage };
+
+/// exposure time of a run that neither gives one nor meters its light
+pub const DEFAULT_EXPOSURE_TIME: f32 = 700.0;
+
+#[derive(Debug, Clone)]
+/// Every knob of a simulation run
+pub struct Params {
+    /// number of grains scattered over the emulsion
+    pub num_grains: usize,
+    /// mean grains per emulsion pixel; when set the count under each pixel is
+    /// Poisson distributed and `num_grains` is ignored
+    pub grains_per_pixel: Option<f32>,
+    /// share of the grain density kept where grain is least visible, in
+    /// deep shadows and blown highlights, with mid-tones keeping the full
+    /// density; implies Poisson placement. Uniform density when unset.
+    pub adaptive_budget: Option<f32>,
+    /// simulate the emulsion this many rows at a time, holding only one
+    /// band's grains in memory, for grain counts that do not fit in RAM
+    pub band_rows: Option<u32>,
+    /// worker threads for the run, every core when unset
+    pub threads: Option<usize>,
+    /// thread counts for individual stages, overriding `threads`
+    pub stage_threads: Vec<(Stage, usize)>,
+    /// run on one thread with a fixed default seed, so identical inputs
+    /// give bit-identical output
+    pub single_thread: bool,
+    /// seed for every random draw of the simulation, from grain placement
+    /// to photon absorption; a fresh emulsion every run when unset
+    pub seed: Option<u64>,
+    /// seed of the grain field alone, its placement, sensitization, ageing
+    /// and coating, following `seed` when unset. Frames sharing it share
+    /// one field, which a stage cache generates only once, while exposure
+    /// and development still draw from `seed`.
+    pub grain_seed: Option<u64>,
+    /// frames of a sequence sharing one grain field before it is drawn
+    /// afresh; 1 gives every frame its own grain, as on film, and more cut
+    /// the setup of each frame at the price of grain that stands still
+    pub grain_reuse_frames: u64,
+    /// exposure time the input intensity is integrated over, worked out
+    /// when unset; see [`Params::exposure_time`]
+    pub exposure_time: Option<f32>,
+    /// contrast filter on the lens, none when unset
+    pub lens_filter: Option<LensFilter>,
+    /// exposure increase for the lens filter, replacing the factor worked
+    /// out from the stock's spectral sensitivity
+    pub filter_factor: Option<f32>,
+    /// density of a neutral density filter on the lens in stops, 0 for
+    /// none
+    pub nd_stops: f32,
+    /// relative red, green and blue transmission of the ND filter
+    pub nd_cast: [f32; 3],
+    /// lengthen the exposure to make up for the ND filter, so only
+    /// reciprocity failure and the cast change the result
+    pub nd_compensated: bool,
+    /// strength of a polarizer on the lens between 0 and 1, darkening the
+    /// sky, 0 for none
+    pub polarizer: f32,
+    /// grayscale mask of the sky the polarizer darkens, white for sky;
+    /// found from the colours of the scene when unset
+    pub polarizer_mask: Option<PathBuf>,
+    /// how photons are distributed onto the grains; splatting only
+    /// differs where grains cover more than their pixel, which the default
+    /// grain count does at most input sizes, so it is opt-in
+    pub exposure_sampling: ExposureSampling,
+    /// ISO speed; when set `exposure_time` is replaced by the calibrated
+    /// equivalent of `shutter_seconds` under the input illuminance
+    pub iso: Option<f32>,
+    /// film plane illuminance in lux of an input value of 1. With it, or
+    /// `scene_ev`, and no ISO speed, the exposure time is the real photon
+    /// count of `shutter_seconds` under it; an explicit `exposure_time`
+    /// cannot be given as well.
+    pub film_plane_lux: Option<f32>,
+    /// exposure value at ISO 100 the scene meters at, putting the input's
+    /// middle gray where the meter does through `f_number`; ignored when
+    /// `film_plane_lux` is given
+    pub scene_ev: Option<f32>,
+    /// aperture the scene is exposed through with `scene_ev`
+    pub f_number: f32,
+    /// emulsion being simulated
+    pub stock: Stock,
+    /// years the stock was kept past its date, 0 for fresh film
+    pub expired_years: f32,
+    /// where expired stock was kept, setting how fast it aged
+    pub storage: Storage,
+    /// passes of the film through airport X-ray scanners, each leaving a
+    /// band of fog
+    pub xray_scans: u32,
+    /// fog dose of one X-ray scan at the middle of its band
+    pub xray_dose: f32,
+    /// fog dose at the edges of the strip from heat reaching past the
+    /// can, 0 to disable
+    pub heat_fog: f32,
+    /// cosmic ray specks per square millimetre and year past the date
+    pub cosmic_rate: f32,
+    /// static discharges from winding the film in dry air, 0 to disable
+    pub static_marks: u32,
+    /// fog dose along the trunk of a static discharge
+    pub static_dose: f32,
+    /// relative grain to grain variation in sensitizing dye uptake
+    pub dye_uptake_variation: f32,
+    /// thickness of the emulsion layer in microns the grains are spread
+    /// through, 0 for a thin coating with every grain at the surface
+    pub coating_thickness_um: f32,
+    /// fraction of light absorbed and scattered per micron of emulsion
+    pub light_attenuation: f32,
+    /// σ in microns of the light the crystals scatter sideways
+    /// (irradiation), 0 for none
+    pub irradiation_um: f32,
+    /// depth in microns over which developer activity falls by a factor e
+    pub developer_penetration_um: f32,
+    /// temporal profile of the light source
+    pub light_profile: LightProfile,
+    /// how the shutter uncovers the frame
+    pub shutter: Shutter,
+    /// real shutter time in seconds, used to integrate the light profile
+    pub shutter_seconds: f32,
+    /// time offset of the shutter opening against the light profile
+    pub light_phase: f32,
+    /// angle in degrees a swing-lens shutter sweeps across the frame
+    pub swing_angle: f32,
+    /// unevenness of the swing lens drive, banding the exposure across
+    /// the frame
+    pub swing_banding: f32,
+    /// real duration of the exposure in seconds when it differs from
+    /// `shutter_seconds`, as through a pinhole whose aperture the input
+    /// does not model; reciprocity failure follows it
+    pub exposure_seconds: Option<f32>,
+    /// intensity of a brief, intense pre-exposure that desensitizes grains
+    /// to the main exposure (Clayden effect), 0 to disable
+    pub clayden_exposure: f32,
+    /// grayscale pattern of the pre-exposure, uniform when unset
+    pub clayden_pattern: Option<PathBuf>,
+    /// intensity of a red/infrared re-exposure after the main exposure
+    /// that erases latent silver (Herschel effect), 0 to disable
+    pub herschel_exposure: f32,
+    /// chance an absorbed Herschel photon removes a latent silver atom
+    pub herschel_efficiency: f32,
+    /// filter of the darkroom safelight
+    pub safelight: Safelight,
+    /// illuminance of the safelight at the easel in lux
+    pub safelight_lux: f32,
+    /// time the emulsion spends under the safelight, 0 for handling in
+    /// total darkness; with `contact_print` only the print sees it
+    pub safelight_minutes: f32,
+    /// strength of the uneven thickness of a hand-poured collodion coating,
+    /// 0 for an even machine coating
+    pub pour_artifacts: f32,
+    /// exposure added by fog creeping in from the plate edges where the
+    /// silver nitrate dried, 0 to disable
+    pub edge_fog: f32,
+    /// developer used for the development stage
+    pub developer: Developer,
+    /// rate law advancing each grain's development
+    pub development_model: Arc<dyn DevelopmentModel>,
+    /// total time spent in the developer
+    pub development_time: f32,
+    /// length of one development step
+    pub dt: f32,
+    /// immersions of a water-bath development, the development time split
+    /// evenly over them; 0 for continuous development
+    pub water_bath_cycles: usize,
+    /// time in still water after each immersion
+    pub water_rest: f32,
+    /// developed grain area per unit of emulsion area that exhausts the
+    /// developer soaked into the layer, while it is not replenished
+    pub developer_capacity: f32,
+    /// lateral diffusion of the developer in the layer, in pixels squared
+    /// per unit of development time
+    pub developer_diffusion: f32,
+    /// how sheet film is agitated; by hand in a tray or tank the developer
+    /// surges at the edges and drags below dense areas
+    pub agitation: Agitation,
+    /// strength of hand agitation, 1 for an exchange as fast as
+    /// continuous agitation
+    pub agitation_vigor: f32,
+    /// relative variation of the developer's activity over the frame from
+    /// uneven flow, 0 for perfectly even development
+    pub development_mottle: f32,
+    /// temperature of the developer in °C as this frame goes in
+    pub developer_celsius: f32,
+    /// temperature of the room in °C, which the bath drifts toward over a
+    /// batch of frames
+    pub ambient_celsius: f32,
+    /// minutes for the bath's difference from the room to fall by a
+    /// factor e
+    pub bath_time_constant: f32,
+    /// minutes from one frame of a batch going into the bath to the next
+    pub batch_interval: f32,
+
+    /// fraction of exposure scattered back from the base as halation
+    pub halation_strength: f32,
+    /// spread of the halation glow in pixels
+    pub halation_sigma: f32,
+    /// spread across the halation direction, circular when unset
+    pub halation_sigma_y: Option<f32>,
+    /// direction of the long halation axis in degrees from horizontal
+    pub halation_angle: f32,
+    /// image of a custom halation point spread function, replacing the Gaussian
+    pub halation_psf: Option<PathBuf>,
+    /// EXR to write the halation glow alone to, as added to the exposure,
+    /// for inspection or compositing at another strength
+    pub halation_export: Option<PathBuf>,
+    /// how the halation and irradiation spreads are applied
+    pub convolution: Convolution,
+
+    /// only process this region of the input
+    pub crop: Option<Rect>,
+    /// paste the processed region back into the full input frame
+    pub crop_paste: bool,
+
+    /// grains are placed and rendered on a grid this many times finer
+    /// than the output, then downsampled
+    pub supersample: u32,
+    /// filter used to bring the supersampled render down to output size
+    pub downsample_filter: Filter,
+    /// appearance of developed grains in the render
+    pub grain_renderer: Arc<dyn GrainRenderer>,
+    /// density of the clear film base behind the rendered grains
+    pub background_density: f32,
+    /// colour of the clear base against white, per channel
+    pub background_tint: [f32; 3],
+    /// completeness of the final wash, 1 for one that carries away all of
+    /// the stock's residual sensitizing dye
+    pub washing: f32,
+    /// show the render as a negative or inverted to a positive
+    pub polarity: Polarity,
+    /// mapping of rendered density to output, the renderer's own when
+    /// unset
+    pub transfer: Option<Transfer>,
+    /// render the noise-free expected value instead of simulating grains
+    pub expected_value: bool,
+    /// how the negative is written out, following the stock when unset
+    pub output_encoding: Option<OutputEncoding>,
+    /// spectral response densities are reported with
+    pub densitometer: Densitometer,
+    /// peak density of the calcium streaks and spots left by drying with
+    /// hard water, 0 for a clean negative
+    pub drying_marks: f32,
+    /// fingerprints on the emulsion
+    pub fingerprints: usize,
+    /// density added on the ridges of a fingerprint
+    pub fingerprint_density: f32,
+    /// density of the silver mirror aged film grows on its densest areas
+    /// toward the edges, 0 for fresh film
+    pub silvering: f32,
+    /// dust and fibres on the negative in the scanner, 0 for a clean one
+    pub scan_dust: u32,
+    /// remove dust with the scanner's infrared channel, which fails on
+    /// silver images
+    pub infrared_cleaning: bool,
+    /// strength of the scanner's unsharp mask on the display image, 0 for
+    /// an unsharpened scan; density output is never sharpened
+    pub sharpen_amount: f32,
+    /// radius in output pixels of the unsharp mask
+    pub sharpen_radius: f32,
+    /// detail up to this many times the grain noise is left unsharpened,
+    /// 0 sharpens the grain along with the image
+    pub sharpen_protection: f32,
+    /// show the negative as an ambrotype, a direct positive seen against
+    /// a black backing
+    pub ambrotype: bool,
+    /// show the final image as projected onto a screen instead of scanned
+    pub projection: bool,
+    /// colour temperature of the projector lamp in kelvin
+    pub lamp_kelvin: f32,
+    /// share of the projected light the lens scatters over the screen
+    pub lens_flare: f32,
+    /// brightness of the screen relative to a matte white one
+    pub screen_gain: f32,
+    /// room light on the screen, relative to the open gate
+    pub ambient_flare: f32,
+    /// contact print the negative onto a fresh sheet of its own stock,
+    /// turning a paper negative into a positive; display output only
+    pub contact_print: bool,
+    /// exposure of the contact print, in `exposure_time` units
+    pub print_exposure_time: f32,
+    /// years the finished print or slide is kept before it is seen, its
+    /// dyes fading and the paper staining; 0 for a fresh one
+    pub print_age_years: f32,
+    /// how the print or slide is kept while it ages
+    pub print_keeping: Keeping,
+    /// burn the stock, exposure, development and seed into the display
+    /// output, none when unset
+    pub burn_in: Option<BurnIn>,
+
+    /// film format the input is fitted to, setting the physical frame
+    /// size unless `format_width_mm` or `emulsion_width` is given
+    pub frame: Option<FrameFormat>,
+    /// how an input of another aspect is fitted to the frame
+    pub frame_fit: FrameFit,
+    /// physical width of the film frame in millimetres; together with
+    /// `grain_pitch_um` this fixes the emulsion resolution
+    pub format_width_mm: Option<f32>,
+    /// spacing of the emulsion sampling grid in microns
+    pub grain_pitch_um: f32,
+    /// explicit emulsion width in pixels, overriding the physical size
+    pub emulsion_width: Option<u32>,
+    /// width of the rendered output, the input width when unset
+    pub output_width: Option<u32>,
+
+    /// file to write the latent image to before development
+    pub latent_export: Option<PathBuf>,
+    /// latent image to develop instead of exposing the input
+    pub latent_import: Option<PathBuf>,
+
+    /// directory to write intermediate stage fields into
+    pub dump_stages: Option<PathBuf>,
+    /// CSV file to record the development of sampled grains into
+    pub development_history: Option<PathBuf>,
+    /// grains sampled for the development history
+    pub history_grains: usize,
+    /// development steps between history samples
+    pub history_every: usize,
+    /// only sample grains in this region of the processed frame
+    pub history_region: Option<Rect>,
+    /// follow the photons through the stages and report where they went
+    pub photon_accounting: bool,
+    /// stem of a PNG sequence showing the image coming up during
+    /// development, written as `STEM-001.png` onwards
+    pub timelapse: Option<PathBuf>,
+    /// frames of the time-lapse before the final one
+    pub timelapse_frames: usize,
+
+    /// grayscale mask restricting where the simulation acts
+    pub mask: Option<PathBuf>,
+    /// stages the mask applies to
+    pub mask_targets: MaskTargets,
+    /// blend the result with the input through the mask
+    pub mask_composite: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Stages a mask can restrict
+pub struct MaskTargets {
+    /// scale the halation glow received by each pixel
+    pub halation: bool,
+    /// scale the light reaching each grain
+    pub exposure: bool,
+    /// scale the developer strength acting on each grain
+    pub development: bool,
+}
+
+impl Default for MaskTargets {
+    fn default() -> Self {
+        Self { halation: true, exposure: true, development: false }
+    }
+}
+
+impl MaskTargets {
+    /// Parse a comma separated list of `halation`, `exposure`, `development` or `all`
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut targets = Self { halation: false, exposure: false, development: false };
+        for name in text.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match name {
+                "halation" => {
+                    targets.halation = true;
+                }
+                "exposure" => {
+                    targets.exposure = true;
+                }
+                "development" => {
+                    targets.development = true;
+                }
+                "all" => {
+                    targets = Self { halation: true, exposure: true, development: true };
+                }
+                _ => {
+                    return Err(Error::Parse(format!("unknown mask target '{name}'")));
+                }
+            }
+        }
+        Ok(targets)
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            num_grains: 10_000_000,
+            grains_per_pixel: None,
+            adaptive_budget: None,
+            band_rows: None,
+            threads: None,
+            stage_threads: Vec::new(),
+            single_thread: false,
+            seed: None,
+            grain_seed: None,
+            grain_reuse_frames: 1,
+            exposure_time: None,
+            lens_filter: None,
+            filter_factor: None,
+            nd_stops: 0.0,
+            nd_cast: [1.0; 3],
+            nd_compensated: true,
+            polarizer: 0.0,
+            polarizer_mask: None,
+            exposure_sampling: ExposureSampling::PerGrain,
+            iso: None,
+            film_plane_lux: None,
+            scene_ev: None,
+            f_number: 8.0,
+            stock: Stock::default(),
+            expired_years: 0.0,
+            storage: Storage::Room,
+            xray_scans: 0,
+            xray_dose: 0.3,
+            heat_fog: 0.0,
+            cosmic_rate: 0.5,
+            static_marks: 0,
+            static_dose: 2.0,
+            dye_uptake_variation: 0.2,
+            coating_thickness_um: 0.0,
+            light_attenuation: 0.1,
+            irradiation_um: 0.0,
+            developer_penetration_um: 8.0,
+            light_profile: LightProfile::Constant,
+            shutter: Shutter::Leaf,
+            shutter_seconds: 1.0 / 125.0,
+            light_phase: 0.0,
+            swing_angle: 140.0,
+            swing_banding: 0.03,
+            exposure_seconds: None,
+            clayden_exposure: 0.0,
+            clayden_pattern: None,
+            herschel_exposure: 0.0,
+            herschel_efficiency: 0.3,
+            safelight: Safelight::Amber,
+            safelight_lux: 0.1,
+            safelight_minutes: 0.0,
+            pour_artifacts: 0.0,
+            edge_fog: 0.0,
+            developer: Developer {
+                strength: 0.1,
+                max_development: 1.0,
+            },
+            development_model: Arc::new(FirstOrder),
+            development_time: 0.1,
+            dt: 0.1,
+            water_bath_cycles: 0,
+            water_rest: 1.0,
+            developer_capacity: 0.05,
+            developer_diffusion: 2.0,
+            agitation: Agitation::Continuous,
+            agitation_vigor: 0.3,
+            development_mottle: 0.0,
+            developer_celsius: 20.0,
+            ambient_celsius: 20.0,
+            bath_time_constant: 30.0,
+            batch_interval: 10.0,
+            halation_strength: 0.0,
+            halation_sigma: 8.0,
+            halation_sigma_y: None,
+            halation_angle: 0.0,
+            halation_psf: None,
+            halation_export: None,
+            convolution: Convolution::Direct,
+            crop: None,
+            crop_paste: false,
+            supersample: 1,
+            downsample_filter: Filter::Box,
+            grain_renderer: Arc::new(Point),
+            background_density: 0.0,
+            background_tint: [1.0; 3],
+            washing: 1.0,
+            polarity: Polarity::Negative,
+            transfer: None,
+            expected_value: false,
+            output_encoding: None,
+            densitometer: Densitometer::Visual,
+            drying_marks: 0.0,
+            fingerprints: 0,
+            fingerprint_density: 0.15,
+            silvering: 0.0,
+            scan_dust: 0,
+            infrared_cleaning: false,
+            sharpen_amount: 0.0,
+            sharpen_radius: 1.5,
+            sharpen_protection: 2.0,
+            ambrotype: false,
+            projection: false,
+            lamp_kelvin: 3200.0,
+            lens_flare: 0.02,
+            screen_gain: 1.0,
+            ambient_flare: 0.01,
+            contact_print: false,
+            print_exposure_time: 100.0,
+            print_age_years: 0.0,
+            print_keeping: Keeping::Dark,
+            burn_in: None,
+            frame: None,
+            frame_fit: FrameFit::Crop,
+            format_width_mm: None,
+            grain_pitch_um: 2.0,
+            emulsion_width: None,
+            output_width: None,
+            latent_export: None,
+            latent_import: None,
+            dump_stages: None,
+            development_history: None,
+            history_grains: 1000,
+            history_every: 10,
+            history_region: None,
+            photon_accounting: false,
+            timelapse: None,
+            timelapse_frames: 24,
+            mask: None,
+            mask_targets: MaskTargets::default(),
+            mask_composite: false,
+        }
+    }
+}
+
+impl Params {
+    /// Set a single parameter by name from its textual value. Dashes and
+    /// underscores are interchangeable so CLI flags map straight onto keys.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let key = key.replace('-', "_");
+        let key = key.as_str();
+        match key {
+            "num_grains" => {
+                self.num_grains = parse_value(key, value)?;
+            }
+            "grains_per_pixel" => {
+                self.grains_per_pixel = parse_optional(key, value)?;
+            }
+            "adaptive_budget" => {
+                self.adaptive_budget = parse_optional(key, value)?;
+            }
+            "band_rows" => {
+                self.band_rows = parse_optional(key, value)?;
+            }
+            "threads" => {
+                self.threads = parse_optional(key, value)?;
+            }
+            "stage_threads" => {
+                self.stage_threads = parallel::parse_stage_threads(value)?;
+            }
+            "single_thread" => {
+                self.single_thread = parse_bool(key, value)?;
+            }
+            "seed" => {
+                self.seed = parse_optional(key, value)?;
+            }
+            "grain_seed" => {
+                self.grain_seed = parse_optional(key, value)?;
+            }
+            "grain_reuse_frames" => {
+                self.grain_reuse_frames = parse_value::<u64>(key, value)?.max(1);
+            }
+            "exposure_time" => {
+                self.exposure_time = parse_optional(key, value)?;
+            }
+            "exposure_sampling" => {
+                self.exposure_sampling = ExposureSampling::parse(value)?;
+            }
+            "iso" => {
+                self.iso = parse_optional(key, value)?;
+            }
+            "film_plane_lux" => {
+                self.film_plane_lux = parse_optional(key, value)?;
+            }
+            "scene_ev" => {
+                self.scene_ev = parse_optional(key, value)?;
+            }
+            "f_number" => {
+                self.f_number = parse_value(key, value)?;
+            }
+            "process" => {
+                self.apply_process(value)?;
+            }
+            "processing" => {
+                self.apply_processing(value)?;
+            }
+            "quality" => {
+                self.apply_quality(value)?;
+            }
+            "params" => {
+                self.apply_file(std::path::Path::new(value.trim()))?;
+            }
+            "stock" => {
+                self.stock = Stock::preset(value)?;
+            }
+            "expired_years" => {
+                self.expired_years = parse_value(key, value)?;
+            }
+            "storage" => {
+                self.storage = Storage::parse(value)?;
+            }
+            "xray_scans" => {
+                self.xray_scans = parse_value(key, value)?;
+            }
+            "xray_dose" => {
+                self.xray_dose = parse_value(key, value)?;
+            }
+            "heat_fog" => {
+                self.heat_fog = parse_value(key, value)?;
+            }
+            "cosmic_rate" => {
+                self.cosmic_rate = parse_value(key, value)?;
+            }
+            "static_marks" => {
+                self.static_marks = parse_value(key, value)?;
+            }
+            "static_dose" => {
+                self.static_dose = parse_value(key, value)?;
+            }
+            "crystal" => {
+                self.stock.crystal = CrystalComposition::parse(value)?;
+            }
+            "chemical_sensitization" => {
+                self.stock.chemical_sensitization = parse_value(key, value)?;
+            }
+            "reciprocity_exponent" => {
+                self.stock.reciprocity_exponent = parse_value(key, value)?;
+            }
+            "base_density" => {
+                self.stock.base_density = parse_value::<f32>(key, value)?.max(0.0);
+            }
+            "base_tint" => {
+                self.stock.base_tint = Look::parse_tint(value)?;
+            }
+            "residual_dye" => {
+                self.stock.residual_dye = parse_value::<f32>(key, value)?.max(0.0);
+            }
+            "sensitization" => {
+                self.stock.sensitivity = SpectralSensitivity::parse(value)?;
+            }
+            "dye_uptake_variation" => {
+                self.dye_uptake_variation = parse_value(key, value)?;
+            }
+            "coating_thickness_um" => {
+                self.coating_thickness_um = parse_value(key, value)?;
+            }
+            "light_attenuation" => {
+                self.light_attenuation = parse_value(key, value)?;
+            }
+            "irradiation_um" => {
+                self.irradiation_um = parse_value(key, value)?;
+            }
+            "developer_penetration_um" => {
+                self.developer_penetration_um = parse_value(key, value)?;
+            }
+            "light_profile" => {
+                self.light_profile = LightProfile::parse(value)?;
+            }
+            "shutter" => {
+                self.shutter = Shutter::parse(value)?;
+            }
+            "shutter_seconds" => {
+                self.shutter_seconds = parse_value(key, value)?;
+            }
+            "lens_filter" => {
+                self.lens_filter = match value.trim() {
+                    "" | "none" => None,
+                    value => Some(LensFilter::parse(value)?),
+                };
+            }
+            "filter_factor" => {
+                self.filter_factor = parse_optional(key, value)?;
+            }
+            "nd_stops" => {
+                self.nd_stops = parse_value(key, value)?;
+            }
+            "nd_cast" => {
+                self.nd_cast = Look::parse_tint(value)?;
+            }
+            "nd_compensated" => {
+                self.nd_compensated = parse_bool(key, value)?;
+            }
+            "polarizer" => {
+                self.polarizer = parse_value(key, value)?;
+            }
+            "polarizer_mask" => {
+                self.polarizer_mask = parse_path(value);
+            }
+            "light_phase" => {
+                self.light_phase = parse_value(key, value)?;
+            }
+            "swing_angle" => {
+                self.swing_angle = parse_value(key, value)?;
+            }
+            "swing_banding" => {
+                self.swing_banding = parse_value(key, value)?;
+            }
+            "exposure_seconds" => {
+                self.exposure_seconds = parse_optional(key, value)?;
+            }
+            "clayden_exposure" => {
+                self.clayden_exposure = parse_value(key, value)?;
+            }
+            "clayden_pattern" => {
+                self.clayden_pattern = parse_path(value);
+            }
+            "herschel_exposure" => {
+                self.herschel_exposure = parse_value(key, value)?;
+            }
+            "herschel_efficiency" => {
+                self.herschel_efficiency = parse_value(key, value)?;
+            }
+            "safelight" => {
+                self.safelight = Safelight::parse(value)?;
+            }
+            "safelight_lux" => {
+                self.safelight_lux = parse_value(key, value)?;
+            }
+            "safelight_minutes" => {
+                self.safelight_minutes = parse_value(key, value)?;
+            }
+            "pour_artifacts" => {
+                self.pour_artifacts = parse_value(key, value)?;
+            }
+            "edge_fog" => {
+                self.edge_fog = parse_value(key, value)?;
+            }
+            "developer_strength" => {
+                self.developer.strength = parse_value(key, value)?;
+            }
+            "max_development" => {
+                self.developer.max_development = parse_value(key, value)?;
+            }
+            "development_model" => {
+                self.development_model = development::parse(value)?;
+            }
+            "development_time" => {
+                self.development_time = parse_value(key, value)?;
+            }
+            "dt" => {
+                self.dt = parse_value(key, value)?;
+            }
+            "water_bath_cycles" => {
+                self.water_bath_cycles = parse_value(key, value)?;
+            }
+            "water_rest" => {
+                self.water_rest = parse_value(key, value)?;
+            }
+            "developer_capacity" => {
+                self.developer_capacity = parse_value(key, value)?;
+            }
+            "developer_diffusion" => {
+                self.developer_diffusion = parse_value(key, value)?;
+            }
+            "agitation" => {
+                self.agitation = Agitation::parse(value)?;
+            }
+            "agitation_vigor" => {
+                self.agitation_vigor = parse_value(key, value)?;
+            }
+            "development_mottle" => {
+                self.development_mottle = parse_value(key, value)?;
+            }
+            "developer_celsius" => {
+                self.developer_celsius = parse_value(key, value)?;
+            }
+            "ambient_celsius" => {
+                self.ambient_celsius = parse_value(key, value)?;
+            }
+            "bath_time_constant" => {
+                self.bath_time_constant = parse_value(key, value)?;
+            }
+            "batch_interval" => {
+                self.batch_interval = parse_value(key, value)?;
+            }
+            "halation_strength" => {
+                self.halation_strength = parse_value(key, value)?;
+            }
+            "halation_sigma" => {
+                self.halation_sigma = parse_value(key, value)?;
+            }
+            "halation_sigma_y" => {
+                self.halation_sigma_y = parse_optional(key, value)?;
+            }
+            "halation_angle" => {
+                self.halation_angle = parse_value(key, value)?;
+            }
+            "halation_psf" => {
+                self.halation_psf = parse_path(value);
+            }
+            "halation_export" => {
+                self.halation_export = parse_path(value);
+            }
+            "convolution" => {
+                self.convolution = Convolution::parse(value)?;
+            }
+            "crop" => {
+                self.crop = match value.trim() {
+                    "" | "none" => None,
+                    rect => Some(Rect::parse(rect)?),
+                };
+            }
+            "crop_paste" => {
+                self.crop_paste = parse_bool(key, value)?;
+            }
+            "supersample" => {
+                self.supersample = parse_value::<u32>(key, value)?.max(1);
+            }
+            "downsample_filter" => {
+                self.downsample_filter = Filter::parse(value)?;
+            }
+            "grain_renderer" => {
+                self.grain_renderer = render::parse(value)?;
+            }
+            "background_density" => {
+                self.background_density = parse_value::<f32>(key, value)?.max(0.0);
+            }
+            "background_tint" => {
+                self.background_tint = Look::parse_tint(value)?;
+            }
+            "washing" => {
+                self.washing = parse_value::<f32>(key, value)?.clamp(0.0, 1.0);
+            }
+            "polarity" => {
+                self.polarity = Polarity::parse(value)?;
+            }
+            "transfer" => {
+                self.transfer = match value.trim() {
+                    "" | "none" | "native" => None,
+                    value => Some(Transfer::parse(value)?),
+                };
+            }
+            "expected_value" => {
+                self.expected_value = parse_bool(key, value)?;
+            }
+            "output_encoding" => {
+                self.output_encoding = match value.trim() {
+                    "" | "none" | "stock" => None,
+                    value => Some(OutputEncoding::parse(value)?),
+                };
+            }
+            "densitometer" => {
+                self.densitometer = Densitometer::parse(value)?;
+            }
+            "drying_marks" => {
+                self.drying_marks = parse_value(key, value)?;
+            }
+            "fingerprints" => {
+                self.fingerprints = parse_value(key, value)?;
+            }
+            "fingerprint_density" => {
+                self.fingerprint_density = parse_value(key, value)?;
+            }
+            "silvering" => {
+                self.silvering = parse_value(key, value)?;
+            }
+            "scan_dust" => {
+                self.scan_dust = parse_value(key, value)?;
+            }
+            "infrared_cleaning" => {
+                self.infrared_cleaning = parse_bool(key, value)?;
+            }
+            "sharpen_amount" => {
+                self.sharpen_amount = parse_value(key, value)?;
+            }
+            "sharpen_radius" => {
+                self.sharpen_radius = parse_value(key, value)?;
+            }
+            "sharpen_protection" => {
+                self.sharpen_protection = parse_value(key, value)?;
+            }
+            "ambrotype" => {
+                self.ambrotype = parse_bool(key, value)?;
+            }
+            "projection" => {
+                self.projection = parse_bool(key, value)?;
+            }
+            "lamp_kelvin" => {
+                self.lamp_kelvin = parse_value(key, value)?;
+            }
+            "lens_flare" => {
+                self.lens_flare = parse_value(key, value)?;
+            }
+            "screen_gain" => {
+                self.screen_gain = parse_value(key, value)?;
+            }
+            "ambient_flare" => {
+                self.ambient_flare = parse_value(key, value)?;
+            }
+            "contact_print" => {
+                self.contact_print = parse_bool(key, value)?;
+            }
+            "print_exposure_time" => {
+                self.print_exposure_time = parse_value(key, value)?;
+            }
+            "print_age_years" => {
+                self.print_age_years = parse_value(key, value)?;
+            }
+            "print_keeping" => {
+                self.print_keeping = Keeping::parse(value)?;
+            }
+            "burn_in" => {
+                self.burn_in = match value.trim() {
+                    "" | "none" => None,
+                    value => Some(BurnIn::parse(value)?),
+                };
+            }
+            "frame" => {
+                self.frame = match value.trim() {
+                    "" | "none" => None,
+                    value => Some(FrameFormat::parse(value)?),
+                };
+            }
+            "frame_fit" => {
+                self.frame_fit = FrameFit::parse(value)?;
+            }
+            "format_width_mm" => {
+                self.format_width_mm = parse_optional(key, value)?;
+            }
+            "grain_pitch_um" => {
+                self.grain_pitch_um = parse_value(key, value)?;
+            }
+            "emulsion_width" => {
+                self.emulsion_width = parse_optional(key, value)?;
+            }
+            "output_width" => {
+                self.output_width = parse_optional(key, value)?;
+            }
+            "latent_export" => {
+                self.latent_export = parse_path(value);
+            }
+            "latent_import" => {
+                self.latent_import = parse_path(value);
+            }
+            "dump_stages" => {
+                self.dump_stages = parse_path(value);
+            }
+            "development_history" => {
+                self.development_history = parse_path(value);
+            }
+            "history_grains" => {
+                self.history_grains = parse_value(key, value)?;
+            }
+            "history_every" => {
+                self.history_every = parse_value(key, value)?;
+            }
+            "photon_accounting" => {
+                self.photon_accounting = parse_bool(key, value)?;
+            }
+            "history_region" => {
+                self.history_region = match value.trim() {
+                    "" | "none" => None,
+                    rect => Some(Rect::parse(rect)?),
+                };
+            }
+            "timelapse" => {
+                self.timelapse = parse_path(value);
+            }
+            "timelapse_frames" => {
+                self.timelapse_frames = parse_value(key, value)?;
+            }
+            "mask" => {
+                self.mask = parse_path(value);
+            }
+            "mask_targets" => {
+                self.mask_targets = MaskTargets::parse(value)?;
+            }
+            "mask_composite" => {
+                self.mask_composite = parse_bool(key, value)?;
+            }
+            _ => {
+                return Err(Error::Parse(format!("unknown parameter '{key}'")));
+            }
+        }
+        let exposure = matches!(key, "exposure_time" | "film_plane_lux" | "scene_ev");
+        if exposure && self.exposure_time.is_some() && self.metered_illuminance().is_some() {
+            return Err(
+                Error::Parse(
+                    "exposure_time cannot be combined with film_plane_lux or scene_ev, which set the exposure".into()
+                )
+            );
+        }
+        Ok(())
+    }
+
+    /// Set the parameters of a historic process at once. Exposure is left
+    /// alone, so slow processes need a correspondingly longer one.
+    pub fn apply_process(&mut self, name: &str) -> Result<()> {
+        let settings: &[(&str, &str)] = match name.trim() {
+            // collodion on glass, developed with a fast iron developer for
+            // a steep curve, with halation from the back of the glass
+            "wet-plate" => &[
+                ("stock", "wet-plate"),
+                ("developer_strength", "0.3"),
+                ("halation_strength", "0.15"),
+                ("halation_sigma", "12"),
+                ("pour_artifacts", "1"),
+                ("edge_fog", "0.4"),
+            ],
+            "ambrotype" => {
+                self.apply_process("wet-plate")?;
+                &[("ambrotype", "true")]
+            }
+            // photographic paper in the camera, typically a pinhole one,
+            // contact printed onto the same paper for the positive
+            "paper-negative" => &[
+                ("stock", "bromide-paper"),
+                ("contact_print", "true"),
+            ],
+            _ => {
+                return Err(
+                    Error::Parse(
+                        format!("unknown process '{name}', expected wet-plate, ambrotype or paper-negative")
+                    )
+                );
+            }
+        };
+        for (key, value) in settings {
+            self.set(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Set the agitation of a way of processing film at once. Developer and
+    /// time are left alone, so the same development can be compared across
+    /// them.
+    pub fn apply_processing(&mut self, name: &str) -> Result<()> {
+        let settings: &[(&str, &str)] = match name.trim() {
+            // a drum turning on rollers, the film bathed in a thin, always
+            // moving layer of developer
+            "rotary" | "jobo" => &[
+                ("agitation", "continuous"),
+                ("developer_diffusion", "4"),
+                ("development_mottle", "0.01"),
+            ],
+            // a reel in a small tank, inverted a few times each minute and
+            // standing still in between
+            "inversion" => &[
+                ("agitation", "tank"),
+                ("agitation_vigor", "0.4"),
+                ("developer_diffusion", "2"),
+                ("development_mottle", "0.05"),
+            ],
+            // film on hangers lowered into deep tanks, agitated by bursts of
+            // nitrogen rising through the developer
+            "dip-and-dunk" => &[
+                ("agitation", "tank"),
+                ("agitation_vigor", "0.8"),
+                ("developer_diffusion", "3"),
+                ("development_mottle", "0.03"),
+            ],
+            _ => {
+                return Err(
+                    Error::Parse(
+                        format!("unknown processing '{name}', expected rotary, inversion or dip-and-dunk")
+                    )
+                );
+            }
+        };
+        for (key, value) in settings {
+            self.set(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Trade speed for fidelity at once: how many grains there are, how
+    /// finely they are rendered, how the spreads are convolved, and whether
+    /// the grains are simulated at all. Settings given after it still
+    /// override its choices.
+    pub fn apply_quality(&mut self, name: &str) -> Result<()> {
+        let settings: &[(&str, &str)] = match name.trim() {
+            // the noise-free expected value, for framing and tone
+            "draft" => &[
+                ("expected_value", "true"),
+                ("num_grains", "1000000"),
+                ("supersample", "1"),
+                ("downsample_filter", "box"),
+                ("convolution", "separable"),
+                ("exposure_sampling", "per-grain"),
+            ],
+            // the defaults
+            "standard" => &[
+                ("expected_value", "false"),
+                ("num_grains", "10000000"),
+                ("supersample", "1"),
+                ("downsample_filter", "box"),
+                ("convolution", "direct"),
+                ("exposure_sampling", "per-grain"),
+            ],
+            // grains resolved below the output pixel
+            "high" => &[
+                ("expected_value", "false"),
+                ("num_grains", "20000000"),
+                ("supersample", "2"),
+                ("downsample_filter", "lanczos"),
+                ("convolution", "direct"),
+                ("exposure_sampling", "per-grain"),
+            ],
+            // photons splatted onto whichever grain they land on, so grains
+            // shadow one another
+            "reference" => &[
+                ("expected_value", "false"),
+                ("num_grains", "40000000"),
+                ("supersample", "3"),
+                ("downsample_filter", "lanczos"),
+                ("convolution", "direct"),
+                ("exposure_sampling", "splat"),
+            ],
+            _ => {
+                return Err(
+                    Error::Parse(
+                        format!("unknown quality '{name}', expected draft, standard, high or reference")
+                    )
+                );
+            }
+        };
+        for (key, value) in settings {
+            self.set(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Apply every member of a flat JSON object with [`Params::set`]
+    pub fn apply_json(&mut self, value: &json::Value) -> Result<()> {
+        let json::Value::Object(members) = value else {
+            return Err(Error::Parse("parameters must be a json object".into()));
+        };
+        for (key, value) in members {
+            let text = value
+                .as_param()
+                .ok_or_else(|| Error::Parse(format!("parameter '{key}' must be a scalar")))?;
+            self.set(key, &text)?;
+        }
+        Ok(())
+    }
+
+    /// Apply a parameter file, a flat TOML table when its extension says so
+    /// and a JSON object otherwise
+    pub fn apply_file(&mut self, path: &std::path::Path) -> Result<()> {
+        let text = std::fs::read_to_string(path)?;
+        let is_toml = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+        self.apply_json(&(if is_toml { toml::parse(&text)? } else { json::parse(&text)? }))
+    }
+
+    /// How the rendered density is shown
+    pub fn look(&self) -> Look {
+        let base = self.stock.base_densities(self.washing);
+        Look {
+            background: [0, 1, 2].map(|c| {
+                self.background_density - self.background_tint[c].log10() + base[c]
+            }),
+            polarity: self.polarity,
+            transfer: self.transfer,
+        }
+    }
+
+    /// Fog left over the frame by travel and storage, `None` without any.
+    /// Cosmic rays pass through any storage, so only the years count.
+    pub fn fog_pattern(&self) -> Option<FogPattern> {
+        let pattern = FogPattern {
+            xray_scans: self.xray_scans,
+            xray_dose: self.xray_dose,
+            heat_fog: self.heat_fog,
+            specks_per_mm2: self.cosmic_rate * self.expired_years.max(0.0),
+        };
+        (!pattern.is_empty()).then_some(pattern)
+    }
+
+    /// Gains of the input's red, green and blue channels through the lens
+    /// filter, including its filter factor, and the ND filter, none
+    /// without either
+    pub fn filter_gains(&self) -> Option<[f32; 3]> {
+        if self.lens_filter.is_none() && self.nd_stops == 0.0 && self.nd_cast == [1.0; 3] {
+            return None;
+        }
+        let lens = self.lens_filter.map_or([1.0; 3], |filter| {
+            let sensitivity = self.stock.spectral_sensitivity();
+            let factor = self.filter_factor.unwrap_or_else(|| filter.factor(&sensitivity));
+            filter.channel_transmission(&sensitivity).map(|t| t * factor)
+        });
+        let nd = if self.nd_compensated { 1.0 } else { (2.0f32).powf(-self.nd_stops) };
+        Some([0, 1, 2].map(|c| lens[c] * self.nd_cast[c] * nd))
+    }
+
+    /// Real duration of the exposure in seconds, which reciprocity
+    /// failure follows
+    pub fn exposure_duration(&self) -> f32 {
+        let seconds = self.exposure_seconds.unwrap_or(self.shutter_seconds);
+        if self.nd_compensated { seconds * (2.0f32).powf(self.nd_stops) } else { seconds }
+    }
+
+    /// Halation strength left after the stock's anti-halation backing
+    pub fn effective_halation_strength(&self) -> f32 {
+        self.halation_strength * (1.0 - self.stock.anti_halation.clamp(0.0, 1.0))
+    }
+
+    /// Sensitivity of the stock over this run's exposure, after any
+    /// reciprocity failure
+    pub fn effective_sensitivity(&self) -> f32 {
+        self.stock.sensitivity() * self.stock.reciprocity_factor(self.exposure_duration())
+    }
+
+    /// Output encoding of this run, the stock's when none was chosen
+    pub fn encoding(&self) -> OutputEncoding {
+        self.output_encoding.unwrap_or(
+            if self.stock.log_density { OutputEncoding::Cineon } else { OutputEncoding::Display }
+        )
+    }
+
+    /// Point spread function of the halation pass, `None` when it is disabled
+    pub fn halation_kernel(&self) -> Result<Option<Kernel>> {
+        if self.effective_halation_strength() <= 0.0 {
+            return Ok(None);
+        }
+        match &self.halation_psf {
+            Some(path) => Kernel::load(path).map(Some),
+            None =>
+                Ok(
+                    Some(match self.halation_sigma_y {
+                        Some(sigma_y) =>
+                            Kernel::anisotropic_gaussian(
+                                self.halation_sigma,
+                                sigma_y,
+                                self.halation_angle
+                            ),
+                        None => Kernel::gaussian(self.halation_sigma),
+                    })
+                ),
+        }
+    }
+
+    /// Point spread of the irradiation in input pixels of a frame
+    /// `input_width` wide, `None` when it is disabled
+    pub fn irradiation_kernel(&self, input_width: u32) -> Option<Kernel> {
+        let sigma = (self.irradiation_um * self.emulsion_scale(input_width)) / self.grain_pitch_um.max(f32::EPSILON);
+        (sigma > 0.0).then(|| Kernel::gaussian(sigma))
+    }
+
+    /// Emulsion pixels per input pixel for a frame `input_width` wide
+    pub fn emulsion_scale(&self, input_width: u32) -> f32 {
+        let width = match (self.emulsion_width, self.format_width_mm) {
+            (Some(width), _) => width as f32,
+            (None, Some(mm)) if self.grain_pitch_um > 0.0 => (mm * 1000.0) / self.grain_pitch_um,
+            _ => {
+                return 1.0;
+            }
+        };
+        width / (input_width.max(1) as f32)
+    }
+
+    /// Output pixels per input pixel for a frame `input_width` wide
+    pub fn output_scale(&self, input_width: u32) -> f32 {
+        self.output_width.map_or(1.0, |width| (width as f32) / (input_width.max(1) as f32))
+    }
+
+    /// Number of development steps needed to cover `development_time`,
+    /// and the rests of a water bath
+    pub fn development_steps(&self) -> usize {
+        if self.dt <= 0.0 {
+            return 0;
+        }
+        let total = self.water_bath().map_or(self.development_time, |bath| bath.total_time());
+        (total / self.dt).ceil().max(0.0) as usize
+    }
+
+    /// Projector the final image is shown through
+    pub fn projector(&self) -> Projector {
+        Projector {
+            lamp_kelvin: self.lamp_kelvin,
+            lens_flare: self.lens_flare.clamp(0.0, 1.0),
+            screen_gain: self.screen_gain.max(0.0),
+            ambient_flare: self.ambient_flare.max(0.0),
+        }
+    }
+
+    /// Film plane illuminance of an input value of 1 when the run gives
+    /// one, directly or from the scene's exposure value
+    pub fn metered_illuminance(&self) -> Option<Lux> {
+        match (self.film_plane_lux, self.scene_ev) {
+            (Some(lux), _) => Some(Lux(lux)),
+            (None, Some(ev)) => Some(Lux(Lux::metered(ev, self.f_number).0 / MIDDLE_GRAY)),
+            (None, None) => None,
+        }
+    }
+
+    /// Film plane illuminance of an input value of 1, the reference one
+    /// unless the run gives its own
+    pub fn input_illuminance(&self) -> Lux {
+        self.metered_illuminance().unwrap_or(sensitometry::REFERENCE_LUX)
+    }
+
+    /// Exposure time the input intensity is integrated over: the one given,
+    /// else the real photon count of `shutter_seconds` under a metered
+    /// illuminance when there is no ISO speed to calibrate for, else
+    /// [`DEFAULT_EXPOSURE_TIME`]
+    pub fn exposure_time(&self) -> f32 {
+        match (self.exposure_time, self.iso, self.metered_illuminance()) {
+            (Some(exposure_time), _, _) => exposure_time,
+            (None, None, Some(illuminance)) =>
+                ExposureScale::PHYSICAL.exposure_time(illuminance, Seconds(self.shutter_seconds)),
+            _ => DEFAULT_EXPOSURE_TIME,
+        }
+    }
+
+    /// Simulation exposure units per lux second the exposure time stands for
+    pub fn exposure_scale(&self) -> ExposureScale {
+        ExposureScale::implied(self.exposure_time(), self.input_illuminance(), Seconds(self.shutter_seconds))
+    }
+
+    /// The developer bath as mixed for the first frame of a batch
+    pub fn bath(&self) -> Bath {
+        Bath {
+            start: self.developer_celsius,
+            ambient: self.ambient_celsius,
+            time_constant: self.bath_time_constant,
+            interval: self.batch_interval,
+        }
+    }
+
+    /// Params of frame `index` of a batch developed one after another in
+    /// the same bath, which has drifted toward the room's temperature
+    pub fn batch_frame(&self, index: usize) -> Params {
+        Params { developer_celsius: self.bath().frame(index), ..self.clone() }
+    }
+
+    /// Params of frame `number` of a sequence seeded with `seed`: a seed of
+    /// its own, and a grain field shared with the rest of its block of
+    /// `grain_reuse_frames` frames
+    pub fn sequence_frame(&self, seed: u64, number: u64) -> Params {
+        let reuse = self.grain_reuse_frames.max(1);
+        Params {
+            seed: Some(random::derive_seed(seed, number)),
+            grain_seed: (reuse > 1).then(|| random::stream_seed(seed, random::GRAIN_FIELD_STREAM, number / reuse)),
+            ..self.clone()
+        }
+    }
+
+    /// Seed of the grain field, see `grain_seed`
+    pub fn grain_field_seed(&self) -> Option<u64> {
+        self.grain_seed.or(self.seed)
+    }
+
+    /// Schedule of a water-bath development, if one is set
+    pub fn water_bath(&self) -> Option<WaterBath> {
+        (self.water_bath_cycles > 0).then(|| WaterBath {
+            cycles: self.water_bath_cycles,
+            developer_time: self.development_time / (self.water_bath_cycles as f32),
+            rest_time: self.water_rest.max(0.0),
+        })
+    }
+}
+
+pub(crate) fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid value '{value}' for '{key}'")))
+}
+
+/// Paths are cleared with an empty value or `none`
+pub(crate) fn parse_path(value: &str) -> Option<PathBuf> {
+    match value.trim() {
+        "" | "none" => None,
+        path => Some(PathBuf::from(path)),
+    }
+}
+
+/// Optional values are cleared with an empty value or `none`
+pub(crate) fn parse_optional<T: std::str::FromStr>(key: &str, value: &str) -> Result<Option<T>> {
+    match value.trim() {
+        "" | "none" => Ok(None),
+        value => parse_value(key, value).map(Some),
+    }
+}
+
+/// Booleans accept `true/false`, `1/0`, `yes/no`, and an empty value as `true`
+/// so switches can be passed without an argument
+pub(crate) fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    match value.trim() {
+        "" | "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err(Error::Parse(format!("invalid value '{value}' for '{key}'"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_exposure_time_is_kept() {
+        let mut params = Params::default();
+        assert_eq!(params.exposure_time(), DEFAULT_EXPOSURE_TIME);
+        params.set("exposure_time", "250").unwrap();
+        params.set("shutter_seconds", "0.5").unwrap();
+        assert_eq!(params.exposure_time(), 250.0);
+    }
+
+    #[test]
+    fn metered_exposure_follows_the_shutter() {
+        let mut params = Params::default();
+        params.set("film_plane_lux", "40").unwrap();
+        params.set("shutter_seconds", "0.25").unwrap();
+        let physical = |seconds| ExposureScale::PHYSICAL.exposure_time(Lux(40.0), Seconds(seconds));
+        assert_eq!(params.exposure_time(), physical(0.25));
+        params.set("shutter_seconds", "0.5").unwrap();
+        assert_eq!(params.exposure_time(), physical(0.5));
+        // a literal gets the same exposure as the keys would give
+        let literal = Params { film_plane_lux: Some(40.0), shutter_seconds: 0.5, ..Params::default() };
+        assert_eq!(literal.exposure_time(), physical(0.5));
+        // an ISO speed is calibrated for instead
+        params.set("iso", "400").unwrap();
+        assert_eq!(params.exposure_time(), DEFAULT_EXPOSURE_TIME);
+    }
+
+    #[test]
+    fn exposure_time_conflicts_with_metered_light() {
+        let mut params = Params::default();
+        params.set("exposure_time", "250").unwrap();
+        assert!(params.set("scene_ev", "12").is_err());
+
+        let mut params = Params::default();
+        params.set("film-plane-lux", "40").unwrap();
+        assert!(params.set("exposure_time", "250").is_err());
+        // clearing the exposure time leaves it to the meter again
+        params.set("exposure_time", "none").unwrap();
+        assert!(params.exposure_time.is_none());
+    }
+}
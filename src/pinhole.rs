@@ -0,0 +1,61 @@
+//! Exposure planning for pinhole cameras: aperture from the pinhole and
+//! focal length, the metered time, and the longer time needed once the
+//! stock's reciprocity fails
+
+use crate::stock::Stock;
+
+/// constant of Rayleigh's optimal pinhole, `d = 1.9 * sqrt(f * λ)`
+const RAYLEIGH: f32 = 1.9;
+/// shutter time at which an input of middle gray is the metered exposure
+/// at ISO 100, the convention the input scale follows
+const METERED_SECONDS_ISO_100: f32 = 1.0 / 125.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pinhole {
+    /// pinhole to film distance in millimetres
+    pub focal_length_mm: f32,
+    /// pinhole diameter in millimetres
+    pub diameter_mm: f32,
+}
+
+impl Pinhole {
+    /// Pinhole balancing diffraction against geometric blur at
+    /// `wavelength_nm`
+    pub fn optimal(focal_length_mm: f32, wavelength_nm: f32) -> Self {
+        let diameter_mm = RAYLEIGH * (focal_length_mm * wavelength_nm * 1e-6).sqrt();
+        Self { focal_length_mm, diameter_mm }
+    }
+
+    pub fn f_number(&self) -> f32 {
+        self.focal_length_mm / self.diameter_mm.max(f32::EPSILON)
+    }
+}
+
+/// Exposure plan for one scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plan {
+    pub f_number: f32,
+    /// time a meter gives at the f-number
+    pub metered_seconds: f32,
+    /// time to expose for, after reciprocity failure
+    pub corrected_seconds: f32,
+    /// `shutter_seconds` giving the simulation the film plane exposure of
+    /// the corrected time; the input does not model the pinhole's aperture,
+    /// so the real duration goes in `exposure_seconds`
+    pub shutter_seconds: f32,
+}
+
+/// Plan the exposure of a scene metering `ev` (exposure value at ISO 100)
+/// on `stock` rated at `iso` through `pinhole`
+pub fn plan(pinhole: &Pinhole, ev: f32, iso: f32, stock: &Stock) -> Plan {
+    let f_number = pinhole.f_number();
+    let metered_seconds = (f_number * f_number) / (2.0f32).powf(ev) * (100.0 / iso.max(f32::EPSILON));
+    let corrected_seconds = stock.reciprocity_corrected(metered_seconds);
+    let metered_shutter = METERED_SECONDS_ISO_100 * (100.0 / iso.max(f32::EPSILON));
+    Plan {
+        f_number,
+        metered_seconds,
+        corrected_seconds,
+        shutter_seconds: metered_shutter * (corrected_seconds / metered_seconds.max(f32::EPSILON)),
+    }
+}
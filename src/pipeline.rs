@@ -0,0 +1,1283 @@
+use std::ops::Range;
+use std::sync::Mutex;
+
+use rand::Rng;
+use rayon::iter::Either;
+use rayon::prelude::*;
+
+use crate::accounting::{ self, Ledger };
+use crate::ageing::Ageing;
+use crate::archival::DyeFade;
+use crate::burnin;
+use crate::cache::{ self, StageCache };
+use crate::contactsheet;
+use crate::defects;
+use crate::diffusion::{ self, Agitation, DeveloperGrid };
+use crate::discharge;
+use crate::dump::{ self, StageDump };
+use crate::emulsion::{ Emulsion, ExposureSampling };
+use crate::error::{ Error, Result };
+use crate::expected;
+use crate::field::{ Field, Rect };
+use crate::filter;
+use crate::frame;
+use crate::halation;
+use crate::halide::{ GrainCluster, Halide };
+use crate::history::History;
+use crate::infrared;
+use crate::latent;
+use crate::parallel::{ Pools, Stage };
+use crate::panoramic;
+use crate::params::Params;
+use crate::plate;
+use crate::projection;
+use crate::render::Polarity;
+use crate::random;
+use crate::resample;
+use crate::sensitometry;
+use crate::sharpen;
+use crate::temperature;
+use crate::temporal::{ self, LightProfile, Shutter };
+use crate::units::{ Lux, Seconds };
+
+/// duration of the Clayden pre-exposure in `exposure_time` units; it is a
+/// short, intense flash, so its strength is set by intensity alone
+const CLAYDEN_DURATION: f32 = 50.0;
+/// cell size of the index the grains under a development mask are found
+/// in, in grid pixels
+const MASK_CELL: u32 = 32;
+
+/// Developed frame, drawn as an image or measured as optical density
+enum Developed {
+    Image(image::RgbaImage),
+    Density(Field),
+}
+
+/// Settings shared by the stages of one simulation
+#[derive(Clone, Copy)]
+struct Run<'a> {
+    params: &'a Params,
+    pools: &'a Pools,
+    /// measure optical density instead of drawing an image
+    as_density: bool,
+    /// part of the input frame simulated
+    region: Rect,
+    /// where the photons go, when accounting for them
+    ledger: Option<&'a Ledger>,
+    /// statistics of the developed grains, when asked for
+    grains: Option<&'a Mutex<GrainCluster>>,
+}
+
+impl Developed {
+    /// Copy a band of rows into place starting at row `y`
+    fn paste(&mut self, band: Developed, y: u32) {
+        match (self, band) {
+            (Developed::Image(frame), Developed::Image(band)) => {
+                image::imageops::replace(frame, &band, 0, y as i64);
+            }
+            (Developed::Density(frame), Developed::Density(band)) => {
+                let start = (y as usize) * (frame.width as usize);
+                frame.data[start..start + band.data.len()].copy_from_slice(&band.data);
+            }
+            _ => unreachable!("bands of one frame share their output"),
+        }
+    }
+}
+
+/// Run the full expose/develop/render pipeline on an input image
+pub fn process(image: &image::DynamicImage, params: &Params) -> Result<image::RgbaImage> {
+    process_cached(image, params, None)
+}
+
+/// [`process`], reusing the stages of an earlier run whose inputs did not
+/// change. Stage dumps and latent import bypass the cache.
+pub fn process_cached(
+    image: &image::DynamicImage,
+    params: &Params,
+    cache: Option<&StageCache>
+) -> Result<image::RgbaImage> {
+    match run(image, params, cache, false, None)? {
+        Developed::Image(image) => Ok(image),
+        Developed::Density(_) => unreachable!("density was not requested"),
+    }
+}
+
+/// [`process`], along with the statistics of the developed grains. With a
+/// contact print they are the grains of the negative, and they are empty
+/// for expected-value rendering, which has no grains.
+pub fn process_with_grains(
+    image: &image::DynamicImage,
+    params: &Params
+) -> Result<(image::RgbaImage, GrainCluster)> {
+    let grains = Mutex::new(GrainCluster::default());
+    match run(image, params, None, false, Some(&grains))? {
+        Developed::Image(image) => Ok((image, grains.into_inner().unwrap())),
+        Developed::Density(_) => unreachable!("density was not requested"),
+    }
+}
+
+/// Run the pipeline up to the developed optical density of each output
+/// pixel, for output encoded as density. Masks still act on the
+/// simulation, but there is no display image to composite or paste.
+pub fn process_density(
+    image: &image::DynamicImage,
+    params: &Params,
+    cache: Option<&StageCache>
+) -> Result<Field> {
+    match run(image, params, cache, true, None)? {
+        Developed::Density(density) => Ok(density),
+        Developed::Image(_) => unreachable!("density was requested"),
+    }
+}
+
+/// The negative of a contact print: handled in the dark and kept as it
+/// is, whatever polarity and transfer the print is shown with
+pub fn negative_params(params: &Params) -> Params {
+    Params { safelight_minutes: 0.0, polarity: Polarity::Negative, transfer: None, ..params.clone() }
+}
+
+/// The paper of a contact print, exposed for `print_exposure_time` and
+/// going through the same developer as the negative
+pub fn print_params(params: &Params) -> Params {
+    Params {
+        stock: params.stock.clone(),
+        developer: params.developer.clone(),
+        development_model: params.development_model.clone(),
+        development_time: params.development_time,
+        dt: params.dt,
+        exposure_time: Some(params.print_exposure_time),
+        safelight: params.safelight,
+        safelight_lux: params.safelight_lux,
+        safelight_minutes: params.safelight_minutes,
+        polarity: params.polarity,
+        transfer: params.transfer,
+        grains_per_pixel: params.grains_per_pixel,
+        seed: params.seed.map(|seed| random::derive_seed(seed, 1)),
+        threads: params.threads,
+        ..Params::default()
+    }
+}
+
+fn run(
+    image: &image::DynamicImage,
+    params: &Params,
+    cache: Option<&StageCache>,
+    as_density: bool,
+    grains: Option<&Mutex<GrainCluster>>
+) -> Result<Developed> {
+    let deterministic;
+    let params = if params.single_thread {
+        // one thread fixes the order of every floating point reduction, and
+        // with a seed every random draw, so reruns are bit-identical
+        deterministic = Params {
+            seed: Some(params.seed.unwrap_or(0)),
+            threads: Some(1),
+            stage_threads: Vec::new(),
+            ..params.clone()
+        };
+        &deterministic
+    } else {
+        params
+    };
+    let pools = Pools::new(params.threads, &params.stage_threads)?;
+    // with a contact print only the print is handled under the safelight
+    // and shown with the chosen polarity and transfer; the negative is
+    // printed as it is
+    let printed;
+    let negative = if params.contact_print {
+        printed = negative_params(params);
+        &printed
+    } else {
+        params
+    };
+    let developed = pools.install(|| process_on(image, negative, &pools, cache, as_density, grains))?;
+    let developed = match developed {
+        Developed::Image(negative) if params.contact_print => {
+            tracing::info!("Contact printing negative");
+            Developed::Image(contactsheet::print(&negative, &print_params(params))?)
+        }
+        developed => developed,
+    };
+    let developed = match developed {
+        Developed::Image(print) if params.print_age_years > 0.0 => {
+            tracing::info!("Ageing the dyes over {} years", params.print_age_years);
+            Developed::Image(DyeFade::after(params.print_age_years, params.print_keeping).apply(&print))
+        }
+        developed => developed,
+    };
+    let developed = match developed {
+        Developed::Image(film) if params.projection => {
+            tracing::info!("Projecting onto the screen");
+            Developed::Image(projection::project(&film, &params.projector()))
+        }
+        developed => developed,
+    };
+    match (developed, params.burn_in) {
+        (Developed::Image(image), Some(placement)) => {
+            tracing::info!("Burning in the settings");
+            Ok(Developed::Image(burnin::apply(&image, &burnin::caption(params), placement)))
+        }
+        (developed, _) => Ok(developed),
+    }
+}
+
+fn process_on(
+    image: &image::DynamicImage,
+    params: &Params,
+    pools: &Pools,
+    cache: Option<&StageCache>,
+    as_density: bool,
+    grains: Option<&Mutex<GrainCluster>>
+) -> Result<Developed> {
+    // the input fitted to the film format, which then sets the physical
+    // frame size unless one is given
+    let (framed, sized);
+    let (image, params) = match params.frame {
+        Some(format) => {
+            framed = frame::fit(image, format, params.frame_fit);
+            let (width_mm, _) = format.dimensions_mm(framed.width(), framed.height());
+            sized = Params {
+                format_width_mm: params.format_width_mm.or(
+                    params.emulsion_width.is_none().then_some(width_mm)
+                ),
+                ..params.clone()
+            };
+            (&framed, &sized)
+        }
+        None => (image, params),
+    };
+    let panorama;
+    let image = match params.shutter {
+        Shutter::SwingLens { .. } => {
+            tracing::info!("Projecting the scene onto the swing lens's cylinder");
+            panorama = panoramic::cylindrical(image, params.swing_angle);
+            &panorama
+        }
+        _ => image,
+    };
+    let (full_width, full_height) = (image.width(), image.height());
+    let full = Rect::new(0, 0, full_width, full_height);
+    let calibrated;
+    let params = match params.iso {
+        Some(iso) => {
+            calibrated = calibrate(params, iso, full)?;
+            &calibrated
+        }
+        None => params,
+    };
+    let region = params.crop.map_or(full, |crop| crop.clamp_to(full_width, full_height));
+    if region.area() == 0 {
+        return Err(
+            Error::Parse(format!("crop lies outside the {full_width}x{full_height} input"))
+        );
+    }
+
+    // halation reaches outside the region, so expose a padded window and
+    // only keep the inside once the glow has been added
+    let halation_kernel = params.halation_kernel()?;
+    let irradiation_kernel = params.irradiation_kernel(full_width);
+    let padding = [&halation_kernel, &irradiation_kernel]
+        .iter()
+        .filter_map(|kernel| kernel.as_ref())
+        .map(|kernel| kernel.radius() as u32)
+        .sum();
+    let padded = region.expand(padding, full_width, full_height);
+    let dump = params.dump_stages.as_ref().map(StageDump::new).transpose()?;
+    let accounting = params.photon_accounting.then(Ledger::new);
+    let ledger = accounting.as_ref();
+    // photons per unit of exposure on one emulsion pixel, and on one input
+    // pixel
+    let emulsion_scale = params.emulsion_scale(full_width);
+    let grid_photons = (params.effective_sensitivity() *
+        params.exposure_time() *
+        params.grain_pitch_um *
+        params.grain_pitch_um) as f64;
+    let input_photons = grid_photons * ((emulsion_scale * emulsion_scale) as f64);
+    let mask = load_mask(params, full_width, full_height)?.map(|mask| mask.crop(padded));
+    let inner = Rect::new(region.x - padded.x, region.y - padded.y, region.width, region.height);
+    let cached = cache
+        .filter(|_| uses_cache(params))
+        .map(|cache| (cache, cache::input_key(image)));
+
+    let expose = || {
+        let window = image.crop_imm(padded.x, padded.y, padded.width, padded.height);
+        let mut exposure = Field::from_rgb(&window);
+
+        if params.polarizer > 0.0 {
+            tracing::info!("Darkening the sky through the polarizer");
+            let sky = params.polarizer_mask
+                .as_ref()
+                .map(|path| load_gray(path, full_width, full_height).map(|sky| sky.crop(padded)))
+                .transpose()?;
+            let before = accounting::total(&exposure) * input_photons;
+            filter::polarize(&mut exposure, params.polarizer, sky.as_ref());
+            if let Some(ledger) = ledger {
+                let after = accounting::total(&exposure) * input_photons;
+                ledger.record("polarizer", before, after, &[("polarized sky light", after - before)]);
+            }
+        }
+
+        if let Some(gains) = params.filter_gains() {
+            tracing::info!(
+                "Filtering the light through the lens filters, red, green and blue times {:.2}, {:.2}, {:.2}",
+                gains[0],
+                gains[1],
+                gains[2]
+            );
+            let before = accounting::total(&exposure) * input_photons;
+            for (channel, gain) in exposure.iter_mut().zip(gains) {
+                channel.data.iter_mut().for_each(|v| *v *= gain);
+            }
+            if let Some(ledger) = ledger {
+                let after = accounting::total(&exposure) * input_photons;
+                ledger.record("lens filters", before, after, &[("filters and factors", after - before)]);
+            }
+        }
+
+        if let Shutter::SwingLens { .. } = params.shutter {
+            tracing::info!("Sweeping the swing lens across the frame");
+            let gains: Vec<f32> = temporal::column_gains(
+                &params.light_profile,
+                &params.shutter,
+                params.shutter_seconds,
+                params.light_phase,
+                full_width
+            )
+                .into_iter()
+                .zip(panoramic::banding(full_width, params.swing_banding))
+                .map(|(light, drive)| light * drive)
+                .collect();
+            for channel in exposure.iter_mut() {
+                temporal::apply_column_gains(channel, &gains, padded.x);
+            }
+        } else if params.light_profile != LightProfile::Constant {
+            tracing::info!("Integrating light profile over the shutter");
+            let gains = temporal::row_gains(
+                &params.light_profile,
+                &params.shutter,
+                params.shutter_seconds,
+                params.light_phase,
+                full_height
+            );
+            for channel in exposure.iter_mut() {
+                temporal::apply_row_gains(channel, &gains, padded.y);
+            }
+        }
+
+        if let Some(kernel) = &irradiation_kernel {
+            tracing::info!("Scattering light within the emulsion");
+            let before = accounting::total(&exposure) * input_photons;
+            for channel in exposure.iter_mut() {
+                *channel = kernel.convolve_with(channel, params.convolution);
+            }
+            if let Some(ledger) = ledger {
+                ledger.check_kernel("irradiation", kernel);
+                ledger.record("irradiation", before, accounting::total(&exposure) * input_photons, &[]);
+            }
+        }
+
+        if let Some(dump) = &dump {
+            dump.rgb("01_exposure", &exposure)?;
+        }
+
+        if let Some(kernel) = &halation_kernel {
+            tracing::info!("Simulating halation");
+            let halation_mask = mask.as_ref().filter(|_| params.mask_targets.halation);
+            // the glow of each channel is only kept when it is exported,
+            // otherwise one scratch buffer serves all three
+            let export = params.halation_export.as_ref();
+            let mut glow = [(); 3].map(|_| Field::new(0, 0));
+            let mut rows = Field::new(0, 0);
+            let before = accounting::total(&exposure) * input_photons;
+            let mut reflected = 0.0;
+            pools.run(Stage::Halation, || {
+                for (i, channel) in exposure.iter_mut().enumerate() {
+                    let scratch = &mut glow[if export.is_some() { i } else { 0 }];
+                    halation::add_halation_in_place(
+                        channel,
+                        kernel,
+                        params.convolution,
+                        params.effective_halation_strength(),
+                        halation_mask,
+                        scratch,
+                        &mut rows
+                    );
+                    reflected += scratch.data.iter().map(|&v| v as f64).sum::<f64>() * input_photons;
+                }
+            });
+            if let Some(ledger) = ledger {
+                ledger.check_kernel("halation", kernel);
+                // a mask shapes the glow, which is then taken as it is
+                let expected = match halation_mask {
+                    Some(_) => reflected,
+                    None => (params.effective_halation_strength() as f64) * before,
+                };
+                ledger.record("halation", before, before + reflected, &[("reflected by the base", expected)]);
+            }
+            if let Some(dump) = &dump {
+                dump.rgb("02_halation", &exposure)?;
+            }
+            if let Some(path) = export {
+                tracing::info!("Exporting halation to {}", path.display());
+                dump::write_rgb(path, &glow.map(|channel| channel.crop(inner)))?;
+            }
+        }
+        let kept = exposure.each_ref().map(|channel| channel.crop(inner));
+        if let Some(ledger) = ledger {
+            let (before, after) = (accounting::total(&exposure), accounting::total(&kept));
+            ledger.record(
+                "crop",
+                before * input_photons,
+                after * input_photons,
+                &[("outside the region", (after - before) * input_photons)]
+            );
+        }
+        Ok(kept)
+    };
+    let mut exposure = match cached {
+        Some((cache, input)) => cache.exposure(cache::exposure_key(input, params), expose)?,
+        None => expose()?,
+    };
+    if params.pour_artifacts > 0.0 || params.edge_fog > 0.0 {
+        tracing::info!("Pouring collodion coating");
+        let before = accounting::total(&exposure) * input_photons;
+        plate::apply(
+            &mut exposure,
+            params.pour_artifacts,
+            params.edge_fog,
+            params.seed,
+            (full_width, full_height),
+            region
+        );
+        if let Some(ledger) = ledger {
+            let after = accounting::total(&exposure) * input_photons;
+            ledger.record("coating", before, after, &[("pour and edge fog", after - before)]);
+        }
+    }
+    let mask = mask.map(|mask| mask.crop(inner));
+
+    // the emulsion runs at its own resolution, the render is then brought
+    // to the requested output size
+    let output_scale = params.output_scale(full_width);
+    let (emulsion_width, emulsion_height) = scaled(region, emulsion_scale);
+    let clayden = params.clayden_pattern
+        .as_ref()
+        .map(|path| load_gray(path, full_width, full_height).map(|pattern| pattern.crop(region)))
+        .transpose()?;
+    let mut fog = params.fog_pattern().map(|pattern| {
+        let pixel_um = params.grain_pitch_um / params.emulsion_scale(full_width);
+        pattern.map(full_width, full_height, pixel_um, params.seed)
+    });
+    if params.static_marks > 0 {
+        let marks = discharge::marks(full_width, full_height, params.static_marks, params.static_dose, params.seed);
+        fog = Some(match fog {
+            Some(mut fog) => {
+                fog.data.iter_mut().zip(&marks.data).for_each(|(f, m)| *f += m);
+                fog
+            }
+            None => marks,
+        });
+    }
+    let fog = fog.map(|fog| fog.crop(region));
+    let surge = params.agitation.surge(full_width, full_height).map(|surge| surge.crop(region));
+    let mottle = (params.development_mottle > 0.0).then(|| {
+        diffusion::mottle(full_width, full_height, params.development_mottle, params.seed).crop(region)
+    });
+    // rows of the emulsion grid, resampled from the region on demand so a
+    // banded run never holds the full-resolution fields
+    let emulsion_band = |rows: Range<u32>| {
+        let resize = |field: &Field| field.resize_rows(emulsion_width, emulsion_height, rows.clone());
+        let maps = EmulsionMaps {
+            mask: mask.as_ref().map(resize),
+            clayden: clayden.as_ref().map(resize),
+            fog: fog.as_ref().map(resize),
+            surge: surge.as_ref().map(resize),
+            mottle: mottle.as_ref().map(resize),
+        };
+        let resized = exposure.each_ref().map(resize);
+        if let Some(ledger) = ledger {
+            // the share of the region's light that falls on these rows
+            let share = ((rows.end - rows.start) as f64) / (emulsion_height.max(1) as f64);
+            ledger.record(
+                "resampling",
+                accounting::total(&exposure) * input_photons * share,
+                accounting::total(&resized) * grid_photons,
+                &[]
+            );
+        }
+        (resized, maps)
+    };
+
+    let run = Run { params, pools, as_density, region, ledger, grains };
+    // keep the grain density of the full frame
+    let num_grains = (((params.num_grains as f64) * (region.area() as f64)) /
+        (full.area().max(1) as f64)) as usize;
+    let mut developed = if params.expected_value {
+        if as_density {
+            return Err(Error::Parse("expected-value rendering has no density output".into()));
+        }
+        tracing::info!("Rendering expected value");
+        let (exposure, maps) = emulsion_band(0..emulsion_height);
+        Developed::Image(expected::render(&exposure, maps.mask.as_ref(), num_grains, params))
+    } else if let Some(rows) = params.band_rows {
+        simulate_in_bands(emulsion_width, emulsion_height, rows, num_grains, run, emulsion_band)?
+    } else {
+        let (exposure, maps) = emulsion_band(0..emulsion_height);
+        let latent = cached.map(|(cache, input)| (cache, cache::latent_key(input, params)));
+        simulate(&exposure, &maps, num_grains, run, latent, dump.as_ref())?
+    };
+    if let Some(ledger) = ledger {
+        tracing::info!("Photon accounting\n{}", ledger.report());
+        for warning in ledger.warnings() {
+            tracing::warn!("Photon accounting: {warning}");
+        }
+    }
+    if defects::enabled(params) {
+        tracing::info!("Adding drying and archival defects");
+        match &mut developed {
+            Developed::Image(rendered) => defects::apply_image(rendered, params, full, region),
+            Developed::Density(density) => defects::apply_density(density, params, full, region),
+        }
+    }
+    let (output_width, output_height) = scaled(region, output_scale);
+    let rendered = match developed {
+        Developed::Image(rendered) => rendered,
+        Developed::Density(density) => {
+            return Ok(Developed::Density(density.resize(output_width, output_height)));
+        }
+    };
+    let mut output = resample::resize(
+        &rendered,
+        output_width,
+        output_height,
+        params.downsample_filter
+    );
+    if infrared::enabled(params) {
+        tracing::info!("Scanning with dust and infrared cleaning");
+        infrared::scan(&mut output, params, full, region);
+    }
+    if params.sharpen_amount > 0.0 {
+        tracing::info!("Sharpening scan");
+        output = sharpen::unsharp_mask(
+            &output,
+            params.sharpen_amount,
+            params.sharpen_radius,
+            params.sharpen_protection
+        );
+    }
+    if params.ambrotype {
+        output = plate::ambrotype(&output);
+    }
+
+    if params.mask_composite {
+        if let Some(mask) = &mask {
+            let mask = mask.resize(output_width, output_height);
+            let original = image
+                .crop_imm(region.x, region.y, region.width, region.height)
+                .resize_exact(output_width, output_height, image::imageops::FilterType::Triangle)
+                .to_rgba8();
+            for ((out, orig), &m) in output.pixels_mut().zip(original.pixels()).zip(&mask.data) {
+                for c in 0..4 {
+                    out.0[c] = ((out.0[c] as f32) * m + (orig.0[c] as f32) * (1.0 - m)).round() as u8;
+                }
+            }
+        }
+    }
+
+    if params.crop_paste && region != full {
+        let (canvas_width, canvas_height) = scaled(full, output_scale);
+        let mut canvas = image
+            .resize_exact(canvas_width, canvas_height, image::imageops::FilterType::Triangle)
+            .to_rgba8();
+        let x = ((region.x as f32) * output_scale).round() as i64;
+        let y = ((region.y as f32) * output_scale).round() as i64;
+        image::imageops::replace(&mut canvas, &output, x, y);
+        Ok(Developed::Image(canvas))
+    } else {
+        Ok(Developed::Image(output))
+    }
+}
+
+/// Replace the exposure time with the one giving speed `iso` at the grain
+/// density this frame will be simulated with
+fn calibrate(params: &Params, iso: f32, full: Rect) -> Result<Params> {
+    tracing::info!("Calibrating exposure for ISO {iso}");
+    let grains_per_pixel = params.grains_per_pixel.unwrap_or_else(|| {
+        let scale = params.emulsion_scale(full.width);
+        (params.num_grains as f32) / ((full.area().max(1) as f32) * scale * scale)
+    });
+    // expired film is rated at the speed of the fresh stock
+    let fresh = Params { expired_years: 0.0, ..params.clone() };
+    let scale = sensitometry::calibrate(&fresh, iso, Some(grains_per_pixel))?;
+    Ok(Params {
+        exposure_time: Some(scale.exposure_time(params.input_illuminance(), Seconds(params.shutter_seconds))),
+        iso: None,
+        ..params.clone()
+    })
+}
+
+/// Share of the full grain density to place under each pixel. Grain is
+/// most visible in the mid-tones and least in deep shadows and blown
+/// highlights, which get down to `floor` of the density.
+fn grain_budget(exposure: &[Field; 3], floor: f32) -> Field {
+    let (width, height) = (exposure[0].width, exposure[0].height);
+    let mut budget = Field::new(width, height);
+    let luminance: Vec<f32> = (0..budget.data.len())
+        .map(|i| exposure.iter().map(|c| c.data[i]).sum::<f32>() / 3.0)
+        .collect();
+    let peak = luminance.iter().copied().fold(0.0, f32::max).max(f32::EPSILON);
+    let floor = floor.clamp(0.0, 1.0);
+    for (b, l) in budget.data.iter_mut().zip(&luminance) {
+        let level = (l / peak).clamp(0.0, 1.0);
+        let visibility = 4.0 * level * (1.0 - level);
+        *b = floor + (1.0 - floor) * visibility;
+    }
+    budget
+}
+
+/// Size of a rectangle after scaling, never collapsing to zero
+fn scaled(rect: Rect, scale: f32) -> (u32, u32) {
+    (
+        (((rect.width as f32) * scale).round() as u32).max(1),
+        (((rect.height as f32) * scale).round() as u32).max(1),
+    )
+}
+
+/// Per-pixel maps on the emulsion grid that steer individual grains
+#[derive(Default)]
+struct EmulsionMaps {
+    /// user mask restricting exposure and development
+    mask: Option<Field>,
+    /// pattern of the intense Clayden pre-exposure
+    clayden: Option<Field>,
+    /// dose of the fog left by travel, storage and static
+    fog: Option<Field>,
+    /// where hand agitation replaces the developer fastest
+    surge: Option<Field>,
+    /// developer activity left uneven by the flow
+    mottle: Option<Field>,
+}
+
+/// Load the mask as a 0..1 field matching the input frame
+fn load_mask(params: &Params, width: u32, height: u32) -> Result<Option<Field>> {
+    params.mask
+        .as_ref()
+        .map(|path| load_gray(path, width, height))
+        .transpose()
+}
+
+/// Parameters of band `index`, with seeds of its own, or every band would
+/// repeat the grain
+fn band_params(params: &Params, index: u64) -> Params {
+    Params {
+        seed: params.seed.map(|seed| random::derive_seed(seed, index)),
+        grain_seed: params.grain_seed.map(|seed| random::derive_seed(seed, index)),
+        ..params.clone()
+    }
+}
+
+/// The grains at `indices`, which are sorted, borrowed mutably together
+fn select_mut<'a>(grains: &'a mut [Halide], indices: &[usize]) -> Vec<&'a mut Halide> {
+    let mut wanted = indices.iter().copied().peekable();
+    grains
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(i, grain)| wanted.next_if_eq(&i).map(|_| grain))
+        .collect()
+}
+
+/// Load a grayscale image as a 0..1 field resized to `width`×`height`
+fn load_gray(path: &std::path::Path, width: u32, height: u32) -> Result<Field> {
+    let mut gray = image::open(path)?.to_luma16();
+    if gray.dimensions() != (width, height) {
+        gray = image::imageops::resize(&gray, width, height, image::imageops::FilterType::Triangle);
+    }
+    Ok(Field::from_luma16(&gray))
+}
+
+/// Simulate the emulsion `rows` rows at a time so that only one band's
+/// grains are held in memory. Grains do not interact across bands, so this
+/// matches a whole-frame run apart from the random draws. Water baths,
+/// whose developer diffuses across the sheet, and renderers drawing grains
+/// beyond their own pixel are refused, as band edges would cut them off.
+fn simulate_in_bands(
+    width: u32,
+    height: u32,
+    rows: u32,
+    num_grains: usize,
+    run: Run,
+    band: impl Fn(Range<u32>) -> ([Field; 3], EmulsionMaps)
+) -> Result<Developed> {
+    let params = run.params;
+    if params.latent_import.is_some() ||
+        params.latent_export.is_some() ||
+        params.dump_stages.is_some() ||
+        params.development_history.is_some() ||
+        params.timelapse.is_some() {
+        return Err(
+            Error::Parse(
+                "latent import and export, stage dumps, development history and time-lapses need the whole emulsion, they cannot be combined with band_rows".into()
+            )
+        );
+    }
+    if params.agitation != Agitation::Continuous {
+        // the spent developer runs down the whole sheet
+        return Err(Error::Parse("tray and tank agitation cannot be combined with band_rows".into()));
+    }
+    if params.water_bath_cycles > 0 {
+        // developer diffusing between grains would stop at band edges
+        return Err(Error::Parse("water bath development cannot be combined with band_rows".into()));
+    }
+    if params.grain_renderer.spreads() {
+        return Err(Error::Parse("only the point grain renderer can be combined with band_rows".into()));
+    }
+    let mut output = if run.as_density {
+        Developed::Density(Field::new(width, height))
+    } else {
+        Developed::Image(image::RgbaImage::new(width, height))
+    };
+    for (index, y0) in (0..height).step_by(rows.max(1) as usize).enumerate() {
+        let y1 = (y0 + rows.max(1)).min(height);
+        tracing::info!("Simulating rows {y0}..{y1} of {height}");
+        let (exposure, maps) = band(y0..y1);
+        let band_params = band_params(params, index as u64);
+        let grains = (((num_grains as u64) * ((y1 - y0) as u64)) / (height.max(1) as u64)) as usize;
+        let band_run = Run { params: &band_params, ..run };
+        let developed = simulate(&exposure, &maps, grains, band_run, None, None)?;
+        output.paste(developed, y0);
+    }
+    Ok(output)
+}
+
+/// Expose, develop and render an emulsion covering the exposure field
+fn simulate(
+    exposure: &[Field; 3],
+    maps: &EmulsionMaps,
+    num_grains: usize,
+    run: Run,
+    cached: Option<(&StageCache, u64)>,
+    dump: Option<&StageDump>
+) -> Result<Developed> {
+    let Run { params, pools, as_density, region, ledger, grains } = run;
+    let (width, height) = (exposure[0].width, exposure[0].height);
+    // grains live on a grid `factor` times finer than the exposure field
+    let factor = params.supersample.max(1);
+    let pixel = |grain: &Halide| ((grain.x as u32) / factor, (grain.y as u32) / factor);
+
+    let expose = || {
+        expose_emulsion(exposure, maps, num_grains, params, pools, ledger, cached.map(|(cache, _)| cache))
+    };
+    let mut emulsion = match cached {
+        Some((cache, key)) => cache.latent(key, expose)?,
+        None => expose()?,
+    };
+    let (grid_width, grid_height) = (width * factor, height * factor);
+    let mask = maps.mask.as_ref();
+    let crystal = params.stock.crystal;
+    let mut developer = params.developer.clone();
+    developer.strength *= crystal.development_rate() * temperature::activity(params.developer_celsius);
+    if (params.developer_celsius - temperature::REFERENCE_CELSIUS).abs() > 0.05 {
+        tracing::info!("Developer at {:.1} °C", params.developer_celsius);
+    }
+
+    if let Some(path) = &params.latent_export {
+        latent::export(&emulsion, grid_width, grid_height, path)?;
+    }
+    if let Some(dump) = dump {
+        let latent = emulsion.rasterize(grid_width, grid_height, |g| g.silver_count as f32);
+        dump.field("03_latent", &latent)?;
+    }
+
+    // grid cells are one emulsion pixel, `grain_pitch_um` across, split
+    // `factor` ways
+    let pixel_um = params.grain_pitch_um / (factor as f32);
+    let renderer = params.grain_renderer.as_ref();
+    let look = params.look();
+
+    // develop emulsion
+    tracing::info!("Developing emulsion");
+    let model = params.development_model.as_ref();
+    let mut history = params.development_history
+        .as_ref()
+        .map(|_| {
+            let grid_region = params.history_region
+                .map(|rect| (to_grid(rect, region, grid_width, grid_height), grid_width, grid_height));
+            History::new(&emulsion, params.history_grains, params.history_every, grid_region)
+        });
+    let steps = params.development_steps();
+    // a water bath or hand agitation tracks the developer in the layer as
+    // it is spent and replenished, otherwise every grain sees fresh
+    // developer
+    let water_bath = params.water_bath();
+    let mut developer_grid = (water_bath.is_some() || params.agitation != Agitation::Continuous).then(|| {
+        let grid = DeveloperGrid::new(
+            width,
+            height,
+            params.developer_diffusion,
+            params.developer_capacity
+        );
+        match params.agitation {
+            Agitation::Continuous => grid,
+            agitation =>
+                grid.with_agitation(
+                    maps.surge.as_ref(),
+                    params.agitation_vigor,
+                    agitation.drag_um() / params.grain_pitch_um.max(f32::EPSILON)
+                ),
+        }
+    });
+    let pixel_area = params.grain_pitch_um * params.grain_pitch_um;
+    // developer held back by the mask leaves a grain as it is, so only the
+    // grains under the mask need developing, found through the index
+    let concentration = mask.filter(|_| params.mask_targets.development);
+    let masked = concentration.map(|mask| {
+        let Some(support) = mask.support() else {
+            return Vec::new();
+        };
+        let grid_support = Rect::new(
+            support.x * factor,
+            support.y * factor,
+            support.width * factor,
+            support.height * factor
+        );
+        let mut inside: Vec<usize> = emulsion
+            .index(grid_width, grid_height, MASK_CELL)
+            .in_rect(&emulsion.grains, grid_support)
+            .filter(|&i| {
+                let (x, y) = pixel(&emulsion.grains[i]);
+                mask.get(x, y) > 0.0
+            })
+            .collect();
+        inside.sort_unstable();
+        inside
+    });
+    // frames at even step intervals, the last one after development
+    let frame_every = steps.div_ceil(params.timelapse_frames.max(1)).max(1);
+    let mut frames = 0;
+    let mut timelapse_frame = |emulsion: &Emulsion| -> Result<()> {
+        if let Some(stem) = &params.timelapse {
+            let rendered = renderer.render(emulsion, grid_width, grid_height, pixel_um, &look);
+            let frame = resample::downsample(&rendered, factor, params.downsample_filter);
+            frames += 1;
+            frame.save(format!("{}-{frames:03}.png", stem.display()))?;
+        }
+        Ok(())
+    };
+    pools.run(Stage::Development, || -> Result<()> {
+        for step in 0..steps {
+            let t = (step as f32) * params.dt;
+            if let Some(history) = &mut history {
+                history.step(&emulsion, step, t);
+            }
+            if step.is_multiple_of(frame_every) {
+                timelapse_frame(&emulsion)?;
+            }
+            let local = |grain: &Halide, grid: Option<&DeveloperGrid>| {
+                let (x, y) = pixel(grain);
+                concentration.map_or(1.0, |mask| mask.get(x, y)) *
+                    maps.mottle.as_ref().map_or(1.0, |mottle| mottle.get(x, y)) *
+                    grid.map_or(1.0, |grid| grid.get(x, y)) *
+                    grain.developer_access(params.developer_penetration_um)
+            };
+            let developing = match &masked {
+                Some(inside) => Either::Left(select_mut(&mut emulsion.grains, inside).into_par_iter()),
+                None => Either::Right(emulsion.grains.par_iter_mut()),
+            };
+            let Some(grid) = &mut developer_grid else {
+                developing.for_each(|grain| {
+                    let local = local(grain, None);
+                    grain.developed_fraction = model.advance(grain, &developer, local, t, params.dt);
+                });
+                continue;
+            };
+            // developed grain area per pixel, the developer it used up
+            let developed = developing
+                .fold(
+                    || Field::new(width, height),
+                    |mut developed, grain| {
+                        let local = local(grain, Some(grid));
+                        let before = grain.developed_fraction;
+                        grain.developed_fraction = model.advance(grain, &developer, local, t, params.dt);
+                        let (x, y) = pixel(grain);
+                        let i = (y as usize) * (width as usize) + (x as usize);
+                        developed.data[i] +=
+                            ((grain.developed_fraction - before) * grain.area() * grain.weight) /
+                            pixel_area;
+                        developed
+                    }
+                )
+                .reduce(
+                    || Field::new(width, height),
+                    |mut a, b| {
+                        a.data.iter_mut().zip(&b.data).for_each(|(a, b)| *a += b);
+                        a
+                    }
+                );
+            grid.consume(&developed);
+            grid.diffuse(params.dt);
+            grid.exchange(water_bath.is_none_or(|bath| bath.in_developer(t)), params.dt);
+        }
+        timelapse_frame(&emulsion)
+    })?;
+    if let (Some(history), Some(path)) = (&mut history, &params.development_history) {
+        history.record(&emulsion, (steps as f32) * params.dt);
+        history.write_csv(&emulsion, path)?;
+    }
+    if let Some(grains) = grains {
+        grains.lock().unwrap().merge(&Halide::aggregate(&emulsion.grains));
+    }
+
+    if let Some(dump) = dump {
+        let developed = emulsion.rasterize(grid_width, grid_height, |g| g.developed_fraction);
+        dump.field("04_developed", &developed)?;
+        dump.field("05_density", &emulsion.rasterize(grid_width, grid_height, Halide::density))?;
+    }
+
+    tracing::info!("Rendering developed grains");
+    pools.run(Stage::Render, || {
+        if as_density {
+            let density = renderer.density(&emulsion, grid_width, grid_height, pixel_um);
+            return Ok(Developed::Density(density.downsample(factor)));
+        }
+        let rendered = renderer.render(&emulsion, grid_width, grid_height, pixel_um, &look);
+        Ok(Developed::Image(resample::downsample(&rendered, factor, params.downsample_filter)))
+    })
+}
+
+/// Pixels of the `width`×`height` grid covering `rect` of the input frame,
+/// where the grid covers `region` of it
+fn to_grid(rect: Rect, region: Rect, width: u32, height: u32) -> Rect {
+    let (sx, sy) = ((width as f32) / (region.width as f32), (height as f32) / (region.height as f32));
+    let x = |v: u32| ((v as f32) - (region.x as f32)).max(0.0) * sx;
+    let y = |v: u32| ((v as f32) - (region.y as f32)).max(0.0) * sy;
+    let (x0, y0) = (x(rect.x).floor() as u32, y(rect.y).floor() as u32);
+    let (x1, y1) = (x(rect.x + rect.width).ceil() as u32, y(rect.y + rect.height).ceil() as u32);
+    Rect::new(x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0)).clamp_to(width, height)
+}
+
+/// Create the emulsion, or take its grain field from `cache`, and form its
+/// latent image, either by exposing it to the exposure field or by
+/// importing one
+fn expose_emulsion(
+    exposure: &[Field; 3],
+    maps: &EmulsionMaps,
+    num_grains: usize,
+    params: &Params,
+    pools: &Pools,
+    ledger: Option<&Ledger>,
+    cache: Option<&StageCache>
+) -> Result<Emulsion> {
+    let (width, height) = (exposure[0].width, exposure[0].height);
+    let factor = params.supersample.max(1);
+    let pixel = |grain: &Halide| ((grain.x as u32) / factor, (grain.y as u32) / factor);
+
+    let create = || {
+        tracing::info!("Creating emulsion");
+        Ok(pools.run(Stage::Emulsion, || create_emulsion(exposure, num_grains, params)))
+    };
+    // an adaptive budget follows the exposure, so its field is never reused
+    let mut emulsion = match cache.filter(|_| params.adaptive_budget.is_none()) {
+        Some(cache) => cache.grain_field(cache::grain_key(width, height, num_grains, params), create)?,
+        None => create()?,
+    };
+    let (grid_width, grid_height) = (width * factor, height * factor);
+    let mask = maps.mask.as_ref();
+    let sensitivity = params.effective_sensitivity();
+    let attenuation = params.light_attenuation;
+    if let Some(path) = &params.latent_import {
+        latent::import(&mut emulsion, grid_width, grid_height, path)?;
+    } else {
+        pools.run(Stage::Exposure, || {
+            if let Some(fog) = &maps.fog {
+                tracing::info!("Fogging from travel, storage and static");
+                emulsion.irradiate(|grain| {
+                    let (x, y) = pixel(grain);
+                    fog.get(x, y)
+                }, params.seed);
+            }
+            if params.clayden_exposure > 0.0 {
+                tracing::info!("Applying Clayden pre-exposure");
+                emulsion.for_each_grain(params.seed, random::CLAYDEN_STREAM, |grain, rng| {
+                    let (x, y) = pixel(grain);
+                    let pattern = maps.clayden.as_ref().map_or(1.0, |c| c.get(x, y));
+                    let intensity =
+                        pattern * params.clayden_exposure * grain.light_transmission(attenuation);
+                    grain.pre_expose_internal(intensity, CLAYDEN_DURATION, rng);
+                });
+            }
+
+            // expose emulsion to image
+            tracing::info!("Exposing emulsion to image");
+            let exposure_mask = mask.filter(|_| params.mask_targets.exposure);
+            let silver = |emulsion: &Emulsion| {
+                emulsion.grains
+                    .par_iter()
+                    .map(|g| (g.silver_count as f64) * (g.weight as f64))
+                    .sum::<f64>()
+            };
+            let silver_before = ledger.map(|_| silver(&emulsion));
+            match params.exposure_sampling {
+                ExposureSampling::Splat => {
+                    let pixel_um = params.grain_pitch_um / (factor as f32);
+                    let pixel_area = pixel_um * pixel_um;
+                    let scale = sensitivity * params.exposure_time() * pixel_area;
+                    emulsion.splat_photons(
+                        grid_width,
+                        grid_height,
+                        pixel_area,
+                        params.seed,
+                        |gx, gy| {
+                            let (x, y) = (gx / factor, gy / factor);
+                            let gain = scale * exposure_mask.map_or(1.0, |mask| mask.get(x, y));
+                            exposure.each_ref().map(|c| c.get(x, y) * gain)
+                        },
+                        |grain, channel| {
+                            grain.effective_absorption() *
+                                grain.spectral_response[channel] *
+                                grain.light_transmission(attenuation)
+                        }
+                    );
+                }
+                ExposureSampling::PerGrain => {
+                    let intensity = |grain: &Halide| {
+                        let (x, y) = pixel(grain);
+                        let mut intensity = grain.spectral_intensity(
+                            exposure.each_ref().map(|c| c.get(x, y))
+                        );
+                        if let Some(mask) = exposure_mask {
+                            intensity *= mask.get(x, y);
+                        }
+                        intensity * grain.light_transmission(attenuation) * sensitivity
+                    };
+                    emulsion.expose(intensity, params.exposure_time(), params.seed);
+                }
+            }
+            if let (Some(ledger), Some(before)) = (ledger, silver_before) {
+                let photons = (sensitivity * params.exposure_time() * params.grain_pitch_um * params.grain_pitch_um) as f64;
+                let incident = (0..(width as usize) * (height as usize))
+                    .map(|i| {
+                        let light: f32 = exposure.iter().map(|c| c.data[i]).sum();
+                        (light * exposure_mask.map_or(1.0, |mask| mask.data[i])) as f64
+                    })
+                    .sum::<f64>() * photons;
+                let absorbed = silver(&emulsion) - before;
+                ledger.record("exposure", incident, incident - absorbed, &[("absorbed by grains", -absorbed)]);
+                if absorbed > incident {
+                    ledger.warn(
+                        format!(
+                            "the grains absorbed {:.3} times the photons that reached the emulsion",
+                            absorbed / incident.max(f64::EPSILON)
+                        )
+                    );
+                }
+            }
+
+            if params.herschel_exposure > 0.0 {
+                tracing::info!("Applying Herschel re-exposure");
+                emulsion.for_each_grain(params.seed, random::HERSCHEL_STREAM, |grain, rng| {
+                    let intensity = params.herschel_exposure * grain.light_transmission(attenuation);
+                    grain.herschel_bleach(
+                        intensity,
+                        params.exposure_time(),
+                        params.herschel_efficiency,
+                        rng
+                    );
+                });
+            }
+
+            if params.safelight_minutes > 0.0 {
+                tracing::info!("Fogging under the safelight");
+                let seconds = params.safelight_minutes * 60.0;
+                // lux seconds in the simulation's exposure units, with the
+                // reciprocity failure of the long, dim exposure
+                let response = params.safelight.relative_response(&params.stock.spectral_sensitivity());
+                let intensity =
+                    params.exposure_scale().units(Lux(params.safelight_lux) * Seconds(1.0)) *
+                    response *
+                    sensitivity *
+                    params.stock.reciprocity_factor(seconds);
+                emulsion.for_each_grain(params.seed, random::SAFELIGHT_STREAM, |grain, rng| {
+                    let mean = intensity * grain.light_transmission(attenuation) * grain.area() * seconds;
+                    for _ in 0..random::poisson(mean, rng) {
+                        if rng.random::<f32>() < grain.effective_absorption() {
+                            grain.absorb_photon();
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    Ok(emulsion)
+}
+
+/// Place and sensitize the grains of an emulsion covering the exposure field
+fn create_emulsion(exposure: &[Field; 3], num_grains: usize, params: &Params) -> Emulsion {
+    let (width, height) = (exposure[0].width, exposure[0].height);
+    let factor = params.supersample.max(1);
+    let seed = params.grain_field_seed();
+    let mut emulsion = match (params.adaptive_budget, params.grains_per_pixel) {
+        (Some(floor), density) => {
+            let density = density.unwrap_or((num_grains as f32) / ((width * height).max(1) as f32));
+            let per_cell = density / ((factor * factor) as f32);
+            let budget = grain_budget(exposure, floor);
+            let kept = budget.data.iter().sum::<f32>() / (budget.data.len().max(1) as f32);
+            tracing::info!("Adaptive budget keeps {:.0}% of grains", kept * 100.0);
+            Emulsion::create_budgeted_emulsion(
+                width * factor,
+                height * factor,
+                per_cell,
+                |x, y| budget.get((x as u32) / factor, (y as u32) / factor),
+                seed
+            )
+        }
+        (None, Some(density)) => {
+            // density is given per emulsion pixel, i.e. per `factor`² grid cells
+            let per_cell = density / ((factor * factor) as f32);
+            Emulsion::create_poisson_emulsion(
+                width * factor,
+                height * factor,
+                per_cell,
+                seed
+            )
+        }
+        (None, None) =>
+            Emulsion::create_random_emulsion(
+                width * factor,
+                height * factor,
+                num_grains,
+                seed
+            ),
+    };
+    emulsion.sensitize(
+        &params.stock.spectral_sensitivity(),
+        params.dye_uptake_variation,
+        seed
+    );
+    if params.stock.chemical_sensitization > 0.0 {
+        emulsion.apply_chemical_sensitization(
+            params.stock.latent_threshold_scale(),
+            params.stock.fog_fraction(),
+            seed
+        );
+    }
+    if let Some(ageing) = Ageing::new(params.expired_years, params.storage) {
+        emulsion.age(&ageing, seed);
+    }
+    if params.coating_thickness_um > 0.0 {
+        emulsion.coat(params.coating_thickness_um, seed);
+    }
+    emulsion
+}
+
+/// whether a run may take its stages from the cache: runs that dump or
+/// export stages need them recomputed, and a cache hit would leave the
+/// photon ledger without the rows of the stages it skipped
+fn uses_cache(params: &Params) -> bool {
+    params.dump_stages.is_none() &&
+        params.latent_import.is_none() &&
+        params.halation_export.is_none() &&
+        !params.photon_accounting
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(emulsion: &Emulsion) -> Vec<(usize, usize)> {
+        emulsion.grains
+            .iter()
+            .map(|g| (g.x, g.y))
+            .collect()
+    }
+
+    #[test]
+    fn bands_draw_their_own_grain() {
+        let exposure = [(); 3].map(|_| Field::new(32, 8));
+        for params in [
+            Params { seed: Some(5), ..Params::default() },
+            Params { seed: Some(5), grain_seed: Some(9), ..Params::default() },
+        ] {
+            let band = |index| positions(&create_emulsion(&exposure, 500, &band_params(&params, index)));
+            assert_eq!(band(0), band(0));
+            assert_ne!(band(0), band(1));
+        }
+    }
+
+    #[test]
+    fn accounting_runs_bypass_the_cache() {
+        assert!(uses_cache(&Params::default()));
+        assert!(!uses_cache(&Params { photon_accounting: true, ..Params::default() }));
+    }
+
+    #[test]
+    fn bands_match_a_whole_frame_run() {
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(48, 48, |x, _| {
+            image::Rgb([if x < 24 { 60 } else { 180 }; 3])
+        }));
+        let whole = Params { seed: Some(3), num_grains: 50_000, ..Params::default() };
+        let banded = Params { band_rows: Some(10), ..whole.clone() };
+        let mean = |density: &Field, x0: usize, x1: usize| {
+            let sum: f32 = (0..density.height as usize)
+                .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                .map(|(x, y)| density.data[y * (density.width as usize) + x])
+                .sum();
+            sum / (((x1 - x0) as f32) * (density.height as f32))
+        };
+        let whole = process_density(&image, &whole, None).unwrap();
+        let banded = process_density(&image, &banded, None).unwrap();
+        assert_eq!((whole.width, whole.height), (banded.width, banded.height));
+        let half = (whole.width / 2) as usize;
+        for (x0, x1) in [(0, half), (half, whole.width as usize)] {
+            let (a, b) = (mean(&whole, x0, x1), mean(&banded, x0, x1));
+            assert!((a - b).abs() < 0.01 * a, "whole {a} banded {b} over {x0}..{x1}");
+        }
+        // more light develops more silver
+        assert!(mean(&whole, half, whole.width as usize) > mean(&whole, 0, half));
+    }
+
+    #[test]
+    fn padded_crops_match_the_full_frame() {
+        // a highlight left of the crop whose halation reaches into it
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 48, |x, y| {
+            image::Rgb([if (6..14).contains(&x) && (20..28).contains(&y) { 255 } else { 40 }; 3])
+        }));
+        let crop = Rect::new(20, 8, 32, 32);
+        let params = Params {
+            seed: Some(8),
+            num_grains: 60_000,
+            halation_strength: 0.8,
+            development_time: 1.0,
+            ..Params::default()
+        };
+        let region = |image: &image::RgbaImage| {
+            image::imageops::crop_imm(image, crop.x, crop.y, crop.width, crop.height).to_image()
+        };
+        let mean = |image: &image::RgbaImage, columns: std::ops::Range<u32>| {
+            let pixels: Vec<f32> = image
+                .enumerate_pixels()
+                .filter(|(x, _, _)| columns.contains(x))
+                .map(|(_, _, p)| p.0[1] as f32)
+                .collect();
+            pixels.iter().sum::<f32>() / (pixels.len() as f32)
+        };
+
+        // the expected value has no grain to differ, so it matches pixel for pixel
+        let expected = Params { expected_value: true, ..params.clone() };
+        let full = region(&process(&image, &expected).unwrap());
+        let cropped = process(&image, &Params { crop: Some(crop), ..expected }).unwrap();
+        assert_eq!(cropped.dimensions(), (crop.width, crop.height));
+        for (a, b) in full.pixels().zip(cropped.pixels()) {
+            assert!(a.0.iter().zip(b.0).all(|(&a, b)| a.abs_diff(b) <= 1), "{a:?} against {b:?}");
+        }
+        assert!(mean(&full, 0..4) != mean(&full, 28..32), "the glow does not reach the crop");
+
+        // grain differs between the runs, but not on average
+        let full = region(&process(&image, &params).unwrap());
+        let cropped = process(&image, &Params { crop: Some(crop), ..params }).unwrap();
+        for columns in [0..8, 24..32] {
+            let (a, b) = (mean(&full, columns.clone()), mean(&cropped, columns.clone()));
+            assert!((a - b).abs() < 3.0, "full frame {a} against crop {b} over columns {columns:?}");
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Wet collodion plates: a coating poured by hand onto glass, exposed and
+//! developed while still wet, and the unevenness that comes with it
+//!
+//! The collodion is poured onto the middle of the plate, tilted into each
+//! corner in turn and drained off the last one, leaving the coating
+//! thicker toward the drain corner with ripples along the flow and a bare
+//! patch where the plate was held. Silver nitrate drying at the edges
+//! before development fogs them.
+
+use rand::Rng;
+
+use crate::field::{ Field, Rect };
+use crate::random::{ self, smoothstep, Noise };
+
+/// peak to peak thickness variation along the drain diagonal
+const DRAIN_SLOPE: f32 = 0.3;
+/// amplitude of the ripples left by the flow
+const RIPPLE: f32 = 0.08;
+/// ripples across the plate diagonal
+const RIPPLES: usize = 5;
+/// radius of the bare patch under the holding thumb, in short sides
+const THUMB_RADIUS: f32 = 0.07;
+/// depth of the edge fog, in short sides
+const FOG_DEPTH: f32 = 0.04;
+/// varnish backing showing through clear glass in an ambrotype
+const BACKING: [f32; 3] = [16.0, 13.0, 11.0];
+/// colour of the silver image seen by reflection
+const SILVER: [f32; 3] = [226.0, 214.0, 188.0];
+
+/// Relative coating thickness over a `width`×`height` plate, 1 on average;
+/// `strength` scales how far it departs from an even coating
+pub fn coating(width: u32, height: u32, strength: f32, seed: Option<u64>) -> Field {
+    let mut rng = random::rng_for(seed, random::POUR_STREAM, 0);
+    let ripples: Vec<(f32, f32)> = (0..RIPPLES)
+        .map(|_| (rng.random_range(2.0..9.0), rng.random_range(0.0..std::f32::consts::TAU)))
+        .collect();
+    let wobble = Noise::new(&mut rng, 4);
+    let short = width.min(height).max(1) as f32;
+    let mut field = Field::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let (u, v) = (((x as f32) + 0.5) / (width as f32), ((y as f32) + 0.5) / (height as f32));
+            // along the drain diagonal, from the holding corner at the
+            // top left to the drain corner at the bottom right
+            let along = (u + v) / 2.0;
+            let across = (u - v) / 2.0 + 0.15 * wobble.at(u, v);
+            let ripple: f32 = ripples
+                .iter()
+                .map(|&(frequency, phase)| (std::f32::consts::TAU * frequency * across + phase).sin())
+                .sum::<f32>() / (RIPPLES as f32);
+            let mut thickness = 1.0 + DRAIN_SLOPE * (along - 0.5) + RIPPLE * ripple * along;
+            let thumb = ((x as f32).hypot(y as f32) / (THUMB_RADIUS * short)).min(1.0);
+            thickness *= smoothstep(thumb);
+            field.set(x, y, 1.0 + strength * (thickness - 1.0));
+        }
+    }
+    field
+}
+
+/// Fog reaching in from the plate edges, 1 at the edge, fading to 0 over a
+/// ragged band a few percent of the short side deep
+pub fn edge_fog(width: u32, height: u32, seed: Option<u64>) -> Field {
+    let mut rng = random::rng_for(seed, random::POUR_STREAM, 1);
+    let ragged = Noise::new(&mut rng, 16);
+    let short = width.min(height).max(1) as f32;
+    let mut field = Field::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let (u, v) = (((x as f32) + 0.5) / (width as f32), ((y as f32) + 0.5) / (height as f32));
+            let edge = x.min(y).min(width - 1 - x).min(height - 1 - y) as f32;
+            let depth = FOG_DEPTH * short * (1.0 + 0.5 * ragged.at(u, v));
+            field.set(x, y, 1.0 - smoothstep(edge / depth.max(1.0)));
+        }
+    }
+    field
+}
+
+/// Lay the pour pattern and edge fog of a plate covering the full
+/// `width`×`height` input over the exposure of `region`
+pub fn apply(
+    exposure: &mut [Field; 3],
+    pour: f32,
+    fog: f32,
+    seed: Option<u64>,
+    (width, height): (u32, u32),
+    region: Rect
+) {
+    let coating = (pour > 0.0).then(|| coating(width, height, pour, seed).crop(region));
+    let fogged = (fog > 0.0).then(|| edge_fog(width, height, seed).crop(region));
+    for channel in exposure.iter_mut() {
+        for (i, value) in channel.data.iter_mut().enumerate() {
+            if let Some(coating) = &coating {
+                *value *= coating.data[i].max(0.0);
+            }
+            if let Some(fogged) = &fogged {
+                *value += fog * fogged.data[i];
+            }
+        }
+    }
+}
+
+/// View a plate negative as an ambrotype: backed with black varnish, the
+/// silver reflects light and clear glass shows the backing, so the
+/// negative reads as a positive
+pub fn ambrotype(negative: &image::RgbaImage) -> image::RgbaImage {
+    let mut positive = negative.clone();
+    for pixel in positive.pixels_mut() {
+        let silver = 1.0 - (pixel.0[0] as f32) / 255.0;
+        for c in 0..3 {
+            pixel.0[c] = (BACKING[c] + silver * (SILVER[c] - BACKING[c])).round() as u8;
+        }
+    }
+    positive
+}
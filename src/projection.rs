@@ -0,0 +1,139 @@
+//! Projection: the film as it looks thrown on a screen, for previewing
+//! slides and prints the way they are viewed rather than as a scan
+//!
+//! The display image is taken as the transmission of the film in the gate.
+//! The lamp colours it, stray light inside the lens veils it, the screen
+//! scales it and whatever light the room adds lifts its blacks, so a
+//! projected print never reaches the density range the film holds.
+
+use rayon::prelude::*;
+
+use crate::field::Field;
+use crate::psf::{ Convolution, Kernel };
+use crate::spectral::{ self, RGB_PRIMARIES };
+
+/// colour temperature the output is balanced for, so a lamp this hot
+/// projects neutral
+const WHITE_KELVIN: f32 = 6500.0;
+/// share of the lamp's colour cast the eye adapts away in the dark
+const ADAPTATION: f32 = 0.7;
+/// spread of the local part of the lens flare, in image widths
+const FLARE_SIGMA: f32 = 0.05;
+/// share of the lens flare spread evenly over the screen, the rest stays
+/// around the bright areas
+const FLARE_UNIFORM: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projector {
+    /// colour temperature of the lamp in kelvin
+    pub lamp_kelvin: f32,
+    /// share of the light through the film the lens scatters across the
+    /// screen
+    pub lens_flare: f32,
+    /// brightness of the screen relative to a matte white one
+    pub screen_gain: f32,
+    /// room light on the screen, relative to the open gate
+    pub ambient_flare: f32,
+}
+
+impl Projector {
+    /// Relative RGB output of the lamp as the adapted eye sees it, the
+    /// strongest channel at 1
+    pub fn lamp_rgb(&self) -> [f32; 3] {
+        let rgb = |kelvin: f32| RGB_PRIMARIES.map(|primary| {
+            spectral::integrate(|nm| primary.response(nm) * planck(nm, kelvin))
+        });
+        let (lamp, white) = (rgb(self.lamp_kelvin.max(1000.0)), rgb(WHITE_KELVIN));
+        let balance = [0, 1, 2].map(|c| (lamp[c] / white[c].max(f32::EPSILON)).powf(1.0 - ADAPTATION));
+        let peak = balance.iter().copied().fold(f32::EPSILON, f32::max);
+        balance.map(|b| b / peak)
+    }
+}
+
+/// Throw `film` onto the screen through `projector`
+pub fn project(film: &image::RgbaImage, projector: &Projector) -> image::RgbaImage {
+    let (width, height) = film.dimensions();
+    let lamp = projector.lamp_rgb();
+    // the flare is as wide as a good part of the frame, which only the
+    // separable passes get through in reasonable time
+    let kernel = (projector.lens_flare > 0.0).then(|| Kernel::gaussian(FLARE_SIGMA * (width as f32)));
+    let flare: Vec<Field> = (0..3)
+        .filter_map(|c| {
+            let kernel = kernel.as_ref()?;
+            let channel = Field {
+                width,
+                height,
+                data: film.pixels().map(|p| (p.0[c] as f32) / 255.0).collect(),
+            };
+            let mean = channel.data.iter().sum::<f32>() / (channel.data.len().max(1) as f32);
+            let mut local = kernel.convolve_with(&channel, Convolution::Separable);
+            for value in &mut local.data {
+                *value = FLARE_UNIFORM * mean + (1.0 - FLARE_UNIFORM) * *value;
+            }
+            Some(local)
+        })
+        .collect();
+
+    let mut screen = film.clone();
+    screen
+        .par_chunks_mut(4)
+        .enumerate()
+        .for_each(|(i, pixel)| {
+            for c in 0..3 {
+                let through = (pixel[c] as f32) / 255.0;
+                let scattered = flare.get(c).map_or(0.0, |flare| projector.lens_flare * flare.data[i]);
+                let light = lamp[c] * ((1.0 - projector.lens_flare) * through + scattered);
+                let value = projector.screen_gain * light + projector.ambient_flare;
+                pixel[c] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+        });
+    screen
+}
+
+/// Spectral radiance of a black body at `kelvin`, up to a constant
+fn planck(nm: f32, kelvin: f32) -> f32 {
+    // second radiation constant in nanometre kelvin
+    const C2: f32 = 1.438_777e7;
+    let um = nm / 1000.0;
+    1.0 / (um.powi(5) * ((C2 / (nm * kelvin)).exp() - 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projector(lens_flare: f32) -> Projector {
+        Projector { lamp_kelvin: WHITE_KELVIN, lens_flare, screen_gain: 0.9, ambient_flare: 0.02 }
+    }
+
+    #[test]
+    fn flat_film_projects_flat() {
+        let film = image::RgbaImage::from_pixel(64, 40, image::Rgba([128, 128, 128, 255]));
+        for flare in [0.0, 0.1] {
+            let projector = projector(flare);
+            let lamp = projector.lamp_rgb();
+            let screen = project(&film, &projector);
+            for pixel in screen.pixels() {
+                for (&value, lamp) in pixel.0.iter().zip(lamp) {
+                    let expected = projector.screen_gain * lamp * (128.0 / 255.0) + projector.ambient_flare;
+                    assert!(((value as f32) - expected * 255.0).abs() <= 1.0, "{pixel:?} with flare {flare}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flare_lifts_the_dark_around_a_highlight() {
+        let mut film = image::RgbaImage::from_pixel(64, 64, image::Rgba([0, 0, 0, 255]));
+        for y in 28..36 {
+            for x in 28..36 {
+                film.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+            }
+        }
+        let clean = project(&film, &Projector { ambient_flare: 0.0, ..projector(0.0) });
+        let flared = project(&film, &Projector { ambient_flare: 0.0, ..projector(0.2) });
+        assert_eq!(clean.get_pixel(40, 32).0[1], 0);
+        assert!(flared.get_pixel(40, 32).0[1] > flared.get_pixel(2, 2).0[1]);
+        assert!(flared.get_pixel(32, 32).0[1] < clean.get_pixel(32, 32).0[1]);
+    }
+}
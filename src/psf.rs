@@ -0,0 +1,446 @@
+//! Point spread functions shared by the optical and scattering stages
+
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::error::{ Error, Result };
+use crate::field::Field;
+
+/// power iterations finding a kernel's separable approximation
+const RANK_ONE_ITERATIONS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How kernels are applied to a field
+pub enum Convolution {
+    /// every weight of the kernel, exact for any shape
+    #[default]
+    Direct,
+    /// a pass along the rows and one down the columns with the kernel's
+    /// nearest separable approximation, exact for axis-aligned Gaussians
+    /// and much faster for wide ones, but rounding off rotated and measured
+    /// spreads
+    Separable,
+}
+
+impl Convolution {
+    /// Parse `direct` or `separable`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "direct" => Ok(Convolution::Direct),
+            "separable" => Ok(Convolution::Separable),
+            _ => Err(Error::Parse(format!("unknown convolution '{text}', expected direct or separable"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Odd-sized 2D kernel centred on its middle sample
+pub struct Kernel {
+    /// half width, the kernel is `2 * radius_x + 1` samples wide
+    pub radius_x: usize,
+    /// half height, the kernel is `2 * radius_y + 1` samples tall
+    pub radius_y: usize,
+    /// row-major weights
+    pub data: Vec<f32>,
+}
+
+impl Kernel {
+    /// Single unit weight, leaving anything it is applied to unchanged
+    pub fn identity() -> Self {
+        Self { radius_x: 0, radius_y: 0, data: vec![1.0] }
+    }
+
+    /// Build a kernel by evaluating `f(dx, dy)` at every sample offset
+    pub fn from_fn(radius_x: usize, radius_y: usize, f: impl Fn(f32, f32) -> f32) -> Self {
+        let mut data = Vec::with_capacity((2 * radius_x + 1) * (2 * radius_y + 1));
+        for y in 0..2 * radius_y + 1 {
+            for x in 0..2 * radius_x + 1 {
+                data.push(f((x as f32) - (radius_x as f32), (y as f32) - (radius_y as f32)));
+            }
+        }
+        Self { radius_x, radius_y, data }
+    }
+
+    /// Normalized Gaussian covering ±3σ
+    pub fn gaussian(sigma: f32) -> Self {
+        let radius = gaussian_radius(sigma);
+        let denom = 2.0 * sigma.max(1e-6).powi(2);
+        Self::from_fn(radius, radius, |dx, dy| (-(dx * dx + dy * dy) / denom).exp()).normalized()
+    }
+
+    /// Normalized elliptical Gaussian with σ `sigma_x` along the direction
+    /// `angle` degrees from the x axis and `sigma_y` across it
+    pub fn anisotropic_gaussian(sigma_x: f32, sigma_y: f32, angle: f32) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (sx, sy) = (sigma_x.max(1e-6), sigma_y.max(1e-6));
+        // half extents of the rotated ±3σ ellipse
+        let rx = (3.0 * ((sx * cos).powi(2) + (sy * sin).powi(2)).sqrt()).ceil() as usize;
+        let ry = (3.0 * ((sx * sin).powi(2) + (sy * cos).powi(2)).sqrt()).ceil() as usize;
+        Self::from_fn(rx, ry, |dx, dy| {
+            let along = dx * cos + dy * sin;
+            let across = -dx * sin + dy * cos;
+            (-0.5 * ((along / sx).powi(2) + (across / sy).powi(2))).exp()
+        }).normalized()
+    }
+
+    /// Normalized uniform disc, edge samples weighted by their coverage
+    pub fn disc(radius: f32) -> Self {
+        Self::ring(0.0, radius)
+    }
+
+    /// Normalized annulus between `inner` and `outer` radius
+    pub fn ring(inner: f32, outer: f32) -> Self {
+        let r = outer.max(0.0).ceil() as usize;
+        Self::from_fn(r, r, |dx, dy| {
+            coverage(dx, dy, |x, y| {
+                let d = (x * x + y * y).sqrt();
+                d >= inner && d <= outer.max(0.5)
+            })
+        }).normalized()
+    }
+
+    /// Normalized line `length` pixels long at `angle` degrees from the x axis
+    pub fn motion_line(length: f32, angle: f32) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let half = length.max(1.0) / 2.0;
+        let rx = (half * cos.abs()).ceil() as usize;
+        let ry = (half * sin.abs()).ceil() as usize;
+        Self::from_fn(rx, ry, |dx, dy| {
+            coverage(dx, dy, |x, y| {
+                let along = x * cos + y * sin;
+                let across = -x * sin + y * cos;
+                along.abs() <= half && across.abs() <= 0.5
+            })
+        }).normalized()
+    }
+
+    /// Normalized kernel read from a grayscale image, cropped to odd size
+    pub fn from_image(image: &image::GrayImage) -> Self {
+        let rx = (image.width().max(1) as usize - 1) / 2;
+        let ry = (image.height().max(1) as usize - 1) / 2;
+        Self::from_fn(rx, ry, |dx, dy| {
+            let x = (dx as i64 + rx as i64) as u32;
+            let y = (dy as i64 + ry as i64) as u32;
+            image.get_pixel(x, y).0[0] as f32 / 255.0
+        }).normalized()
+    }
+
+    /// Load a user supplied kernel image
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_image(&image::open(path)?.to_luma8()))
+    }
+
+    pub fn width(&self) -> usize {
+        2 * self.radius_x + 1
+    }
+
+    pub fn height(&self) -> usize {
+        2 * self.radius_y + 1
+    }
+
+    /// Largest reach in pixels, i.e. the padding a region needs
+    pub fn radius(&self) -> usize {
+        self.radius_x.max(self.radius_y)
+    }
+
+    #[inline]
+    pub fn at(&self, x: usize, y: usize) -> f32 {
+        self.data[y * self.width() + x]
+    }
+
+    pub fn sum(&self) -> f32 {
+        self.data.iter().sum()
+    }
+
+    /// Scale so the weights sum to one, leaving all-zero kernels alone
+    pub fn normalized(mut self) -> Self {
+        let sum = self.sum();
+        if sum.abs() > f32::EPSILON {
+            self.data.iter_mut().for_each(|k| {
+                *k /= sum;
+            });
+        }
+        self
+    }
+
+    pub fn scaled(mut self, factor: f32) -> Self {
+        self.data.iter_mut().for_each(|k| {
+            *k *= factor;
+        });
+        self
+    }
+
+    /// Kernel equivalent to applying `self` and then `other`
+    pub fn compose(&self, other: &Kernel) -> Kernel {
+        let rx = self.radius_x + other.radius_x;
+        let ry = self.radius_y + other.radius_y;
+        let mut out = Kernel {
+            radius_x: rx,
+            radius_y: ry,
+            data: vec![0.0; (2 * rx + 1) * (2 * ry + 1)],
+        };
+        let out_width = out.width();
+        for ay in 0..self.height() {
+            for ax in 0..self.width() {
+                let a = self.at(ax, ay);
+                if a == 0.0 {
+                    continue;
+                }
+                for by in 0..other.height() {
+                    for bx in 0..other.width() {
+                        out.data[(ay + by) * out_width + ax + bx] += a * other.at(bx, by);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Apply to a field, clamping samples at the edges
+    pub fn convolve(&self, field: &Field) -> Field {
+        let mut out = Field::new(field.width, field.height);
+        self.convolve_into(field, &mut out);
+        out
+    }
+
+    /// Apply to a field, writing into `out`. `out` is reshaped to the
+    /// field's size, reusing its allocation when it is large enough.
+    pub fn convolve_into(&self, field: &Field, out: &mut Field) {
+        let (rx, ry) = (self.radius_x as i64, self.radius_y as i64);
+        let (kw, kh) = (self.width(), self.height());
+        out.width = field.width;
+        out.height = field.height;
+        out.data.resize((field.width as usize) * (field.height as usize), 0.0);
+        out.data
+            .par_chunks_mut(field.width as usize)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, value) in row.iter_mut().enumerate() {
+                    let mut acc = 0.0;
+                    for ky in 0..kh {
+                        let sy = (y as i64) + (ky as i64) - ry;
+                        for kx in 0..kw {
+                            let k = self.data[ky * kw + kx];
+                            if k != 0.0 {
+                                acc += k * field.get_clamped((x as i64) + (kx as i64) - rx, sy);
+                            }
+                        }
+                    }
+                    *value = acc;
+                }
+            });
+    }
+
+    /// Apply to a field as `convolution` asks, writing into `out`. The
+    /// separable row pass goes to `horizontal`, so that neither buffer is
+    /// reallocated once they have grown to size.
+    pub fn convolve_with_into(
+        &self,
+        field: &Field,
+        convolution: Convolution,
+        out: &mut Field,
+        horizontal: &mut Field
+    ) {
+        match convolution {
+            Convolution::Direct => self.convolve_into(field, out),
+            Convolution::Separable => {
+                let (row, column) = self.rank_one();
+                convolve_rows_into(field, &row, self.radius_x, horizontal);
+                let horizontal = &*horizontal;
+                out.width = field.width;
+                out.height = field.height;
+                out.data.resize(field.data.len(), 0.0);
+                let ry = self.radius_y as i64;
+                out.data
+                    .par_chunks_mut(field.width as usize)
+                    .enumerate()
+                    .for_each(|(y, out_row)| {
+                        for (x, value) in out_row.iter_mut().enumerate() {
+                            *value = column
+                                .iter()
+                                .enumerate()
+                                .map(|(ky, &k)| k * horizontal.get_clamped(x as i64, (y as i64) + (ky as i64) - ry))
+                                .sum();
+                        }
+                    });
+            }
+        }
+    }
+
+    /// Apply to a field as `convolution` asks
+    pub fn convolve_with(&self, field: &Field, convolution: Convolution) -> Field {
+        let mut out = Field::new(field.width, field.height);
+        self.convolve_with_into(field, convolution, &mut out, &mut Field::new(0, 0));
+        out
+    }
+
+    /// Row and column weights whose outer product is nearest the kernel,
+    /// by power iteration, scaled to keep its sum
+    pub fn rank_one(&self) -> (Vec<f32>, Vec<f32>) {
+        let (width, height) = (self.width(), self.height());
+        let mut row: Vec<f32> = (0..width).map(|x| (0..height).map(|y| self.at(x, y)).sum()).collect();
+        let mut column = vec![0.0; height];
+        for _ in 0..RANK_ONE_ITERATIONS {
+            for (y, c) in column.iter_mut().enumerate() {
+                *c = (0..width).map(|x| self.at(x, y) * row[x]).sum();
+            }
+            let norm = column.iter().map(|c| c * c).sum::<f32>().sqrt().max(f32::MIN_POSITIVE);
+            column.iter_mut().for_each(|c| *c /= norm);
+            for (x, r) in row.iter_mut().enumerate() {
+                *r = (0..height).map(|y| self.at(x, y) * column[y]).sum();
+            }
+        }
+        let approximate = row.iter().sum::<f32>() * column.iter().sum::<f32>();
+        if approximate.abs() > f32::EPSILON {
+            let scale = self.sum() / approximate;
+            row.iter_mut().for_each(|r| *r *= scale);
+        }
+        (row, column)
+    }
+}
+
+/// Convolve every row of a field with `weights` centred at `radius` into
+/// `out`, clamping samples at the edges
+fn convolve_rows_into(field: &Field, weights: &[f32], radius: usize, out: &mut Field) {
+    out.width = field.width;
+    out.height = field.height;
+    out.data.resize(field.data.len(), 0.0);
+    let width = field.width as usize;
+    out.data
+        .par_chunks_mut(width.max(1))
+        .zip(field.data.par_chunks(width.max(1)))
+        .for_each(|(out_row, row)| {
+            for (x, value) in out_row.iter_mut().enumerate() {
+                *value = weights
+                    .iter()
+                    .enumerate()
+                    .map(|(k, &w)| w * row[(x + k).saturating_sub(radius).min(width - 1)])
+                    .sum();
+            }
+        });
+}
+
+/// Normalized Gaussian kernel covering ±3σ
+pub fn make_gaussian_kernel_2d(sigma: f32) -> Kernel {
+    Kernel::gaussian(sigma)
+}
+
+/// Convolve a field with a kernel, clamping samples at the edges
+pub fn convolve_2d(field: &Field, kernel: &Kernel) -> Field {
+    kernel.convolve(field)
+}
+
+/// [`convolve_2d`] into a caller-provided buffer, see [`Kernel::convolve_into`]
+pub fn convolve_2d_into(field: &Field, kernel: &Kernel, out: &mut Field) {
+    kernel.convolve_into(field, out);
+}
+
+/// Radius in pixels of the support of a Gaussian with the given σ
+pub fn gaussian_radius(sigma: f32) -> usize {
+    (3.0 * sigma.max(0.0)).ceil() as usize
+}
+
+/// Fraction of a pixel centred on `(dx, dy)` inside a shape, from 4×4 samples
+fn coverage(dx: f32, dy: f32, inside: impl Fn(f32, f32) -> bool) -> f32 {
+    let mut hits = 0;
+    for sy in 0..4 {
+        for sx in 0..4 {
+            let x = dx + ((sx as f32) + 0.5) / 4.0 - 0.5;
+            let y = dy + ((sy as f32) + 0.5) / 4.0 - 0.5;
+            if inside(x, y) {
+                hits += 1;
+            }
+        }
+    }
+    (hits as f32) / 16.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Second moments of a kernel's weights about its centre
+    fn moments(kernel: &Kernel) -> (f32, f32, f32) {
+        let mut moments = (0.0, 0.0, 0.0);
+        for y in 0..kernel.height() {
+            for x in 0..kernel.width() {
+                let (dx, dy) = ((x as f32) - (kernel.radius_x as f32), (y as f32) - (kernel.radius_y as f32));
+                let k = kernel.at(x, y);
+                moments.0 += k * dx * dx;
+                moments.1 += k * dy * dy;
+                moments.2 += k * dx * dy;
+            }
+        }
+        moments
+    }
+
+    #[test]
+    fn gaussians_are_normalized() {
+        for sigma in [0.0, 0.3, 1.0, 2.5, 8.0] {
+            let kernel = Kernel::gaussian(sigma);
+            assert!((kernel.sum() - 1.0).abs() < 1e-5, "σ {sigma} sums to {}", kernel.sum());
+            assert_eq!(kernel.radius(), gaussian_radius(sigma));
+        }
+        for (sigma_x, sigma_y, angle) in [(3.0, 1.0, 0.0), (1.0, 4.0, 30.0), (2.0, 2.0, 75.0)] {
+            let kernel = Kernel::anisotropic_gaussian(sigma_x, sigma_y, angle);
+            assert!((kernel.sum() - 1.0).abs() < 1e-5, "{sigma_x}×{sigma_y} at {angle}° sums to {}", kernel.sum());
+        }
+    }
+
+    #[test]
+    fn anisotropic_gaussians_follow_their_angle() {
+        let along_x = Kernel::anisotropic_gaussian(4.0, 1.0, 0.0);
+        assert!(along_x.radius_x > along_x.radius_y);
+        let (xx, yy, _) = moments(&along_x);
+        assert!((xx - 16.0).abs() < 0.5 && (yy - 1.0).abs() < 0.1, "variances {xx} and {yy}");
+
+        let along_y = Kernel::anisotropic_gaussian(4.0, 1.0, 90.0);
+        assert_eq!((along_y.radius_x, along_y.radius_y), (along_x.radius_y, along_x.radius_x));
+
+        // at 45° the spread runs up the diagonal, at -45° down it
+        let (_, _, rising) = moments(&Kernel::anisotropic_gaussian(4.0, 1.0, 45.0));
+        let (_, _, falling) = moments(&Kernel::anisotropic_gaussian(4.0, 1.0, -45.0));
+        assert!(rising > 5.0 && falling < -5.0, "covariances {rising} and {falling}");
+    }
+
+    #[test]
+    fn composed_kernels_apply_both() {
+        let (a, b) = (Kernel::gaussian(1.0), Kernel::anisotropic_gaussian(2.0, 0.5, 20.0));
+        let composed = a.compose(&b);
+        assert_eq!((composed.radius_x, composed.radius_y), (a.radius_x + b.radius_x, a.radius_y + b.radius_y));
+        assert!((composed.sum() - 1.0).abs() < 1e-5);
+        // variances of independent spreads add
+        let (ma, mb, mc) = (moments(&a), moments(&b), moments(&composed));
+        assert!((mc.0 - ma.0 - mb.0).abs() < 1e-3 && (mc.1 - ma.1 - mb.1).abs() < 1e-3);
+
+        let mut field = Field::new(40, 40);
+        field.data[20 * 40 + 20] = 1.0;
+        let twice = b.convolve(&a.convolve(&field));
+        let once = composed.convolve(&field);
+        for (x, y) in twice.data.iter().zip(&once.data) {
+            assert!((x - y).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn rank_one_reproduces_a_separable_gaussian() {
+        let kernel = Kernel::gaussian(2.0);
+        let (row, column) = kernel.rank_one();
+        assert_eq!((row.len(), column.len()), (kernel.width(), kernel.height()));
+        for (y, c) in column.iter().enumerate() {
+            for (x, r) in row.iter().enumerate() {
+                assert!((r * c - kernel.at(x, y)).abs() < 1e-6, "({x}, {y}): {} against {}", r * c, kernel.at(x, y));
+            }
+        }
+        let mut field = Field::new(30, 20);
+        for (i, value) in field.data.iter_mut().enumerate() {
+            *value = ((i * 37) % 11) as f32;
+        }
+        let direct = kernel.convolve_with(&field, Convolution::Direct);
+        let separable = kernel.convolve_with(&field, Convolution::Separable);
+        for (a, b) in direct.data.iter().zip(&separable.data) {
+            assert!((a - b).abs() < 1e-4, "{a} against {b}");
+        }
+    }
+}
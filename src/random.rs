@@ -0,0 +1,112 @@
+//! Sampling helpers not covered by `rand`
+
+use rand::rngs::StdRng;
+use rand::{ Rng, SeedableRng };
+
+/// independent random streams for the stages drawing per-grain randomness
+pub const PLACEMENT_STREAM: u64 = 1;
+pub const SENSITIZATION_STREAM: u64 = 2;
+pub const FOG_STREAM: u64 = 3;
+pub const CLAYDEN_STREAM: u64 = 4;
+pub const EXPOSURE_STREAM: u64 = 5;
+pub const HERSCHEL_STREAM: u64 = 6;
+pub const COATING_STREAM: u64 = 7;
+pub const POUR_STREAM: u64 = 8;
+pub const SAFELIGHT_STREAM: u64 = 9;
+pub const DEFECT_STREAM: u64 = 10;
+pub const AGEING_STREAM: u64 = 11;
+pub const FOG_PATTERN_STREAM: u64 = 12;
+pub const RADIATION_STREAM: u64 = 13;
+pub const STATIC_STREAM: u64 = 14;
+pub const MOTTLE_STREAM: u64 = 15;
+pub const DUST_STREAM: u64 = 16;
+/// seeds of the grain fields a sequence reuses across frames
+pub const GRAIN_FIELD_STREAM: u64 = 17;
+
+/// Generator for one independent piece of work, e.g. a chunk of grains
+/// processed on its own thread. With a seed the sequence depends only on
+/// `seed`, `stream` and `index`, so results do not depend on scheduling;
+/// without one it is seeded from the thread generator.
+pub fn rng_for(seed: Option<u64>, stream: u64, index: u64) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(stream_seed(seed, stream, index)),
+        None => StdRng::from_rng(&mut rand::rng()),
+    }
+}
+
+/// Seed of the generator [`rng_for`] gives for `seed`, `stream` and `index`
+pub fn stream_seed(seed: u64, stream: u64, index: u64) -> u64 {
+    splitmix(splitmix(seed ^ stream) ^ index)
+}
+
+/// Seed for the `index`th of several independent runs sharing `seed`
+pub fn derive_seed(seed: u64, index: u64) -> u64 {
+    splitmix(seed ^ splitmix(index))
+}
+
+/// SplitMix64 finalizer, spreads nearby inputs over the whole range
+fn splitmix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Draw from a Poisson distribution with mean `lambda`
+pub fn poisson(lambda: f32, rng: &mut impl Rng) -> usize {
+    if lambda <= 0.0 {
+        return 0;
+    }
+    if lambda < 30.0 {
+        // Knuth: multiply uniforms until the product drops below e^-λ
+        let limit = (-lambda).exp();
+        let mut product: f32 = rng.random();
+        let mut count = 0;
+        while product > limit {
+            product *= rng.random::<f32>();
+            count += 1;
+        }
+        count
+    } else {
+        // normal approximation is accurate to well under a percent here
+        (lambda + lambda.sqrt() * standard_normal(rng)).round().max(0.0) as usize
+    }
+}
+
+/// Draw from a standard normal distribution (Box-Muller)
+pub fn standard_normal(rng: &mut impl Rng) -> f32 {
+    let u1: f32 = rng.random_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.random();
+    (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+}
+
+/// Smooth random field in -1..1 from a `cells`×`cells` lattice
+pub struct Noise {
+    cells: usize,
+    lattice: Vec<f32>,
+}
+
+impl Noise {
+    pub fn new(rng: &mut impl Rng, cells: usize) -> Self {
+        let lattice = (0..(cells + 1) * (cells + 1)).map(|_| rng.random_range(-1.0..1.0)).collect();
+        Self { cells, lattice }
+    }
+
+    /// Value at `(u, v)` in 0..1, smoothly interpolated between lattice points
+    pub fn at(&self, u: f32, v: f32) -> f32 {
+        let n = self.cells as f32;
+        let (fx, fy) = (u.clamp(0.0, 1.0) * n, v.clamp(0.0, 1.0) * n);
+        let (x0, y0) = ((fx as usize).min(self.cells - 1), (fy as usize).min(self.cells - 1));
+        let (tx, ty) = (smoothstep(fx - (x0 as f32)), smoothstep(fy - (y0 as f32)));
+        let at = |x: usize, y: usize| self.lattice[y * (self.cells + 1) + x];
+        let top = at(x0, y0) + tx * (at(x0 + 1, y0) - at(x0, y0));
+        let bottom = at(x0, y0 + 1) + tx * (at(x0 + 1, y0 + 1) - at(x0, y0 + 1));
+        top + ty * (bottom - top)
+    }
+}
+
+/// Hermite step from 0 at `t = 0` to 1 at `t = 1`
+pub fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
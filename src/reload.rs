@@ -0,0 +1,69 @@
+//! Parameters reloaded from a file as it is edited, for look development
+//! on long runs
+//!
+//! The file holds a JSON object or flat TOML table of parameters, as the
+//! `params` key takes, applied over the parameters the run was started
+//! with. Changes are noticed by polling the file's modification time. A
+//! run that keeps a [`crate::cache::StageCache`] across reloads only
+//! repeats the stages the changed parameters affect.
+
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, SystemTime };
+
+use crate::error::Result;
+use crate::params::Params;
+
+/// how often the file is checked for changes
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct Watched {
+    path: PathBuf,
+    base: Params,
+    /// modification time of the file when it was last read
+    modified: Option<SystemTime>,
+}
+
+impl Watched {
+    pub fn new(path: &Path, base: Params) -> Self {
+        Self { path: path.to_path_buf(), base, modified: None }
+    }
+
+    /// The base parameters with the file applied over them
+    pub fn load(&mut self) -> Result<Params> {
+        self.modified = std::fs::metadata(&self.path)?.modified().ok();
+        let mut params = self.base.clone();
+        params.apply_file(&self.path)?;
+        Ok(params)
+    }
+
+    /// The reloaded parameters when the file changed since it was last
+    /// read. A file that does not parse is reported once and skipped
+    /// until it changes again.
+    pub fn poll(&mut self) -> Option<Params> {
+        let modified = std::fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        match self.load() {
+            Ok(params) => {
+                tracing::info!("Reloaded {}", self.path.display());
+                Some(params)
+            }
+            Err(err) => {
+                self.modified = modified;
+                tracing::warn!("Keeping the previous parameters, {} failed to load: {err}", self.path.display());
+                None
+            }
+        }
+    }
+
+    /// Block until the file changes to parameters that load
+    pub fn wait(&mut self) -> Params {
+        loop {
+            if let Some(params) = self.poll() {
+                return params;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
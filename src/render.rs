@@ -0,0 +1,454 @@
+//! Grain renderers: how developed grains appear in the rendered image,
+//! behind a trait so new appearance models can be added without touching
+//! the emulsion
+//!
+//! Apart from the point renderer, renderers deposit each grain's optical
+//! density over the pixels it covers; a pixel's density is the sum of what
+//! it received and it is drawn with transmission `10^-D`.
+//!
+//! How density becomes an output value is the [`Look`]: the clear base
+//! behind the grains, whether the film is shown as a negative or inverted
+//! to a positive, and the transfer from density to output.
+
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rand::{ Rng, SeedableRng };
+use rayon::prelude::*;
+
+use crate::emulsion::Emulsion;
+use crate::error::{ Error, Result };
+use crate::field::Field;
+use crate::halide::Halide;
+
+/// grains splatted by one task before its density field is merged
+const CHUNK: usize = 16_384;
+
+/// Whether the output shows the film as it is or inverted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Polarity {
+    #[default]
+    Negative,
+    Positive,
+}
+
+impl Polarity {
+    /// Parse `negative` or `positive`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "negative" => Ok(Polarity::Negative),
+            "positive" => Ok(Polarity::Positive),
+            _ => Err(Error::Parse(format!("invalid polarity '{text}', expected negative or positive"))),
+        }
+    }
+}
+
+/// Mapping from density to an output value between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transfer {
+    /// transmission `10^-D`
+    Linear,
+    /// falls linearly with density, reaching 0 at `range`
+    Log { range: f32 },
+    /// transmission encoded with `1 / gamma`, as display images are
+    Gamma { gamma: f32 },
+}
+
+impl Transfer {
+    /// Parse `linear`, `log[:RANGE]` or `gamma[:GAMMA]`
+    pub fn parse(text: &str) -> Result<Self> {
+        let (kind, arg) = match text.trim().split_once(':') {
+            Some((kind, arg)) => {
+                let arg = arg
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|&a| a > 0.0)
+                    .ok_or_else(|| Error::Parse(format!("invalid transfer '{text}'")))?;
+                (kind, Some(arg))
+            }
+            None => (text.trim(), None),
+        };
+        match (kind, arg) {
+            ("linear", None) => Ok(Transfer::Linear),
+            ("log", range) => Ok(Transfer::Log { range: range.unwrap_or(1.0) }),
+            ("gamma", gamma) => Ok(Transfer::Gamma { gamma: gamma.unwrap_or(2.2) }),
+            _ =>
+                Err(
+                    Error::Parse(format!("invalid transfer '{text}', expected linear, log[:RANGE] or gamma[:GAMMA]"))
+                ),
+        }
+    }
+
+    /// Output value of density `d`
+    pub fn apply(&self, d: f32) -> f32 {
+        match *self {
+            Transfer::Linear => (10.0f32).powf(-d),
+            Transfer::Log { range } => 1.0 - d / range,
+            Transfer::Gamma { gamma } => (10.0f32).powf(-d / gamma),
+        }
+    }
+}
+
+/// How rendered density is shown
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Look {
+    /// red, green and blue density of the clear base every pixel is seen
+    /// through
+    pub background: [f32; 3],
+    pub polarity: Polarity,
+    /// the renderer's own transfer when unset
+    pub transfer: Option<Transfer>,
+}
+
+impl Look {
+    /// Parse a comma separated red, green and blue tint
+    pub fn parse_tint(text: &str) -> Result<[f32; 3]> {
+        let tint: Vec<f32> = text
+            .split(',')
+            .map(|p| p.trim().parse::<f32>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| Error::Parse(format!("invalid tint '{text}'")))?;
+        match tint[..] {
+            [r, g, b] if r > 0.0 && g > 0.0 && b > 0.0 => Ok([r, g, b]),
+            _ => Err(Error::Parse(format!("invalid tint '{text}', expected R,G,B"))),
+        }
+    }
+
+    /// Output value between 0 and 1 of `channel` where the grains add
+    /// density `d`, with `native` the renderer's own transfer
+    pub fn value(&self, d: f32, channel: usize, native: Transfer) -> f32 {
+        let value = self.transfer.unwrap_or(native).apply(d + self.background[channel]);
+        match self.polarity {
+            Polarity::Negative => value,
+            Polarity::Positive => 1.0 - value,
+        }
+    }
+}
+
+pub trait GrainRenderer: std::fmt::Debug + Send + Sync {
+    /// Draw the developed grains of `emulsion` on a `width`×`height` grid
+    /// whose cells are `pixel_um` microns across, shown with `look`
+    fn render(
+        &self,
+        emulsion: &Emulsion,
+        width: u32,
+        height: u32,
+        pixel_um: f32,
+        look: &Look
+    ) -> image::RgbaImage;
+
+    /// Optical density of each cell of the grid, for output encoded as
+    /// density rather than drawn. Defaults to the mean density of the
+    /// grains on the cell.
+    fn density(&self, emulsion: &Emulsion, width: u32, height: u32, _pixel_um: f32) -> Field {
+        emulsion.rasterize(width, height, Halide::density)
+    }
+
+    /// Whether the image is dye, which infrared passes, rather than silver,
+    /// which holds it back like visible light
+    fn infrared_transparent(&self) -> bool {
+        false
+    }
+
+    /// Whether a grain's density can land on cells other than its own,
+    /// where rendering a frame piece by piece would clip it
+    fn spreads(&self) -> bool {
+        true
+    }
+}
+
+/// Parse `point`, `disc`, `filament[:STRANDS]` or `dye-cloud[:SPREAD]`
+pub fn parse(text: &str) -> Result<Arc<dyn GrainRenderer>> {
+    let (kind, arg) = match text.trim().split_once(':') {
+        Some((kind, arg)) => {
+            let arg = arg
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| Error::Parse(format!("invalid grain renderer '{text}'")))?;
+            (kind, Some(arg))
+        }
+        None => (text.trim(), None),
+    };
+    match (kind, arg) {
+        ("point", None) => Ok(Arc::new(Point)),
+        ("disc", None) => Ok(Arc::new(Disc)),
+        ("filament", strands) =>
+            Ok(Arc::new(Filament { strands: strands.unwrap_or(4.0).max(1.0) as u32 })),
+        ("dye-cloud", spread) => Ok(Arc::new(DyeCloud { spread: spread.unwrap_or(2.0).max(0.1) })),
+        _ =>
+            Err(
+                Error::Parse(
+                    format!(
+                        "invalid grain renderer '{text}', expected point, disc, filament[:STRANDS] or dye-cloud[:SPREAD]"
+                    )
+                )
+            ),
+    }
+}
+
+/// One pixel per grain, the last grain on a pixel covering the others
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Point;
+
+impl GrainRenderer for Point {
+    fn render(
+        &self,
+        emulsion: &Emulsion,
+        width: u32,
+        height: u32,
+        _pixel_um: f32,
+        look: &Look
+    ) -> image::RgbaImage {
+        emulsion.render_emulsion(width, height, look)
+    }
+
+    fn spreads(&self) -> bool {
+        false
+    }
+}
+
+/// Opaque silver discs of the grain's size
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Disc;
+
+impl GrainRenderer for Disc {
+    fn render(
+        &self,
+        emulsion: &Emulsion,
+        width: u32,
+        height: u32,
+        pixel_um: f32,
+        look: &Look
+    ) -> image::RgbaImage {
+        draw(&self.density(emulsion, width, height, pixel_um), look)
+    }
+
+    fn density(&self, emulsion: &Emulsion, width: u32, height: u32, pixel_um: f32) -> Field {
+        accumulate(emulsion, width, height, |_, grain, density| {
+            let radius = grain.radius / pixel_um;
+            let (cx, cy) = centre(grain);
+            let mass = grain.weight * grain.density() * std::f32::consts::PI * radius * radius;
+            if radius < 0.5 {
+                deposit(density, cx, cy, mass);
+                return;
+            }
+            // 4×4 samples per pixel for the coverage of the disc edge
+            let (x0, x1) = ((cx - radius).floor() as i64, (cx + radius).ceil() as i64);
+            let (y0, y1) = ((cy - radius).floor() as i64, (cy + radius).ceil() as i64);
+            for py in y0..y1 {
+                for px in x0..x1 {
+                    let mut covered = 0;
+                    for s in 0..16 {
+                        let sx = (px as f32) + ((s % 4) as f32 + 0.5) / 4.0;
+                        let sy = (py as f32) + ((s / 4) as f32 + 0.5) / 4.0;
+                        if (sx - cx).powi(2) + (sy - cy).powi(2) <= radius * radius {
+                            covered += 1;
+                        }
+                    }
+                    if covered > 0 {
+                        let coverage = (covered as f32) / 16.0;
+                        add(density, px, py, grain.weight * grain.density() * coverage);
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Developed silver as tangled filaments growing out of the grain, the
+/// look of grains developed in a chemical developer under the microscope
+#[derive(Debug, Clone, Copy)]
+pub struct Filament {
+    /// strands grown from a fully developed grain
+    pub strands: u32,
+}
+
+impl GrainRenderer for Filament {
+    fn render(
+        &self,
+        emulsion: &Emulsion,
+        width: u32,
+        height: u32,
+        pixel_um: f32,
+        look: &Look
+    ) -> image::RgbaImage {
+        draw(&self.density(emulsion, width, height, pixel_um), look)
+    }
+
+    fn density(&self, emulsion: &Emulsion, width: u32, height: u32, pixel_um: f32) -> Field {
+        accumulate(emulsion, width, height, |index, grain, density| {
+            let radius = grain.radius / pixel_um;
+            let mass = grain.weight * grain.density() * std::f32::consts::PI * radius * radius;
+            if mass <= 0.0 {
+                return;
+            }
+            // the same grain always grows the same filaments
+            let mut rng = SmallRng::seed_from_u64(index as u64);
+            let strands = ((self.strands as f32) * grain.developed_fraction).ceil().max(1.0) as u32;
+            let step = (radius / 4.0).max(0.05);
+            let steps = 8;
+            let share = mass / ((strands * steps) as f32);
+            let (cx, cy) = centre(grain);
+            for _ in 0..strands {
+                let (mut x, mut y) = (cx, cy);
+                let mut angle = rng.random_range(0.0..std::f32::consts::TAU);
+                for _ in 0..steps {
+                    angle += rng.random_range(-0.8..0.8);
+                    x += step * angle.cos();
+                    y += step * angle.sin();
+                    deposit(density, x, y, share);
+                }
+            }
+        })
+    }
+}
+
+/// Soft Gaussian clouds of dye formed around each developed grain, as in
+/// chromogenic films where the silver is bleached away
+#[derive(Debug, Clone, Copy)]
+pub struct DyeCloud {
+    /// cloud radius relative to the grain
+    pub spread: f32,
+}
+
+impl GrainRenderer for DyeCloud {
+    fn render(
+        &self,
+        emulsion: &Emulsion,
+        width: u32,
+        height: u32,
+        pixel_um: f32,
+        look: &Look
+    ) -> image::RgbaImage {
+        draw(&self.density(emulsion, width, height, pixel_um), look)
+    }
+
+    fn density(&self, emulsion: &Emulsion, width: u32, height: u32, pixel_um: f32) -> Field {
+        accumulate(emulsion, width, height, |_, grain, density| {
+            let radius = grain.radius / pixel_um;
+            let mass = grain.weight * grain.density() * std::f32::consts::PI * radius * radius;
+            let sigma = self.spread * radius;
+            let (cx, cy) = centre(grain);
+            if mass <= 0.0 {
+                return;
+            }
+            if sigma < 0.3 {
+                deposit(density, cx, cy, mass);
+                return;
+            }
+            let reach = (3.0 * sigma).ceil() as i64;
+            let (px0, py0) = (cx.floor() as i64, cy.floor() as i64);
+            let norm = mass / (std::f32::consts::TAU * sigma * sigma);
+            for py in py0 - reach..=py0 + reach {
+                for px in px0 - reach..=px0 + reach {
+                    let dx = (px as f32) + 0.5 - cx;
+                    let dy = (py as f32) + 0.5 - cy;
+                    add(density, px, py, norm * (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp());
+                }
+            }
+        })
+    }
+
+    fn infrared_transparent(&self) -> bool {
+        true
+    }
+}
+
+/// Centre of the grid cell a grain sits in
+fn centre(grain: &Halide) -> (f32, f32) {
+    ((grain.x as f32) + 0.5, (grain.y as f32) + 0.5)
+}
+
+/// Accumulate the density every grain deposits
+fn accumulate<F>(emulsion: &Emulsion, width: u32, height: u32, splat: F) -> Field
+    where F: Fn(usize, &Halide, &mut Field) + Sync
+{
+    emulsion.grains
+        .par_chunks(CHUNK)
+        .enumerate()
+        .fold(
+            || Field::new(width, height),
+            |mut density, (chunk, grains)| {
+                for (i, grain) in grains.iter().enumerate() {
+                    if grain.developed_fraction > 0.0 {
+                        splat(chunk * CHUNK + i, grain, &mut density);
+                    }
+                }
+                density
+            }
+        )
+        .reduce(
+            || Field::new(width, height),
+            |mut a, b| {
+                a.data.iter_mut().zip(&b.data).for_each(|(a, b)| {
+                    *a += b;
+                });
+                a
+            }
+        )
+}
+
+/// Draw accumulated density with transmission `10^-D`
+fn draw(density: &Field, look: &Look) -> image::RgbaImage {
+    let mut output = image::RgbaImage::new(density.width, density.height);
+    for (pixel, &d) in output.pixels_mut().zip(&density.data) {
+        let value = |c| (255.0 * look.value(d, c, Transfer::Linear)).round().clamp(0.0, 255.0) as u8;
+        *pixel = image::Rgba([value(0), value(1), value(2), 255]);
+    }
+    output
+}
+
+/// Spread `mass` (density times area in pixels) bilinearly over the four
+/// pixels around `(x, y)`
+fn deposit(density: &mut Field, x: f32, y: f32, mass: f32) {
+    let (fx, fy) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (fx.floor(), fy.floor());
+    let (tx, ty) = (fx - x0, fy - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    add(density, x0, y0, mass * (1.0 - tx) * (1.0 - ty));
+    add(density, x0 + 1, y0, mass * tx * (1.0 - ty));
+    add(density, x0, y0 + 1, mass * (1.0 - tx) * ty);
+    add(density, x0 + 1, y0 + 1, mass * tx * ty);
+}
+
+fn add(density: &mut Field, x: i64, y: i64, value: f32) {
+    if x >= 0 && y >= 0 && x < (density.width as i64) && y < (density.height as i64) {
+        let i = (y as usize) * (density.width as usize) + (x as usize);
+        density.data[i] += value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one_grain(radius: f32) -> Emulsion {
+        let grain = Halide { developed_fraction: 0.6, ..Halide::new_with_params(16, 16, radius, 1, 0.5) };
+        Emulsion { grains: vec![grain] }
+    }
+
+    fn total(field: &Field) -> f32 {
+        field.data.iter().sum()
+    }
+
+    #[test]
+    fn renderers_deposit_the_same_density() {
+        let renderers = ["point", "disc", "filament", "dye-cloud"].map(|name| parse(name).unwrap());
+        // a grain covering one pixel, which is all the point renderer draws
+        let pixel_um = 0.5;
+        let emulsion = one_grain(pixel_um / std::f32::consts::PI.sqrt());
+        let density = emulsion.grains[0].density();
+        for renderer in &renderers {
+            let total = total(&renderer.density(&emulsion, 32, 32, pixel_um));
+            assert!((total / density - 1.0).abs() < 0.05, "{renderer:?} deposits {total} for {density}");
+        }
+        // larger grains spread their density without losing any
+        let emulsion = one_grain(3.0 * pixel_um);
+        let mass = density * std::f32::consts::PI * 9.0;
+        for renderer in renderers.iter().filter(|renderer| renderer.spreads()) {
+            let total = total(&renderer.density(&emulsion, 32, 32, pixel_um));
+            assert!((total / mass - 1.0).abs() < 0.02, "{renderer:?} deposits {total} for {mass}");
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Resampling between the emulsion grid and the input/output resolution
+
+use rayon::prelude::*;
+
+use crate::error::{ Error, Result };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// average of the covered samples
+    Box,
+    /// windowed sinc with three lobes
+    Lanczos,
+}
+
+impl Filter {
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "box" => Ok(Filter::Box),
+            "lanczos" | "lanczos3" => Ok(Filter::Lanczos),
+            _ => Err(Error::Parse(format!("unknown filter '{text}', expected box or lanczos"))),
+        }
+    }
+}
+
+/// Reduce an image by an integer `factor` in both directions
+pub fn downsample(image: &image::RgbaImage, factor: u32, filter: Filter) -> image::RgbaImage {
+    if factor <= 1 {
+        return image.clone();
+    }
+    let width = image.width() / factor;
+    let height = image.height() / factor;
+    match filter {
+        Filter::Box => downsample_box(image, factor),
+        Filter::Lanczos =>
+            image::imageops::resize(image, width, height, image::imageops::FilterType::Lanczos3),
+    }
+}
+
+/// Resize to an arbitrary size, box falling back to a triangle filter
+pub fn resize(image: &image::RgbaImage, width: u32, height: u32, filter: Filter) -> image::RgbaImage {
+    if image.dimensions() == (width, height) {
+        return image.clone();
+    }
+    let filter = match filter {
+        Filter::Box => image::imageops::FilterType::Triangle,
+        Filter::Lanczos => image::imageops::FilterType::Lanczos3,
+    };
+    image::imageops::resize(image, width, height, filter)
+}
+
+/// Average every `factor`×`factor` block into one pixel
+pub fn downsample_box(image: &image::RgbaImage, factor: u32) -> image::RgbaImage {
+    let width = image.width() / factor;
+    let height = image.height() / factor;
+    let count = factor * factor;
+    let mut out = image::RgbaImage::new(width, height);
+    out.par_chunks_mut((width as usize) * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for x in 0..width as usize {
+                let mut acc = [0u32; 4];
+                for sy in 0..factor {
+                    for sx in 0..factor {
+                        let pixel = image.get_pixel((x as u32) * factor + sx, (y as u32) * factor + sy);
+                        for (a, &v) in acc.iter_mut().zip(&pixel.0) {
+                            *a += v as u32;
+                        }
+                    }
+                }
+                for c in 0..4 {
+                    row[x * 4 + c] = ((acc[c] + count / 2) / count) as u8;
+                }
+            }
+        });
+    out
+}
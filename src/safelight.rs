@@ -0,0 +1,56 @@
+//! Darkroom safelights: filtered lamps the paper is handled under, safe
+//! only as long as their light stays below what it takes to fog the paper
+//!
+//! The filter passes a band of long wavelengths the paper is meant to be
+//! blind to, but the native absorption of the halide has a tail reaching
+//! into it. Minutes under the lamp add a faint, even exposure that alone
+//! stays below the latent threshold of most grains and shows up as a
+//! veil on the highlights once it adds to the image.
+
+use crate::error::{ Error, Result };
+use crate::spectral::{ Band, SpectralSensitivity, RGB_PRIMARIES };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Safelight {
+    /// Kodak OC style light amber, for blue sensitive and variable
+    /// contrast papers
+    Amber,
+    /// Kodak 1A style light red, for blue sensitive papers only
+    LightRed,
+    /// deep red, for orthochromatic film
+    Red,
+}
+
+impl Safelight {
+    /// Parse `amber`, `light-red` or `red`, or a filter name
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "amber" | "oc" => Ok(Safelight::Amber),
+            "light-red" | "light_red" | "1a" => Ok(Safelight::LightRed),
+            "red" | "2" => Ok(Safelight::Red),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!("unknown safelight '{text}', expected amber, light-red or red")
+                    )
+                ),
+        }
+    }
+
+    /// Transmission band of the filter
+    pub fn band(&self) -> Band {
+        match self {
+            Safelight::Amber => Band::new(595.0, 18.0, 1.0),
+            Safelight::LightRed => Band::new(625.0, 18.0, 1.0),
+            Safelight::Red => Band::new(660.0, 15.0, 1.0),
+        }
+    }
+
+    /// Response of `sensitivity` to the lamp relative to a neutral input
+    /// of the same illuminance
+    pub fn relative_response(&self, sensitivity: &SpectralSensitivity) -> f32 {
+        let band = self.band();
+        let neutral = sensitivity.rgb_weights().iter().sum::<f32>() / (RGB_PRIMARIES.len() as f32);
+        sensitivity.integrate(|nm| band.response(nm)) / neutral.max(f32::EPSILON)
+    }
+}
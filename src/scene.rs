@@ -0,0 +1,125 @@
+//! Synthetic test scenes for exercising tone reproduction and halation
+//! without hunting for suitable photographs
+//!
+//! Scenes are linear and scene-referred: 0.18 is middle gray, 1.0 diffuse
+//! white, and highlights go above it up to `peak`. Save them as EXR to keep
+//! the values above one; 8 and 16 bit formats clip them.
+
+use crate::error::{ Error, Result };
+
+/// samples per pixel along each axis, so edges and small highlights are
+/// antialiased rather than aliased into the grain
+const SUPERSAMPLE: u32 = 4;
+/// background of the highlight target, deep shadow
+const DARK: f32 = 0.02;
+/// middle gray
+const GRAY: f32 = 0.18;
+/// stops covered by the gray ramp below its peak
+const RAMP_STOPS: f32 = 12.0;
+/// spokes of the star target
+const SPOKES: u32 = 36;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scene {
+    /// horizontal ramp, even in stops, from deep shadow to the peak
+    GrayRamp,
+    /// Siemens star of black and white spokes on middle gray, for
+    /// resolution and the spread of light across fine edges
+    Star,
+    /// grid of point highlights rising in brightness from diffuse white to
+    /// the peak on a dark ground, for halation
+    Highlights,
+    /// the eleven zones 0 to X, one stop apart with zone V at middle gray,
+    /// for the toe and shoulder
+    Zones,
+    /// all of the above on one frame
+    Chart,
+}
+
+impl Scene {
+    /// Parse `gray-ramp`, `star`, `highlights`, `zones` or `chart`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "gray-ramp" | "ramp" => Ok(Scene::GrayRamp),
+            "star" => Ok(Scene::Star),
+            "highlights" => Ok(Scene::Highlights),
+            "zones" => Ok(Scene::Zones),
+            "chart" => Ok(Scene::Chart),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!(
+                            "unknown scene '{text}', expected gray-ramp, star, highlights, zones or chart"
+                        )
+                    )
+                ),
+        }
+    }
+
+    /// Render the scene at `width`×`height` with the brightest highlight at
+    /// `peak` times diffuse white
+    pub fn render(&self, width: u32, height: u32, peak: f32) -> image::DynamicImage {
+        let (w, h) = (width.max(1) as f32, height.max(1) as f32);
+        let mut image = image::Rgb32FImage::new(width, height);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let mut sum = 0.0;
+            for sy in 0..SUPERSAMPLE {
+                for sx in 0..SUPERSAMPLE {
+                    let u = ((x as f32) + ((sx as f32) + 0.5) / (SUPERSAMPLE as f32)) / w;
+                    let v = ((y as f32) + ((sy as f32) + 0.5) / (SUPERSAMPLE as f32)) / h;
+                    sum += self.value(u, v, w / h, peak);
+                }
+            }
+            pixel.0 = [sum / ((SUPERSAMPLE * SUPERSAMPLE) as f32); 3];
+        }
+        image::DynamicImage::ImageRgb32F(image)
+    }
+
+    /// Scene value at `(u, v)` in 0..1 frame coordinates of a frame with
+    /// the given width to height `aspect`
+    fn value(&self, u: f32, v: f32, aspect: f32, peak: f32) -> f32 {
+        match self {
+            Scene::GrayRamp => peak * (2.0f32).powf(-RAMP_STOPS * (1.0 - u)),
+            Scene::Star => star(u, v, aspect),
+            Scene::Highlights => highlights(u, v, aspect, peak),
+            Scene::Zones => GRAY * (2.0f32).powi(((u * 11.0).floor() as i32).min(10) - 5),
+            Scene::Chart => {
+                // star and highlights side by side on top, zones and the
+                // ramp in strips below
+                let half = aspect / 2.0 / 0.6;
+                match (u < 0.5, v < 0.6) {
+                    (true, true) => Scene::Star.value(u * 2.0, v / 0.6, half, peak),
+                    (false, true) => Scene::Highlights.value(u * 2.0 - 1.0, v / 0.6, half, peak),
+                    (_, false) if v < 0.8 => Scene::Zones.value(u, (v - 0.6) / 0.2, aspect / 0.2, peak),
+                    _ => Scene::GrayRamp.value(u, (v - 0.8) / 0.2, aspect / 0.2, peak),
+                }
+            }
+        }
+    }
+}
+
+fn star(u: f32, v: f32, aspect: f32) -> f32 {
+    // centred circle filling the shorter side
+    let (dx, dy) = ((u - 0.5) * aspect.max(1.0), (v - 0.5) / aspect.min(1.0));
+    let r = (dx * dx + dy * dy).sqrt();
+    if r > 0.45 {
+        return GRAY;
+    }
+    let turn = (dy.atan2(dx) / std::f32::consts::TAU).rem_euclid(1.0);
+    if ((turn * (SPOKES as f32)).floor() as u32).is_multiple_of(2) { 1.0 } else { DARK }
+}
+
+fn highlights(u: f32, v: f32, aspect: f32, peak: f32) -> f32 {
+    // 4×4 grid of discs, each brighter than the last by an even number of
+    // stops, from diffuse white to the peak
+    const GRID: f32 = 4.0;
+    let (column, row) = ((u * GRID).floor().min(GRID - 1.0), (v * GRID).floor().min(GRID - 1.0));
+    let index = row * GRID + column;
+    let (cx, cy) = ((column + 0.5) / GRID, (row + 0.5) / GRID);
+    let (dx, dy) = ((u - cx) * aspect.max(1.0), (v - cy) / aspect.min(1.0));
+    if (dx * dx + dy * dy).sqrt() > 0.01 {
+        return DARK;
+    }
+    let stops = peak.max(1.0).log2();
+    (2.0f32).powf((stops * index) / (GRID * GRID - 1.0))
+}
@@ -0,0 +1,379 @@
+//! Sensitometry: characteristic curves measured from a step wedge and
+//! ISO speed calibration against them
+//!
+//! Input values are treated as film plane illuminance in units of
+//! [`REFERENCE_LUX`], unless the run gives its own, so a pixel receives
+//! `value * REFERENCE_LUX * shutter_seconds` lux seconds. Calibration
+//! solves for the [`ExposureScale`] that lands the ISO speed point, a
+//! density of 0.1 above base plus fog, at `0.8 / ISO` lux seconds.
+
+use crate::diffusion::Agitation;
+use crate::error::{ Error, Result };
+use crate::params::Params;
+use crate::render::Polarity;
+use crate::pipeline;
+use crate::stock::RECIPROCITY_SECONDS;
+use crate::temporal::Shutter;
+use crate::units::{ ExposureScale, Lux, LuxSeconds };
+
+/// steps of the wedge
+pub const STEPS: usize = 21;
+/// log exposure increment between steps, a standard 0.15 step tablet
+pub const STEP_LOG: f32 = 0.15;
+/// illuminance of an input value of 1.0, chosen so that middle gray (0.18)
+/// at 1/125 s is the metered exposure for ISO 100
+pub const REFERENCE_LUX: Lux = Lux(69.4);
+/// density above base plus fog defining the speed point
+pub const SPEED_DENSITY: f32 = 0.1;
+/// grains per pixel used when the caller does not match a run's density
+pub const DEFAULT_GRAINS_PER_PIXEL: f32 = 8.0;
+
+/// side of each wedge step in pixels
+const STEP_SIZE: u32 = 24;
+/// size of the knife edge target in pixels
+const EDGE_SIZE: (u32, u32) = (128, 256);
+/// input values either side of the edge, two stops below and above
+/// middle gray
+const EDGE_VALUES: [f32; 2] = [0.045, 0.72];
+/// half width in pixels of the window kept around the edge, which keeps
+/// the grain noise of the flat sides out of the line spread
+const EDGE_WINDOW: f32 = 24.0;
+/// calibration stops once the exposure moves less than this in log units
+const TOLERANCE: f32 = 0.01;
+const MAX_ITERATIONS: usize = 6;
+
+/// Density against log exposure, in simulation units of
+/// `intensity * exposure_time`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    pub log_exposure: Vec<f32>,
+    pub density: Vec<f32>,
+}
+
+impl Curve {
+    /// Base plus fog, read from the least exposed step
+    pub fn fog(&self) -> f32 {
+        self.density.first().copied().unwrap_or(0.0)
+    }
+
+    /// Log exposure at which the curve first rises `SPEED_DENSITY` above
+    /// fog, interpolated between steps
+    pub fn speed_point(&self) -> Option<f32> {
+        let target = self.fog() + SPEED_DENSITY;
+        let points: Vec<(f32, f32)> = self.log_exposure
+            .iter()
+            .copied()
+            .zip(self.density.iter().copied())
+            .collect();
+        points.windows(2).find_map(|pair| {
+            let [(x0, d0), (x1, d1)] = [pair[0], pair[1]];
+            (d0 < target && d1 >= target).then(|| x0 + ((target - d0) / (d1 - d0)) * (x1 - x0))
+        })
+    }
+
+    /// Whether the most exposed steps have stopped gaining density
+    pub fn shoulder_reached(&self) -> bool {
+        let top = &self.density[self.density.len().saturating_sub(3)..];
+        let (low, high) = top
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(low, high), &d| (low.min(d), high.max(d)));
+        top.len() == 3 && high - low < 0.02
+    }
+}
+
+/// Step wedge with one step per value, left to right
+pub fn wedge(values: &[f32]) -> image::DynamicImage {
+    let mut wedge = image::Rgb32FImage::new(STEP_SIZE * (values.len() as u32), STEP_SIZE);
+    for (x, _, pixel) in wedge.enumerate_pixels_mut() {
+        pixel.0 = [values[(x / STEP_SIZE) as usize]; 3];
+    }
+    image::DynamicImage::ImageRgb32F(wedge)
+}
+
+/// Input values of the standard wedge, from the densest step to clear.
+/// Step `i` transmits `10^-(STEP_LOG * (STEPS - 1 - i))`.
+pub fn step_tablet() -> Vec<f32> {
+    (0..STEPS).map(|step| (10.0f32).powf(-STEP_LOG * ((STEPS - 1 - step) as f32))).collect()
+}
+
+/// Params for exposing a wedge with the emulsion and processing of
+/// `params`, dropping everything tied to a particular frame
+fn wedge_params(params: &Params, grains_per_pixel: f32) -> Params {
+    Params {
+        grains_per_pixel: Some(grains_per_pixel),
+        iso: None,
+        lens_filter: None,
+        nd_stops: 0.0,
+        nd_cast: [1.0; 3],
+        polarizer: 0.0,
+        shutter: Shutter::Leaf,
+        xray_scans: 0,
+        heat_fog: 0.0,
+        static_marks: 0,
+        agitation: Agitation::Continuous,
+        development_mottle: 0.0,
+        halation_strength: 0.0,
+        clayden_pattern: None,
+        pour_artifacts: 0.0,
+        edge_fog: 0.0,
+        drying_marks: 0.0,
+        fingerprints: 0,
+        silvering: 0.0,
+        scan_dust: 0,
+        infrared_cleaning: false,
+        sharpen_amount: 0.0,
+        ambrotype: false,
+        contact_print: false,
+        projection: false,
+        polarity: Polarity::Negative,
+        transfer: None,
+        crop: None,
+        crop_paste: false,
+        emulsion_width: None,
+        frame: None,
+        format_width_mm: None,
+        output_width: None,
+        latent_export: None,
+        latent_import: None,
+        dump_stages: None,
+        development_history: None,
+        photon_accounting: false,
+        timelapse: None,
+        mask: None,
+        mask_composite: false,
+        ..params.clone()
+    }
+}
+
+/// Expose and develop a wedge of `values` and read back the mean
+/// transmission of every step from the render
+pub fn read_wedge(params: &Params, values: &[f32], grains_per_pixel: f32) -> Result<Vec<f32>> {
+    let render = render_wedge(params, values, grains_per_pixel)?.to_luma32f();
+    let readings = step_means(values.len(), |x, y| render.get_pixel(x, y).0);
+    Ok(readings.into_iter().map(|[luma]| luma).collect())
+}
+
+/// Like [`read_wedge`], with the mean transmission of every channel
+pub fn read_wedge_rgb(params: &Params, values: &[f32], grains_per_pixel: f32) -> Result<Vec<[f32; 3]>> {
+    let render = render_wedge(params, values, grains_per_pixel)?.to_rgb32f();
+    Ok(step_means(values.len(), |x, y| render.get_pixel(x, y).0))
+}
+
+fn render_wedge(params: &Params, values: &[f32], grains_per_pixel: f32) -> Result<image::DynamicImage> {
+    let params = wedge_params(params, grains_per_pixel);
+    let render = pipeline::process(&wedge(values), &params)?;
+    Ok(image::DynamicImage::ImageRgba8(render))
+}
+
+/// Mean of `pixel` over the middle of each of `steps` steps, clear of the
+/// neighbours
+fn step_means<const N: usize>(steps: usize, pixel: impl Fn(u32, u32) -> [f32; N]) -> Vec<[f32; N]> {
+    let inset = STEP_SIZE / 4;
+    (0..steps as u32)
+        .map(|step| {
+            let x0 = step * STEP_SIZE + inset;
+            let mut sum = [0.0; N];
+            let mut count = 0;
+            for y in inset..STEP_SIZE - inset {
+                for x in x0..x0 + STEP_SIZE - 2 * inset {
+                    for (s, v) in sum.iter_mut().zip(pixel(x, y)) {
+                        *s += v;
+                    }
+                    count += 1;
+                }
+            }
+            sum.map(|s| s / (count.max(1) as f32))
+        })
+        .collect()
+}
+
+/// Expose and develop the step tablet and read back the density of every
+/// step, as a transmission densitometer would
+pub fn measure(params: &Params, grains_per_pixel: f32) -> Result<Curve> {
+    let values = step_tablet();
+    let transmission = read_wedge(params, &values, grains_per_pixel)?;
+    Ok(Curve {
+        log_exposure: values
+            .iter()
+            .map(|v| (v * params.exposure_time()).log10())
+            .collect(),
+        density: transmission
+            .iter()
+            .map(|t| -t.max(1e-4).log10())
+            .collect(),
+    })
+}
+
+/// Red, green and blue characteristic curves of the step tablet, read
+/// with the densitometer of `params`
+pub fn measure_channels(params: &Params, grains_per_pixel: f32) -> Result<[Curve; 3]> {
+    let values = step_tablet();
+    let transmission = read_wedge_rgb(params, &values, grains_per_pixel)?;
+    let readings: Vec<[f32; 3]> = transmission
+        .iter()
+        .map(|&rgb| params.densitometer.read(rgb))
+        .collect();
+    let log_exposure: Vec<f32> = values
+        .iter()
+        .map(|v| (v * params.exposure_time()).log10())
+        .collect();
+    Ok(
+        [0, 1, 2].map(|c| Curve {
+            log_exposure: log_exposure.clone(),
+            density: readings
+                .iter()
+                .map(|reading| reading[c])
+                .collect(),
+        })
+    )
+}
+
+/// Modulation transfer of the emulsion and processing of `params`, read
+/// from the density profile across a developed knife edge: spatial
+/// frequency in cycles per millimetre against modulation, 1 at zero
+/// frequency
+pub fn mtf(params: &Params, grains_per_pixel: f32) -> Result<Vec<(f32, f32)>> {
+    let (width, height) = EDGE_SIZE;
+    let mut edge = image::Rgb32FImage::new(width, height);
+    for (x, _, pixel) in edge.enumerate_pixels_mut() {
+        pixel.0 = [EDGE_VALUES[(x >= width / 2) as usize]; 3];
+    }
+    let params = wedge_params(params, grains_per_pixel);
+    let render = pipeline::process(&image::DynamicImage::ImageRgb32F(edge), &params)?;
+    let render = image::DynamicImage::ImageRgba8(render).to_luma32f();
+
+    // edge spread down the columns, differentiated to the line spread
+    let spread: Vec<f32> = (0..width)
+        .map(|x| {
+            (0..height).map(|y| -render.get_pixel(x, y).0[0].max(1e-4).log10()).sum::<f32>() /
+                (height as f32)
+        })
+        .collect();
+    let centre = (width / 2) as f32 - 0.5;
+    let line: Vec<f32> = spread
+        .windows(2)
+        .enumerate()
+        .map(|(x, pair)| {
+            let offset = ((x as f32) + 0.5 - centre) / EDGE_WINDOW;
+            let hann = if offset.abs() < 1.0 { 0.5 + 0.5 * (std::f32::consts::PI * offset).cos() } else { 0.0 };
+            (pair[1] - pair[0]) * hann
+        })
+        .collect();
+    let amplitudes = amplitude_spectrum(&line);
+    if amplitudes[0] <= f32::EPSILON {
+        return Err(Error::Parse("the edge developed without contrast, there is no MTF to read".into()));
+    }
+    let pitch_mm = params.grain_pitch_um / 1000.0;
+    Ok(
+        amplitudes
+            .iter()
+            .enumerate()
+            .map(|(k, a)| ((k as f32) / ((line.len() as f32) * pitch_mm), a / amplitudes[0]))
+            .collect()
+    )
+}
+
+/// Magnitudes of the discrete Fourier transform of `values` from zero
+/// frequency up to Nyquist
+pub fn amplitude_spectrum(values: &[f32]) -> Vec<f32> {
+    let n = values.len();
+    let angle = (2.0 * std::f64::consts::PI) / (n.max(1) as f64);
+    (0..=n / 2)
+        .map(|k| {
+            let (re, im) = values
+                .iter()
+                .enumerate()
+                .fold((0.0f64, 0.0f64), |(re, im), (i, &v)| {
+                    // reduce the phase first to keep it accurate for long inputs
+                    let phase = angle * (((k * i) % n) as f64);
+                    (re + (v as f64) * phase.cos(), im - (v as f64) * phase.sin())
+                });
+            re.hypot(im) as f32
+        })
+        .collect()
+}
+
+/// Solve for the simulation exposure units per lux second that give the
+/// emulsion of `params` the speed `iso`, at a grain density of
+/// `grains_per_pixel` (the rendered density depends on it)
+pub fn calibrate(params: &Params, iso: f32, grains_per_pixel: Option<f32>) -> Result<ExposureScale> {
+    if !(iso > 0.0 && iso.is_finite()) {
+        return Err(Error::Parse(format!("invalid ISO {iso}")));
+    }
+    let grains_per_pixel = grains_per_pixel.unwrap_or(DEFAULT_GRAINS_PER_PIXEL);
+    let speed_lux_seconds = LuxSeconds::speed_point(iso).0;
+    let range = STEP_LOG * ((STEPS - 1) as f32);
+
+    let mut params = params.clone();
+    let mut units_per_lux_second = params.exposure_scale().0;
+    // speed is rated where the stock obeys reciprocity, any failure at
+    // the real shutter time comes on top
+    params.shutter_seconds = params.shutter_seconds.min(RECIPROCITY_SECONDS);
+    params.exposure_seconds = None;
+    let mut converged = false;
+    for iteration in 0..MAX_ITERATIONS {
+        let curve = measure(&params, grains_per_pixel)?;
+        let previous = units_per_lux_second;
+        match curve.speed_point() {
+            Some(log_speed) => {
+                units_per_lux_second = (10.0f32).powf(log_speed) / speed_lux_seconds;
+            }
+            // the wedge missed the speed point, move a full wedge range
+            // unless the curve has already levelled off below it
+            None if curve.density.last().copied().unwrap_or(0.0) < curve.fog() + SPEED_DENSITY => {
+                if curve.shoulder_reached() {
+                    return Err(
+                        Error::Parse(
+                            format!(
+                                "density never rises {SPEED_DENSITY} above fog, develop longer or with a stronger developer"
+                            )
+                        )
+                    );
+                }
+                units_per_lux_second *= (10.0f32).powf(range);
+            }
+            None => {
+                units_per_lux_second /= (10.0f32).powf(range);
+            }
+        }
+        tracing::info!(
+            "Calibration pass {}: {units_per_lux_second} units per lux second",
+            iteration + 1
+        );
+        if (units_per_lux_second / previous).log10().abs() < TOLERANCE {
+            converged = true;
+            break;
+        }
+        // centre the next wedge on the speed point
+        params.exposure_time = Some(speed_lux_seconds * units_per_lux_second * (10.0f32).powf(range / 2.0));
+    }
+    if !converged {
+        // grain noise can keep the speed point moving; the last pass is
+        // the best estimate there is
+        tracing::warn!(
+            "Calibration for ISO {iso} did not settle within {TOLERANCE} log units in {MAX_ITERATIONS} passes, using {units_per_lux_second} units per lux second"
+        );
+    }
+    Ok(ExposureScale(units_per_lux_second))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibrated_speed_point_lands_on_the_iso_exposure() {
+        // the default development stops short of the speed density
+        let params = Params { seed: Some(11), development_time: 1.0, ..Params::default() };
+        let iso = 200.0;
+        let scale = calibrate(&params, iso, None).unwrap();
+        // a wedge centred on the speed point, as calibration reads it
+        let range = STEP_LOG * ((STEPS - 1) as f32);
+        let speed = LuxSeconds::speed_point(iso);
+        let exposed = Params { exposure_time: Some(scale.units(speed) * (10.0f32).powf(range / 2.0)), ..params };
+        let curve = measure(&exposed, DEFAULT_GRAINS_PER_PIXEL).unwrap();
+        let lux_seconds = (10.0f32).powf(curve.speed_point().unwrap()) / scale.0;
+        let error = (lux_seconds / speed.0).log10();
+        assert!(error.abs() < 2.0 * TOLERANCE, "speed point off by {error} log units");
+    }
+}
@@ -0,0 +1,88 @@
+//! Tricolor separations: three black and white negatives exposed through
+//! red, green and blue filters, and their recombination into a color
+//! positive
+
+use crate::error::{ Error, Result };
+use crate::params::Params;
+use crate::pipeline;
+
+/// A taking filter, described by how much of each input channel it passes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeparationFilter {
+    pub name: &'static str,
+    pub transmission: [f32; 3],
+    /// exposure increase compensating for the light the filter absorbs
+    pub factor: f32,
+}
+
+/// Tricolor set with the usual filter factors for panchromatic film
+/// (Wratten 25, 58 and 47)
+pub const TRICOLOR: [SeparationFilter; 3] = [
+    SeparationFilter { name: "red", transmission: [1.0, 0.0, 0.0], factor: 8.0 },
+    SeparationFilter { name: "green", transmission: [0.0, 1.0, 0.0], factor: 6.0 },
+    SeparationFilter { name: "blue", transmission: [0.0, 0.0, 1.0], factor: 6.0 },
+];
+
+/// Parse comma separated red, green and blue filter factors
+pub fn parse_factors(text: &str) -> Result<[f32; 3]> {
+    let factors: Vec<f32> = text
+        .split(',')
+        .map(|p| p.trim().parse::<f32>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| Error::Parse(format!("invalid filter factors '{text}'")))?;
+    match factors[..] {
+        [r, g, b] if r > 0.0 && g > 0.0 && b > 0.0 => Ok([r, g, b]),
+        _ => Err(Error::Parse(format!("invalid filter factors '{text}', expected R,G,B"))),
+    }
+}
+
+/// Expose one negative through `filter`
+pub fn expose(
+    image: &image::DynamicImage,
+    params: &Params,
+    filter: &SeparationFilter
+) -> Result<image::RgbaImage> {
+    tracing::info!("Exposing {} separation", filter.name);
+    let mut filtered = image.to_rgb32f();
+    for pixel in filtered.pixels_mut() {
+        for (value, &t) in pixel.0.iter_mut().zip(&filter.transmission) {
+            *value *= t;
+        }
+    }
+    let mut params = params.clone();
+    params.exposure_time = Some(params.exposure_time() * filter.factor);
+    pipeline::process(&image::DynamicImage::ImageRgb32F(filtered), &params)
+}
+
+/// Expose the red, green and blue separations of `image`
+pub fn expose_all(
+    image: &image::DynamicImage,
+    params: &Params,
+    factors: [f32; 3]
+) -> Result<[image::RgbaImage; 3]> {
+    let [red, green, blue] = [0, 1, 2].map(|i| SeparationFilter { factor: factors[i], ..TRICOLOR[i] });
+    Ok([expose(image, params, &red)?, expose(image, params, &green)?, expose(image, params, &blue)?])
+}
+
+/// Assemble a color positive from red, green and blue separation
+/// negatives. Each negative is inverted and stretched to the full range,
+/// which stands in for printing every separation to its own matrix with
+/// the exposure set for a neutral result.
+pub fn recombine(negatives: &[image::RgbaImage; 3]) -> Result<image::RgbImage> {
+    let (width, height) = negatives[0].dimensions();
+    if negatives.iter().any(|n| n.dimensions() != (width, height)) {
+        return Err(Error::Parse("separations differ in size".into()));
+    }
+    let mut positive = image::RgbImage::new(width, height);
+    for (channel, negative) in negatives.iter().enumerate() {
+        let luma = image::DynamicImage::ImageRgba8(negative.clone()).to_luma8();
+        let (low, high) = luma
+            .pixels()
+            .fold((u8::MAX, u8::MIN), |(low, high), p| (low.min(p.0[0]), high.max(p.0[0])));
+        let range = (high.saturating_sub(low) as f32).max(1.0);
+        for (out, p) in positive.pixels_mut().zip(luma.pixels()) {
+            out.0[channel] = ((1.0 - ((p.0[0] - low) as f32) / range) * 255.0).round() as u8;
+        }
+    }
+    Ok(positive)
+}
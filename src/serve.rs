@@ -0,0 +1,454 @@
+//! HTTP service mode
+//!
+//! `POST /process` takes the encoded input image as the request body and
+//! returns the processed frame as a chunked PNG stream, 16-bit when the
+//! output encoding is Cineon. Parameters are read from the query string
+//! (`?num_grains=2000000&dt=0.05`) and from a JSON object in the
+//! `X-Halide-Params` header, the header winning on conflicts.
+//! `GET /health` answers `ok` for load balancers.
+//!
+//! Requests share a cache of intermediate stages, so resubmitting a frame
+//! with only development or rendering changed skips exposure. Defaults can
+//! come from a watched JSON file, reloaded for later requests whenever it
+//! is saved.
+//!
+//! Parameters naming files, read or written, are refused in a request:
+//! a client could otherwise read or overwrite anything the server can.
+//! Such files can only come from the server's own defaults, and grain
+//! counts, sizes and thread counts a request asks for are capped.
+//! Connections beyond a limit are answered `503` rather than given a thread
+//! each, and ones that go idle are dropped.
+
+use std::io::{ BufRead, BufReader, Read, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::path::Path;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::{ Arc, RwLock };
+use std::time::Duration;
+
+use image::{ DynamicImage, ImageEncoder };
+
+use crate::cache::StageCache;
+use crate::cineon::{ self, OutputEncoding };
+use crate::error::{ Error, Result };
+use crate::json;
+use crate::params::Params;
+use crate::pipeline;
+use crate::reload::Watched;
+
+/// largest request body accepted, in bytes
+const MAX_BODY: usize = 64 * 1024 * 1024;
+/// largest request head accepted, in bytes
+const MAX_HEAD: usize = 64 * 1024;
+/// connections handled at once; more are turned away
+const MAX_CONNECTIONS: usize = 16;
+/// how long a client may go silent while sending a request or taking the
+/// response, so idle connections give their slot back
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// largest grain count a request may ask for
+const MAX_GRAINS: usize = 50_000_000;
+/// largest mean grains per pixel a request may ask for
+const MAX_GRAINS_PER_PIXEL: f32 = 1000.0;
+/// largest supersampling factor a request may ask for
+const MAX_SUPERSAMPLE: u32 = 4;
+/// largest emulsion or output width a request may ask for, in pixels
+const MAX_WIDTH: u32 = 16_384;
+
+/// Parameters that name files, which only the server's defaults may set
+const LOCAL_ONLY: &[&str] = &[
+    "params",
+    "mask",
+    "halation_psf",
+    "halation_export",
+    "clayden_pattern",
+    "polarizer_mask",
+    "dump_stages",
+    "latent_import",
+    "latent_export",
+    "development_history",
+    "timelapse",
+];
+
+/// Whether `key` names a file and so is refused in a request
+pub fn is_local_only(key: &str) -> bool {
+    LOCAL_ONLY.contains(&key.replace('-', "_").as_str())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// One of the connections counted against [`MAX_CONNECTIONS`], given
+/// back when dropped, even by a panicking handler
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Listen on `addr` and serve requests until the process is stopped,
+/// with `watch` applied over `defaults` and reloaded as it changes
+pub fn serve(addr: &str, defaults: Params, watch: Option<&Path>) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    tracing::info!("Listening on http://{}", listener.local_addr()?);
+    let defaults = match watch {
+        Some(path) => {
+            let mut watched = Watched::new(path, defaults);
+            let defaults = Arc::new(RwLock::new(watched.load()?));
+            let shared = Arc::clone(&defaults);
+            std::thread::spawn(move || {
+                loop {
+                    let params = watched.wait();
+                    *shared.write().unwrap() = params;
+                }
+            });
+            defaults
+        }
+        None => Arc::new(RwLock::new(defaults)),
+    };
+    // look development sends the same frame over and over with small
+    // changes, so intermediate stages are shared between requests
+    let cache = Arc::new(StageCache::new());
+    let open = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                tracing::warn!("Failed to accept connection: {err}");
+                continue;
+            }
+        };
+        if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            open.fetch_sub(1, Ordering::SeqCst);
+            let mut stream = stream;
+            if let Err(err) = respond_text(&mut stream, 503, "Service Unavailable", "too many connections") {
+                tracing::warn!("Connection failed: {err}");
+            }
+            continue;
+        }
+        let defaults = defaults.read().unwrap().clone();
+        let cache = Arc::clone(&cache);
+        let slot = Slot(Arc::clone(&open));
+        std::thread::spawn(move || {
+            let _slot = slot;
+            if let Err(err) = handle_connection(stream, &defaults, &cache) {
+                tracing::warn!("Connection failed: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, defaults: &Params, cache: &StageCache) -> Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+
+    let request = match read_request(&mut reader) {
+        Ok(request) => request,
+        Err(err) => {
+            return respond_text(&mut stream, 400, "Bad Request", &err.to_string());
+        }
+    };
+    tracing::info!("{} {}", request.method, request.path);
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => respond_text(&mut stream, 200, "OK", "ok"),
+        ("POST", "/process") => {
+            let result = request_params(&request, defaults).and_then(|params| {
+                render(&image::load_from_memory(&request.body)?, &params, Some(cache))
+            });
+            match result {
+                Ok(output) => stream_png(&mut stream, &output),
+                Err(err) => respond_text(&mut stream, 400, "Bad Request", &err.to_string()),
+            }
+        }
+        (_, "/health" | "/process") =>
+            respond_text(&mut stream, 405, "Method Not Allowed", "method not allowed"),
+        _ => respond_text(&mut stream, 404, "Not Found", "not found"),
+    }
+}
+
+/// The frame as a response carries it: the display image, or 16-bit
+/// Cineon density when that is the output encoding
+pub fn render(image: &DynamicImage, params: &Params, cache: Option<&StageCache>) -> Result<DynamicImage> {
+    match params.encoding() {
+        OutputEncoding::Display => Ok(DynamicImage::ImageRgba8(pipeline::process_cached(image, params, cache)?)),
+        OutputEncoding::Cineon => {
+            let density = pipeline::process_density(image, params, cache)?;
+            Ok(DynamicImage::ImageRgb16(cineon::encode(&density)))
+        }
+    }
+}
+
+fn request_params(request: &Request, defaults: &Params) -> Result<Params> {
+    let mut params = defaults.clone();
+    for pair in request.query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let key = percent_decode(key);
+        refuse_local_only(&key)?;
+        params.set(&key, &percent_decode(value))?;
+    }
+    if let Some(blob) = request.header("X-Halide-Params") {
+        let value = json::parse(blob)?;
+        if let json::Value::Object(members) = &value {
+            for (key, _) in members {
+                refuse_local_only(key)?;
+            }
+        }
+        params.apply_json(&value)?;
+    }
+    check_limits(&params, defaults)?;
+    Ok(params)
+}
+
+/// Refuse sizes a request changed beyond what one request may allocate;
+/// the server's own defaults are trusted
+fn check_limits(params: &Params, defaults: &Params) -> Result<()> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let over = |key: &str, limit: String| Err(Error::Parse(format!("{key} is limited to {limit} in a request")));
+    if params.num_grains != defaults.num_grains && params.num_grains > MAX_GRAINS {
+        return over("num_grains", MAX_GRAINS.to_string());
+    }
+    if params.grains_per_pixel != defaults.grains_per_pixel &&
+        params.grains_per_pixel.is_some_and(|density| density.is_nan() || density > MAX_GRAINS_PER_PIXEL) {
+        return over("grains_per_pixel", MAX_GRAINS_PER_PIXEL.to_string());
+    }
+    if params.supersample != defaults.supersample && params.supersample > MAX_SUPERSAMPLE {
+        return over("supersample", MAX_SUPERSAMPLE.to_string());
+    }
+    let stage_threads = params.stage_threads.iter().map(|&(_, n)| n).max();
+    if params.threads != defaults.threads && params.threads.is_some_and(|n| n > threads) {
+        return over("threads", threads.to_string());
+    }
+    if params.stage_threads != defaults.stage_threads && stage_threads.is_some_and(|n| n > threads) {
+        return over("stage_threads", threads.to_string());
+    }
+    for (key, width, default) in [
+        ("emulsion_width", params.emulsion_width, defaults.emulsion_width),
+        ("output_width", params.output_width, defaults.output_width),
+    ] {
+        if width != default && width.is_some_and(|width| width > MAX_WIDTH) {
+            return over(key, MAX_WIDTH.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// One line of the request head, which must end before the head's limit
+fn read_head_line(head: &mut std::io::Take<impl BufRead>, line: &mut String) -> Result<()> {
+    head.read_line(line)?;
+    if !line.ends_with('\n') {
+        let reason = if head.limit() == 0 { "request head too large" } else { "connection closed in request head" };
+        return Err(Error::Parse(reason.into()));
+    }
+    Ok(())
+}
+
+fn refuse_local_only(key: &str) -> Result<()> {
+    if is_local_only(key) {
+        return Err(Error::Parse(format!("parameter '{key}' names a file and can only be set on the server")));
+    }
+    Ok(())
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<Request> {
+    // the head is read through a limit, so a line that never ends cannot
+    // grow without bound
+    let mut head = reader.by_ref().take(MAX_HEAD as u64);
+    let mut line = String::new();
+    read_head_line(&mut head, &mut line)?;
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    if method.is_empty() || target.is_empty() {
+        return Err(Error::Parse("malformed request line".into()));
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        read_head_line(&mut head, &mut line)?;
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = trimmed.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let mut request = Request { method, path, query, headers, body: Vec::new() };
+    let length = match request.header("Content-Length") {
+        Some(value) =>
+            value
+                .parse::<usize>()
+                .map_err(|_| Error::Parse("invalid Content-Length".into()))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(Error::Parse("request body too large".into()));
+    }
+    request.body.resize(length, 0);
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+fn respond_text(stream: &mut TcpStream, status: u16, reason: &str, body: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}\n",
+        body.len() + 1
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Encode straight into the socket so large frames start arriving before
+/// the whole PNG has been produced
+fn stream_png(stream: &mut TcpStream, image: &DynamicImage) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut chunked = ChunkedWriter { inner: stream };
+    image::codecs::png::PngEncoder
+        ::new(&mut chunked)
+        .write_image(image.as_bytes(), image.width(), image.height(), image.color().into())?;
+    chunked.finish()
+}
+
+/// `Transfer-Encoding: chunked` framing over a writer
+struct ChunkedWriter<'a, W: Write> {
+    inner: &'a mut W,
+}
+
+impl<W: Write> ChunkedWriter<'_, W> {
+    fn finish(self) -> Result<()> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:x}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(query: &str, header: Option<&str>) -> Request {
+        Request {
+            method: "POST".into(),
+            path: "/process".into(),
+            query: query.into(),
+            headers: header.map(|blob| ("X-Halide-Params".to_string(), blob.to_string())).into_iter().collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reads_a_request() {
+        let text = b"POST /process?dt=0.1 HTTP/1.1\r\nContent-Length: 3\r\nX-Halide-Params: {}\r\n\r\nabcextra";
+        let request = read_request(&mut &text[..]).unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str(), request.query.as_str()), (
+            "POST",
+            "/process",
+            "dt=0.1",
+        ));
+        assert_eq!(request.header("x-halide-params"), Some("{}"));
+        assert_eq!(request.body, b"abc");
+    }
+
+    #[test]
+    fn head_lines_are_limited() {
+        let endless = vec![b'a'; MAX_HEAD * 2];
+        let Err(err) = read_request(&mut &endless[..]) else {
+            panic!("an endless line is refused");
+        };
+        assert!(err.to_string().contains("too large"), "{err}");
+        let mut long = b"GET /health HTTP/1.1\r\nX-Pad: ".to_vec();
+        long.extend(std::iter::repeat_n(b'a', MAX_HEAD));
+        long.extend_from_slice(b"\r\n\r\n");
+        assert!(read_request(&mut &long[..]).is_err());
+        assert!(read_request(&mut &b"GET /health HTTP/1.1\r\nHost: x"[..]).is_err());
+    }
+
+    #[test]
+    fn requests_cannot_name_files_or_ask_for_too_much() {
+        let defaults = Params::default();
+        assert!(request_params(&request("dt=0.1", Some(r#"{"num_grains":"1000"}"#)), &defaults).is_ok());
+        for (query, header) in [
+            ("mask=%2Fetc%2Fpasswd", None),
+            ("", Some(r#"{"latent-export":"/tmp/grains.csv"}"#)),
+            ("num_grains=1000000000000", None),
+            ("", Some(r#"{"supersample":64}"#)),
+            ("output_width=1000000", None),
+            ("threads=100000", None),
+        ] {
+            assert!(request_params(&request(query, header), &defaults).is_err(), "{query} {header:?}");
+        }
+        // the server's own defaults are not held to the limits
+        let big = Params { num_grains: MAX_GRAINS * 2, ..Params::default() };
+        assert!(request_params(&request("dt=0.1", None), &big).is_ok());
+    }
+}
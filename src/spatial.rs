@@ -0,0 +1,224 @@
+//! Spatial index over the grains of an emulsion, for work that only
+//! concerns part of it
+//!
+//! The grid is divided into square cells and the grain indices sorted by
+//! cell, so the grains of a region are found by visiting the cells it
+//! overlaps instead of scanning every grain. Within a cell the grains keep
+//! their order in the emulsion. The index holds positions only and stays
+//! valid while grains are exposed and developed, as long as none is added,
+//! removed or moved.
+
+use crate::field::Rect;
+use crate::halide::Halide;
+
+pub struct GrainIndex {
+    /// side of a cell in grid pixels
+    cell: u32,
+    columns: u32,
+    rows: u32,
+    /// start of every cell's run in `order`, plus its end
+    starts: Vec<usize>,
+    /// grain indices sorted by cell
+    order: Vec<usize>,
+}
+
+impl GrainIndex {
+    /// Index the grains lying on a `width`×`height` grid in cells of
+    /// `cell` pixels; grains off the grid are left out
+    pub fn new(grains: &[Halide], width: u32, height: u32, cell: u32) -> Self {
+        let cell = cell.max(1);
+        let (columns, rows) = (width.div_ceil(cell), height.div_ceil(cell));
+        let cell_of = |grain: &Halide| {
+            let on_grid = grain.x < (width as usize) && grain.y < (height as usize);
+            on_grid.then(|| (grain.y / (cell as usize)) * (columns as usize) + grain.x / (cell as usize))
+        };
+
+        // counting sort: sizes, then starts, then fill in grain order
+        let mut starts = vec![0; (columns as usize) * (rows as usize) + 1];
+        for grain in grains {
+            if let Some(c) = cell_of(grain) {
+                starts[c + 1] += 1;
+            }
+        }
+        for c in 1..starts.len() {
+            starts[c] += starts[c - 1];
+        }
+        let mut next = starts.clone();
+        let mut order = vec![0; starts[starts.len() - 1]];
+        for (i, grain) in grains.iter().enumerate() {
+            if let Some(c) = cell_of(grain) {
+                order[next[c]] = i;
+                next[c] += 1;
+            }
+        }
+        Self { cell, columns, rows, starts, order }
+    }
+
+    /// Side of a cell in grid pixels
+    pub fn cell_size(&self) -> u32 {
+        self.cell
+    }
+
+    /// Number of indexed grains
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Grains in the cell at `column`, `row`, in emulsion order
+    pub fn in_cell(&self, column: u32, row: u32) -> &[usize] {
+        if column >= self.columns || row >= self.rows {
+            return &[];
+        }
+        let c = (row as usize) * (self.columns as usize) + (column as usize);
+        &self.order[self.starts[c]..self.starts[c + 1]]
+    }
+
+    /// Grains on the pixels of `rect`, cell by cell
+    pub fn in_rect<'a>(&'a self, grains: &'a [Halide], rect: Rect) -> impl Iterator<Item = usize> + 'a {
+        let (x1, y1) = (rect.x.saturating_add(rect.width), rect.y.saturating_add(rect.height));
+        self.cells(rect.x, rect.y, x1, y1)
+            .filter(move |&i| {
+                let (x, y) = (grains[i].x as u32, grains[i].y as u32);
+                x >= rect.x && x < x1 && y >= rect.y && y < y1
+            })
+    }
+
+    /// Grains whose pixel centre lies within `radius` pixels of `(x, y)`,
+    /// cell by cell
+    pub fn in_circle<'a>(
+        &'a self,
+        grains: &'a [Halide],
+        x: f32,
+        y: f32,
+        radius: f32
+    ) -> impl Iterator<Item = usize> + 'a {
+        let radius = radius.max(0.0);
+        let low = |v: f32| (v - radius).floor().max(0.0) as u32;
+        let high = |v: f32| ((v + radius).ceil().max(0.0) as u32).saturating_add(1);
+        self.cells(low(x), low(y), high(x), high(y)).filter(move |&i| {
+            let dx = (grains[i].x as f32) + 0.5 - x;
+            let dy = (grains[i].y as f32) + 0.5 - y;
+            dx * dx + dy * dy <= radius * radius
+        })
+    }
+
+    /// Grains of every cell overlapping pixels `x0..x1`, `y0..y1`
+    fn cells(&self, x0: u32, y0: u32, x1: u32, y1: u32) -> impl Iterator<Item = usize> + '_ {
+        let columns = x0 / self.cell..x1.div_ceil(self.cell).min(self.columns);
+        let rows = y0 / self.cell..y1.div_ceil(self.cell).min(self.rows);
+        rows.flat_map(move |row| {
+            columns.clone().flat_map(move |column| self.in_cell(column, row).iter().copied())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::SmallRng;
+    use rand::{ Rng, SeedableRng };
+
+    use super::*;
+
+    const WIDTH: u32 = 100;
+    const HEIGHT: u32 = 70;
+
+    /// Grains scattered over the grid, a few of them off it
+    fn grains() -> Vec<Halide> {
+        let mut rng = SmallRng::seed_from_u64(7);
+        (0..2000)
+            .map(|_| {
+                let x = rng.random_range(0..WIDTH + 5) as usize;
+                let y = rng.random_range(0..HEIGHT + 5) as usize;
+                Halide::new_with_params(x, y, 0.3, 10, 0.5)
+            })
+            .collect()
+    }
+
+    fn sorted(found: impl Iterator<Item = usize>) -> Vec<usize> {
+        let mut found: Vec<usize> = found.collect();
+        found.sort_unstable();
+        found
+    }
+
+    fn on_grid(grain: &Halide) -> bool {
+        grain.x < (WIDTH as usize) && grain.y < (HEIGHT as usize)
+    }
+
+    #[test]
+    fn cells_hold_every_grain_on_the_grid_in_order() {
+        let grains = grains();
+        let index = GrainIndex::new(&grains, WIDTH, HEIGHT, 8);
+        assert_eq!(index.len(), grains.iter().filter(|g| on_grid(g)).count());
+        let mut seen = Vec::new();
+        for row in 0..HEIGHT.div_ceil(8) {
+            for column in 0..WIDTH.div_ceil(8) {
+                let cell = index.in_cell(column, row);
+                assert!(cell.windows(2).all(|w| w[0] < w[1]));
+                for &i in cell {
+                    assert_eq!(((grains[i].x as u32) / 8, (grains[i].y as u32) / 8), (column, row));
+                }
+                seen.extend_from_slice(cell);
+            }
+        }
+        assert_eq!(seen.len(), index.len());
+        assert!(index.in_cell(WIDTH, 0).is_empty());
+    }
+
+    #[test]
+    fn rect_queries_match_a_scan() {
+        let grains = grains();
+        for cell in [1, 7, 8, 32, 200] {
+            let index = GrainIndex::new(&grains, WIDTH, HEIGHT, cell);
+            for rect in [
+                Rect::new(0, 0, WIDTH, HEIGHT),
+                Rect::new(8, 8, 8, 8),
+                Rect::new(7, 9, 1, 1),
+                Rect::new(13, 5, 41, 29),
+                Rect::new(90, 60, 50, 50),
+                Rect::new(120, 0, 10, 10),
+                Rect::new(3, 4, u32::MAX, u32::MAX),
+            ] {
+                let (x1, y1) = ((rect.x as u64) + (rect.width as u64), (rect.y as u64) + (rect.height as u64));
+                let expected: Vec<usize> = (0..grains.len())
+                    .filter(|&i| {
+                        let (x, y) = (grains[i].x as u64, grains[i].y as u64);
+                        on_grid(&grains[i]) && x >= (rect.x as u64) && x < x1 && y >= (rect.y as u64) && y < y1
+                    })
+                    .collect();
+                assert_eq!(sorted(index.in_rect(&grains, rect)), expected, "{rect:?} in cells of {cell}");
+            }
+        }
+    }
+
+    #[test]
+    fn circle_queries_match_a_scan() {
+        let grains = grains();
+        for cell in [1, 7, 8, 32] {
+            let index = GrainIndex::new(&grains, WIDTH, HEIGHT, cell);
+            for (x, y, radius) in [
+                (50.0, 35.0, 10.0),
+                (8.0, 8.0, 0.5),
+                (7.5, 7.5, 0.0),
+                (0.2, 0.7, 3.3),
+                (99.9, 69.9, 6.0),
+                (15.49, 23.51, 8.01),
+                (-4.0, 10.0, 6.0),
+                (50.0, 35.0, 500.0),
+            ] {
+                let expected: Vec<usize> = (0..grains.len())
+                    .filter(|&i| {
+                        let dx = (grains[i].x as f32) + 0.5 - x;
+                        let dy = (grains[i].y as f32) + 0.5 - y;
+                        on_grid(&grains[i]) && dx * dx + dy * dy <= radius * radius
+                    })
+                    .collect();
+                let found = sorted(index.in_circle(&grains, x, y, radius));
+                assert_eq!(found, expected, "circle at ({x}, {y}) of {radius} in cells of {cell}");
+            }
+        }
+    }
+}
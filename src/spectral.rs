@@ -0,0 +1,221 @@
+//! Spectral sensitivity of the emulsion: the native blue/UV absorption of
+//! silver halide plus the bands added by sensitizing dyes
+
+use rand::Rng;
+
+use crate::error::{ Error, Result };
+
+/// wavelength range integrated over, in nanometres
+const SPECTRUM_START: f32 = 350.0;
+const SPECTRUM_END: f32 = 800.0;
+const SPECTRUM_STEP: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Gaussian absorption band
+pub struct Band {
+    /// wavelength of peak absorption in nanometres
+    pub peak_nm: f32,
+    /// standard deviation of the band in nanometres
+    pub width_nm: f32,
+    /// quantum efficiency relative to the native absorption
+    pub efficiency: f32,
+}
+
+impl Band {
+    pub const fn new(peak_nm: f32, width_nm: f32, efficiency: f32) -> Self {
+        Self { peak_nm, width_nm, efficiency }
+    }
+
+    pub fn response(&self, wavelength_nm: f32) -> f32 {
+        let d = (wavelength_nm - self.peak_nm) / self.width_nm;
+        self.efficiency * (-0.5 * d * d).exp()
+    }
+}
+
+/// Approximate emission spectra of the red, green and blue channels of an
+/// RGB input, used as a proxy spectrum when no real spectrum is available
+pub const RGB_PRIMARIES: [Band; 3] = [
+    Band::new(610.0, 30.0, 1.0),
+    Band::new(540.0, 30.0, 1.0),
+    Band::new(450.0, 25.0, 1.0),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpectralSensitivity {
+    /// intrinsic absorption of the silver halide crystal
+    pub native: Band,
+    /// bands added by sensitizing dyes adsorbed on the crystal surface
+    pub sensitizers: Vec<Band>,
+}
+
+impl Default for SpectralSensitivity {
+    fn default() -> Self {
+        Self::panchromatic()
+    }
+}
+
+impl SpectralSensitivity {
+    /// Unsensitized emulsion, blue and UV only
+    pub fn blue() -> Self {
+        Self { native: Band::new(420.0, 35.0, 1.0), sensitizers: Vec::new() }
+    }
+
+    /// Green sensitized, blind to red
+    pub fn orthochromatic() -> Self {
+        let mut curve = Self::blue();
+        curve.sensitizers.push(Band::new(550.0, 30.0, 0.8));
+        curve
+    }
+
+    /// Green and red sensitized
+    pub fn panchromatic() -> Self {
+        let mut curve = Self::orthochromatic();
+        curve.sensitizers.push(Band::new(630.0, 30.0, 0.6));
+        curve
+    }
+
+    /// Red sensitization pushed further out, as in high speed films
+    pub fn super_panchromatic() -> Self {
+        let mut curve = Self::orthochromatic();
+        curve.sensitizers.push(Band::new(660.0, 35.0, 0.7));
+        curve
+    }
+
+    /// Parse a preset name, or sensitizing bands as comma separated
+    /// `peak_nm:width_nm:efficiency` triples
+    pub fn parse(text: &str) -> Result<Self> {
+        if text.contains(':') {
+            let sensitizers = text
+                .split(',')
+                .map(|band| {
+                    let values: Vec<f32> = band
+                        .split(':')
+                        .map(|v| v.trim().parse())
+                        .collect::<std::result::Result<_, _>>()
+                        .map_err(|_| Error::Parse(format!("invalid sensitizing band '{band}'")))?;
+                    match values[..] {
+                        [peak_nm, width_nm, efficiency] => Ok(Band::new(peak_nm, width_nm, efficiency)),
+                        _ =>
+                            Err(
+                                Error::Parse(
+                                    format!("sensitizing band '{band}' must be peak_nm:width_nm:efficiency")
+                                )
+                            ),
+                    }
+                })
+                .collect::<Result<_>>()?;
+            return Ok(Self { sensitizers, ..Self::blue() });
+        }
+        match text.trim() {
+            "blue" | "unsensitized" => Ok(Self::blue()),
+            "ortho" | "orthochromatic" => Ok(Self::orthochromatic()),
+            "pan" | "panchromatic" => Ok(Self::panchromatic()),
+            "super-pan" | "super_panchromatic" => Ok(Self::super_panchromatic()),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!("unknown sensitization '{text}', expected blue, ortho, pan or super-pan")
+                    )
+                ),
+        }
+    }
+
+    /// The sensitizing bands in the form [`SpectralSensitivity::parse`]
+    /// reads, `blue` without any
+    pub fn describe(&self) -> String {
+        if self.sensitizers.is_empty() {
+            return "blue".into();
+        }
+        self.sensitizers
+            .iter()
+            .map(|band| format!("{}:{}:{}", band.peak_nm, band.width_nm, band.efficiency))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Relative sensitivity at a wavelength
+    pub fn response(&self, wavelength_nm: f32) -> f32 {
+        self.native.response(wavelength_nm) +
+            self.sensitizers
+                .iter()
+                .map(|band| band.response(wavelength_nm))
+                .sum::<f32>()
+    }
+
+    /// Integrate a spectral power distribution against the sensitivity
+    pub fn integrate(&self, spectrum: impl Fn(f32) -> f32) -> f32 {
+        integrate(|nm| self.response(nm) * spectrum(nm))
+    }
+
+    /// Sensitivity weighted mean wavelength, where the stock sees
+    pub fn mean_wavelength(&self) -> f32 {
+        let total = integrate(|nm| self.response(nm));
+        integrate(|nm| nm * self.response(nm)) / total.max(f32::EPSILON)
+    }
+
+    /// Response to the red, green and blue proxy spectra
+    pub fn rgb_weights(&self) -> [f32; 3] {
+        RGB_PRIMARIES.map(|primary| self.integrate(|nm| primary.response(nm)))
+    }
+
+    /// Precompute the per-channel response of the native band and of each
+    /// sensitizer, scaled so the average grain sees a neutral input at unit
+    /// intensity
+    pub fn rgb_response(&self) -> RgbResponse {
+        let band_rgb = |band: &Band| {
+            RGB_PRIMARIES.map(|primary| integrate(|nm| band.response(nm) * primary.response(nm)))
+        };
+        let total: f32 = self.rgb_weights().iter().sum();
+        let scale = 1.0 / total.max(f32::EPSILON);
+        RgbResponse {
+            native: band_rgb(&self.native).map(|w| w * scale),
+            sensitizers: self.sensitizers
+                .iter()
+                .map(|band| band_rgb(band).map(|w| w * scale))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Red, green and blue response of each absorption band
+pub struct RgbResponse {
+    pub native: [f32; 3],
+    pub sensitizers: Vec<[f32; 3]>,
+}
+
+impl RgbResponse {
+    /// Response of a grain with the nominal dye uptake
+    pub fn nominal(&self) -> [f32; 3] {
+        let mut response = self.native;
+        for band in &self.sensitizers {
+            for (r, w) in response.iter_mut().zip(band) {
+                *r += w;
+            }
+        }
+        response
+    }
+
+    /// Response of one grain. Dye uptake varies from crystal to crystal by
+    /// up to `uptake_variation` around the nominal amount.
+    pub fn sample(&self, uptake_variation: f32, rng: &mut impl Rng) -> [f32; 3] {
+        let mut response = self.native;
+        for band in &self.sensitizers {
+            let uptake = if uptake_variation > 0.0 {
+                1.0 + rng.random_range(-uptake_variation..uptake_variation)
+            } else {
+                1.0
+            };
+            for (r, w) in response.iter_mut().zip(band) {
+                *r += uptake * w;
+            }
+        }
+        response
+    }
+}
+
+/// Integrate `f` over the visible and near spectrum in nanometres
+pub fn integrate(f: impl Fn(f32) -> f32) -> f32 {
+    let steps = ((SPECTRUM_END - SPECTRUM_START) / SPECTRUM_STEP) as usize;
+    (0..=steps).map(|i| f(SPECTRUM_START + (i as f32) * SPECTRUM_STEP) * SPECTRUM_STEP).sum()
+}
@@ -0,0 +1,309 @@
+//! Film and paper stocks: the emulsion properties that stay fixed for a
+//! given product, independent of how it is exposed and processed
+
+use crate::error::{ Error, Result };
+use crate::spectral::{ Band, SpectralSensitivity };
+
+/// exposure time up to which stocks obey reciprocity; speeds are rated
+/// within it
+pub const RECIPROCITY_SECONDS: f32 = 1.0;
+/// red, green and blue density of sensitizing dye left in the emulsion,
+/// per unit of `residual_dye`: mostly green absorbing, a magenta to
+/// purple cast
+const RESIDUAL_DYE: [f32; 3] = [0.45, 1.0, 0.6];
+/// relative transmission of an orange colour negative mask
+const ORANGE_MASK: [f32; 3] = [1.0, 0.4, 0.2];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Halide make-up of the crystals as mole fractions
+pub struct CrystalComposition {
+    pub chloride: f32,
+    pub bromide: f32,
+    pub iodide: f32,
+}
+
+impl Default for CrystalComposition {
+    fn default() -> Self {
+        Self::bromide()
+    }
+}
+
+impl CrystalComposition {
+    /// Mole fractions, normalized to sum to one
+    pub fn new(chloride: f32, bromide: f32, iodide: f32) -> Self {
+        let total = (chloride + bromide + iodide).max(f32::EPSILON);
+        Self {
+            chloride: chloride.max(0.0) / total,
+            bromide: bromide.max(0.0) / total,
+            iodide: iodide.max(0.0) / total,
+        }
+    }
+
+    /// Silver chloride, as in slow contact printing papers
+    pub fn chloride() -> Self {
+        Self::new(1.0, 0.0, 0.0)
+    }
+
+    /// Silver bromide, as in enlarging papers and general films
+    pub fn bromide() -> Self {
+        Self::new(0.0, 1.0, 0.0)
+    }
+
+    /// Iodobromide with a few percent iodide, as in camera films
+    pub fn iodobromide() -> Self {
+        Self::new(0.0, 0.95, 0.05)
+    }
+
+    /// Parse `AgCl`, `AgBr`, `AgBrI` or explicit `chloride:bromide:iodide`
+    /// fractions such as `0.6:0.4:0`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim() {
+            "AgCl" | "chloride" => Ok(Self::chloride()),
+            "AgBr" | "bromide" => Ok(Self::bromide()),
+            "AgBrI" | "iodobromide" => Ok(Self::iodobromide()),
+            "AgClBr" | "chlorobromide" => Ok(Self::new(0.6, 0.4, 0.0)),
+            fractions => {
+                let parts: Vec<f32> = fractions
+                    .split(':')
+                    .map(|p| p.trim().parse::<f32>())
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|_| Error::Parse(format!("invalid crystal composition '{text}'")))?;
+                match parts[..] {
+                    [cl, br, i] if cl + br + i > 0.0 => Ok(Self::new(cl, br, i)),
+                    _ => Err(Error::Parse(format!("invalid crystal composition '{text}'"))),
+                }
+            }
+        }
+    }
+
+    /// Share of chloride among the chloride and bromide host lattice
+    fn chloride_share(&self) -> f32 {
+        self.chloride / (self.chloride + self.bromide).max(f32::EPSILON)
+    }
+
+    /// Intrinsic sensitivity relative to pure silver bromide. Chloride is
+    /// roughly ten times slower; a few percent iodide raises speed by
+    /// introducing lattice defects that trap photoelectrons.
+    pub fn sensitivity(&self) -> f32 {
+        let host = lerp(1.0, 0.1, self.chloride_share());
+        host * (1.0 + 8.0 * self.iodide.min(0.1))
+    }
+
+    /// Native absorption band. The absorption edge moves from ~410 nm for
+    /// chloride to ~480 nm for bromide and further toward green with iodide.
+    pub fn native_band(&self) -> Band {
+        let share = self.chloride_share();
+        let peak = lerp(420.0, 380.0, share) + 300.0 * self.iodide.min(0.1);
+        let width = lerp(35.0, 25.0, share) + 100.0 * self.iodide.min(0.1);
+        Band::new(peak, width, 1.0)
+    }
+
+    /// Development rate relative to silver bromide. Chloride reduces much
+    /// faster, iodide adsorbed on the surface restrains development.
+    pub fn development_rate(&self) -> f32 {
+        let host = lerp(1.0, 2.5, self.chloride_share());
+        host * (1.0 - 4.0 * self.iodide).max(0.3)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stock {
+    pub name: String,
+    /// halide make-up of the grains
+    pub crystal: CrystalComposition,
+    /// spectral sensitization; the native band is taken from `crystal`
+    pub sensitivity: SpectralSensitivity,
+    /// speed of the coating relative to a gelatin emulsion of the same
+    /// crystals; collodion holds far less halide in a far thinner layer
+    pub speed: f32,
+    /// Schwarzschild exponent beyond [`RECIPROCITY_SECONDS`]: effective
+    /// exposure grows as `t^p` rather than `t`, 1 for no reciprocity failure
+    pub reciprocity_exponent: f32,
+    /// sulfur plus gold sensitization level, 0 for a primitive emulsion and
+    /// 1 for a typical optimum; digestion past the optimum mostly adds fog
+    pub chemical_sensitization: f32,
+    /// share of the halation a clear base would give that the backing
+    /// absorbs: 0 for a clear base, 1 for a rem-jet backing
+    pub anti_halation: f32,
+    /// scanned as printing density in Cineon log encoding instead of drawn
+    /// as an image, as motion-picture negatives are
+    pub log_density: bool,
+    /// density of the processed film base, with any gray dye in it
+    pub base_density: f32,
+    /// colour of the base against white as a relative transmission per
+    /// channel: neutral for black and white, orange under a colour
+    /// negative's mask
+    pub base_tint: [f32; 3],
+    /// density of sensitizing dye the processing leaves behind until the
+    /// film is fully washed
+    pub residual_dye: f32,
+}
+
+impl Default for Stock {
+    fn default() -> Self {
+        Self {
+            name: "generic".into(),
+            crystal: CrystalComposition::bromide(),
+            sensitivity: SpectralSensitivity::panchromatic(),
+            speed: 1.0,
+            reciprocity_exponent: 1.0,
+            chemical_sensitization: 0.0,
+            anti_halation: 0.0,
+            log_density: false,
+            base_density: 0.0,
+            base_tint: [1.0; 3],
+            residual_dye: 0.0,
+        }
+    }
+}
+
+impl Stock {
+    /// Look up a built-in stock by name
+    pub fn preset(name: &str) -> Result<Self> {
+        let stock = match name.trim() {
+            "generic" => Self::default(),
+            "iodobromide-film" => Self {
+                name: "iodobromide-film".into(),
+                crystal: CrystalComposition::iodobromide(),
+                sensitivity: SpectralSensitivity::panchromatic(),
+                chemical_sensitization: 1.0,
+                reciprocity_exponent: 0.85,
+                base_density: 0.05,
+                residual_dye: 0.1,
+                ..Self::default()
+            },
+            "bromide-paper" => Self {
+                name: "bromide-paper".into(),
+                crystal: CrystalComposition::bromide(),
+                sensitivity: SpectralSensitivity::blue(),
+                chemical_sensitization: 0.5,
+                reciprocity_exponent: 0.8,
+                ..Self::default()
+            },
+            "chlorobromide-paper" => Self {
+                name: "chlorobromide-paper".into(),
+                crystal: CrystalComposition::new(0.6, 0.4, 0.0),
+                sensitivity: SpectralSensitivity::blue(),
+                chemical_sensitization: 0.5,
+                reciprocity_exponent: 0.8,
+                ..Self::default()
+            },
+            "chloride-paper" => Self {
+                name: "chloride-paper".into(),
+                crystal: CrystalComposition::chloride(),
+                sensitivity: SpectralSensitivity::blue(),
+                chemical_sensitization: 0.5,
+                reciprocity_exponent: 0.8,
+                ..Self::default()
+            },
+            // motion-picture negatives: a rem-jet backing absorbs the light
+            // that would reflect off the base, and the black and white
+            // stock has a gray base that only absorbs some of it. The
+            // colour negatives carry an orange mask.
+            "kodak-5219" | "5219" => Self {
+                name: "kodak-5219".into(),
+                crystal: CrystalComposition::iodobromide(),
+                sensitivity: SpectralSensitivity::panchromatic(),
+                chemical_sensitization: 1.3,
+                anti_halation: 1.0,
+                log_density: true,
+                base_density: 0.25,
+                base_tint: ORANGE_MASK,
+                ..Self::default()
+            },
+            "kodak-5207" | "5207" => Self {
+                name: "kodak-5207".into(),
+                crystal: CrystalComposition::iodobromide(),
+                sensitivity: SpectralSensitivity::panchromatic(),
+                chemical_sensitization: 0.8,
+                anti_halation: 1.0,
+                log_density: true,
+                base_density: 0.25,
+                base_tint: ORANGE_MASK,
+                ..Self::default()
+            },
+            "kodak-double-x" | "double-x" | "5222" => Self {
+                name: "kodak-double-x".into(),
+                crystal: CrystalComposition::iodobromide(),
+                sensitivity: SpectralSensitivity::panchromatic(),
+                chemical_sensitization: 1.0,
+                anti_halation: 0.7,
+                log_density: true,
+                base_density: 0.2,
+                residual_dye: 0.05,
+                ..Self::default()
+            },
+            // wet collodion: iodide-rich crystals with no sensitizing dye
+            // or digestion, blind beyond the blue and around ISO 1
+            "wet-plate" | "collodion" => Self {
+                name: "wet-plate".into(),
+                crystal: CrystalComposition::new(0.0, 0.25, 0.75),
+                sensitivity: SpectralSensitivity::blue(),
+                speed: 0.01,
+                ..Self::default()
+            },
+            _ => {
+                return Err(Error::Parse(format!("unknown stock '{name}'")));
+            }
+        };
+        Ok(stock)
+    }
+
+    /// Sensitivity relative to a silver bromide gelatin emulsion
+    pub fn sensitivity(&self) -> f32 {
+        self.crystal.sensitivity() * self.speed.max(0.0)
+    }
+
+    /// Share of an exposure of `seconds` that is effective, below one
+    /// once reciprocity fails
+    pub fn reciprocity_factor(&self, seconds: f32) -> f32 {
+        if seconds <= RECIPROCITY_SECONDS {
+            return 1.0;
+        }
+        (seconds / RECIPROCITY_SECONDS).powf(self.reciprocity_exponent - 1.0)
+    }
+
+    /// Exposure time that gives the effect of a metered `seconds` once
+    /// reciprocity fails
+    pub fn reciprocity_corrected(&self, seconds: f32) -> f32 {
+        if seconds <= RECIPROCITY_SECONDS {
+            return seconds;
+        }
+        RECIPROCITY_SECONDS * (seconds / RECIPROCITY_SECONDS).powf(1.0 / self.reciprocity_exponent.max(0.01))
+    }
+
+    /// Factor applied to the photons each grain needs for a latent image.
+    /// Sensitivity specks make latent sites form from fewer silver atoms
+    /// regardless of how large the crystal is.
+    pub fn latent_threshold_scale(&self) -> f32 {
+        1.0 / (1.0 + self.chemical_sensitization.max(0.0))
+    }
+
+    /// Fraction of grains developable without any exposure. Grows slowly
+    /// up to the optimum and quickly beyond it.
+    pub fn fog_fraction(&self) -> f32 {
+        (0.002 * ((1.5 * self.chemical_sensitization.max(0.0)).exp() - 1.0)).min(1.0)
+    }
+
+    /// Red, green and blue density of the clear processed film, base plus
+    /// whatever sensitizing dye a wash of `washing` left behind, 1 for a
+    /// complete wash
+    pub fn base_densities(&self, washing: f32) -> [f32; 3] {
+        let dye = self.residual_dye.max(0.0) * (1.0 - washing.clamp(0.0, 1.0));
+        [0, 1, 2].map(|c| {
+            self.base_density.max(0.0) - self.base_tint[c].log10() + dye * RESIDUAL_DYE[c]
+        })
+    }
+
+    /// Sensitivity curve with the native band of the crystal composition
+    pub fn spectral_sensitivity(&self) -> SpectralSensitivity {
+        SpectralSensitivity {
+            native: self.crystal.native_band(),
+            sensitizers: self.sensitivity.sensitizers.clone(),
+        }
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
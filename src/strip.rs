@@ -0,0 +1,98 @@
+//! Several frames on one strip of film: half-frame diptychs, pairs of
+//! 6×6 frames on 120, or any run of inputs exposed side by side
+//!
+//! The frames share one emulsion and go through one development, so what
+//! a dense frame does to the developer near its edge reaches into the
+//! unexposed gap and the frame beside it, as it does on a real roll.
+
+use crate::error::{ Error, Result };
+use crate::field::Rect;
+use crate::frame::{ self, FrameFit, FrameFormat };
+use crate::params::Params;
+use crate::pipeline;
+
+/// Inputs laid out along the film, ready to expose as one image
+pub struct Strip {
+    /// the frames side by side with the gaps between them left unexposed
+    pub image: image::DynamicImage,
+    /// where each frame lies in `image`
+    pub frames: Vec<Rect>,
+    /// physical width of the whole strip in millimetres
+    pub width_mm: f32,
+}
+
+/// Fit each input to `format` as the camera frames it along the film and
+/// lay them side by side, `gap_mm` apart. Every frame is brought to the
+/// height of the tallest fitted input, so the strip keeps its resolution.
+pub fn layout(
+    inputs: &[image::DynamicImage],
+    format: FrameFormat,
+    fit: FrameFit,
+    gap_mm: f32
+) -> Result<Strip> {
+    if inputs.is_empty() {
+        return Err(Error::Parse("a strip needs at least one frame".into()));
+    }
+    let (along_mm, across_mm) = format.along_film_mm();
+    let fitted: Vec<image::DynamicImage> = inputs
+        .iter()
+        .map(|input| frame::fit_aspect(input, along_mm / across_mm, fit))
+        .collect();
+    let height = fitted
+        .iter()
+        .map(|f| f.height())
+        .max()
+        .unwrap_or(1);
+    let frame_width = ((height as f32) * (along_mm / across_mm)).round().max(1.0) as u32;
+    let gap = ((height as f32) * (gap_mm.max(0.0) / across_mm)).round() as u32;
+    let count = fitted.len() as u32;
+
+    let mut strip = image::Rgb32FImage::new(count * frame_width + (count - 1) * gap, height);
+    let mut frames = Vec::with_capacity(fitted.len());
+    for (i, fitted) in fitted.iter().enumerate() {
+        let x = (i as u32) * (frame_width + gap);
+        let resized = image::imageops::resize(
+            &fitted.to_rgb32f(),
+            frame_width,
+            height,
+            image::imageops::FilterType::Triangle
+        );
+        image::imageops::replace(&mut strip, &resized, x as i64, 0);
+        frames.push(Rect::new(x, 0, frame_width, height));
+    }
+    Ok(Strip {
+        image: image::DynamicImage::ImageRgb32F(strip),
+        frames,
+        width_mm: (count as f32) * along_mm + ((count - 1) as f32) * gap_mm.max(0.0),
+    })
+}
+
+/// Expose and develop the strip in one run. The strip sets the physical
+/// frame size unless the emulsion is sized some other way.
+pub fn process(strip: &Strip, params: &Params) -> Result<image::RgbaImage> {
+    let params = Params {
+        frame: None,
+        format_width_mm: params.format_width_mm.or(
+            params.emulsion_width.is_none().then_some(strip.width_mm)
+        ),
+        ..params.clone()
+    };
+    pipeline::process(&strip.image, &params)
+}
+
+/// Cut the frames of a developed strip apart, scaled from the input strip
+/// to the developed one
+pub fn cut(strip: &Strip, developed: &image::RgbaImage) -> Vec<image::RgbaImage> {
+    let scale_x = (developed.width() as f32) / (strip.image.width().max(1) as f32);
+    let scale_y = (developed.height() as f32) / (strip.image.height().max(1) as f32);
+    strip.frames
+        .iter()
+        .map(|frame| {
+            let x = ((frame.x as f32) * scale_x).round() as u32;
+            let y = ((frame.y as f32) * scale_y).round() as u32;
+            let width = ((frame.width as f32) * scale_x).round().max(1.0) as u32;
+            let height = ((frame.height as f32) * scale_y).round().max(1.0) as u32;
+            image::imageops::crop_imm(developed, x, y, width, height).to_image()
+        })
+        .collect()
+}
@@ -0,0 +1,45 @@
+//! Temperature of the developer bath and how it drifts over a batch
+//!
+//! Developer works faster warm and slower cold. A bath mixed at one
+//! temperature and left in the tray settles toward the room's over a
+//! batch, so the frames developed late in a run come out thinner or denser
+//! than the first, as they do in hand processing without a water jacket.
+
+/// temperature the developer's strength is given for
+pub const REFERENCE_CELSIUS: f32 = 20.0;
+/// relative change in developer activity per degree, the roughly 8% a
+/// degree of the usual time and temperature charts
+const ACTIVITY_PER_DEGREE: f32 = 0.08;
+
+/// Activity of a developer at `celsius` against the reference temperature
+pub fn activity(celsius: f32) -> f32 {
+    (ACTIVITY_PER_DEGREE * (celsius - REFERENCE_CELSIUS)).exp()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A bath left to settle toward the room's temperature
+pub struct Bath {
+    /// temperature as mixed, when the first frame goes in
+    pub start: f32,
+    /// temperature of the room
+    pub ambient: f32,
+    /// minutes for the difference from the room to fall by a factor e
+    pub time_constant: f32,
+    /// minutes from one frame going in to the next
+    pub interval: f32,
+}
+
+impl Bath {
+    /// Temperature `minutes` after the first frame went in
+    pub fn at(&self, minutes: f32) -> f32 {
+        if self.time_constant <= 0.0 {
+            return self.ambient;
+        }
+        self.ambient + (self.start - self.ambient) * (-minutes.max(0.0) / self.time_constant).exp()
+    }
+
+    /// Temperature as frame `index` of the batch goes in
+    pub fn frame(&self, index: usize) -> f32 {
+        self.at((index as f32) * self.interval)
+    }
+}
@@ -0,0 +1,198 @@
+//! Time-resolved exposure: the light source's temporal profile integrated
+//! over the interval each part of the frame is uncovered by the shutter
+
+use crate::error::{ Error, Result };
+use crate::field::Field;
+
+/// samples used to integrate the profile over one exposure window
+const SAMPLES: usize = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Intensity of the light source over time, with a mean of one so that a
+/// steady source and a flickering one of the same average are comparable
+pub enum LightProfile {
+    Constant,
+    /// single pulse, all of its light delivered within `duration` seconds
+    /// starting `start` seconds after the shutter opens
+    Flash {
+        start: f32,
+        duration: f32,
+    },
+    /// sinusoidal ripple, e.g. fluorescent tubes at twice the mains frequency
+    Flicker {
+        hz: f32,
+        depth: f32,
+    },
+    /// pulse width modulated LED, on for `duty` of every period
+    Pwm {
+        hz: f32,
+        duty: f32,
+    },
+}
+
+impl LightProfile {
+    /// Parse `constant`, `flash:START_MS:DURATION_MS`, `flicker:HZ:DEPTH` or
+    /// `pwm:HZ:DUTY`
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parts = text.trim().split(':');
+        let kind = parts.next().unwrap_or_default();
+        let args: Vec<f32> = parts
+            .map(|p| p.trim().parse::<f32>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| Error::Parse(format!("invalid light profile '{text}'")))?;
+        match (kind, &args[..]) {
+            ("constant", []) => Ok(LightProfile::Constant),
+            ("flash", &[start, duration]) if duration > 0.0 =>
+                Ok(LightProfile::Flash { start: start / 1000.0, duration: duration / 1000.0 }),
+            ("flicker", &[hz, depth]) =>
+                Ok(LightProfile::Flicker { hz, depth: depth.clamp(0.0, 1.0) }),
+            ("pwm", &[hz, duty]) if duty > 0.0 =>
+                Ok(LightProfile::Pwm { hz, duty: duty.min(1.0) }),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!(
+                            "invalid light profile '{text}', expected constant, flash:START_MS:DURATION_MS, flicker:HZ:DEPTH or pwm:HZ:DUTY"
+                        )
+                    )
+                ),
+        }
+    }
+
+    /// Relative intensity at time `t` seconds. A flash is scaled by the
+    /// shutter time so that catching the whole pulse gives unit exposure.
+    pub fn intensity(&self, t: f32, shutter_seconds: f32) -> f32 {
+        match *self {
+            LightProfile::Constant => 1.0,
+            LightProfile::Flash { start, duration } => {
+                if t >= start && t < start + duration { shutter_seconds / duration } else { 0.0 }
+            }
+            LightProfile::Flicker { hz, depth } => 1.0 + depth * (std::f32::consts::TAU * hz * t).sin(),
+            LightProfile::Pwm { hz, duty } => {
+                if (t * hz).rem_euclid(1.0) < duty { 1.0 / duty } else { 0.0 }
+            }
+        }
+    }
+
+    /// Mean intensity over `[start, start + duration)`
+    pub fn average(&self, start: f32, duration: f32, shutter_seconds: f32) -> f32 {
+        if duration <= 0.0 {
+            return self.intensity(start, shutter_seconds);
+        }
+        let step = duration / (SAMPLES as f32);
+        (0..SAMPLES)
+            .map(|i| self.intensity(start + ((i as f32) + 0.5) * step, shutter_seconds))
+            .sum::<f32>() / (SAMPLES as f32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shutter {
+    /// leaf shutter, the whole frame is exposed over the same interval
+    Leaf,
+    /// focal plane slit travelling top to bottom in `travel` seconds
+    FocalPlane {
+        travel: f32,
+    },
+    /// swing lens turning behind a vertical slit across the frame in
+    /// `sweep` seconds, exposing it column by column
+    SwingLens {
+        sweep: f32,
+    },
+}
+
+impl Shutter {
+    /// Parse `leaf`, `focal-plane:TRAVEL_MS` or `swing-lens:SWEEP_MS`
+    pub fn parse(text: &str) -> Result<Self> {
+        let millis = |ms: &str| {
+            ms.trim()
+                .parse::<f32>()
+                .map(|ms| ms / 1000.0)
+                .map_err(|_| Error::Parse(format!("invalid shutter '{text}'")))
+        };
+        match text.trim().split_once(':') {
+            None if text.trim() == "leaf" => Ok(Shutter::Leaf),
+            Some(("focal-plane", travel)) => Ok(Shutter::FocalPlane { travel: millis(travel)? }),
+            Some(("swing-lens", sweep)) => Ok(Shutter::SwingLens { sweep: millis(sweep)? }),
+            _ =>
+                Err(
+                    Error::Parse(
+                        format!(
+                            "invalid shutter '{text}', expected leaf, focal-plane:TRAVEL_MS or swing-lens:SWEEP_MS"
+                        )
+                    )
+                ),
+        }
+    }
+
+    /// Time at which row `row` of `height` starts being exposed
+    pub fn row_start(&self, row: u32, height: u32) -> f32 {
+        match *self {
+            Shutter::Leaf | Shutter::SwingLens { .. } => 0.0,
+            Shutter::FocalPlane { travel } => travel * (row as f32) / (height.max(2) as f32 - 1.0),
+        }
+    }
+
+    /// Time at which column `column` of `width` starts being exposed
+    pub fn column_start(&self, column: u32, width: u32) -> f32 {
+        match *self {
+            Shutter::SwingLens { sweep } => sweep * (column as f32) / (width.max(2) as f32 - 1.0),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Exposure gain of every row of a `height` tall frame relative to a steady
+/// source, for a shutter open `shutter_seconds` starting at time `phase`
+pub fn row_gains(
+    profile: &LightProfile,
+    shutter: &Shutter,
+    shutter_seconds: f32,
+    phase: f32,
+    height: u32
+) -> Vec<f32> {
+    (0..height)
+        .map(|row| {
+            let start = phase + shutter.row_start(row, height);
+            profile.average(start, shutter_seconds, shutter_seconds)
+        })
+        .collect()
+}
+
+/// Exposure gain of every column of a `width` wide frame relative to a
+/// steady source, each column seen through the slit for `shutter_seconds`
+pub fn column_gains(
+    profile: &LightProfile,
+    shutter: &Shutter,
+    shutter_seconds: f32,
+    phase: f32,
+    width: u32
+) -> Vec<f32> {
+    (0..width)
+        .map(|column| {
+            let start = phase + shutter.column_start(column, width);
+            profile.average(start, shutter_seconds, shutter_seconds)
+        })
+        .collect()
+}
+
+/// Scale the columns of a window starting `offset` columns into the frame
+pub fn apply_column_gains(field: &mut Field, gains: &[f32], offset: u32) {
+    let width = field.width as usize;
+    for row in field.data.chunks_mut(width) {
+        for (x, v) in row.iter_mut().enumerate() {
+            *v *= gains.get((offset as usize) + x).copied().unwrap_or(1.0);
+        }
+    }
+}
+
+/// Scale the rows of a window starting `offset` rows into the frame
+pub fn apply_row_gains(field: &mut Field, gains: &[f32], offset: u32) {
+    let width = field.width as usize;
+    for (y, row) in field.data.chunks_mut(width).enumerate() {
+        let gain = gains.get((offset as usize) + y).copied().unwrap_or(1.0);
+        row.iter_mut().for_each(|v| {
+            *v *= gain;
+        });
+    }
+}
@@ -0,0 +1,130 @@
+//! Darkroom test strips: bands across one sheet of paper given longer and
+//! longer exposures through the same negative, to pick the print time from
+//!
+//! In the darkroom the sheet is covered with a card that is pulled back a
+//! band at a time, so each band sees the enlarger for one more increment
+//! than the band before. Here the light through the negative is cut band
+//! by band to its share of the longest time and the sheet is exposed and
+//! developed once, so every band shares the paper and the developer.
+
+use crate::error::{ Error, Result };
+use crate::font;
+use crate::params::Params;
+use crate::pipeline;
+
+/// bare paper under the print that the exposure times are written on
+const MARGIN: u32 = 12;
+/// scale of the built-in font for the exposure times
+const LABEL_SCALE: u32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct TestStrip {
+    /// number of bands across the print
+    pub bands: u32,
+    /// exposure of the first band, in `exposure_time` units
+    pub first: f32,
+    /// exposure added for each further band
+    pub increment: f32,
+}
+
+impl TestStrip {
+    /// `bands` bands in steps of a third of `print_time`, passing through
+    /// it, as a first strip is usually made
+    pub fn around(print_time: f32, bands: u32) -> Self {
+        let increment = print_time / 3.0;
+        Self { bands, first: increment, increment }
+    }
+
+    /// Exposure of each band, left to right
+    pub fn times(&self) -> Vec<f32> {
+        (0..self.bands).map(|i| self.first + (i as f32) * self.increment).collect()
+    }
+
+    /// Exposure of the last, longest band
+    pub fn longest(&self) -> f32 {
+        self.first + (self.bands.saturating_sub(1) as f32) * self.increment
+    }
+
+    /// Column where band `band` starts on a print `width` wide
+    fn band_start(&self, band: u32, width: u32) -> u32 {
+        (((band as u64) * (width as u64)) / (self.bands.max(1) as u64)) as u32
+    }
+}
+
+/// The light reaching the paper through `negative`, each band cut to its
+/// share of the longest exposure
+pub fn expose(negative: &image::RgbaImage, strip: &TestStrip) -> image::DynamicImage {
+    let times = strip.times();
+    let longest = strip.longest().max(f32::MIN_POSITIVE);
+    let mut light = image::DynamicImage::ImageRgba8(negative.clone()).to_rgb32f();
+    let width = light.width();
+    for band in 0..strip.bands {
+        let share = times[band as usize].max(0.0) / longest;
+        let (start, end) = (strip.band_start(band, width), strip.band_start(band + 1, width));
+        for (x, _, pixel) in light.enumerate_pixels_mut() {
+            if x >= start && x < end {
+                pixel.0.iter_mut().for_each(|v| *v *= share);
+            }
+        }
+    }
+    image::DynamicImage::ImageRgb32F(light)
+}
+
+/// Process `image` as a negative and print it as a test strip on the
+/// paper `params` contact prints onto, with each band's exposure written
+/// beneath it
+pub fn print(image: &image::DynamicImage, params: &Params, strip: &TestStrip) -> Result<image::RgbaImage> {
+    if strip.bands == 0 || strip.first <= 0.0 || strip.increment < 0.0 {
+        return Err(
+            Error::Parse(
+                format!(
+                    "invalid test strip of {} bands from {} in steps of {}",
+                    strip.bands,
+                    strip.first,
+                    strip.increment
+                )
+            )
+        );
+    }
+    let params = Params { contact_print: false, projection: false, burn_in: None, ..params.clone() };
+    tracing::info!("Processing the negative");
+    let negative = pipeline::process(image, &pipeline::negative_params(&params))?;
+
+    let longest = strip.longest();
+    tracing::info!("Printing {} bands from {} to {longest}", strip.bands, strip.first);
+    let paper = Params { print_exposure_time: longest, ..params };
+    let print = pipeline::process(&expose(&negative, strip), &pipeline::print_params(&paper))?;
+    Ok(label(&print, strip))
+}
+
+/// `print` on a margin of bare paper with each band's exposure beneath it
+fn label(print: &image::RgbaImage, strip: &TestStrip) -> image::RgbaImage {
+    let label_height = font::text_height(LABEL_SCALE) + 2 * MARGIN;
+    let mut sheet = image::RgbaImage::from_pixel(
+        print.width(),
+        print.height() + label_height,
+        image::Rgba([255; 4])
+    );
+    image::imageops::replace(&mut sheet, print, 0, 0);
+    for (band, time) in strip.times().iter().enumerate() {
+        let band = band as u32;
+        let (start, end) = (strip.band_start(band, print.width()), strip.band_start(band + 1, print.width()));
+        let text = format!("{}", (time * 10.0).round() / 10.0);
+        let x = start + (end - start).saturating_sub(font::text_width(&text, LABEL_SCALE)) / 2;
+        font::draw_text(
+            &mut sheet,
+            x as i64,
+            (print.height() + MARGIN) as i64,
+            LABEL_SCALE,
+            &text,
+            image::Rgba([0, 0, 0, 255])
+        );
+        // pencil line between bands, where the card stopped
+        if band > 0 {
+            for y in print.height()..sheet.height() {
+                sheet.put_pixel(start, y, image::Rgba([0, 0, 0, 255]));
+            }
+        }
+    }
+    sheet
+}
@@ -0,0 +1,168 @@
+//! Minimal TOML reader for flat parameter files
+//!
+//! Only what a list of parameters needs: `key = value` lines with strings,
+//! numbers and booleans, blank lines and `#` comments. Tables, arrays and
+//! dates are refused rather than guessed at. The result is the JSON object
+//! the same parameters would be given as.
+
+use crate::error::{ Error, Result };
+use crate::json::{ self, Value };
+
+pub fn parse(text: &str) -> Result<Value> {
+    let mut members = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let error = |msg: &str| Error::Parse(format!("toml: {msg} on line {}", number + 1));
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(error("tables are not supported, parameters are flat"));
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| error("expected key = value"))?;
+        let key = unquote(key.trim()).ok_or_else(|| error("invalid key"))?;
+        let value = scalar(value.trim()).ok_or_else(|| error("invalid value"))?;
+        members.push((key, value));
+    }
+    Ok(Value::Object(members))
+}
+
+/// The line up to a `#` outside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => {
+                quote = None;
+            }
+            (None, '"' | '\'') => {
+                quote = Some(c);
+            }
+            (None, '#') => {
+                return &line[..i];
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// A bare or quoted key
+fn unquote(key: &str) -> Option<String> {
+    if key.starts_with('"') || key.starts_with('\'') {
+        return string(key);
+    }
+    let bare = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    bare.then(|| key.to_string())
+}
+
+fn scalar(text: &str) -> Option<Value> {
+    match text {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ if text.starts_with('"') || text.starts_with('\'') => string(text).map(Value::String),
+        _ => {
+            // kept as written, as JSON would keep it, so integers stay exact
+            let digits: String = text.chars().filter(|&c| c != '_').collect();
+            let digits = digits.strip_prefix('+').unwrap_or(&digits);
+            json::is_number(digits).then(|| Value::Number(digits.to_string()))
+        }
+    }
+}
+
+/// A basic `"..."` string with its escapes, or a literal `'...'` one
+fn string(text: &str) -> Option<String> {
+    if let Some(literal) = text.strip_prefix('\'') {
+        return literal.strip_suffix('\'').filter(|s| !s.contains('\'')).map(str::to_string);
+    }
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' =>
+                out.push(match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '"' => '"',
+                    '\\' => '\\',
+                    _ => {
+                        return None;
+                    }
+                }),
+            '"' => {
+                return None;
+            }
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Members of a parsed file as the parameter text they set
+    fn params(text: &str) -> Vec<(String, String)> {
+        let Value::Object(members) = parse(text).unwrap() else {
+            panic!("parameters parse to an object");
+        };
+        members
+            .into_iter()
+            .map(|(key, value)| (key, value.as_param().unwrap()))
+            .collect()
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn parses_scalars() {
+        let text = "num_grains = 2_000_000\ndt = 0.05\ncolor = true\nstock = \"hp5\"\n";
+        assert_eq!(params(text), [
+            pair("num_grains", "2000000"),
+            pair("dt", "0.05"),
+            pair("color", "true"),
+            pair("stock", "hp5"),
+        ]);
+    }
+
+    #[test]
+    fn quoting_and_escapes() {
+        let text = "\"quoted key\" = 'C:\\raw\\mask.png'\nlabel = \"say \\\"hi\\\"\\tthere\"\n";
+        assert_eq!(params(text), [pair("quoted key", "C:\\raw\\mask.png"), pair("label", "say \"hi\"\tthere")]);
+    }
+
+    #[test]
+    fn comments_outside_quotes() {
+        let text = "# a look\n\n  dt = 0.1  # step\nstock = \"tri-x # 400\" # film\nmask = 'a#b'\n";
+        assert_eq!(params(text), [pair("dt", "0.1"), pair("stock", "tri-x # 400"), pair("mask", "a#b")]);
+    }
+
+    #[test]
+    fn refuses_what_it_does_not_read() {
+        for text in [
+            "[develop]\ndt = 0.1",
+            "dt 0.1",
+            "dt = [0.1, 0.2]",
+            "dt = nan",
+            "stock = \"unterminated",
+            "stock = 'it's'",
+            "label = \"bad \\q escape\"",
+            "bad key = 1",
+        ] {
+            assert!(parse(text).is_err(), "{text:?} should be refused");
+        }
+        let err = parse("dt = 0.1\n\nstock = hp5").unwrap_err().to_string();
+        assert!(err.contains("line 3"), "{err}");
+    }
+}
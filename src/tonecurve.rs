@@ -0,0 +1,152 @@
+//! Tone curve export: the measured tonal response of a stock and its
+//! processing written out for grading tools, so the tone of the simulation
+//! can be applied without grain
+//!
+//! The format is picked by file extension:
+//! - `.csv`: `input,output` pairs
+//! - `.cube`: a 1D LUT, which Resolve imports as a custom curve
+//! - `.xmp`: a Lightroom / Camera Raw preset with a custom point curve
+
+use std::io::{ BufWriter, Write };
+use std::path::Path;
+
+use crate::error::Result;
+use crate::params::Params;
+use crate::sensitometry;
+
+/// entries of the exported 1D LUT
+const LUT_SIZE: usize = 1024;
+
+/// Input to output mapping sampled at increasing inputs, both in 0..1
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneCurve {
+    pub points: Vec<(f32, f32)>,
+}
+
+impl ToneCurve {
+    /// Measure `samples` evenly spaced input levels through the pipeline.
+    /// Unless `negative` is set the response is inverted and stretched to
+    /// the full range, as a print exposed for paper white and maximum black
+    /// would be.
+    pub fn measure(
+        params: &Params,
+        samples: usize,
+        grains_per_pixel: f32,
+        negative: bool
+    ) -> Result<Self> {
+        let samples = samples.max(2);
+        let inputs: Vec<f32> = (0..samples).map(|i| (i as f32) / ((samples - 1) as f32)).collect();
+        let readings = sensitometry::read_wedge(params, &inputs, grains_per_pixel)?;
+        let outputs: Vec<f32> = if negative {
+            readings
+        } else {
+            let (low, high) = readings
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(low, high), &t| (low.min(t), high.max(t)));
+            let range = (high - low).max(f32::EPSILON);
+            readings
+                .iter()
+                .map(|t| (high - t) / range)
+                .collect()
+        };
+        let mut curve = Self { points: inputs.into_iter().zip(outputs).collect() };
+        curve.make_monotonic(!negative);
+        Ok(curve)
+    }
+
+    /// Remove grain noise that would make the curve fold back on itself;
+    /// grading tools expect a monotonic curve
+    fn make_monotonic(&mut self, rising: bool) {
+        let mut bound = if rising { f32::MIN } else { f32::MAX };
+        for (_, y) in self.points.iter_mut() {
+            bound = if rising { bound.max(*y) } else { bound.min(*y) };
+            *y = bound;
+        }
+    }
+
+    /// Output for `x`, linearly interpolated between points
+    pub fn eval(&self, x: f32) -> f32 {
+        let i = self.points.partition_point(|&(px, _)| px < x);
+        match (i.checked_sub(1).map(|j| self.points[j]), self.points.get(i)) {
+            (Some((x0, y0)), Some(&(x1, y1))) => {
+                y0 + ((x - x0) / (x1 - x0).max(f32::EPSILON)) * (y1 - y0)
+            }
+            (None, Some(&(_, y))) | (Some((_, y)), None) => y,
+            (None, None) => x,
+        }
+    }
+
+    /// Write the curve in the format given by the extension of `path`
+    pub fn export(&self, path: &Path, name: &str) -> Result<()> {
+        tracing::info!("Exporting tone curve to {}", path.display());
+        let mut out = BufWriter::new(std::fs::File::create(path)?);
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("cube") => self.write_cube(&mut out, name)?,
+            Some("xmp") => self.write_xmp(&mut out, name)?,
+            _ => self.write_csv(&mut out)?,
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn write_csv(&self, out: &mut impl Write) -> Result<()> {
+        writeln!(out, "input,output")?;
+        for (x, y) in &self.points {
+            writeln!(out, "{x:.6},{y:.6}")?;
+        }
+        Ok(())
+    }
+
+    fn write_cube(&self, out: &mut impl Write, name: &str) -> Result<()> {
+        writeln!(out, "TITLE \"{name}\"")?;
+        writeln!(out, "LUT_1D_SIZE {LUT_SIZE}")?;
+        writeln!(out, "DOMAIN_MIN 0.0 0.0 0.0")?;
+        writeln!(out, "DOMAIN_MAX 1.0 1.0 1.0")?;
+        for i in 0..LUT_SIZE {
+            let y = self.eval((i as f32) / ((LUT_SIZE - 1) as f32)).clamp(0.0, 1.0);
+            writeln!(out, "{y:.6} {y:.6} {y:.6}")?;
+        }
+        Ok(())
+    }
+
+    fn write_xmp(&self, out: &mut impl Write, name: &str) -> Result<()> {
+        writeln!(out, r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#)?;
+        writeln!(out, r#" <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#)?;
+        writeln!(out, r#"  <rdf:Description rdf:about="""#)?;
+        writeln!(out, r#"    xmlns:crs="http://ns.adobe.com/camera-raw-settings/1.0/""#)?;
+        writeln!(out, r#"   crs:PresetType="Normal""#)?;
+        writeln!(out, r#"   crs:SupportsAmount="False""#)?;
+        writeln!(out, r#"   crs:SupportsColor="True""#)?;
+        writeln!(out, r#"   crs:SupportsMonochrome="True""#)?;
+        writeln!(out, r#"   crs:ProcessVersion="11.0""#)?;
+        writeln!(out, r#"   crs:ToneCurveName2012="Custom""#)?;
+        writeln!(out, r#"   crs:HasSettings="True">"#)?;
+        writeln!(out, "   <crs:Name>")?;
+        writeln!(out, "    <rdf:Alt>")?;
+        writeln!(out, r#"     <rdf:li xml:lang="x-default">{}</rdf:li>"#, escape_xml(name))?;
+        writeln!(out, "    </rdf:Alt>")?;
+        writeln!(out, "   </crs:Name>")?;
+        writeln!(out, "   <crs:ToneCurvePV2012>")?;
+        writeln!(out, "    <rdf:Seq>")?;
+        // Lightroom wants integer levels with strictly increasing inputs
+        let mut last = None;
+        for &(x, y) in &self.points {
+            let x = (x * 255.0).round() as u8;
+            if last.is_some_and(|last| x <= last) {
+                continue;
+            }
+            last = Some(x);
+            writeln!(out, "     <rdf:li>{x}, {}</rdf:li>", (y.clamp(0.0, 1.0) * 255.0).round() as u8)?;
+        }
+        writeln!(out, "    </rdf:Seq>")?;
+        writeln!(out, "   </crs:ToneCurvePV2012>")?;
+        writeln!(out, "  </rdf:Description>")?;
+        writeln!(out, " </rdf:RDF>")?;
+        writeln!(out, "</x:xmpmeta>")?;
+        Ok(())
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
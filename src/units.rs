@@ -0,0 +1,122 @@
+//! Photometric units the exposure is reckoned in
+//!
+//! The simulation counts photons: an input value of 1 delivers
+//! `exposure_time` photons per square micron of emulsion, before the
+//! stock's sensitivity. These types tie that count to the film plane
+//! exposure in lux seconds, either physically, through the photons a lux
+//! second carries, or through a stock's rated speed, so a shutter time, an
+//! exposure value or an ISO rating means the same whatever the image.
+
+use std::ops::Mul;
+
+/// photons per square micron in one lux second of light at 555 nm, where
+/// a lumen is 1/683 W: 1/683 J/m² over `h c / 555 nm` per photon
+pub const PHOTONS_PER_LUX_SECOND: f32 = 4091.0;
+/// reflected light meter calibration constant in cd s/m²
+const METER_CONSTANT: f32 = 12.5;
+/// reflectance a meter reading renders as, the middle gray of the input
+pub const MIDDLE_GRAY: f32 = 0.18;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Seconds(pub f32);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+/// Illuminance
+pub struct Lux(pub f32);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+/// Film plane exposure
+pub struct LuxSeconds(pub f32);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+/// Photon fluence on the emulsion
+pub struct PhotonsPerUm2(pub f32);
+
+impl Mul<Seconds> for Lux {
+    type Output = LuxSeconds;
+
+    fn mul(self, seconds: Seconds) -> LuxSeconds {
+        LuxSeconds(self.0 * seconds.0)
+    }
+}
+
+impl Lux {
+    /// Film plane illuminance of a middle gray subject metered at `ev`
+    /// (exposure value at ISO 100) through a lens at `f_number`, by the
+    /// camera equation without lens losses or falloff
+    pub fn metered(ev: f32, f_number: f32) -> Self {
+        let luminance = (METER_CONSTANT * (2.0f32).powf(ev)) / 100.0;
+        let n = f_number.max(f32::EPSILON);
+        Lux((std::f32::consts::PI * luminance) / (4.0 * n * n))
+    }
+}
+
+impl LuxSeconds {
+    /// Exposure at the ISO speed point of a stock rated at `iso`, where
+    /// its density first rises 0.1 above base plus fog
+    pub fn speed_point(iso: f32) -> Self {
+        LuxSeconds(0.8 / iso.max(f32::EPSILON))
+    }
+
+    /// ISO speed of a stock whose speed point is at this exposure
+    pub fn speed(self) -> f32 {
+        0.8 / self.0.max(f32::MIN_POSITIVE)
+    }
+
+    /// Exposure a meter gives middle gray on a stock rated at `iso`
+    pub fn metered(iso: f32) -> Self {
+        LuxSeconds(10.0 / iso.max(f32::EPSILON))
+    }
+
+    pub fn log10(self) -> f32 {
+        self.0.max(f32::MIN_POSITIVE).log10()
+    }
+
+    /// Photons of 555 nm light carrying this exposure
+    pub fn photons(self) -> PhotonsPerUm2 {
+        PhotonsPerUm2(self.0 * PHOTONS_PER_LUX_SECOND)
+    }
+}
+
+impl PhotonsPerUm2 {
+    /// Exposure in lux seconds of this many photons of 555 nm light
+    pub fn lux_seconds(self) -> LuxSeconds {
+        LuxSeconds(self.0 / PHOTONS_PER_LUX_SECOND)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+/// Simulation exposure units, the `exposure_time` of an input value of 1,
+/// per lux second at the film plane
+pub struct ExposureScale(pub f32);
+
+impl ExposureScale {
+    /// Scale at which the simulation counts real photons
+    pub const PHYSICAL: ExposureScale = ExposureScale(PHOTONS_PER_LUX_SECOND);
+
+    /// Scale at which `exposure_time` stands for an input value of 1
+    /// putting `illuminance` on the film for `shutter`
+    pub fn implied(exposure_time: f32, illuminance: Lux, shutter: Seconds) -> Self {
+        ExposureScale(exposure_time / (illuminance * shutter).0.max(f32::MIN_POSITIVE))
+    }
+
+    /// `exposure_time` of an input value of 1 putting `illuminance` on the
+    /// film for `shutter`
+    pub fn exposure_time(self, illuminance: Lux, shutter: Seconds) -> f32 {
+        self.units(illuminance * shutter)
+    }
+
+    /// Simulation units of an exposure
+    pub fn units(self, exposure: LuxSeconds) -> f32 {
+        self.0 * exposure.0
+    }
+
+    /// Exposure of a number of simulation units
+    pub fn lux_seconds(self, units: f32) -> LuxSeconds {
+        LuxSeconds(units / self.0.max(f32::MIN_POSITIVE))
+    }
+
+    pub fn log10(self) -> f32 {
+        self.0.max(f32::MIN_POSITIVE).log10()
+    }
+}